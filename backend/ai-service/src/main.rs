@@ -1,13 +1,20 @@
 use aws_lambda_events::event::cloudwatch_events::CloudWatchEvent;
 use journal_common::lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
 use journal_common::{
-    chrono, get_dynamo_client, publish_event, serde_json, JournalError,
+    ai_quota_exceeded, analysis_content_hash, analyze_lexicon, apply_entry_rollup, chrono, complete_structured,
+    get_dynamo_client, get_privacy_settings, llm_provider_from_env, publish_event, record_ai_usage, resolve_template,
+    serde_json, AnalysisResult, EmotionScore, JournalError, LlmProviderKind, LlmRequest, LlmResponse, PrivacySettings,
+    QuotaAction, RedactionMode, RollupDelta, ANALYSIS_STATUS_ANALYZED, ANALYSIS_TEMPLATE, DEFAULT_INSIGHT_TONE,
+    DEFAULT_TEMPLATE_LANGUAGE, INSIGHT_TONES, REDACTION_INSTRUCTION,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::env;
+
+// Analyses go to OpenAI when AI_PROVIDER is unset
+const DEFAULT_PROVIDER: LlmProviderKind = LlmProviderKind::OpenAi;
 
 // Event data structure
 #[derive(Debug, Deserialize)]
@@ -17,14 +24,22 @@ struct EntryEvent {
     user_id: String,
     title: String,
     content: String,
-}
-
-// AI Provider enum to represent different LLM providers
-enum AiProvider {
-    OpenAI,
-    Anthropic,
-    // Legacy model using rust-bert
-    RustBert,
+    // Set to false by entries in notebooks with AI analysis turned off
+    #[serde(default)]
+    ai_enabled: Option<bool>,
+    // Set by AnalysisRequested to re-analyze text that hasn't changed
+    #[serde(default)]
+    force: bool,
+    // Set by EntryCreated for time capsules, which are analyzed once they unlock
+    #[serde(default)]
+    sealed: bool,
+    // Entry metadata available to prompt templates
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    word_count: Option<i64>,
 }
 
 // Analysis result structure
@@ -39,291 +54,195 @@ struct EntryAnalysis {
     suggested_categories: Vec<String>,
     insights: Option<String>,
     reflections: Option<String>,
+    // Set by the offline analyzer only
+    sentiment_confidence: Option<f32>,
+    emotions: Vec<EmotionScore>,
     provider: String,
+    // Whether personal details were redacted from the text the provider saw
+    redacted: bool,
+    // analysis_content_hash of the analyzed title and content
+    content_hash: String,
+    // Prompt template version the model was given; None for offline analyses
+    prompt_template: Option<String>,
+    prompt_version: Option<u32>,
+    prompt_source: Option<String>,
 }
 
-// OpenAI API structures
-#[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    temperature: f32,
-    max_tokens: u32,
+// Why an entry was not analyzed; recorded on the entry as analysis_skip_reason
+#[derive(Debug, Clone, Copy)]
+enum SkipReason {
+    // The entry is a sealed time capsule
+    Sealed,
+    // The entry's notebook has AI analysis turned off
+    NotebookDisabled,
+    // AI_PROVIDER=none
+    AiDisabled,
+    // The user turned off AI insights in their settings
+    UserOptedOut,
+    // The tenant used up its monthly AI quota and AI_QUOTA_ACTION=block
+    QuotaExceeded,
 }
 
-#[derive(Serialize, Deserialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
+impl SkipReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::Sealed => "sealed",
+            SkipReason::NotebookDisabled => "notebook_disabled",
+            SkipReason::AiDisabled => "ai_disabled",
+            SkipReason::UserOptedOut => "user_opted_out",
+            SkipReason::QuotaExceeded => "quota_exceeded",
+        }
+    }
 }
 
-#[derive(Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
-}
+// Analyze an entry with the configured provider. When the provider fails the
+// offline analyzer is used instead, and the analysis says so in `provider`.
+async fn analyze_entry(
+    client: &DynamoDbClient,
+    entry: &EntryEvent,
+    provider_kind: LlmProviderKind,
+    settings: &PrivacySettings,
+) -> Result<EntryAnalysis, JournalError> {
+    if provider_kind == LlmProviderKind::Local {
+        return Ok(analyze_offline(entry));
+    }
 
-#[derive(Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
+    match analyze_with_llm(client, entry, settings).await {
+        Ok(analysis) => Ok(analysis),
+        Err(e) => {
+            tracing::warn!("AI provider analysis failed, using the offline analyzer: {}", e);
+            Ok(analyze_offline(entry))
+        }
+    }
 }
 
-// Anthropic API structures
-#[derive(Serialize)]
-struct AnthropicRequest {
-    model: String,
-    max_tokens: u32,
-    temperature: f32,
-    system: String,
-    messages: Vec<AnthropicMessage>,
-}
+// Analyze an entry with the offline sentiment lexicon
+fn analyze_offline(entry: &EntryEvent) -> EntryAnalysis {
+    let analysis = analyze_lexicon(&format!("{}\n\n{}", entry.title, entry.content), 5);
 
-#[derive(Serialize, Deserialize)]
-struct AnthropicMessage {
-    role: String,
-    content: String,
+    EntryAnalysis {
+        entry_id: entry.entry_id.clone(),
+        tenant_id: entry.tenant_id.clone(),
+        user_id: entry.user_id.clone(),
+        sentiment: analysis.sentiment.as_str().to_string(),
+        sentiment_score: analysis.score,
+        keywords: analysis.keywords,
+        suggested_categories: Vec::new(),
+        insights: None,
+        reflections: None,
+        sentiment_confidence: Some(analysis.confidence),
+        emotions: analysis.emotions,
+        provider: "local".to_string(),
+        redacted: false,
+        content_hash: analysis_content_hash(&entry.title, &entry.content),
+        prompt_template: None,
+        prompt_version: None,
+        prompt_source: None,
+    }
 }
 
-#[derive(Deserialize)]
-struct AnthropicResponse {
-    content: Vec<AnthropicContent>,
+async fn record_analysis_usage(client: &DynamoDbClient, entry: &EntryEvent, response: &LlmResponse) {
+    if let Err(e) = record_ai_usage(client, &entry.tenant_id, &entry.user_id, "analysis", response).await {
+        tracing::warn!("Failed to record AI usage for entry {}: {}", entry.entry_id, e);
+    }
 }
 
-#[derive(Deserialize)]
-struct AnthropicContent {
-    text: String,
-}
+// Analyze an entry with the language model provider, using the tenant's
+// analysis prompt template. Personal details are redacted first when the
+// user's privacy settings ask for it, and put back into the reply before it
+// is saved. The tokens used go on the tenant's usage ledger.
+async fn analyze_with_llm(
+    client: &DynamoDbClient,
+    entry: &EntryEvent,
+    settings: &PrivacySettings,
+) -> Result<EntryAnalysis, JournalError> {
+    let provider = llm_provider_from_env(DEFAULT_PROVIDER)?;
+    let mut redactor = RedactionMode::from_env()?.redactor(settings);
 
-// Get AI provider based on configuration
-fn get_ai_provider() -> AiProvider {
-    let provider = env::var("AI_PROVIDER").unwrap_or_else(|_| "openai".to_string());
-    
-    match provider.to_lowercase().as_str() {
-        "openai" => AiProvider::OpenAI,
-        "anthropic" => AiProvider::Anthropic,
-        "rustbert" => AiProvider::RustBert,
-        _ => AiProvider::OpenAI, // Default to OpenAI
-    }
-}
+    let template = resolve_template(client, &entry.tenant_id, ANALYSIS_TEMPLATE, &entry.entry_id).await?;
 
-// API key getters
-fn get_openai_api_key() -> Result<String, JournalError> {
-    env::var("OPENAI_API_KEY").map_err(|_| {
-        JournalError::ConfigurationError("OPENAI_API_KEY environment variable is not set".into())
-    })
-}
+    let tags = entry.tags.as_deref().unwrap_or_default().join(", ");
+    let (title, content, tags) = match redactor.as_mut() {
+        Some(redactor) => {
+            let redacted = (redactor.redact(&entry.title), redactor.redact(&entry.content), redactor.redact(&tags));
+            tracing::info!("Redacted {} personal details from entry {}", redactor.redacted_count(), entry.entry_id);
+            redacted
+        }
+        None => (entry.title.clone(), entry.content.clone(), tags),
+    };
+    let tone = settings
+        .insight_tone
+        .as_deref()
+        .filter(|tone| INSIGHT_TONES.contains(tone))
+        .unwrap_or(DEFAULT_INSIGHT_TONE);
+    let variables = HashMap::from([
+        ("title", title),
+        ("content", content),
+        ("tags", tags),
+        ("created_at", entry.created_at.clone().unwrap_or_default()),
+        ("word_count", entry.word_count.map(|n| n.to_string()).unwrap_or_default()),
+        ("language", settings.language.clone().unwrap_or_else(|| DEFAULT_TEMPLATE_LANGUAGE.to_string())),
+        ("tone", tone.to_string()),
+    ]);
 
-fn get_anthropic_api_key() -> Result<String, JournalError> {
-    env::var("ANTHROPIC_API_KEY").map_err(|_| {
-        JournalError::ConfigurationError("ANTHROPIC_API_KEY environment variable is not set".into())
-    })
-}
+    let (mut system, prompt) = template.render(&variables);
+    if redactor.is_some() {
+        system.push(' ');
+        system.push_str(REDACTION_INSTRUCTION);
+    }
 
-// Process entry with OpenAI
-async fn analyze_with_openai(entry: &EntryEvent) -> Result<EntryAnalysis, JournalError> {
-    let api_key = get_openai_api_key()?;
-    let client = reqwest::Client::new();
-    
-    // Prepare prompt for analysis
-    let system_prompt = "You are an AI journal assistant that analyzes journal entries. \
-                         Analyze the entry and provide: \
-                         1. The sentiment (positive, negative, or neutral) \
-                         2. A sentiment score between -1.0 (very negative) and 1.0 (very positive) \
-                         3. 5 keywords from the entry \
-                         4. 3 suggested categories for the entry \
-                         5. A brief insight about the entry \
-                         6. A reflective question to help the writer think deeper \
-                         Format your response as JSON with fields: sentiment, sentiment_score, keywords, suggested_categories, insights, reflections";
-    
-    let entry_text = format!("Title: {}\n\nContent: {}", entry.title, entry.content);
-    
-    let request = OpenAIRequest {
-        model: env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string()),
-        messages: vec![
-            OpenAIMessage {
-                role: "system".to_string(),
-                content: system_prompt.to_string(),
-            },
-            OpenAIMessage {
-                role: "user".to_string(),
-                content: entry_text,
-            },
-        ],
+    let request = LlmRequest {
+        system,
+        prompt,
         temperature: 0.3,
         max_tokens: 1000,
+        json: true,
+    };
+    // Replies are paid for whether or not they could be used
+    let (result, response) = match complete_structured(provider.as_ref(), &request, AnalysisResult::validate).await {
+        Ok((result, response)) => {
+            record_analysis_usage(client, entry, &response).await;
+            (result, response)
+        }
+        Err(e) => {
+            if let Some(spent) = &e.spent {
+                record_analysis_usage(client, entry, spent).await;
+            }
+            return Err(e.into());
+        }
     };
-    
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| JournalError::ExternalApiError(format!("OpenAI API request failed: {}", e)))?;
-        
-    let response_body: OpenAIResponse = response
-        .json()
-        .await
-        .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse OpenAI response: {}", e)))?;
-        
-    if response_body.choices.is_empty() {
-        return Err(JournalError::ExternalApiError("Empty response from OpenAI".into()));
-    }
-    
-    let analysis_json = response_body.choices[0].message.content.clone();
-    
-    // Parse JSON response from LLM
-    let analysis_value: Value = serde_json::from_str(&analysis_json)
-        .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse analysis JSON: {}", e)))?;
-    
-    let sentiment = analysis_value["sentiment"].as_str()
-        .unwrap_or("neutral")
-        .to_string();
-    
-    let sentiment_score = analysis_value["sentiment_score"].as_f64()
-        .unwrap_or(0.0) as f32;
-    
-    let keywords = analysis_value["keywords"].as_array()
-        .map(|arr| arr.iter().filter_map(|k| k.as_str().map(String::from)).collect())
-        .unwrap_or_else(Vec::new);
-    
-    let suggested_categories = analysis_value["suggested_categories"].as_array()
-        .map(|arr| arr.iter().filter_map(|c| c.as_str().map(String::from)).collect())
-        .unwrap_or_else(Vec::new);
-    
-    let insights = analysis_value["insights"].as_str()
-        .map(String::from);
-    
-    let reflections = analysis_value["reflections"].as_str()
-        .map(String::from);
-    
-    Ok(EntryAnalysis {
-        entry_id: entry.entry_id.clone(),
-        tenant_id: entry.tenant_id.clone(),
-        user_id: entry.user_id.clone(),
-        sentiment,
-        sentiment_score,
-        keywords,
-        suggested_categories,
-        insights,
-        reflections,
-        provider: "openai".to_string(),
-    })
-}
 
-// Process entry with Anthropic
-async fn analyze_with_anthropic(entry: &EntryEvent) -> Result<EntryAnalysis, JournalError> {
-    let api_key = get_anthropic_api_key()?;
-    let client = reqwest::Client::new();
-    
-    // Prepare prompt for analysis
-    let system_prompt = "You are an AI journal assistant that analyzes journal entries. \
-                         Analyze the entry and provide: \
-                         1. The sentiment (positive, negative, or neutral) \
-                         2. A sentiment score between -1.0 (very negative) and 1.0 (very positive) \
-                         3. 5 keywords from the entry \
-                         4. 3 suggested categories for the entry \
-                         5. A brief insight about the entry \
-                         6. A reflective question to help the writer think deeper \
-                         Format your response as JSON with fields: sentiment, sentiment_score, keywords, suggested_categories, insights, reflections";
-    
-    let entry_text = format!("Title: {}\n\nContent: {}", entry.title, entry.content);
-    
-    let request = AnthropicRequest {
-        model: env::var("ANTHROPIC_MODEL").unwrap_or_else(|_| "claude-3-haiku-20240307".to_string()),
-        max_tokens: 1000,
-        temperature: 0.3,
-        system: system_prompt.to_string(),
-        messages: vec![
-            AnthropicMessage {
-                role: "user".to_string(),
-                content: entry_text,
-            },
-        ],
+    let restore = |text: String| match &redactor {
+        Some(redactor) => redactor.restore(&text),
+        None => text,
     };
     
-    let response = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| JournalError::ExternalApiError(format!("Anthropic API request failed: {}", e)))?;
-        
-    let response_body: AnthropicResponse = response
-        .json()
-        .await
-        .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse Anthropic response: {}", e)))?;
-        
-    if response_body.content.is_empty() {
-        return Err(JournalError::ExternalApiError("Empty response from Anthropic".into()));
-    }
-    
-    let analysis_json = response_body.content[0].text.clone();
-    
-    // Parse JSON response from LLM
-    let analysis_value: Value = serde_json::from_str(&analysis_json)
-        .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse analysis JSON: {}", e)))?;
-    
-    let sentiment = analysis_value["sentiment"].as_str()
-        .unwrap_or("neutral")
-        .to_string();
-    
-    let sentiment_score = analysis_value["sentiment_score"].as_f64()
-        .unwrap_or(0.0) as f32;
-    
-    let keywords = analysis_value["keywords"].as_array()
-        .map(|arr| arr.iter().filter_map(|k| k.as_str().map(String::from)).collect())
-        .unwrap_or_else(Vec::new);
-    
-    let suggested_categories = analysis_value["suggested_categories"].as_array()
-        .map(|arr| arr.iter().filter_map(|c| c.as_str().map(String::from)).collect())
-        .unwrap_or_else(Vec::new);
-    
-    let insights = analysis_value["insights"].as_str()
-        .map(String::from);
-    
-    let reflections = analysis_value["reflections"].as_str()
-        .map(String::from);
-    
     Ok(EntryAnalysis {
         entry_id: entry.entry_id.clone(),
         tenant_id: entry.tenant_id.clone(),
         user_id: entry.user_id.clone(),
-        sentiment,
-        sentiment_score,
-        keywords,
-        suggested_categories,
-        insights,
-        reflections,
-        provider: "anthropic".to_string(),
+        sentiment: result.sentiment,
+        sentiment_score: result.sentiment_score,
+        keywords: result.keywords.into_iter().map(restore).collect(),
+        suggested_categories: result.suggested_categories.into_iter().map(restore).collect(),
+        insights: result.insights.map(restore),
+        reflections: result.reflections.map(restore),
+        sentiment_confidence: None,
+        emotions: Vec::new(),
+        provider: response.provider,
+        redacted: redactor.is_some(),
+        content_hash: analysis_content_hash(&entry.title, &entry.content),
+        prompt_template: Some(template.name),
+        prompt_version: Some(template.version),
+        prompt_source: Some(template.source),
     })
 }
 
-// Process entry with fallback analysis (basic text analysis without ML models)
-// Note: rust-bert is not available on Lambda ARM64, so we fallback to API-based analysis
-async fn analyze_with_rustbert(entry: &EntryEvent) -> Result<EntryAnalysis, JournalError> {
-    // Fallback to OpenAI when rust-bert is not available
-    tracing::warn!("rust-bert not available on Lambda ARM64, falling back to OpenAI");
-    analyze_with_openai(entry).await
-}
-
-async fn analyze_entry(
-    event: &EntryEvent,
-) -> Result<EntryAnalysis, JournalError> {
-    // Select provider based on configuration
-    match get_ai_provider() {
-        AiProvider::OpenAI => analyze_with_openai(event).await,
-        AiProvider::Anthropic => analyze_with_anthropic(event).await,
-        AiProvider::RustBert => analyze_with_rustbert(event).await,
-    }
-}
-
+// Save an analysis on its entry and in the insights table. Returns false, and
+// saves nothing, when the entry was deleted before its event was handled.
 async fn save_analysis(
     analysis: &EntryAnalysis,
-) -> Result<(), JournalError> {
+) -> Result<bool, JournalError> {
     let dynamo_client = get_dynamo_client().await;
     let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
     
@@ -333,13 +252,39 @@ async fn save_analysis(
         .table_name(table_name)
         .key("id", AttributeValue::S(analysis.entry_id.clone()))
         .key("tenant_id", AttributeValue::S(analysis.tenant_id.clone()))
-        .update_expression("SET sentiment_score = :score")
+        .update_expression(
+            "SET sentiment_score = :score, analysis_status = :status, analysis_hash = :hash, analyzed_at = :now \
+             REMOVE analysis_skip_reason",
+        )
+        .condition_expression("attribute_exists(id)")
         .expression_attribute_values(":score", AttributeValue::N(analysis.sentiment_score.to_string()))
+        .expression_attribute_values(":status", AttributeValue::S(ANALYSIS_STATUS_ANALYZED.to_string()))
+        .expression_attribute_values(":hash", AttributeValue::S(analysis.content_hash.clone()))
+        .expression_attribute_values(":now", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
         .send()
         .await;
     
-    if let Err(e) = result {
-        return Err(JournalError::DatabaseError(format!("Failed to update entry with sentiment: {}", e)));
+    match result {
+        Ok(response) => {
+            // Keep the day's average sentiment in the calendar rollups in step
+            if let Some(old_item) = response.attributes() {
+                let mut new_item = old_item.clone();
+                new_item.insert("sentiment_score".to_string(), AttributeValue::N(analysis.sentiment_score.to_string()));
+
+                let delta = RollupDelta::between(old_item, &new_item);
+                if let Err(e) = apply_entry_rollup(&dynamo_client, &new_item, &delta).await {
+                    tracing::warn!("Failed to update daily rollup: {}", e);
+                }
+            }
+        }
+        Err(e) => {
+            let e = e.into_service_error();
+            if e.is_conditional_check_failed_exception() {
+                return Ok(false);
+            }
+            return Err(JournalError::DatabaseError(format!("Failed to update entry with sentiment: {}", e)));
+        }
     }
     
     // Save to insights table
@@ -352,6 +297,16 @@ async fn save_analysis(
     item.insert("sentiment".to_string(), AttributeValue::S(analysis.sentiment.clone()));
     item.insert("sentiment_score".to_string(), AttributeValue::N(analysis.sentiment_score.to_string()));
     item.insert("provider".to_string(), AttributeValue::S(analysis.provider.clone()));
+    item.insert("redacted".to_string(), AttributeValue::Bool(analysis.redacted));
+    
+    // Prompt template version, for comparing and tracing prompt changes
+    if let (Some(template), Some(version), Some(source)) =
+        (&analysis.prompt_template, analysis.prompt_version, &analysis.prompt_source)
+    {
+        item.insert("prompt_template".to_string(), AttributeValue::S(template.clone()));
+        item.insert("prompt_version".to_string(), AttributeValue::N(version.to_string()));
+        item.insert("prompt_source".to_string(), AttributeValue::S(source.clone()));
+    }
     
     if !analysis.keywords.is_empty() {
         item.insert("keywords".to_string(), AttributeValue::Ss(analysis.keywords.clone()));
@@ -373,6 +328,20 @@ async fn save_analysis(
         item.insert("reflections".to_string(), AttributeValue::S(reflections.clone()));
     }
     
+    if let Some(confidence) = analysis.sentiment_confidence {
+        item.insert("sentiment_confidence".to_string(), AttributeValue::N(confidence.to_string()));
+    }
+    
+    // Emotion shares as a map of emotion -> share
+    if !analysis.emotions.is_empty() {
+        let emotions = analysis
+            .emotions
+            .iter()
+            .map(|e| (e.emotion.as_str().to_string(), AttributeValue::N(e.score.to_string())))
+            .collect();
+        item.insert("emotions".to_string(), AttributeValue::M(emotions));
+    }
+    
     // Add created_at timestamp
     let timestamp = chrono::Utc::now().to_rfc3339();
     item.insert("created_at".to_string(), AttributeValue::S(timestamp));
@@ -388,6 +357,65 @@ async fn save_analysis(
         return Err(JournalError::DatabaseError(format!("Failed to save insights: {}", e)));
     }
     
+    Ok(true)
+}
+
+// Whether the entry already has an analysis of exactly this text
+async fn is_already_analyzed(
+    client: &DynamoDbClient,
+    entry: &EntryEvent,
+) -> Result<bool, JournalError> {
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    let response = client
+        .get_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(entry.entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(entry.tenant_id.clone()))
+        .projection_expression("analysis_status, analysis_hash")
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to fetch entry: {}", e)))?;
+
+    let Some(item) = response.item() else {
+        return Ok(false);
+    };
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).map(String::as_str);
+    Ok(get_s("analysis_status") == Some(ANALYSIS_STATUS_ANALYZED)
+        && get_s("analysis_hash") == Some(analysis_content_hash(&entry.title, &entry.content).as_str()))
+}
+
+// Record on the entry that it was not analyzed, and why
+async fn record_skip(
+    entry: &EntryEvent,
+    reason: SkipReason,
+) -> Result<(), JournalError> {
+    tracing::info!("Skipping entry {}: {}", entry.entry_id, reason.as_str());
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    let result = dynamo_client
+        .update_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(entry.entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(entry.tenant_id.clone()))
+        .update_expression("SET analysis_status = :status, analysis_skip_reason = :reason")
+        .condition_expression("attribute_exists(id)")
+        .expression_attribute_values(":status", AttributeValue::S("skipped".to_string()))
+        .expression_attribute_values(":reason", AttributeValue::S(reason.as_str().to_string()))
+        .send()
+        .await;
+
+    if let Err(e) = result {
+        let e = e.into_service_error();
+        // The entry was deleted before its event was handled
+        if e.is_conditional_check_failed_exception() {
+            return Ok(());
+        }
+        return Err(JournalError::DatabaseError(format!("Failed to record skipped analysis: {}", e)));
+    }
+
     Ok(())
 }
 
@@ -407,6 +435,61 @@ async fn handler(
         }
     };
     
+    let provider_kind = LlmProviderKind::from_env(DEFAULT_PROVIDER).map_err(|e| {
+        tracing::error!("Invalid AI configuration: {}", e);
+        e
+    })?;
+    
+    let skip_reason = if entry_event.sealed {
+        Some(SkipReason::Sealed)
+    } else if entry_event.ai_enabled == Some(false) {
+        Some(SkipReason::NotebookDisabled)
+    } else if provider_kind == LlmProviderKind::Disabled {
+        Some(SkipReason::AiDisabled)
+    } else {
+        None
+    };
+    if let Some(reason) = skip_reason {
+        record_skip(&entry_event, reason).await?;
+        return Ok(());
+    }
+    
+    // Edits that leave the title and content alone keep their analysis
+    let dynamo_client = get_dynamo_client().await;
+    if !entry_event.force && is_already_analyzed(&dynamo_client, &entry_event).await? {
+        tracing::info!("Entry {} is unchanged since its last analysis, skipping", entry_event.entry_id);
+        return Ok(());
+    }
+    
+    // Failing to read the settings fails the invocation so the event is
+    // retried, rather than analyzing an entry the user may have opted out of
+    let settings = get_privacy_settings(&dynamo_client, &entry_event.tenant_id, &entry_event.user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load settings for user {}: {}", entry_event.user_id, e);
+            e
+        })?;
+    
+    if !settings.ai_insights_enabled {
+        record_skip(&entry_event, SkipReason::UserOptedOut).await?;
+        return Ok(());
+    }
+    
+    // Once the tenant's monthly quota is used up, entries are analyzed
+    // offline or not at all, depending on AI_QUOTA_ACTION
+    let provider_kind = if provider_kind == LlmProviderKind::Local {
+        provider_kind
+    } else {
+        match ai_quota_exceeded(&dynamo_client, &entry_event.tenant_id).await {
+            Some(QuotaAction::Block) => {
+                record_skip(&entry_event, SkipReason::QuotaExceeded).await?;
+                return Ok(());
+            }
+            Some(QuotaAction::Downgrade) => LlmProviderKind::Local,
+            None => provider_kind,
+        }
+    };
+    
     tracing::info!(
         "Processing entry: {} for user {} in tenant {}",
         entry_event.entry_id,
@@ -415,7 +498,7 @@ async fn handler(
     );
     
     // Analyze entry using configured provider
-    let analysis = match analyze_entry(&entry_event).await {
+    let analysis = match analyze_entry(&dynamo_client, &entry_event, provider_kind, &settings).await {
         Ok(analysis) => analysis,
         Err(e) => {
             tracing::error!("Analysis failed: {}", e);
@@ -424,9 +507,16 @@ async fn handler(
     };
     
     // Save analysis results
-    if let Err(e) = save_analysis(&analysis).await {
-        tracing::error!("Failed to save analysis: {}", e);
-        return Err(Box::new(e));
+    match save_analysis(&analysis).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::info!("Entry {} was deleted before its analysis was saved", analysis.entry_id);
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!("Failed to save analysis: {}", e);
+            return Err(Box::new(e));
+        }
     }
     
     // Publish event for insights ready
@@ -439,6 +529,8 @@ async fn handler(
         "keywords": analysis.keywords,
         "suggested_categories": analysis.suggested_categories,
         "provider": analysis.provider,
+        "prompt_template": analysis.prompt_template,
+        "prompt_version": analysis.prompt_version,
     });
     
     // Add insights and reflections if present
@@ -450,6 +542,10 @@ async fn handler(
         event_detail["reflections"] = serde_json::Value::String(reflections.clone());
     }
     
+    if !analysis.emotions.is_empty() {
+        event_detail["emotions"] = serde_json::json!(analysis.emotions);
+    }
+    
     if let Err(e) = publish_event("AiInsightsReady", event_detail).await {
         tracing::error!("Failed to publish event: {}", e);
         // Continue anyway - this is non-critical
@@ -470,3 +566,115 @@ async fn main() -> Result<(), Error> {
 
     run(service_fn(handler)).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use journal_common::test_util::{lock_env, set_mock_ai, EmptyJsonServer};
+
+    const ANALYSIS_REPLY: &str = r#"{"sentiment": "positive", "sentiment_score": 0.6,
+        "keywords": ["garden", "calm"], "suggested_categories": ["nature"],
+        "insights": "Time outside with [NAME_1] settles you.", "reflections": "What else brings this calm?"}"#;
+
+    fn entry() -> EntryEvent {
+        serde_json::from_value(serde_json::json!({
+            "entry_id": "entry-1",
+            "tenant_id": "tenant-1",
+            "user_id": "user-1",
+            "title": "Sunday",
+            "content": "Spent the afternoon in the garden with Anna and felt calm.",
+            "tags": ["garden"],
+            "created_at": "2026-10-18T10:00:00Z",
+            "word_count": 11
+        }))
+        .unwrap()
+    }
+
+    async fn analyze(server: &EmptyJsonServer, settings: &PrivacySettings) -> EntryAnalysis {
+        analyze_entry(&server.dynamo_client(), &entry(), LlmProviderKind::Mock, settings).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn analyzes_a_clean_reply() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("mock", &[ANALYSIS_REPLY]);
+
+        let analysis = analyze(&server, &PrivacySettings::default()).await;
+        assert_eq!(analysis.provider, "mock");
+        assert_eq!(analysis.sentiment, "positive");
+        assert_eq!(analysis.sentiment_score, 0.6);
+        assert_eq!(analysis.keywords, vec!["garden", "calm"]);
+        assert_eq!(analysis.suggested_categories, vec!["nature"]);
+        assert_eq!(analysis.reflections.as_deref(), Some("What else brings this calm?"));
+        assert!(!analysis.redacted);
+        assert_eq!(analysis.prompt_source.as_deref(), Some("builtin"));
+        assert_eq!(analysis.content_hash, analysis_content_hash(&entry().title, &entry().content));
+    }
+
+    #[tokio::test]
+    async fn repairs_a_reply_that_fails_validation() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        let contradictory = r#"{"sentiment": "positive", "sentiment_score": -0.8}"#;
+        set_mock_ai("mock", &[contradictory, ANALYSIS_REPLY]);
+
+        let analysis = analyze(&server, &PrivacySettings::default()).await;
+        assert_eq!(analysis.provider, "mock");
+        assert_eq!(analysis.sentiment_score, 0.6);
+    }
+
+    #[tokio::test]
+    async fn repairs_a_reply_without_json() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        let fenced = format!("```json\n{}\n```", ANALYSIS_REPLY);
+        set_mock_ai("mock", &["The entry feels calm and positive.", fenced.as_str()]);
+
+        let analysis = analyze(&server, &PrivacySettings::default()).await;
+        assert_eq!(analysis.provider, "mock");
+        assert_eq!(analysis.keywords, vec!["garden", "calm"]);
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_provider() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("anthropic,mock", &[ANALYSIS_REPLY]);
+        // Anthropic gets `{}` back, which has no reply in it
+        std::env::set_var("ANTHROPIC_API_KEY", "test");
+        std::env::set_var("ANTHROPIC_BASE_URL", &server.url);
+
+        let analysis = analyze(&server, &PrivacySettings::default()).await;
+        assert_eq!(analysis.provider, "mock");
+        assert_eq!(analysis.sentiment, "positive");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_offline_analyzer() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("mock", &["not json", "still not json"]);
+
+        let analysis = analyze(&server, &PrivacySettings::default()).await;
+        assert_eq!(analysis.provider, "local");
+        assert!(analysis.sentiment_confidence.is_some());
+        assert_eq!(analysis.prompt_template, None);
+    }
+
+    #[tokio::test]
+    async fn restores_redacted_details_in_the_reply() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("mock", &[ANALYSIS_REPLY]);
+        let settings = PrivacySettings {
+            privacy_level: Some("strict".to_string()),
+            redaction_names: vec!["Anna".to_string()],
+            ..Default::default()
+        };
+
+        let analysis = analyze(&server, &settings).await;
+        assert!(analysis.redacted);
+        assert_eq!(analysis.insights.as_deref(), Some("Time outside with Anna settles you."));
+    }
+}
@@ -0,0 +1,173 @@
+// AI usage reporting
+//
+// GET /ai/usage?month=yyyy-mm reports the tenant's AI consumption for a month
+// (the current UTC month by default) against its quota, broken down by model
+// and by day, along with the caller's own share. Admins also get the
+// breakdown by user.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use chrono::{NaiveDate, Utc};
+use journal_common::{
+    error_response, extract_tenant_context, get_dynamo_client, get_quota_status, get_usage_records, json_response,
+    lambda_runtime::Error, usage_month, JournalError, QuotaAction, UsageRecord, UsageTotals,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+struct UsageSummary {
+    calls: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    total_tokens: u64,
+    cost_usd: f64,
+}
+
+impl From<&UsageTotals> for UsageSummary {
+    fn from(totals: &UsageTotals) -> Self {
+        UsageSummary {
+            calls: totals.calls,
+            input_tokens: totals.input_tokens,
+            output_tokens: totals.output_tokens,
+            total_tokens: totals.total_tokens(),
+            cost_usd: round_usd(totals.cost_usd()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct QuotaSummary {
+    monthly_tokens: Option<u64>,
+    monthly_cost_usd: Option<f64>,
+    remaining_tokens: Option<u64>,
+    remaining_cost_usd: Option<f64>,
+    action: QuotaAction,
+    exceeded: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelUsage {
+    provider: String,
+    model: String,
+    #[serde(flatten)]
+    usage: UsageSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct DayUsage {
+    day: String,
+    #[serde(flatten)]
+    usage: UsageSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct UserUsage {
+    user_id: String,
+    #[serde(flatten)]
+    usage: UsageSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct AiUsageResponse {
+    month: String,
+    total: UsageSummary,
+    quota: QuotaSummary,
+    by_model: Vec<ModelUsage>,
+    by_day: Vec<DayUsage>,
+    mine: UsageSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by_user: Option<Vec<UserUsage>>,
+}
+
+// Report hundredths of a cent; the ledger keeps micro-dollars
+fn round_usd(usd: f64) -> f64 {
+    (usd * 10_000.0).round() / 10_000.0
+}
+
+// Sum records under a key, keeping the keys in order
+fn group_by<K: Ord>(records: &[UsageRecord], key: impl Fn(&UsageRecord) -> K) -> BTreeMap<K, UsageTotals> {
+    let mut groups: BTreeMap<K, UsageTotals> = BTreeMap::new();
+    for record in records {
+        groups.entry(key(record)).or_default().add(&record.totals);
+    }
+    groups
+}
+
+// GET /ai/usage
+pub(crate) async fn get_ai_usage(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let month = match event.query_string_parameters.first("month") {
+        Some(month) => {
+            if month.len() != 7 || NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").is_err() {
+                return Ok(error_response(
+                    400,
+                    &JournalError::ValidationError("month must be in yyyy-mm format".into()),
+                ));
+            }
+            month.to_string()
+        }
+        None => usage_month(Utc::now()),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let (status, records) = tokio::join!(
+        get_quota_status(&dynamo_client, &claims.tenant_id, &month),
+        get_usage_records(&dynamo_client, &claims.tenant_id, &month)
+    );
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+    let records = match records {
+        Ok(records) => records,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let quota = &status.quota;
+    let usage = &status.usage;
+    let quota_summary = QuotaSummary {
+        monthly_tokens: quota.monthly_tokens,
+        monthly_cost_usd: quota.monthly_cost_micros.map(|micros| round_usd(micros as f64 / 1_000_000.0)),
+        remaining_tokens: quota.monthly_tokens.map(|limit| limit.saturating_sub(usage.total_tokens())),
+        remaining_cost_usd: quota
+            .monthly_cost_micros
+            .map(|limit| round_usd(limit.saturating_sub(usage.cost_micros) as f64 / 1_000_000.0)),
+        action: quota.action,
+        exceeded: status.exceeded(),
+    };
+
+    let by_model = group_by(&records, |r| (r.provider.clone(), r.model.clone()))
+        .into_iter()
+        .map(|((provider, model), totals)| ModelUsage { provider, model, usage: UsageSummary::from(&totals) })
+        .collect();
+    let by_day = group_by(&records, |r| r.day.clone())
+        .into_iter()
+        .map(|(day, totals)| DayUsage { day, usage: UsageSummary::from(&totals) })
+        .collect();
+
+    let by_user = group_by(&records, |r| r.user_id.clone());
+    let mine = by_user.get(&claims.sub).copied().unwrap_or_default();
+    let by_user = (claims.role.as_deref() == Some("admin")).then(|| {
+        by_user
+            .into_iter()
+            .map(|(user_id, totals)| UserUsage { user_id, usage: UsageSummary::from(&totals) })
+            .collect()
+    });
+
+    Ok(json_response(200, &AiUsageResponse {
+        total: UsageSummary::from(usage),
+        quota: quota_summary,
+        by_model,
+        by_day,
+        mine: UsageSummary::from(&mine),
+        by_user,
+        month,
+    }))
+}
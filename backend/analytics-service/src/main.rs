@@ -2,12 +2,16 @@ use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
 use journal_common::lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use aws_sdk_dynamodb::types::AttributeValue;
 use journal_common::{
-    error_response, extract_tenant_context, get_dynamo_client, json_response, publish_event, serde_json, JournalError,
+    entry_mood, entry_notebook_id, error_response, extract_tenant_context, field_numeric_value,
+    get_dynamo_client, get_template, is_sealed, json_response, publish_event, query_timeline, serde_json,
+    Emotion, FieldType, JournalError, Valence,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use chrono::{DateTime, Utc, Duration, Timelike};
 
+mod ai_usage;
+
 // Analytics data structures
 #[derive(Debug, Serialize)]
 struct EntryFrequency {
@@ -29,6 +33,31 @@ struct MoodTrend {
     entry_count: i32,
 }
 
+#[derive(Debug, Serialize)]
+struct EmotionCount {
+    emotion: Emotion,
+    valence: Valence,
+    count: i32,
+    percentage: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct MoodCheckinTrend {
+    date: String,
+    // Intensity signed by valence: -5 (strongly negative) to 5 (strongly positive)
+    mood_score: f32,
+    average_intensity: f32,
+    average_energy: Option<f32>,
+    entry_count: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct FieldPoint {
+    date: String,
+    value: f64,
+    entry_count: i32,
+}
+
 #[derive(Debug, Serialize)]
 struct WritingPattern {
     hour_of_day: i32,
@@ -53,6 +82,7 @@ struct AnalyticsQueryParams {
     start_date: Option<String>,
     end_date: Option<String>,
     time_period: Option<String>, // day, week, month, year
+    notebook_id: Option<String>,
 }
 
 async fn get_analytics_summary(
@@ -69,6 +99,7 @@ async fn get_analytics_summary(
         start_date: event.query_string_parameters.first("start_date").map(String::from),
         end_date: event.query_string_parameters.first("end_date").map(String::from),
         time_period: event.query_string_parameters.first("time_period").map(String::from),
+        notebook_id: event.query_string_parameters.first("notebook_id").map(String::from),
     };
     
     // Determine date range
@@ -103,6 +134,7 @@ async fn get_analytics_summary(
         &claims.sub,
         &start_date.to_rfc3339(),
         &end_date.to_rfc3339(),
+        query_params.notebook_id.as_deref(),
     ).await {
         Ok(entries) => entries,
         Err(e) => return Ok(error_response(500, &e)),
@@ -163,26 +195,31 @@ async fn get_user_entries(
     user_id: &str,
     start_date: &str,
     end_date: &str,
+    notebook_id: Option<&str>,
 ) -> Result<Vec<HashMap<String, AttributeValue>>, JournalError> {
     let dynamo_client = get_dynamo_client().await;
     let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
     
-    // Query DynamoDB for entries
-    let result = dynamo_client
-        .query()
-        .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
-        .expression_attribute_values(":tenant_id", AttributeValue::S(tenant_id.to_string()))
-        .expression_attribute_values(":user_id", AttributeValue::S(user_id.to_string()))
-        .filter_expression("created_at BETWEEN :start_date AND :end_date")
-        .expression_attribute_values(":start_date", AttributeValue::S(start_date.to_string()))
-        .expression_attribute_values(":end_date", AttributeValue::S(end_date.to_string()))
-        .send()
-        .await
-        .map_err(|e| JournalError::DatabaseError(format!("Failed to query entries: {}", e)))?;
-    
-    Ok(result.items().to_vec())
+    // Read the date range from the user's timeline instead of filtering the whole partition
+    let mut entries = query_timeline(
+        &dynamo_client,
+        &table_name,
+        tenant_id,
+        user_id,
+        Some(start_date),
+        Some(end_date),
+    ).await?;
+    
+    // Sealed time capsules stay out of analytics until they unlock
+    let now = Utc::now();
+    entries.retain(|item| !is_sealed(item, now));
+    
+    // Restrict to one notebook if requested
+    if let Some(notebook_id) = notebook_id {
+        entries.retain(|item| entry_notebook_id(item) == notebook_id);
+    }
+    
+    Ok(entries)
 }
 
 async fn get_user_insights(
@@ -368,6 +405,78 @@ fn calculate_mood_trends(
     trends
 }
 
+fn calculate_emotion_distribution(
+    entries: &[HashMap<String, AttributeValue>],
+) -> Vec<EmotionCount> {
+    let mut emotion_counts: HashMap<Emotion, i32> = HashMap::new();
+    let mut total = 0;
+    
+    for mood in entries.iter().filter_map(entry_mood) {
+        *emotion_counts.entry(mood.primary_emotion).or_insert(0) += 1;
+        total += 1;
+    }
+    
+    let mut distribution: Vec<EmotionCount> = emotion_counts
+        .into_iter()
+        .map(|(emotion, count)| EmotionCount {
+            emotion,
+            valence: emotion.valence(),
+            count,
+            percentage: (count as f32 / total as f32) * 100.0,
+        })
+        .collect();
+    
+    // Sort by count descending, then by name so ties are stable
+    distribution.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emotion.as_str().cmp(b.emotion.as_str())));
+    distribution
+}
+
+fn calculate_checkin_trends(
+    entries: &[HashMap<String, AttributeValue>],
+) -> Vec<MoodCheckinTrend> {
+    // date -> (score total, intensity total, energy total, energy count, entry count)
+    let mut by_date: HashMap<String, (f32, f32, f32, i32, i32)> = HashMap::new();
+    
+    for entry in entries {
+        let (Some(mood), Some(AttributeValue::S(created_at))) = (entry_mood(entry), entry.get("created_at")) else {
+            continue;
+        };
+        let Ok(date_time) = created_at.parse::<DateTime<Utc>>() else {
+            continue;
+        };
+        
+        let intensity = mood.intensity as f32;
+        let score = match mood.valence() {
+            Valence::Positive => intensity,
+            Valence::Negative => -intensity,
+            Valence::Neutral => 0.0,
+        };
+        
+        let day = by_date.entry(date_time.format("%Y-%m-%d").to_string()).or_insert((0.0, 0.0, 0.0, 0, 0));
+        day.0 += score;
+        day.1 += intensity;
+        if let Some(energy) = mood.energy {
+            day.2 += energy as f32;
+            day.3 += 1;
+        }
+        day.4 += 1;
+    }
+    
+    let mut trends: Vec<MoodCheckinTrend> = by_date
+        .into_iter()
+        .map(|(date, (score, intensity, energy, energy_count, count))| MoodCheckinTrend {
+            date,
+            mood_score: score / count as f32,
+            average_intensity: intensity / count as f32,
+            average_energy: (energy_count > 0).then(|| energy / energy_count as f32),
+            entry_count: count,
+        })
+        .collect();
+    
+    trends.sort_by(|a, b| a.date.cmp(&b.date));
+    trends
+}
+
 fn calculate_writing_patterns(
     entries: &[HashMap<String, AttributeValue>],
 ) -> Vec<WritingPattern> {
@@ -536,6 +645,7 @@ async fn get_mood_analytics(
         start_date: event.query_string_parameters.first("start_date").map(String::from),
         end_date: event.query_string_parameters.first("end_date").map(String::from),
         time_period: event.query_string_parameters.first("time_period").map(String::from),
+        notebook_id: event.query_string_parameters.first("notebook_id").map(String::from),
     };
     
     // Determine date range (reuse code from summary)
@@ -570,6 +680,7 @@ async fn get_mood_analytics(
         &claims.sub,
         &start_date.to_rfc3339(),
         &end_date.to_rfc3339(),
+        query_params.notebook_id.as_deref(),
     ).await {
         Ok(entries) => entries,
         Err(e) => return Ok(error_response(500, &e)),
@@ -590,6 +701,10 @@ async fn get_mood_analytics(
     // Calculate mood trends
     let mood_trends = calculate_mood_trends(&entries, &insights);
     
+    // Self-reported moods: which emotions were felt, and how strongly per day
+    let emotion_distribution = calculate_emotion_distribution(&entries);
+    let checkin_trends = calculate_checkin_trends(&entries);
+    
     // Additional mood stats
     let mut positive_count = 0;
     let mut negative_count = 0;
@@ -627,6 +742,8 @@ async fn get_mood_analytics(
     // Prepare response
     let response = serde_json::json!({
         "mood_trends": mood_trends,
+        "emotion_distribution": emotion_distribution,
+        "checkin_trends": checkin_trends,
         "mood_distribution": {
             "positive": {
                 "count": positive_count,
@@ -650,6 +767,138 @@ async fn get_mood_analytics(
     Ok(json_response(200, &response))
 }
 
+async fn get_field_analytics(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+    
+    // Parse query parameters directly from QueryMap
+    let query_params = AnalyticsQueryParams {
+        start_date: event.query_string_parameters.first("start_date").map(String::from),
+        end_date: event.query_string_parameters.first("end_date").map(String::from),
+        time_period: event.query_string_parameters.first("time_period").map(String::from),
+        notebook_id: event.query_string_parameters.first("notebook_id").map(String::from),
+    };
+    
+    let (Some(template_id), Some(field_key)) = (
+        event.query_string_parameters.first("template_id"),
+        event.query_string_parameters.first("field"),
+    ) else {
+        return Ok(error_response(
+            400,
+            &JournalError::ValidationError("template_id and field are required".into()),
+        ));
+    };
+    
+    // Look up the field definition; checklist values are charted as the share checked
+    let dynamo_client = get_dynamo_client().await;
+    let template = match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, template_id).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+    
+    let Some(field) = template.fields.iter().find(|f| f.key == field_key) else {
+        return Ok(error_response(404, &JournalError::NotFoundError("Field not found".into())));
+    };
+    
+    if field.field_type == FieldType::ShortText {
+        return Ok(error_response(
+            400,
+            &JournalError::ValidationError("Short text fields cannot be charted".into()),
+        ));
+    }
+    
+    // Determine date range (reuse code from summary)
+    let end_date = match &query_params.end_date {
+        Some(date) => match date.parse::<DateTime<Utc>>() {
+            Ok(date) => date,
+            Err(_) => Utc::now(),
+        },
+        None => Utc::now(),
+    };
+    
+    let start_date = match &query_params.start_date {
+        Some(date) => match date.parse::<DateTime<Utc>>() {
+            Ok(date) => date,
+            Err(_) => end_date - Duration::days(30), // Default to 30 days
+        },
+        None => {
+            // Default time period based on query param
+            match query_params.time_period.as_deref() {
+                Some("day") => end_date - Duration::days(1),
+                Some("week") => end_date - Duration::days(7),
+                Some("month") => end_date - Duration::days(30),
+                Some("year") => end_date - Duration::days(365),
+                _ => end_date - Duration::days(30), // Default to 30 days
+            }
+        }
+    };
+    
+    let entries = match get_user_entries(
+        &claims.tenant_id,
+        &claims.sub,
+        &start_date.to_rfc3339(),
+        &end_date.to_rfc3339(),
+        query_params.notebook_id.as_deref(),
+    ).await {
+        Ok(entries) => entries,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+    
+    // Average the field per day over entries written from this template
+    let mut values_by_date: HashMap<String, (f64, i32)> = HashMap::new();
+    
+    for entry in &entries {
+        if entry.get("template_id").and_then(|v| v.as_s().ok()).map(String::as_str) != Some(template_id) {
+            continue;
+        }
+        
+        let value = entry
+            .get("field_values")
+            .and_then(|v| v.as_m().ok())
+            .and_then(|values| values.get(&field.key))
+            .and_then(|value| field_numeric_value(field, value));
+        
+        if let (Some(value), Some(AttributeValue::S(created_at))) = (value, entry.get("created_at")) {
+            if let Ok(date_time) = created_at.parse::<DateTime<Utc>>() {
+                let date = date_time.format("%Y-%m-%d").to_string();
+                let (total, count) = values_by_date.entry(date).or_insert((0.0, 0));
+                *total += value;
+                *count += 1;
+            }
+        }
+    }
+    
+    let mut points: Vec<FieldPoint> = values_by_date
+        .into_iter()
+        .map(|(date, (total, count))| FieldPoint {
+            date,
+            value: total / count as f64,
+            entry_count: count,
+        })
+        .collect();
+    points.sort_by(|a, b| a.date.cmp(&b.date));
+    
+    let response = serde_json::json!({
+        "template_id": template.id,
+        "field": field.key,
+        "label": field.label,
+        "field_type": field.field_type,
+        "points": points,
+        "date_range": {
+            "start_date": start_date.to_rfc3339(),
+            "end_date": end_date.to_rfc3339()
+        }
+    });
+    
+    Ok(json_response(200, &response))
+}
+
 async fn request_analytics_generation(
     event: ApiGatewayProxyRequest,
 ) -> Result<ApiGatewayProxyResponse, Error> {
@@ -691,6 +940,8 @@ async fn handler(
         ("GET", "/analytics") => get_analytics_summary(event.payload).await,
         ("POST", "/analytics") => request_analytics_generation(event.payload).await,
         ("GET", "/analytics/mood") => get_mood_analytics(event.payload).await,
+        ("GET", "/analytics/fields") => get_field_analytics(event.payload).await,
+        ("GET", "/ai/usage") => ai_usage::get_ai_usage(event.payload).await,
         // More endpoints can be added
         _ => Ok(error_response(
             404,
@@ -28,32 +28,38 @@ pub struct EntryAnalysis {
     pub sentiment: SentimentAnalysis,
     pub keywords: KeywordAnalysis,
     pub insights: InsightAnalysis,
+    pub emotions: Vec<crate::EmotionScore>,
+}
+
+/// Analyze an entry with the offline sentiment lexicon. Nothing is made up:
+/// categories, insights and reflections are left empty, and the confidence
+/// says how much sentiment-bearing language the score rests on.
+pub fn analyze_entry_offline(title: &str, content: &str) -> EntryAnalysis {
+    let analysis = crate::analyze_lexicon(&format!("{}\n{}", title, content), 5);
+
+    EntryAnalysis {
+        sentiment: SentimentAnalysis {
+            sentiment: analysis.sentiment.as_str().to_string(),
+            score: analysis.score,
+            confidence: Some(analysis.confidence),
+        },
+        keywords: KeywordAnalysis {
+            keywords: analysis.keywords,
+            categories: Vec::new(),
+        },
+        insights: InsightAnalysis {
+            insights: None,
+            reflections: None,
+        },
+        emotions: analysis.emotions,
+    }
 }
 
 /// Extract keywords from text using basic frequency analysis
-/// Used when AI features are not available
+/// Used when AI features are not available; shares the scoring engine used for
+/// tag suggestions, without a reference corpus
 pub fn extract_keywords_basic(text: &str, max_keywords: usize) -> Vec<String> {
-    // Simple keyword extraction using word frequency
-    let words: Vec<String> = text
-        .split_whitespace()
-        .map(|w| w.to_lowercase().trim_matches(|c: char| !c.is_alphanumeric()).to_string())
-        .filter(|w| w.len() > 4) // Only consider longer words
-        .collect();
-    
-    // Count word frequencies
-    let mut word_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-    for word in words {
-        *word_counts.entry(word).or_insert(0) += 1;
-    }
-    
-    // Sort by frequency and take top keywords
-    let mut sorted_words: Vec<(String, usize)> = word_counts.into_iter().collect();
-    sorted_words.sort_by(|a, b| b.1.cmp(&a.1));
-    
-    sorted_words.iter()
-        .take(max_keywords)
-        .map(|(word, _)| word.clone())
-        .collect()
+    crate::extract_keywords_with_corpus(text, None, max_keywords)
 }
 
 #[cfg(feature = "ai-features")]
@@ -96,23 +102,17 @@ pub fn analyze_entry(title: &str, content: &str) -> anyhow::Result<EntryAnalysis
     // Get sentiment analysis
     let sentiment = analyze_sentiment(&full_text)?;
     
-    // Extract keywords
-    let keywords = extract_keywords(&full_text, 5)?;
-    
-    // For categories and insights, we would use more sophisticated models
-    // Here we're using simple placeholders
-    let categories = vec!["journal".to_string(), "personal".to_string()];
+    // Emotions come from the offline analyzer
+    let offline = analyze_entry_offline(title, content);
     
     Ok(EntryAnalysis {
         sentiment,
         keywords: KeywordAnalysis {
-            keywords,
-            categories,
-        },
-        insights: InsightAnalysis {
-            insights: Some("This entry reflects personal thoughts and experiences.".to_string()),
-            reflections: Some("Consider exploring how these experiences connect to your goals.".to_string()),
+            keywords: extract_keywords(&full_text, 5)?,
+            categories: Vec::new(),
         },
+        insights: offline.insights,
+        emotions: offline.emotions,
     })
 }
 
@@ -120,43 +120,14 @@ pub fn analyze_entry(title: &str, content: &str) -> anyhow::Result<EntryAnalysis
 pub fn analyze_text(title: &str, content: &str) -> EntryAnalysis {
     #[cfg(feature = "ai-features")]
     {
-        analyze_entry(title, content).unwrap_or_else(|_| {
-            // Fallback if AI analysis fails
-            EntryAnalysis {
-                sentiment: SentimentAnalysis {
-                    sentiment: "neutral".to_string(),
-                    score: 0.0,
-                    confidence: None,
-                },
-                keywords: KeywordAnalysis {
-                    keywords: extract_keywords_basic(&format!("{}\n{}", title, content), 5),
-                    categories: vec!["general".to_string(), "journal".to_string()],
-                },
-                insights: InsightAnalysis {
-                    insights: Some("This is a journal entry about various thoughts and experiences.".to_string()),
-                    reflections: Some("What more would you like to explore about this topic?".to_string()),
-                },
-            }
+        analyze_entry(title, content).unwrap_or_else(|e| {
+            tracing::warn!("Sentiment model failed, using the offline analyzer: {}", e);
+            analyze_entry_offline(title, content)
         })
     }
     
     #[cfg(not(feature = "ai-features"))]
     {
-        // Use the simplified analysis when ai-features are not enabled
-        EntryAnalysis {
-            sentiment: SentimentAnalysis {
-                sentiment: "neutral".to_string(),
-                score: 0.0,
-                confidence: None,
-            },
-            keywords: KeywordAnalysis {
-                keywords: extract_keywords_basic(&format!("{}\n{}", title, content), 5),
-                categories: vec!["general".to_string(), "journal".to_string()],
-            },
-            insights: InsightAnalysis {
-                insights: Some("This is a journal entry about various thoughts and experiences.".to_string()),
-                reflections: Some("What more would you like to explore about this topic?".to_string()),
-            },
-        }
+        analyze_entry_offline(title, content)
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,528 @@
+// Per-tenant AI usage ledger, cost attribution and monthly quotas
+//
+// Every completion a service gets from a language model is added to the AI
+// usage table (AI_USAGE_TABLE), partitioned by tenant_id with these sort keys:
+//
+//   DAY#<yyyy-mm-dd>#<user_id>#<provider>#<model>   one user's usage of a model on a day
+//   MONTH#<yyyy-mm>                                  the tenant's totals for the month
+//   QUOTA                                            the tenant's own quota, if it has one
+//
+// Each holds calls, input_tokens, output_tokens and cost_micros, added to
+// atomically. Cost is kept in millionths of a US dollar, so the counters stay
+// integers. It comes from list prices per million tokens: the built-in table
+// covers the hosted OpenAI and Anthropic models, self-hosted and mock models
+// cost nothing, and AI_MODEL_PRICES overrides or extends it with
+// {"model": [input, output]} in USD per million tokens.
+//
+// Quotas are monthly per tenant (UTC months). AI_MONTHLY_TOKEN_QUOTA and
+// AI_MONTHLY_COST_QUOTA_USD set the default; a QUOTA item with monthly_tokens,
+// monthly_cost_usd or action overrides it for one tenant. AI_QUOTA_ACTION
+// says what happens once a quota is used up: downgrade (default) to the
+// offline analyzer or stored content, or block the AI feature outright.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::{JournalError, LlmResponse, TokenUsage};
+
+pub const USAGE_DAY_PREFIX: &str = "DAY#";
+pub const USAGE_MONTH_PREFIX: &str = "MONTH#";
+pub const USAGE_QUOTA_KEY: &str = "QUOTA";
+const MICROS_PER_USD: f64 = 1_000_000.0;
+
+// List prices in USD per million input and output tokens. Model names match
+// by prefix, longest first, so dated snapshots share their family's price.
+const MODEL_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-3-opus", 15.00, 75.00),
+];
+// Providers whose models are billed at the list prices above
+const HOSTED_PROVIDERS: &[&str] = &["openai", "anthropic"];
+
+static PRICE_OVERRIDES: OnceLock<HashMap<String, (f64, f64)>> = OnceLock::new();
+
+fn usage_table() -> String {
+    std::env::var("AI_USAGE_TABLE").unwrap_or_else(|_| "reflekt-ai-usage".to_string())
+}
+
+fn price_overrides() -> &'static HashMap<String, (f64, f64)> {
+    PRICE_OVERRIDES.get_or_init(|| {
+        let Ok(value) = std::env::var("AI_MODEL_PRICES") else {
+            return HashMap::new();
+        };
+        match serde_json::from_str::<HashMap<String, (f64, f64)>>(&value) {
+            Ok(prices) => prices,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid AI_MODEL_PRICES: {}", e);
+                HashMap::new()
+            }
+        }
+    })
+}
+
+/// Price of a model in USD per million input and output tokens
+pub fn model_price(provider: &str, model: &str) -> Option<(f64, f64)> {
+    price_with_overrides(price_overrides(), provider, model)
+}
+
+// An override for the exact model name wins over the list prices
+fn price_with_overrides(overrides: &HashMap<String, (f64, f64)>, provider: &str, model: &str) -> Option<(f64, f64)> {
+    if let Some(price) = overrides.get(model) {
+        return Some(*price);
+    }
+    if !HOSTED_PROVIDERS.contains(&provider) {
+        return None;
+    }
+    MODEL_PRICES
+        .iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())
+        .map(|(_, input, output)| (*input, *output))
+}
+
+/// Cost of a completion in millionths of a US dollar
+pub fn usage_cost_micros(provider: &str, model: &str, usage: &TokenUsage) -> u64 {
+    match model_price(provider, model) {
+        // USD per million tokens is exactly micro-dollars per token
+        Some((input, output)) => {
+            (usage.input_tokens as f64 * input + usage.output_tokens as f64 * output).round() as u64
+        }
+        None => 0,
+    }
+}
+
+/// Counters kept on every ledger item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_micros: u64,
+}
+
+impl UsageTotals {
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Self {
+        let get_n = |key: &str| item.get(key).and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0);
+        UsageTotals {
+            calls: get_n("calls"),
+            input_tokens: get_n("input_tokens"),
+            output_tokens: get_n("output_tokens"),
+            cost_micros: get_n("cost_micros"),
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    pub fn cost_usd(&self) -> f64 {
+        self.cost_micros as f64 / MICROS_PER_USD
+    }
+
+    pub fn add(&mut self, other: &UsageTotals) {
+        self.calls += other.calls;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_micros += other.cost_micros;
+    }
+}
+
+/// Calendar month of a day or timestamp, as "yyyy-mm"
+pub fn usage_month(now: chrono::DateTime<chrono::Utc>) -> String {
+    now.format("%Y-%m").to_string()
+}
+
+// Add counters to a ledger item, setting its labels on the way
+async fn add_to_ledger(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    usage_key: &str,
+    totals: &UsageTotals,
+    labels: &[(&str, &str)],
+) -> Result<(), JournalError> {
+    let mut update = client
+        .update_item()
+        .table_name(usage_table())
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .key("usage_key", AttributeValue::S(usage_key.to_string()))
+        .expression_attribute_values(":calls", AttributeValue::N(totals.calls.to_string()))
+        .expression_attribute_values(":input", AttributeValue::N(totals.input_tokens.to_string()))
+        .expression_attribute_values(":output", AttributeValue::N(totals.output_tokens.to_string()))
+        .expression_attribute_values(":cost", AttributeValue::N(totals.cost_micros.to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(chrono::Utc::now().to_rfc3339()));
+
+    let mut set = vec!["updated_at = :now".to_string()];
+    for (i, (name, value)) in labels.iter().enumerate() {
+        set.push(format!("#label{} = :label{}", i, i));
+        update = update
+            .expression_attribute_names(format!("#label{}", i), *name)
+            .expression_attribute_values(format!(":label{}", i), AttributeValue::S(value.to_string()));
+    }
+
+    update
+        .update_expression(format!(
+            "ADD calls :calls, input_tokens :input, output_tokens :output, cost_micros :cost SET {}",
+            set.join(", ")
+        ))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to record AI usage: {}", e)))?;
+    Ok(())
+}
+
+/// Add a completion to the tenant's ledger: the user's daily item for the
+/// model and the tenant's monthly totals
+pub async fn record_ai_usage(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    feature: &str,
+    response: &LlmResponse,
+) -> Result<(), JournalError> {
+    let now = chrono::Utc::now();
+    let day = now.format("%Y-%m-%d").to_string();
+    let month = usage_month(now);
+    let totals = UsageTotals {
+        calls: 1,
+        input_tokens: response.usage.input_tokens,
+        output_tokens: response.usage.output_tokens,
+        cost_micros: usage_cost_micros(&response.provider, &response.model, &response.usage),
+    };
+
+    let day_key = format!("{}{}#{}#{}#{}", USAGE_DAY_PREFIX, day, user_id, response.provider, response.model);
+    let labels = [
+        ("usage_day", day.as_str()),
+        ("user_id", user_id),
+        ("provider", response.provider.as_str()),
+        ("model", response.model.as_str()),
+        ("last_feature", feature),
+    ];
+    add_to_ledger(client, tenant_id, &day_key, &totals, &labels).await?;
+
+    let month_key = format!("{}{}", USAGE_MONTH_PREFIX, month);
+    add_to_ledger(client, tenant_id, &month_key, &totals, &[("usage_month", month.as_str())]).await
+}
+
+/// One user's usage of one model on one day
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub day: String,
+    pub user_id: String,
+    pub provider: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// The tenant's daily ledger items for a month ("yyyy-mm")
+pub async fn get_usage_records(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    month: &str,
+) -> Result<Vec<UsageRecord>, JournalError> {
+    let mut records = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(usage_table())
+            .key_condition_expression("tenant_id = :tenant_id AND begins_with(usage_key, :prefix)")
+            .expression_attribute_values(":tenant_id", AttributeValue::S(tenant_id.to_string()))
+            .expression_attribute_values(":prefix", AttributeValue::S(format!("{}{}-", USAGE_DAY_PREFIX, month)))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query AI usage: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+            records.push(UsageRecord {
+                day: get_s("usage_day"),
+                user_id: get_s("user_id"),
+                provider: get_s("provider"),
+                model: get_s("model"),
+                totals: UsageTotals::from_item(item),
+            });
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(records)
+}
+
+/// What happens to AI requests once a tenant's quota is used up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaAction {
+    /// Fall back to the offline analyzer or stored content
+    Downgrade,
+    /// Refuse the AI feature
+    Block,
+}
+
+impl QuotaAction {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "downgrade" => Some(QuotaAction::Downgrade),
+            "block" => Some(QuotaAction::Block),
+            _ => None,
+        }
+    }
+}
+
+/// A tenant's monthly allowance; no limit where a field is None
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AiQuota {
+    pub monthly_tokens: Option<u64>,
+    pub monthly_cost_micros: Option<u64>,
+    pub action: QuotaAction,
+}
+
+fn env_limit<T: std::str::FromStr>(key: &str) -> Option<T> {
+    let value = std::env::var(key).ok()?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        tracing::warn!("Ignoring invalid {} '{}'", key, value);
+    }
+    parsed
+}
+
+fn usd_to_micros(usd: f64) -> Option<u64> {
+    (usd.is_finite() && usd >= 0.0).then(|| (usd * MICROS_PER_USD).round() as u64)
+}
+
+impl AiQuota {
+    /// The default quota from AI_MONTHLY_TOKEN_QUOTA, AI_MONTHLY_COST_QUOTA_USD
+    /// and AI_QUOTA_ACTION
+    pub fn from_env() -> Self {
+        let action = match std::env::var("AI_QUOTA_ACTION") {
+            Ok(value) => QuotaAction::parse(&value).unwrap_or_else(|| {
+                tracing::warn!("Ignoring invalid AI_QUOTA_ACTION '{}'", value);
+                QuotaAction::Downgrade
+            }),
+            Err(_) => QuotaAction::Downgrade,
+        };
+        AiQuota {
+            monthly_tokens: env_limit("AI_MONTHLY_TOKEN_QUOTA"),
+            monthly_cost_micros: env_limit::<f64>("AI_MONTHLY_COST_QUOTA_USD").and_then(usd_to_micros),
+            action,
+        }
+    }
+
+    // A tenant's QUOTA item replaces the defaults it sets
+    fn with_overrides(self, item: &HashMap<String, AttributeValue>) -> Self {
+        let get_n = |key: &str| item.get(key).and_then(|v| v.as_n().ok());
+        AiQuota {
+            monthly_tokens: get_n("monthly_tokens").and_then(|n| n.parse().ok()).or(self.monthly_tokens),
+            monthly_cost_micros: get_n("monthly_cost_usd")
+                .and_then(|n| n.parse().ok())
+                .and_then(usd_to_micros)
+                .or(self.monthly_cost_micros),
+            action: item
+                .get("action")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|action| QuotaAction::parse(action))
+                .unwrap_or(self.action),
+        }
+    }
+
+    pub fn is_exceeded(&self, usage: &UsageTotals) -> bool {
+        self.monthly_tokens.is_some_and(|limit| usage.total_tokens() >= limit)
+            || self.monthly_cost_micros.is_some_and(|limit| usage.cost_micros >= limit)
+    }
+}
+
+/// A tenant's usage this month against its quota
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaStatus {
+    pub month: String,
+    pub usage: UsageTotals,
+    pub quota: AiQuota,
+}
+
+impl QuotaStatus {
+    pub fn exceeded(&self) -> bool {
+        self.quota.is_exceeded(&self.usage)
+    }
+}
+
+/// The tenant's monthly totals and quota for a month ("yyyy-mm")
+pub async fn get_quota_status(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    month: &str,
+) -> Result<QuotaStatus, JournalError> {
+    let get_item = |usage_key: String| {
+        client
+            .get_item()
+            .table_name(usage_table())
+            .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+            .key("usage_key", AttributeValue::S(usage_key))
+            .send()
+    };
+    let (totals, quota) = tokio::join!(
+        get_item(format!("{}{}", USAGE_MONTH_PREFIX, month)),
+        get_item(USAGE_QUOTA_KEY.to_string())
+    );
+    let totals = totals.map_err(|e| JournalError::DatabaseError(format!("Failed to load AI usage: {}", e)))?;
+    let quota = quota.map_err(|e| JournalError::DatabaseError(format!("Failed to load AI quota: {}", e)))?;
+
+    let defaults = AiQuota::from_env();
+    Ok(QuotaStatus {
+        month: month.to_string(),
+        usage: totals.item().map(UsageTotals::from_item).unwrap_or_default(),
+        quota: quota.item().map(|item| defaults.with_overrides(item)).unwrap_or(defaults),
+    })
+}
+
+/// The action to take if the tenant has used up this month's quota. A quota
+/// that can't be checked doesn't hold requests up.
+pub async fn ai_quota_exceeded(client: &DynamoDbClient, tenant_id: &str) -> Option<QuotaAction> {
+    match get_quota_status(client, tenant_id, &usage_month(chrono::Utc::now())).await {
+        Ok(status) if status.exceeded() => {
+            tracing::info!(
+                "Tenant {} has used its AI quota for {} ({} tokens, ${:.2})",
+                tenant_id,
+                status.month,
+                status.usage.total_tokens(),
+                status.usage.cost_usd()
+            );
+            Some(status.quota.action)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!("Failed to check AI quota for tenant {}: {}", tenant_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> TokenUsage {
+        TokenUsage { input_tokens, output_tokens }
+    }
+
+    fn number(n: &str) -> AttributeValue {
+        AttributeValue::N(n.to_string())
+    }
+
+    #[test]
+    fn model_price_matches_the_longest_prefix() {
+        assert_eq!(model_price("openai", "gpt-4o-mini-2024-07-18"), Some((0.15, 0.60)));
+        assert_eq!(model_price("openai", "gpt-4o-2024-08-06"), Some((2.50, 10.00)));
+        assert_eq!(model_price("openai", "gpt-4.1-mini"), Some((0.40, 1.60)));
+        assert_eq!(model_price("anthropic", "claude-3-5-sonnet-20241022"), Some((3.00, 15.00)));
+        assert_eq!(model_price("openai", "o1-preview"), None);
+    }
+
+    #[test]
+    fn model_price_is_free_for_self_hosted_and_mock_models() {
+        assert_eq!(model_price("ollama", "gpt-4o"), None);
+        assert_eq!(model_price("mock", "mock"), None);
+        assert_eq!(usage_cost_micros("ollama", "llama3", &usage(1000, 1000)), 0);
+    }
+
+    #[test]
+    fn price_overrides_replace_or_extend_the_list_prices() {
+        let overrides = HashMap::from([
+            ("llama3".to_string(), (0.10, 0.20)),
+            ("gpt-4o".to_string(), (1.00, 2.00)),
+        ]);
+        assert_eq!(price_with_overrides(&overrides, "ollama", "llama3"), Some((0.10, 0.20)));
+        assert_eq!(price_with_overrides(&overrides, "openai", "gpt-4o"), Some((1.00, 2.00)));
+        // Overrides name exact models, so snapshots keep the list price
+        assert_eq!(price_with_overrides(&overrides, "openai", "gpt-4o-2024-08-06"), Some((2.50, 10.00)));
+    }
+
+    #[test]
+    fn usage_cost_is_in_micro_dollars_per_token() {
+        assert_eq!(usage_cost_micros("openai", "gpt-4o", &usage(1000, 500)), 7500);
+        assert_eq!(usage_cost_micros("openai", "gpt-4o-mini", &usage(3, 1)), 1);
+        assert_eq!(usage_cost_micros("anthropic", "claude-3-opus", &usage(0, 0)), 0);
+    }
+
+    #[test]
+    fn usage_totals_read_and_add_ledger_items() {
+        let item = HashMap::from([
+            ("calls".to_string(), number("2")),
+            ("input_tokens".to_string(), number("300")),
+            ("output_tokens".to_string(), number("120")),
+            ("cost_micros".to_string(), number("1500000")),
+        ]);
+        let mut totals = UsageTotals::from_item(&item);
+        assert_eq!(totals.total_tokens(), 420);
+        assert_eq!(totals.cost_usd(), 1.5);
+
+        totals.add(&UsageTotals { calls: 1, input_tokens: 10, output_tokens: 5, cost_micros: 20 });
+        assert_eq!(totals, UsageTotals { calls: 3, input_tokens: 310, output_tokens: 125, cost_micros: 1500020 });
+        assert_eq!(UsageTotals::from_item(&HashMap::new()), UsageTotals::default());
+    }
+
+    #[test]
+    fn quota_item_overrides_the_defaults_it_sets() {
+        let defaults = AiQuota { monthly_tokens: Some(1000), monthly_cost_micros: None, action: QuotaAction::Downgrade };
+
+        let item = HashMap::from([
+            ("monthly_cost_usd".to_string(), number("2.5")),
+            ("action".to_string(), AttributeValue::S("Block".to_string())),
+        ]);
+        let quota = defaults.with_overrides(&item);
+        assert_eq!(quota.monthly_tokens, Some(1000));
+        assert_eq!(quota.monthly_cost_micros, Some(2_500_000));
+        assert_eq!(quota.action, QuotaAction::Block);
+
+        let item = HashMap::from([
+            ("monthly_tokens".to_string(), number("50000")),
+            ("monthly_cost_usd".to_string(), number("-1")),
+            ("action".to_string(), AttributeValue::S("ignore".to_string())),
+        ]);
+        let quota = defaults.with_overrides(&item);
+        assert_eq!(quota.monthly_tokens, Some(50000));
+        assert_eq!(quota.monthly_cost_micros, None);
+        assert_eq!(quota.action, QuotaAction::Downgrade);
+    }
+
+    #[test]
+    fn quota_is_exceeded_once_either_limit_is_reached() {
+        let quota = AiQuota { monthly_tokens: Some(100), monthly_cost_micros: Some(5000), action: QuotaAction::Block };
+        let totals = |tokens: u64, cost_micros: u64| UsageTotals {
+            calls: 1,
+            input_tokens: tokens / 2,
+            output_tokens: tokens - tokens / 2,
+            cost_micros,
+        };
+        assert!(!quota.is_exceeded(&totals(99, 4999)));
+        assert!(quota.is_exceeded(&totals(100, 0)));
+        assert!(quota.is_exceeded(&totals(0, 5000)));
+
+        let unlimited = AiQuota { monthly_tokens: None, monthly_cost_micros: None, action: QuotaAction::Block };
+        assert!(!unlimited.is_exceeded(&totals(u64::MAX / 2, u64::MAX)));
+    }
+
+    #[test]
+    fn usage_month_is_the_utc_month() {
+        let at = chrono::DateTime::parse_from_rfc3339("2024-03-31T23:30:00-05:00").unwrap();
+        assert_eq!(usage_month(at.with_timezone(&chrono::Utc)), "2024-04");
+    }
+}
@@ -0,0 +1,185 @@
+// Schema for entry analyses returned by language models
+//
+// The model is asked for a JSON object with these fields. Replies are
+// deserialized into `AnalysisResult` and then validated: the sentiment must be
+// one of the allowed labels and agree with the score, the score is clamped to
+// [-1, 1], and lists and texts are trimmed to bounded sizes. A reply that
+// fails is sent back to the model for one repair (see `complete_structured`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::Valence;
+
+pub const MAX_ANALYSIS_KEYWORDS: usize = 5;
+pub const MAX_ANALYSIS_CATEGORIES: usize = 3;
+// Longest keyword or category kept, in characters
+const MAX_LABEL_LENGTH: usize = 50;
+// Longest insight or reflection kept, in characters
+const MAX_TEXT_LENGTH: usize = 1000;
+// How far the score may point the other way from the label before they disagree
+const SCORE_TOLERANCE: f32 = 0.05;
+
+/// Instructions describing the JSON object `AnalysisResult` reads
+pub const ANALYSIS_SYSTEM_PROMPT: &str = "You are an AI journal assistant that analyzes journal entries. \
+    Analyze the entry and respond with a JSON object with these fields: \
+    \"sentiment\": one of \"positive\", \"negative\" or \"neutral\"; \
+    \"sentiment_score\": a number between -1.0 (very negative) and 1.0 (very positive); \
+    \"keywords\": an array of up to 5 keywords from the entry; \
+    \"suggested_categories\": an array of up to 3 categories for the entry; \
+    \"insights\": a brief insight about the entry; \
+    \"reflections\": a reflective question to help the writer think deeper.";
+
+/// An entry analysis as returned by a model
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AnalysisResult {
+    pub sentiment: String,
+    pub sentiment_score: f32,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub suggested_categories: Vec<String>,
+    #[serde(default)]
+    pub insights: Option<String>,
+    #[serde(default)]
+    pub reflections: Option<String>,
+}
+
+impl AnalysisResult {
+    /// Check and normalize a deserialized reply; the error says what to fix
+    pub fn validate(mut self) -> Result<Self, String> {
+        let valence = match self.sentiment.trim().to_lowercase().as_str() {
+            "positive" => Valence::Positive,
+            "negative" => Valence::Negative,
+            "neutral" => Valence::Neutral,
+            other => {
+                return Err(format!(
+                    "sentiment must be \"positive\", \"negative\" or \"neutral\", not \"{}\"",
+                    other
+                ))
+            }
+        };
+        self.sentiment = valence.as_str().to_string();
+
+        if !self.sentiment_score.is_finite() {
+            return Err("sentiment_score must be a number between -1.0 and 1.0".to_string());
+        }
+        self.sentiment_score = self.sentiment_score.clamp(-1.0, 1.0);
+
+        let disagrees = match valence {
+            Valence::Positive => self.sentiment_score < -SCORE_TOLERANCE,
+            Valence::Negative => self.sentiment_score > SCORE_TOLERANCE,
+            Valence::Neutral => false,
+        };
+        if disagrees {
+            return Err(format!(
+                "sentiment \"{}\" contradicts sentiment_score {}",
+                self.sentiment, self.sentiment_score
+            ));
+        }
+
+        self.keywords = bounded_labels(self.keywords, MAX_ANALYSIS_KEYWORDS);
+        self.suggested_categories = bounded_labels(self.suggested_categories, MAX_ANALYSIS_CATEGORIES);
+        self.insights = bounded_text(self.insights);
+        self.reflections = bounded_text(self.reflections);
+        Ok(self)
+    }
+}
+
+// Trimmed, non-empty, case-insensitively distinct labels, at most `max` of them
+fn bounded_labels(labels: Vec<String>, max: usize) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    labels
+        .into_iter()
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty() && label.chars().count() <= MAX_LABEL_LENGTH)
+        .filter(|label| seen.insert(label.to_lowercase()))
+        .take(max)
+        .collect()
+}
+
+fn bounded_text(text: Option<String>) -> Option<String> {
+    text.map(|t| t.trim().chars().take(MAX_TEXT_LENGTH).collect::<String>())
+        .filter(|t| !t.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{complete_structured, LlmRequest, MockProvider, MockReply};
+
+    fn analysis(sentiment: &str, score: f32) -> AnalysisResult {
+        AnalysisResult {
+            sentiment: sentiment.to_string(),
+            sentiment_score: score,
+            keywords: Vec::new(),
+            suggested_categories: Vec::new(),
+            insights: None,
+            reflections: None,
+        }
+    }
+
+    #[test]
+    fn validate_normalizes_the_sentiment_and_clamps_the_score() {
+        let result = analysis(" Positive ", 3.0).validate().unwrap();
+        assert_eq!(result.sentiment, "positive");
+        assert_eq!(result.sentiment_score, 1.0);
+
+        let result = analysis("NEGATIVE", -1.5).validate().unwrap();
+        assert_eq!((result.sentiment.as_str(), result.sentiment_score), ("negative", -1.0));
+    }
+
+    #[test]
+    fn validate_rejects_unknown_labels_and_missing_scores() {
+        let error = analysis("ecstatic", 0.9).validate().unwrap_err();
+        assert!(error.contains("not \"ecstatic\""), "{}", error);
+        assert!(analysis("neutral", f32::NAN).validate().is_err());
+        assert!(analysis("neutral", f32::INFINITY).validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_a_score_that_contradicts_the_label() {
+        let error = analysis("positive", -0.6).validate().unwrap_err();
+        assert!(error.contains("contradicts sentiment_score -0.6"), "{}", error);
+        assert!(analysis("negative", 0.4).validate().is_err());
+        // Within the tolerance, and any score for neutral
+        assert!(analysis("positive", -0.05).validate().is_ok());
+        assert!(analysis("neutral", -0.9).validate().is_ok());
+    }
+
+    #[test]
+    fn validate_bounds_labels_and_texts() {
+        let mut result = analysis("neutral", 0.0);
+        result.keywords = ["work", " Work ", "", "rest", "sleep", &"x".repeat(51), "food", "walk", "rain"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        result.suggested_categories = vec!["a".into(), "b".into(), "c".into(), "d".into()];
+        result.insights = Some(format!("  {}  ", "i".repeat(1200)));
+        result.reflections = Some("   ".to_string());
+
+        let result = result.validate().unwrap();
+        assert_eq!(result.keywords, vec!["work", "rest", "sleep", "food", "walk"]);
+        assert_eq!(result.suggested_categories, vec!["a", "b", "c"]);
+        assert_eq!(result.insights.map(|i| i.chars().count()), Some(MAX_TEXT_LENGTH));
+        assert_eq!(result.reflections, None);
+    }
+
+    #[tokio::test]
+    async fn a_contradictory_reply_is_repaired() {
+        let provider = MockProvider::new(vec![
+            MockReply::Text(r#"{"sentiment": "positive", "sentiment_score": -0.8}"#.to_string()),
+            MockReply::Text("```json\n{\"sentiment\": \"negative\", \"sentiment_score\": -0.8, \"keywords\": [\"rain\"]}\n```".to_string()),
+        ]);
+        let request = LlmRequest {
+            system: ANALYSIS_SYSTEM_PROMPT.to_string(),
+            prompt: "It rained all day.".to_string(),
+            temperature: 0.0,
+            max_tokens: 500,
+            json: true,
+        };
+        let (result, _) = complete_structured(&provider, &request, AnalysisResult::validate).await.unwrap();
+        assert_eq!(result.sentiment, "negative");
+        assert_eq!(result.keywords, vec!["rain"]);
+        assert!(provider.requests()[1].prompt.contains("contradicts sentiment_score"));
+    }
+}
@@ -0,0 +1,99 @@
+// Pinned, favorite and archived entry states
+//
+// Each state is a BOOL attribute on the entry item, written when it is first
+// set; a missing attribute means false. Pinned entries are listed before all
+// others, and archived entries are left out of lists and search unless they
+// are asked for.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::JournalError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryFlag {
+    Pinned,
+    Favorite,
+    Archived,
+}
+
+impl EntryFlag {
+    pub const ALL: [EntryFlag; 3] = [EntryFlag::Pinned, EntryFlag::Favorite, EntryFlag::Archived];
+
+    /// Attribute holding the flag on the entry item
+    pub fn attribute(self) -> &'static str {
+        match self {
+            EntryFlag::Pinned => "pinned",
+            EntryFlag::Favorite => "favorite",
+            EntryFlag::Archived => "archived",
+        }
+    }
+
+    /// Flag toggled by `/entries/{id}/<segment>`
+    pub fn from_path_segment(segment: &str) -> Option<Self> {
+        match segment {
+            "pin" => Some(EntryFlag::Pinned),
+            "favorite" => Some(EntryFlag::Favorite),
+            "archive" => Some(EntryFlag::Archived),
+            _ => None,
+        }
+    }
+}
+
+pub fn entry_flag(item: &HashMap<String, AttributeValue>, flag: EntryFlag) -> bool {
+    item.get(flag.attribute()).and_then(|v| v.as_bool().ok()).copied().unwrap_or(false)
+}
+
+/// Filter expression selecting entries with the flag set or unset, binding
+/// `:<attribute>`. Entries that never had the flag count as unset.
+pub fn flag_filter_expression(
+    flag: EntryFlag,
+    value: bool,
+    expression_values: &mut HashMap<String, AttributeValue>,
+) -> String {
+    let attribute = flag.attribute();
+    expression_values.insert(format!(":{}", attribute), AttributeValue::Bool(value));
+    if value {
+        format!("{0} = :{0}", attribute)
+    } else {
+        format!("(attribute_not_exists({0}) OR {0} = :{0})", attribute)
+    }
+}
+
+/// Which entries a listing shows with regard to archiving
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArchivedFilter {
+    /// Unarchived entries only, the default
+    #[default]
+    Exclude,
+    /// Archived entries only
+    Only,
+    /// Archived or not
+    Include,
+}
+
+impl ArchivedFilter {
+    /// Parse the `archived` query parameter: `false`, `true` or `all`
+    pub fn parse(value: Option<&str>) -> Result<Self, JournalError> {
+        match value {
+            None | Some("false") => Ok(ArchivedFilter::Exclude),
+            Some("true") => Ok(ArchivedFilter::Only),
+            Some("all") => Ok(ArchivedFilter::Include),
+            Some(other) => Err(JournalError::ValidationError(format!(
+                "Invalid archived '{}'. Supported values: false, true, all",
+                other
+            ))),
+        }
+    }
+
+    /// Filter expression for the listing, if it restricts anything
+    pub fn filter_expression(self, expression_values: &mut HashMap<String, AttributeValue>) -> Option<String> {
+        match self {
+            ArchivedFilter::Exclude => Some(flag_filter_expression(EntryFlag::Archived, false, expression_values)),
+            ArchivedFilter::Only => Some(flag_filter_expression(EntryFlag::Archived, true, expression_values)),
+            ArchivedFilter::Include => None,
+        }
+    }
+}
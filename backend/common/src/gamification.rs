@@ -18,6 +18,7 @@ pub struct GamificationStats {
     pub total_words: i64,
     pub insights_requested: i32,
     pub prompts_used: i32,
+    pub goals_completed: i32,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -52,17 +53,37 @@ pub struct PointTransaction {
     pub metadata: Option<serde_json::Value>,
 }
 
-/// Entry event from EventBridge
+/// Entry event from EventBridge, as published by the entry service. The
+/// camelCase field names of older publishers are still accepted.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
 pub struct EntryEvent {
+    #[serde(alias = "entryId")]
     pub entry_id: String,
+    #[serde(alias = "userId")]
     pub user_id: String,
+    #[serde(alias = "tenantId")]
     pub tenant_id: String,
+    #[serde(default, alias = "wordCount")]
     pub word_count: i64,
+    /// Change in the entry's word count, sent with EntryUpdated and EntryDeleted
+    #[serde(default, alias = "wordCountDelta")]
+    pub word_count_delta: i64,
+    #[serde(default, alias = "createdAt")]
     pub created_at: String,
 }
 
+/// Goal completion event published by the entry service, once per goal period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalCompletedEvent {
+    pub goal_id: String,
+    pub user_id: String,
+    pub tenant_id: String,
+    pub name: String,
+    /// daily, weekly or monthly
+    pub period: String,
+    pub period_start: String,
+}
+
 // Points constants
 pub const POINTS_ENTRY_CREATED: i64 = 10;
 pub const POINTS_WORD_BONUS_100: i64 = 5;
@@ -74,6 +95,9 @@ pub const POINTS_STREAK_30_DAYS: i64 = 200;
 pub const POINTS_AI_INSIGHTS: i64 = 5;
 pub const POINTS_PROMPT_USED: i64 = 5;
 pub const POINTS_FIRST_ENTRY_OF_DAY: i64 = 5;
+pub const POINTS_GOAL_DAILY: i64 = 5;
+pub const POINTS_GOAL_WEEKLY: i64 = 20;
+pub const POINTS_GOAL_MONTHLY: i64 = 50;
 
 /// Get level and title from lifetime points
 pub fn get_level_from_points(points: i64) -> (i32, &'static str) {
@@ -91,6 +115,15 @@ pub fn get_level_from_points(points: i64) -> (i32, &'static str) {
     }
 }
 
+/// Points for completing a goal, scaled by the length of its period
+pub fn goal_points(period: &str) -> i64 {
+    match period {
+        "monthly" => POINTS_GOAL_MONTHLY,
+        "weekly" => POINTS_GOAL_WEEKLY,
+        _ => POINTS_GOAL_DAILY,
+    }
+}
+
 /// Calculate word bonus points
 pub fn calculate_word_bonus(word_count: i64) -> i64 {
     if word_count >= 500 {
@@ -309,6 +342,7 @@ impl Default for GamificationStats {
             total_words: 0,
             insights_requested: 0,
             prompts_used: 0,
+            goals_completed: 0,
             created_at: now.clone(),
             updated_at: now,
         }
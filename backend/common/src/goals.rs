@@ -0,0 +1,600 @@
+// Recurring journaling goals
+//
+// A goal is a target for one metric over a recurring period, counted in the
+// user's timezone:
+//
+//   { "metric": "entries", "target": 3, "period": "weekly" }             write 3 times a week
+//   { "metric": "words", "target": 500, "period": "daily" }              500 words per day
+//   { "metric": "tagged_entries", "tag": "gratitude", "target": 1, "period": "daily" }
+//
+// Goals and their progress history share one partition per user:
+//
+//   goals_pk = USER#<tenant_id>#<user_id>          (same value as timeline_pk)
+//   goal_sk  = GOAL#<goal_id>                      the goal itself
+//   goal_sk  = PROGRESS#<goal_id>#<period_start>   value reached in one period
+//
+// Weeks start on Monday. A period's progress is recomputed from its entries
+// whenever one of them is written. `completed_at` records the first time the
+// period met its target, so completion is announced once per period even if
+// later edits drop below the target and back. It is only ever written by a
+// conditional put, so concurrent writes can't both announce the same period.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{normalize_tag, timeline_pk, JournalError, TAG_SEPARATOR};
+
+const GOAL_SK_PREFIX: &str = "GOAL#";
+const PROGRESS_SK_PREFIX: &str = "PROGRESS#";
+
+pub const GOAL_NAME_MAX_LENGTH: usize = 100;
+/// Most active goals a user can have, and most goal completions rewarded
+/// per user in one period
+pub const MAX_ACTIVE_GOALS: usize = 10;
+
+/// What a goal counts within each period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum GoalMetric {
+    /// Entries written
+    Entries,
+    /// Words written across all entries
+    Words,
+    /// Entries carrying the tag or one of its children
+    TaggedEntries { tag: String },
+}
+
+impl GoalMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalMetric::Entries => "entries",
+            GoalMetric::Words => "words",
+            GoalMetric::TaggedEntries { .. } => "tagged_entries",
+        }
+    }
+
+    /// Value reached by a period's entry items
+    pub fn value(&self, items: &[&HashMap<String, AttributeValue>]) -> i64 {
+        match self {
+            GoalMetric::Entries => items.len() as i64,
+            GoalMetric::Words => items
+                .iter()
+                .filter_map(|item| item.get("word_count"))
+                .filter_map(|v| v.as_n().ok())
+                .filter_map(|n| n.parse::<i64>().ok())
+                .sum(),
+            GoalMetric::TaggedEntries { tag } => {
+                let child_prefix = format!("{}{}", tag, TAG_SEPARATOR);
+                items
+                    .iter()
+                    .filter(|item| {
+                        item.get("tags")
+                            .and_then(|v| v.as_ss().ok())
+                            .is_some_and(|tags| tags.iter().any(|t| t == tag || t.starts_with(&child_prefix)))
+                    })
+                    .count() as i64
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalPeriod {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl GoalPeriod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GoalPeriod::Daily => "daily",
+            GoalPeriod::Weekly => "weekly",
+            GoalPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// First and last day of the period containing `day`
+    pub fn bounds(self, day: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            GoalPeriod::Daily => (day, day),
+            GoalPeriod::Weekly => {
+                let start = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+                (start, start + Duration::days(6))
+            }
+            GoalPeriod::Monthly => {
+                let start = day.with_day(1).unwrap_or(day);
+                let end = start.checked_add_months(Months::new(1)).map(|next| next - Duration::days(1)).unwrap_or(day);
+                (start, end)
+            }
+        }
+    }
+
+    /// Start of the period before the one starting on `start`
+    pub fn previous_start(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            GoalPeriod::Daily => start - Duration::days(1),
+            GoalPeriod::Weekly => start - Duration::days(7),
+            GoalPeriod::Monthly => start.checked_sub_months(Months::new(1)).unwrap_or(start),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Goal {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub metric: GoalMetric,
+    pub target: i64,
+    pub period: GoalPeriod,
+    /// Inactive goals keep their history but are no longer evaluated
+    pub active: bool,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl Goal {
+    /// Check the goal and normalize its tag
+    pub fn validate(&mut self) -> Result<(), JournalError> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() || self.name.chars().count() > GOAL_NAME_MAX_LENGTH {
+            return Err(JournalError::ValidationError(format!(
+                "Goal name must be 1 to {} characters",
+                GOAL_NAME_MAX_LENGTH
+            )));
+        }
+        if self.target < 1 {
+            return Err(JournalError::ValidationError("Goal target must be at least 1".into()));
+        }
+        if let GoalMetric::TaggedEntries { tag } = &mut self.metric {
+            *tag = normalize_tag(tag)
+                .ok_or_else(|| JournalError::ValidationError(format!("Invalid goal tag '{}'", tag)))?;
+        }
+        Ok(())
+    }
+}
+
+/// A goal's progress in one period
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalProgress {
+    pub period_start: String,
+    pub period_end: String,
+    pub value: i64,
+    pub target: i64,
+    pub completed: bool,
+    /// When the period first reached its target
+    pub completed_at: Option<String>,
+}
+
+impl GoalProgress {
+    /// Progress of a period nothing was recorded for
+    pub fn empty(goal: &Goal, start: NaiveDate) -> Self {
+        let (start, end) = goal.period.bounds(start);
+        GoalProgress {
+            period_start: start.to_string(),
+            period_end: end.to_string(),
+            value: 0,
+            target: goal.target,
+            completed: false,
+            completed_at: None,
+        }
+    }
+}
+
+pub fn goals_table() -> String {
+    std::env::var("GOALS_TABLE").unwrap_or_else(|_| "reflekt-goals".to_string())
+}
+
+fn goal_sk(goal_id: &str) -> String {
+    format!("{}{}", GOAL_SK_PREFIX, goal_id)
+}
+
+fn progress_sk(goal_id: &str, period_start: &str) -> String {
+    format!("{}{}#{}", PROGRESS_SK_PREFIX, goal_id, period_start)
+}
+
+pub fn goal_to_item(tenant_id: &str, user_id: &str, goal: &Goal) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("goals_pk".to_string(), AttributeValue::S(timeline_pk(tenant_id, user_id)));
+    item.insert("goal_sk".to_string(), AttributeValue::S(goal_sk(&goal.id)));
+    item.insert("id".to_string(), AttributeValue::S(goal.id.clone()));
+    item.insert("name".to_string(), AttributeValue::S(goal.name.clone()));
+    item.insert("target".to_string(), AttributeValue::N(goal.target.to_string()));
+    item.insert("period".to_string(), AttributeValue::S(goal.period.as_str().to_string()));
+    item.insert("active".to_string(), AttributeValue::Bool(goal.active));
+
+    item.insert("metric".to_string(), AttributeValue::S(goal.metric.as_str().to_string()));
+    if let GoalMetric::TaggedEntries { tag } = &goal.metric {
+        item.insert("tag".to_string(), AttributeValue::S(tag.clone()));
+    }
+    if let Some(created_at) = &goal.created_at {
+        item.insert("created_at".to_string(), AttributeValue::S(created_at.clone()));
+    }
+    if let Some(updated_at) = &goal.updated_at {
+        item.insert("updated_at".to_string(), AttributeValue::S(updated_at.clone()));
+    }
+    item
+}
+
+pub fn item_to_goal(item: &HashMap<String, AttributeValue>) -> Option<Goal> {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+
+    let metric = match get_s("metric")?.as_str() {
+        "entries" => GoalMetric::Entries,
+        "words" => GoalMetric::Words,
+        "tagged_entries" => GoalMetric::TaggedEntries { tag: get_s("tag")? },
+        _ => return None,
+    };
+    let period = match get_s("period")?.as_str() {
+        "daily" => GoalPeriod::Daily,
+        "weekly" => GoalPeriod::Weekly,
+        "monthly" => GoalPeriod::Monthly,
+        _ => return None,
+    };
+
+    Some(Goal {
+        id: get_s("id")?,
+        name: get_s("name").unwrap_or_default(),
+        metric,
+        target: item.get("target").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(1),
+        period,
+        active: item.get("active").and_then(|v| v.as_bool().ok()).copied().unwrap_or(true),
+        created_at: get_s("created_at"),
+        updated_at: get_s("updated_at"),
+    })
+}
+
+pub fn progress_to_item(
+    tenant_id: &str,
+    user_id: &str,
+    goal_id: &str,
+    progress: &GoalProgress,
+) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("goals_pk".to_string(), AttributeValue::S(timeline_pk(tenant_id, user_id)));
+    item.insert("goal_sk".to_string(), AttributeValue::S(progress_sk(goal_id, &progress.period_start)));
+    item.insert("period_start".to_string(), AttributeValue::S(progress.period_start.clone()));
+    item.insert("period_end".to_string(), AttributeValue::S(progress.period_end.clone()));
+    item.insert("value".to_string(), AttributeValue::N(progress.value.to_string()));
+    item.insert("target".to_string(), AttributeValue::N(progress.target.to_string()));
+    item.insert("completed".to_string(), AttributeValue::Bool(progress.completed));
+    if let Some(completed_at) = &progress.completed_at {
+        item.insert("completed_at".to_string(), AttributeValue::S(completed_at.clone()));
+    }
+    item
+}
+
+pub fn item_to_progress(item: &HashMap<String, AttributeValue>) -> GoalProgress {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+    let get_n = |key: &str| item.get(key).and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0);
+
+    GoalProgress {
+        period_start: get_s("period_start").unwrap_or_default(),
+        period_end: get_s("period_end").unwrap_or_default(),
+        value: get_n("value"),
+        target: get_n("target"),
+        completed: item.get("completed").and_then(|v| v.as_bool().ok()).copied().unwrap_or(false),
+        completed_at: get_s("completed_at"),
+    }
+}
+
+/// Load one goal, or None if the user has no goal with this id
+pub async fn get_goal(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    goal_id: &str,
+) -> Result<Option<Goal>, JournalError> {
+    let response = client
+        .get_item()
+        .table_name(goals_table())
+        .key("goals_pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .key("goal_sk", AttributeValue::S(goal_sk(goal_id)))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to get goal: {}", e)))?;
+
+    Ok(response.item().and_then(item_to_goal))
+}
+
+/// All of a user's goals, oldest first
+pub async fn list_goals(client: &DynamoDbClient, tenant_id: &str, user_id: &str) -> Result<Vec<Goal>, JournalError> {
+    let mut goals = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(goals_table())
+            .key_condition_expression("goals_pk = :pk AND begins_with(goal_sk, :prefix)")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .expression_attribute_values(":prefix", AttributeValue::S(GOAL_SK_PREFIX.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to list goals: {}", e)))?;
+
+        goals.extend(response.items().iter().filter_map(item_to_goal));
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    goals.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(goals)
+}
+
+/// Recorded progress of a goal for periods starting on or after `from`, oldest first
+pub async fn query_goal_progress(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    goal_id: &str,
+    from: NaiveDate,
+) -> Result<Vec<GoalProgress>, JournalError> {
+    let mut progress = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(goals_table())
+            .key_condition_expression("goals_pk = :pk AND goal_sk BETWEEN :lower AND :upper")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .expression_attribute_values(":lower", AttributeValue::S(progress_sk(goal_id, &from.to_string())))
+            .expression_attribute_values(":upper", AttributeValue::S(progress_sk(goal_id, "~")))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query goal progress: {}", e)))?;
+
+        progress.extend(response.items().iter().map(item_to_progress));
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(progress)
+}
+
+/// Progress recorded for one period, if any
+pub async fn get_period_progress(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    goal_id: &str,
+    period_start: NaiveDate,
+) -> Result<Option<GoalProgress>, JournalError> {
+    let response = client
+        .get_item()
+        .table_name(goals_table())
+        .key("goals_pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .key("goal_sk", AttributeValue::S(progress_sk(goal_id, &period_start.to_string())))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to get goal progress: {}", e)))?;
+
+    Ok(response.item().map(item_to_progress))
+}
+
+/// Delete a goal together with its progress history
+pub async fn delete_goal_items(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    goal_id: &str,
+) -> Result<(), JournalError> {
+    let pk = timeline_pk(tenant_id, user_id);
+    let mut sks = vec![goal_sk(goal_id)];
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(goals_table())
+            .key_condition_expression("goals_pk = :pk AND begins_with(goal_sk, :prefix)")
+            .expression_attribute_values(":pk", AttributeValue::S(pk.clone()))
+            .expression_attribute_values(":prefix", AttributeValue::S(progress_sk(goal_id, "")))
+            .projection_expression("goal_sk")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query goal progress: {}", e)))?;
+
+        sks.extend(response.items().iter().filter_map(|item| item.get("goal_sk")?.as_s().ok().cloned()));
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    for sk in sks {
+        client
+            .delete_item()
+            .table_name(goals_table())
+            .key("goals_pk", AttributeValue::S(pk.clone()))
+            .key("goal_sk", AttributeValue::S(sk))
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to delete goal: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Completed periods in a row, counting back from the latest period. The
+/// current period only breaks the streak once it has ended.
+pub fn goal_streak(goal: &Goal, history: &[GoalProgress], today: NaiveDate) -> i64 {
+    let completed: HashMap<&str, bool> = history.iter().map(|p| (p.period_start.as_str(), p.completed)).collect();
+    let is_completed = |start: NaiveDate| completed.get(start.to_string().as_str()).copied().unwrap_or(false);
+
+    let (current, _) = goal.period.bounds(today);
+    let mut start = if is_completed(current) { current } else { goal.period.previous_start(current) };
+    let mut streak = 0;
+    while is_completed(start) {
+        streak += 1;
+        start = goal.period.previous_start(start);
+    }
+    streak
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn goal(metric: GoalMetric, period: GoalPeriod) -> Goal {
+        Goal {
+            id: "goal-1".into(),
+            name: "Goal".into(),
+            metric,
+            target: 1,
+            period,
+            active: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn completed(starts: &[&str]) -> Vec<GoalProgress> {
+        starts
+            .iter()
+            .map(|start| GoalProgress {
+                period_start: start.to_string(),
+                period_end: start.to_string(),
+                value: 1,
+                target: 1,
+                completed: true,
+                completed_at: Some(format!("{}T12:00:00+00:00", start)),
+            })
+            .collect()
+    }
+
+    fn entry(tags: &[&str], words: i64) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert("word_count".to_string(), AttributeValue::N(words.to_string()));
+        if !tags.is_empty() {
+            item.insert("tags".to_string(), AttributeValue::Ss(tags.iter().map(|t| t.to_string()).collect()));
+        }
+        item
+    }
+
+    #[test]
+    fn weeks_run_monday_to_sunday() {
+        // 2024-06-05 is a Wednesday
+        assert_eq!(GoalPeriod::Weekly.bounds(date("2024-06-05")), (date("2024-06-03"), date("2024-06-09")));
+        assert_eq!(GoalPeriod::Weekly.bounds(date("2024-06-03")), (date("2024-06-03"), date("2024-06-09")));
+        assert_eq!(GoalPeriod::Weekly.bounds(date("2024-06-09")), (date("2024-06-03"), date("2024-06-09")));
+        // A week spanning the turn of the year
+        assert_eq!(GoalPeriod::Weekly.bounds(date("2025-01-01")), (date("2024-12-30"), date("2025-01-05")));
+    }
+
+    #[test]
+    fn months_end_on_their_last_day() {
+        assert_eq!(GoalPeriod::Monthly.bounds(date("2024-02-14")), (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(GoalPeriod::Monthly.bounds(date("2023-02-14")), (date("2023-02-01"), date("2023-02-28")));
+        assert_eq!(GoalPeriod::Monthly.bounds(date("2024-04-30")), (date("2024-04-01"), date("2024-04-30")));
+        assert_eq!(GoalPeriod::Monthly.bounds(date("2024-12-31")), (date("2024-12-01"), date("2024-12-31")));
+        assert_eq!(GoalPeriod::Daily.bounds(date("2024-12-31")), (date("2024-12-31"), date("2024-12-31")));
+    }
+
+    #[test]
+    fn previous_start_steps_back_one_period() {
+        assert_eq!(GoalPeriod::Daily.previous_start(date("2024-03-01")), date("2024-02-29"));
+        assert_eq!(GoalPeriod::Weekly.previous_start(date("2024-01-01")), date("2023-12-25"));
+        assert_eq!(GoalPeriod::Monthly.previous_start(date("2024-03-01")), date("2024-02-01"));
+        assert_eq!(GoalPeriod::Monthly.previous_start(date("2024-01-01")), date("2023-12-01"));
+    }
+
+    #[test]
+    fn streak_counts_completed_periods_back_from_today() {
+        let daily = goal(GoalMetric::Entries, GoalPeriod::Daily);
+        let history = completed(&["2024-06-07", "2024-06-08", "2024-06-09", "2024-06-10"]);
+        assert_eq!(goal_streak(&daily, &history, date("2024-06-10")), 4);
+    }
+
+    #[test]
+    fn streak_survives_an_unfinished_current_period() {
+        let daily = goal(GoalMetric::Entries, GoalPeriod::Daily);
+        let history = completed(&["2024-06-08", "2024-06-09"]);
+        assert_eq!(goal_streak(&daily, &history, date("2024-06-10")), 2);
+
+        let weekly = goal(GoalMetric::Entries, GoalPeriod::Weekly);
+        let history = completed(&["2024-05-27", "2024-06-03"]);
+        assert_eq!(goal_streak(&weekly, &history, date("2024-06-12")), 2);
+    }
+
+    #[test]
+    fn streak_breaks_on_a_missed_period_that_has_ended() {
+        let daily = goal(GoalMetric::Entries, GoalPeriod::Daily);
+        let history = completed(&["2024-06-06", "2024-06-07", "2024-06-09"]);
+        assert_eq!(goal_streak(&daily, &history, date("2024-06-10")), 1);
+        assert_eq!(goal_streak(&daily, &history, date("2024-06-11")), 0);
+
+        let mut history = completed(&["2024-06-08", "2024-06-09"]);
+        history[1].completed = false;
+        assert_eq!(goal_streak(&daily, &history, date("2024-06-10")), 0);
+    }
+
+    #[test]
+    fn metric_values() {
+        let items = [entry(&["work"], 120), entry(&[], 30), entry(&["gratitude/family", "work"], 50)];
+        let items: Vec<&HashMap<String, AttributeValue>> = items.iter().collect();
+
+        assert_eq!(GoalMetric::Entries.value(&items), 3);
+        assert_eq!(GoalMetric::Words.value(&items), 200);
+        assert_eq!(GoalMetric::TaggedEntries { tag: "work".into() }.value(&items), 2);
+        assert_eq!(GoalMetric::Entries.value(&[]), 0);
+    }
+
+    #[test]
+    fn tagged_entries_count_child_tags_but_not_lookalikes() {
+        let items = [entry(&["gratitude/family"], 0), entry(&["gratitudes"], 0), entry(&["gratitude"], 0)];
+        let items: Vec<&HashMap<String, AttributeValue>> = items.iter().collect();
+
+        assert_eq!(GoalMetric::TaggedEntries { tag: "gratitude".into() }.value(&items), 2);
+        assert_eq!(GoalMetric::TaggedEntries { tag: "gratitude/family".into() }.value(&items), 1);
+    }
+
+    #[test]
+    fn validate_trims_the_name_and_normalizes_the_tag() {
+        let mut tagged = goal(GoalMetric::TaggedEntries { tag: " Gratitude ".into() }, GoalPeriod::Daily);
+        tagged.name = "  Thankful  ".into();
+        tagged.validate().unwrap();
+        assert_eq!(tagged.name, "Thankful");
+        assert_eq!(tagged.metric, GoalMetric::TaggedEntries { tag: "gratitude".into() });
+
+        let mut untargeted = goal(GoalMetric::Entries, GoalPeriod::Daily);
+        untargeted.target = 0;
+        assert!(untargeted.validate().is_err());
+
+        let mut unnamed = goal(GoalMetric::Entries, GoalPeriod::Daily);
+        unnamed.name = "   ".into();
+        assert!(unnamed.validate().is_err());
+    }
+
+    #[test]
+    fn goal_items_round_trip() {
+        let tagged = goal(GoalMetric::TaggedEntries { tag: "work".into() }, GoalPeriod::Monthly);
+        let restored = item_to_goal(&goal_to_item("tenant", "user", &tagged)).unwrap();
+        assert_eq!(restored.metric, tagged.metric);
+        assert_eq!(restored.period, GoalPeriod::Monthly);
+
+        let progress = &completed(&["2024-06-01"])[0];
+        let restored = item_to_progress(&progress_to_item("tenant", "user", "goal-1", progress));
+        assert_eq!(restored.period_start, "2024-06-01");
+        assert_eq!(restored.completed_at, progress.completed_at);
+    }
+}
@@ -0,0 +1,259 @@
+// Keyword scoring shared by tag suggestions and basic keyword extraction
+//
+// Text is tokenized into lowercase words, stop words are dropped, and every
+// word is reduced to a light stem so "meetings", "meeting" and "meet" score as
+// one term. Terms are ranked by TF-IDF: term frequency in the text weighted by
+// how rare the term is across a reference corpus (normally the user's own past
+// entries). Without a corpus every term has the same IDF and ranking falls back
+// to plain frequency.
+
+use std::collections::{HashMap, HashSet};
+
+/// Words that carry no topical meaning in journal writing
+pub const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "always", "am", "an",
+    "and", "any", "are", "around", "as", "at", "be", "because", "been", "before", "being",
+    "below", "between", "both", "but", "by", "call", "came", "can", "come", "could", "day",
+    "did", "do", "does", "doing", "done", "down", "during", "each", "even", "ever", "every",
+    "feel", "feeling", "felt", "few", "find", "for", "from", "further", "get", "getting", "go",
+    "going", "got", "had", "has", "have", "having", "he", "her", "here", "hers", "herself",
+    "him", "himself", "his", "how", "i", "if", "in", "into", "is", "it", "its", "itself",
+    "just", "know", "like", "long", "lot", "made", "make", "many", "may", "me", "might",
+    "more", "most", "much", "must", "my", "myself", "need", "never", "new", "no", "nor",
+    "not", "now", "of", "off", "old", "on", "once", "one", "only", "or", "other", "our",
+    "ours", "ourselves", "out", "over", "own", "really", "same", "say", "said", "see", "she",
+    "should", "so", "some", "something", "still", "such", "take", "than", "that", "the",
+    "their", "theirs", "them", "themselves", "then", "there", "these", "they", "thing",
+    "things", "think", "this", "those", "thought", "through", "time", "to", "today", "too",
+    "under", "until", "up", "use", "very", "want", "was", "way", "we", "well", "were", "what",
+    "when", "where", "which", "while", "who", "whom", "why", "will", "with", "would", "yet",
+    "you", "your", "yours", "yourself", "yourselves",
+];
+
+// Shortest word considered as a keyword
+const MIN_WORD_LENGTH: usize = 3;
+
+/// Light suffix-stripping stemmer; conservative so stems stay readable.
+/// Plural endings are removed first, then verb endings: "meetings" -> "meet".
+/// As in Porter's stemmer, verb endings only go when a vowel is left before
+/// them ("string" stays), and "eed" is never split ("speed", "indeed").
+pub fn stem(word: &str) -> String {
+    let word = word.to_lowercase();
+    let word = strip_suffix(&word, &[("sses", "ss"), ("ies", "y"), ("s", "")]);
+    strip_suffix(&word, &[("ing", ""), ("ed", "")])
+}
+
+// Apply the first matching suffix rule, keeping at least three characters
+fn strip_suffix(word: &str, rules: &[(&str, &str)]) -> String {
+    for (suffix, replacement) in rules {
+        let Some(base) = word.strip_suffix(suffix) else {
+            continue;
+        };
+        // Keep short words intact ("is", "red") and never split "ss"
+        if base.chars().count() < 3 || (*suffix == "s" && (base.ends_with('s') || base.ends_with('u'))) {
+            return word.to_string();
+        }
+        if matches!(*suffix, "ing" | "ed") && (!has_vowel(base) || (*suffix == "ed" && base.ends_with('e'))) {
+            return word.to_string();
+        }
+
+        let mut stemmed = format!("{}{}", base, replacement);
+
+        // Undo consonant doubling: "running" -> "runn" -> "run"
+        let chars: Vec<char> = stemmed.chars().collect();
+        let n = chars.len();
+        if replacement.is_empty()
+            && *suffix != "s"
+            && n >= 4
+            && chars[n - 1] == chars[n - 2]
+            && !matches!(chars[n - 1], 'l' | 's' | 'z' | 'e' | 'o')
+        {
+            stemmed.pop();
+        }
+        return stemmed;
+    }
+
+    word.to_string()
+}
+
+// A vowel, or a "y" after the first letter ("cry" but not "yes")
+fn has_vowel(word: &str) -> bool {
+    word.chars()
+        .enumerate()
+        .any(|(i, c)| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u') || (c == 'y' && i > 0))
+}
+
+/// Lowercased words of the text, without stop words or very short words
+pub fn tokenize(text: &str) -> Vec<String> {
+    let stop_words: HashSet<&str> = STOP_WORDS.iter().copied().collect();
+
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|w| w.trim_matches('\'').to_lowercase())
+        .filter(|w| w.chars().count() >= MIN_WORD_LENGTH)
+        .filter(|w| !w.chars().all(|c| c.is_numeric()))
+        .filter(|w| !stop_words.contains(w.as_str()))
+        .collect()
+}
+
+/// How many documents of a corpus contain each stemmed term
+#[derive(Debug, Default, Clone)]
+pub struct DocumentFrequencies {
+    pub documents: usize,
+    pub frequencies: HashMap<String, usize>,
+}
+
+impl DocumentFrequencies {
+    pub fn from_documents<I, S>(documents: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut df = DocumentFrequencies::default();
+        for document in documents {
+            df.add_document(document.as_ref());
+        }
+        df
+    }
+
+    pub fn add_document(&mut self, text: &str) {
+        self.documents += 1;
+        let terms: HashSet<String> = tokenize(text).iter().map(|w| stem(w)).collect();
+        for term in terms {
+            *self.frequencies.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    /// Smoothed inverse document frequency; never zero, so unseen terms rank highest
+    pub fn idf(&self, term: &str) -> f64 {
+        let df = self.frequencies.get(term).copied().unwrap_or(0) as f64;
+        ((1.0 + self.documents as f64) / (1.0 + df)).ln() + 1.0
+    }
+}
+
+/// A stemmed term with the word form to show and its TF-IDF score
+#[derive(Debug, Clone)]
+pub struct ScoredTerm {
+    pub term: String,
+    pub display: String,
+    pub score: f64,
+}
+
+/// Rank the terms of `text` by TF-IDF against an optional corpus, best first
+pub fn score_terms(text: &str, corpus: Option<&DocumentFrequencies>) -> Vec<ScoredTerm> {
+    let words = tokenize(text);
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    // Count stems and remember the most common surface form of each
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut forms: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    for word in &words {
+        let term = stem(word);
+        *counts.entry(term.clone()).or_insert(0) += 1;
+        *forms.entry(term).or_default().entry(word.clone()).or_insert(0) += 1;
+    }
+
+    let total = words.len() as f64;
+    let mut scored: Vec<ScoredTerm> = counts
+        .into_iter()
+        .map(|(term, count)| {
+            let tf = count as f64 / total;
+            let idf = corpus.map(|c| c.idf(&term)).unwrap_or(1.0);
+            let display = forms
+                .get(&term)
+                .and_then(|f| f.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0))))
+                .map(|(form, _)| form.clone())
+                .unwrap_or_else(|| term.clone());
+
+            ScoredTerm { term, display, score: tf * idf }
+        })
+        .collect();
+
+    scored.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.display.cmp(&b.display))
+    });
+    scored
+}
+
+/// Top keywords of a text, as the word forms that appear in it
+pub fn extract_keywords_with_corpus(
+    text: &str,
+    corpus: Option<&DocumentFrequencies>,
+    max_keywords: usize,
+) -> Vec<String> {
+    score_terms(text, corpus)
+        .into_iter()
+        .take(max_keywords)
+        .map(|t| t.display)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stem_folds_plurals_and_verb_endings() {
+        for (word, expected) in [
+            ("meetings", "meet"),
+            ("meeting", "meet"),
+            ("Meets", "meet"),
+            ("running", "run"),
+            ("stopped", "stop"),
+            ("walked", "walk"),
+            ("stories", "story"),
+            ("classes", "class"),
+            ("crying", "cry"),
+            ("falling", "fall"),
+        ] {
+            assert_eq!(stem(word), expected, "{}", word);
+        }
+    }
+
+    #[test]
+    fn stem_keeps_words_the_endings_belong_to() {
+        for word in ["speed", "indeed", "bleed", "string", "spring", "shred", "red", "is", "glass", "focus"] {
+            assert_eq!(stem(word), word, "{}", word);
+        }
+    }
+
+    #[test]
+    fn tokenize_drops_stop_words_numbers_and_short_words() {
+        assert_eq!(
+            tokenize("I went to the gym at 7 and it's 2024, ok? 'Great' workout."),
+            vec!["went", "gym", "it's", "great", "workout"]
+        );
+    }
+
+    #[test]
+    fn idf_favours_terms_rare_in_the_corpus() {
+        let corpus = DocumentFrequencies::from_documents(["work meeting", "work lunch", "work and running"]);
+        assert_eq!(corpus.documents, 3);
+        assert_eq!(corpus.frequencies.get("work"), Some(&3));
+        assert_eq!(corpus.frequencies.get("run"), Some(&1));
+        assert!(corpus.idf("lunch") > corpus.idf("work"));
+        assert!(corpus.idf("unseen") > corpus.idf("lunch"));
+        assert!(corpus.idf("work") > 0.0);
+    }
+
+    #[test]
+    fn score_terms_ranks_by_frequency_without_a_corpus() {
+        let terms = score_terms("Coffee, more coffee, coffees and a walk", None);
+        assert_eq!(terms[0].term, "coffee");
+        assert_eq!(terms[0].display, "coffee");
+        assert_eq!(terms[1].term, "walk");
+        assert!(terms[0].score > terms[1].score);
+    }
+
+    #[test]
+    fn score_terms_sinks_words_used_everywhere() {
+        let corpus = DocumentFrequencies::from_documents(["work was busy", "work again", "more work"]);
+        let keywords = extract_keywords_with_corpus("Work, work and then pottery class", Some(&corpus), 2);
+        assert_eq!(keywords, vec!["class", "pottery"]);
+        assert!(extract_keywords_with_corpus("the and of", Some(&corpus), 3).is_empty());
+    }
+}
@@ -19,6 +19,7 @@ pub use jwt;
 pub use hmac;
 pub use sha2;
 pub use chrono;
+pub use chrono_tz;
 pub use lambda_runtime;
 pub use lambda_http;
 pub use base64;
@@ -274,6 +275,98 @@ pub use settings::*;
 pub mod gamification;
 pub use gamification::*;
 
+// Timeline key design for date-ordered entry queries
+pub mod timeline;
+pub use timeline::*;
+
+// Tag normalization and hierarchy
+pub mod tags;
+pub use tags::*;
+
+// TF-IDF keyword scoring shared by tag suggestions and keyword extraction
+pub mod keywords;
+pub use keywords::*;
+
+// Persistent per-user tag statistics
+pub mod tag_stats;
+pub use tag_stats::*;
+
+// Daily per-user rollups for calendar views
+pub mod rollups;
+pub use rollups::*;
+
+// Wiki-style links and backlinks between entries
+pub mod links;
+pub use links::*;
+
+// Notebooks that group a user's entries
+pub mod notebooks;
+pub use notebooks::*;
+
+// Entry templates with typed fields
+pub mod templates;
+pub use templates::*;
+
+// Structured moods with an emotion taxonomy
+pub mod mood;
+pub use mood::*;
+
+// Markdown rendering, sanitization and plain-text extraction
+pub mod markdown;
+pub use markdown::*;
+
+// Unicode-aware word counts, reading time and readability
+pub mod text_metrics;
+pub use text_metrics::*;
+
+// Time-capsule entries sealed until a future date
+pub mod time_capsules;
+pub use time_capsules::*;
+
+// Pinned, favorite and archived entry states
+pub mod entry_flags;
+pub use entry_flags::*;
+
+// Recurring journaling goals and their progress
+pub mod goals;
+pub use goals::*;
+
+// Language model providers shared by the AI features
+pub mod llm;
+pub use llm::*;
+
+// Retries, circuit breaking and failover across AI providers
+pub mod llm_resilience;
+pub use llm_resilience::*;
+
+// Offline lexicon-based sentiment and emotion analysis
+pub mod sentiment;
+pub use sentiment::*;
+
+// Validated schema for entry analyses returned by language models
+pub mod analysis;
+pub use analysis::*;
+
+// Redaction of personal details in text sent to AI providers
+pub mod redaction;
+pub use redaction::*;
+
+// Per-tenant AI usage, cost and monthly quotas
+pub mod ai_usage;
+pub use ai_usage::*;
+
+// Content hashing and re-analysis requests for AI insights
+pub mod reanalysis;
+pub use reanalysis::*;
+
+// Versioned prompt templates with per-tenant overrides
+pub mod prompt_templates;
+pub use prompt_templates::*;
+
+// Offline stand-ins for DynamoDB and AI providers, for the services' tests
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
 // AI module - conditionally compiled
 #[cfg(feature = "ai-features")]
 mod ai;
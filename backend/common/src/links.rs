@@ -0,0 +1,624 @@
+// Wiki-style links between entries
+//
+// Entry content may reference other entries as `[[Entry title]]` or `[[<entry id>]]`,
+// optionally with a label: `[[Entry title|shown text]]`. Every link is stored
+// twice in the links table so both directions are a single key-prefix query:
+//
+//   links_pk = USER#<tenant_id>#<user_id>     (same value as timeline_pk)
+//   link_sk  = OUT#<source_id>#<target_key>   links written in an entry
+//   link_sk  = IN#<target_key>#<source_id>    backlinks to an entry
+//
+// The target key is the linked entry's id. A title that does not match any of
+// the user's entries is kept as a dangling link keyed `?<normalized title>`, so
+// it resolves as soon as an entry with that title is written.
+//
+// Only the entries an entry's links name are looked up when it is written: ids
+// by key, and titles through TitleIndex, a sparse GSI on the entries table
+// keyed by `title_pk = USER#<tenant_id>#<user_id>#TITLE#<hash of normalized title>`
+// and timeline_sk, so the oldest entry with a title comes first. Sealed time
+// capsules get title_pk when they unlock.
+
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, WriteRequest};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{is_sealed, timeline_pk, JournalError, TIMELINE_INDEX};
+
+/// Sparse GSI on (title_pk, timeline_sk) of entries that can be linked to by title
+pub const TITLE_INDEX: &str = "TitleIndex";
+
+const OUT_SK_PREFIX: &str = "OUT#";
+const IN_SK_PREFIX: &str = "IN#";
+const DANGLING_PREFIX: char = '?';
+
+// BatchWriteItem accepts at most 25 requests
+const BATCH_WRITE_SIZE: usize = 25;
+// BatchGetItem accepts at most 100 keys
+const BATCH_GET_SIZE: usize = 100;
+
+/// A `[[target]]` or `[[target|label]]` reference found in entry content
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    pub target: String,
+    pub label: Option<String>,
+}
+
+/// One stored link between two entries
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryLink {
+    pub source_id: String,
+    pub target_key: String,
+    /// Set when the link points at an existing entry
+    pub target_id: Option<String>,
+    /// Title as written, kept for dangling links
+    pub target_title: Option<String>,
+}
+
+/// Find all wiki links in the text, in order of appearance
+pub fn parse_wiki_links(content: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            break;
+        };
+        let inner = &after[..end];
+
+        // Links never span lines or nest; skip the opening brackets and keep scanning
+        if inner.contains('\n') || inner.contains("[[") {
+            rest = after;
+            continue;
+        }
+
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target.trim(), Some(label.trim().to_string()).filter(|l| !l.is_empty())),
+            None => (inner.trim(), None),
+        };
+        if !target.is_empty() {
+            links.push(WikiLink { target: target.to_string(), label });
+        }
+        rest = &after[end + 2..];
+    }
+
+    links
+}
+
+/// Replace link targets in the text. `map_target` returns the new target for a
+/// link, or None to leave it unchanged; labels are preserved.
+pub fn rewrite_wiki_links<F>(content: &str, map_target: F) -> String
+where
+    F: Fn(&WikiLink) -> Option<String>,
+{
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            break;
+        };
+        let inner = &after[..end];
+
+        if inner.contains('\n') || inner.contains("[[") {
+            output.push_str(&rest[..start + 2]);
+            rest = after;
+            continue;
+        }
+
+        output.push_str(&rest[..start]);
+        let link = parse_wiki_links(&rest[start..start + end + 4]).into_iter().next();
+        match link.as_ref().and_then(|l| map_target(l).map(|t| (t, l))) {
+            Some((target, link)) => match &link.label {
+                Some(label) => output.push_str(&format!("[[{}|{}]]", target, label)),
+                None => output.push_str(&format!("[[{}]]", target)),
+            },
+            None => output.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Titles match case-insensitively and regardless of surrounding or repeated whitespace
+pub fn normalize_link_title(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Target key of a link that does not point at an existing entry
+pub fn dangling_link_key(title: &str) -> String {
+    format!("{}{}", DANGLING_PREFIX, normalize_link_title(title))
+}
+
+/// title_pk of a user's entry with this title. The title is hashed so that
+/// any title fits in a key.
+pub fn link_title_pk(tenant_id: &str, user_id: &str, title: &str) -> String {
+    let hash = Sha256::digest(normalize_link_title(title).as_bytes());
+    format!("{}#TITLE#{:x}", timeline_pk(tenant_id, user_id), hash)
+}
+
+/// Maps link targets to entry ids for one user
+#[derive(Debug, Default)]
+pub struct LinkResolver {
+    ids: HashSet<String>,
+    titles: HashMap<String, String>,
+}
+
+impl LinkResolver {
+    /// Build from (id, title) pairs in chronological order. When several
+    /// entries share a title, links resolve to the oldest one.
+    pub fn from_entries<I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut resolver = LinkResolver::default();
+        for (id, title) in entries {
+            resolver.titles.entry(normalize_link_title(&title)).or_insert_with(|| id.clone());
+            resolver.ids.insert(id);
+        }
+        resolver
+    }
+
+    /// Target key for a link: the entry id, or a dangling key for unknown titles
+    pub fn resolve(&self, link: &WikiLink) -> String {
+        if self.ids.contains(&link.target) {
+            return link.target.clone();
+        }
+        self.titles
+            .get(&normalize_link_title(&link.target))
+            .cloned()
+            .unwrap_or_else(|| dangling_link_key(&link.target))
+    }
+
+    /// Distinct target keys of the links in `content`, excluding links to the entry itself
+    pub fn resolve_all(&self, source_id: &str, content: &str) -> Vec<(String, WikiLink)> {
+        let mut seen = HashSet::new();
+        parse_wiki_links(content)
+            .into_iter()
+            .map(|link| (self.resolve(&link), link))
+            .filter(|(key, _)| key != source_id && seen.insert(key.clone()))
+            .collect()
+    }
+}
+
+/// (id, title) of every entry of a user, oldest first, leaving out sealed
+/// time capsules
+pub async fn query_entry_titles(
+    client: &DynamoDbClient,
+    entries_table: &str,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<Vec<(String, String)>, JournalError> {
+    let now = chrono::Utc::now();
+    let mut entries = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(entries_table)
+            .index_name(TIMELINE_INDEX)
+            .key_condition_expression("timeline_pk = :pk")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .projection_expression("id, title, unlock_at")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query entry titles: {}", e)))?;
+
+        for item in response.items() {
+            // Sealed time capsules can't be linked to until they unlock
+            if is_sealed(item, now) {
+                continue;
+            }
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            if let (Some(id), Some(title)) = (get_s("id"), get_s("title")) {
+                entries.push((id, title));
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Resolver for the targets of `links`: targets that are ids of the user's
+/// entries, and the oldest linkable entry for each title
+pub async fn resolve_link_targets(
+    client: &DynamoDbClient,
+    entries_table: &str,
+    tenant_id: &str,
+    user_id: &str,
+    links: &[WikiLink],
+) -> Result<LinkResolver, JournalError> {
+    let mut resolver = LinkResolver::default();
+
+    // Entry ids are UUIDs; other targets can only be titles
+    let ids: BTreeSet<&str> = links
+        .iter()
+        .map(|link| link.target.as_str())
+        .filter(|target| uuid::Uuid::parse_str(target).is_ok())
+        .collect();
+    let ids: Vec<&str> = ids.into_iter().collect();
+    let now = chrono::Utc::now();
+    for chunk in ids.chunks(BATCH_GET_SIZE) {
+        let keys = chunk
+            .iter()
+            .map(|id| {
+                HashMap::from([
+                    ("id".to_string(), AttributeValue::S(id.to_string())),
+                    ("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string())),
+                ])
+            })
+            .collect();
+        let mut pending = Some(
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .projection_expression("id, user_id, unlock_at")
+                .build()
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to build entry lookup: {}", e)))?,
+        );
+
+        while let Some(request) = pending.take() {
+            let response = client
+                .batch_get_item()
+                .request_items(entries_table, request)
+                .send()
+                .await
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to look up linked entries: {}", e)))?;
+
+            for item in response.responses().and_then(|r| r.get(entries_table)).into_iter().flatten() {
+                let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok());
+                // Sealed time capsules can't be linked to until they unlock
+                if get_s("user_id").map(String::as_str) == Some(user_id) && !is_sealed(item, now) {
+                    resolver.ids.extend(get_s("id").cloned());
+                }
+            }
+            pending = response
+                .unprocessed_keys()
+                .and_then(|u| u.get(entries_table))
+                .cloned()
+                .filter(|request| !request.keys().is_empty());
+        }
+    }
+
+    let titles: BTreeSet<String> = links
+        .iter()
+        .filter(|link| !resolver.ids.contains(&link.target))
+        .map(|link| normalize_link_title(&link.target))
+        .collect();
+    for title in titles {
+        let response = client
+            .query()
+            .table_name(entries_table)
+            .index_name(TITLE_INDEX)
+            .key_condition_expression("title_pk = :pk")
+            .expression_attribute_values(":pk", AttributeValue::S(link_title_pk(tenant_id, user_id, &title)))
+            .limit(1)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to look up linked title: {}", e)))?;
+
+        if let Some(id) = response.items().first().and_then(|item| item.get("id")).and_then(|v| v.as_s().ok()) {
+            resolver.titles.insert(title, id.clone());
+        }
+    }
+
+    Ok(resolver)
+}
+
+fn links_table() -> String {
+    std::env::var("LINKS_TABLE").unwrap_or_else(|_| "reflekt-entry-links".to_string())
+}
+
+fn out_sk(source_id: &str, target_key: &str) -> String {
+    format!("{}{}#{}", OUT_SK_PREFIX, source_id, target_key)
+}
+
+fn in_sk(target_key: &str, source_id: &str) -> String {
+    format!("{}{}#{}", IN_SK_PREFIX, target_key, source_id)
+}
+
+/// Both stored items (outgoing and incoming) for one link
+pub fn link_items(
+    tenant_id: &str,
+    user_id: &str,
+    source_id: &str,
+    target_key: &str,
+    target_title: Option<&str>,
+) -> Vec<HashMap<String, AttributeValue>> {
+    let pk = timeline_pk(tenant_id, user_id);
+
+    [out_sk(source_id, target_key), in_sk(target_key, source_id)]
+        .into_iter()
+        .map(|sk| {
+            let mut item = HashMap::new();
+            item.insert("links_pk".to_string(), AttributeValue::S(pk.clone()));
+            item.insert("link_sk".to_string(), AttributeValue::S(sk));
+            item.insert("source_id".to_string(), AttributeValue::S(source_id.to_string()));
+            item.insert("target_key".to_string(), AttributeValue::S(target_key.to_string()));
+            if !target_key.starts_with(DANGLING_PREFIX) {
+                item.insert("target_id".to_string(), AttributeValue::S(target_key.to_string()));
+            }
+            if let Some(title) = target_title {
+                item.insert("target_title".to_string(), AttributeValue::S(title.to_string()));
+            }
+            item
+        })
+        .collect()
+}
+
+fn item_to_link(item: &HashMap<String, AttributeValue>) -> Option<EntryLink> {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+    Some(EntryLink {
+        source_id: get_s("source_id")?,
+        target_key: get_s("target_key")?,
+        target_id: get_s("target_id"),
+        target_title: get_s("target_title"),
+    })
+}
+
+async fn query_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    sk_prefix: &str,
+) -> Result<Vec<EntryLink>, JournalError> {
+    let table_name = links_table();
+    let mut links = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(&table_name)
+            .key_condition_expression("links_pk = :pk AND begins_with(link_sk, :prefix)")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .expression_attribute_values(":prefix", AttributeValue::S(sk_prefix.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query entry links: {}", e)))?;
+
+        links.extend(response.items().iter().filter_map(item_to_link));
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(links)
+}
+
+/// Links written in one entry
+pub async fn get_outgoing_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    source_id: &str,
+) -> Result<Vec<EntryLink>, JournalError> {
+    query_links(client, tenant_id, user_id, &format!("{}{}#", OUT_SK_PREFIX, source_id)).await
+}
+
+/// Links pointing at a target key (an entry id or a dangling title key)
+pub async fn get_incoming_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    target_key: &str,
+) -> Result<Vec<EntryLink>, JournalError> {
+    query_links(client, tenant_id, user_id, &format!("{}{}#", IN_SK_PREFIX, target_key)).await
+}
+
+/// Every link of the user, one per link (the outgoing copies)
+pub async fn get_all_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<Vec<EntryLink>, JournalError> {
+    query_links(client, tenant_id, user_id, OUT_SK_PREFIX).await
+}
+
+/// Replace the stored outgoing links of an entry with `targets`, given as
+/// (target key, title as written) pairs
+pub async fn set_entry_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    source_id: &str,
+    targets: &[(String, String)],
+) -> Result<(), JournalError> {
+    let existing = get_outgoing_links(client, tenant_id, user_id, source_id).await?;
+    let wanted: HashSet<&str> = targets.iter().map(|(key, _)| key.as_str()).collect();
+    let stored: HashSet<&str> = existing.iter().map(|l| l.target_key.as_str()).collect();
+
+    let removed: Vec<&EntryLink> = existing.iter().filter(|l| !wanted.contains(l.target_key.as_str())).collect();
+    let mut requests = Vec::new();
+    for link in removed {
+        requests.extend(delete_requests(tenant_id, user_id, &link.source_id, &link.target_key)?);
+    }
+    for (key, title) in targets.iter().filter(|(key, _)| !stored.contains(key.as_str())) {
+        let title = key.starts_with(DANGLING_PREFIX).then_some(title.as_str());
+        requests.extend(put_requests(link_items(tenant_id, user_id, source_id, key, title))?);
+    }
+
+    batch_write(client, requests).await
+}
+
+/// Point every link aimed at `from_key` at `to_key` instead. Used when a
+/// dangling title gains an entry and when a linked entry is deleted.
+pub async fn retarget_links(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    from_key: &str,
+    to_key: &str,
+    target_title: Option<&str>,
+) -> Result<Vec<EntryLink>, JournalError> {
+    let incoming = get_incoming_links(client, tenant_id, user_id, from_key).await?;
+
+    let mut requests = Vec::new();
+    for link in &incoming {
+        requests.extend(delete_requests(tenant_id, user_id, &link.source_id, from_key)?);
+        // An entry never links to itself
+        if link.source_id != to_key {
+            requests.extend(put_requests(link_items(tenant_id, user_id, &link.source_id, to_key, target_title))?);
+        }
+    }
+    batch_write(client, requests).await?;
+
+    Ok(incoming)
+}
+
+fn delete_requests(
+    tenant_id: &str,
+    user_id: &str,
+    source_id: &str,
+    target_key: &str,
+) -> Result<Vec<WriteRequest>, JournalError> {
+    let pk = timeline_pk(tenant_id, user_id);
+    [out_sk(source_id, target_key), in_sk(target_key, source_id)]
+        .into_iter()
+        .map(|sk| {
+            let request = DeleteRequest::builder()
+                .key("links_pk", AttributeValue::S(pk.clone()))
+                .key("link_sk", AttributeValue::S(sk))
+                .build()
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to build delete request: {}", e)))?;
+            Ok(WriteRequest::builder().delete_request(request).build())
+        })
+        .collect()
+}
+
+fn put_requests(items: Vec<HashMap<String, AttributeValue>>) -> Result<Vec<WriteRequest>, JournalError> {
+    items
+        .into_iter()
+        .map(|item| {
+            let request = PutRequest::builder()
+                .set_item(Some(item))
+                .build()
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to build put request: {}", e)))?;
+            Ok(WriteRequest::builder().put_request(request).build())
+        })
+        .collect()
+}
+
+async fn batch_write(client: &DynamoDbClient, requests: Vec<WriteRequest>) -> Result<(), JournalError> {
+    let table_name = links_table();
+
+    for chunk in requests.chunks(BATCH_WRITE_SIZE) {
+        let mut pending = chunk.to_vec();
+        while !pending.is_empty() {
+            let response = client
+                .batch_write_item()
+                .request_items(&table_name, pending)
+                .send()
+                .await
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to write entry links: {}", e)))?;
+
+            pending = response
+                .unprocessed_items()
+                .and_then(|u| u.get(&table_name))
+                .cloned()
+                .unwrap_or_default();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(target: &str, label: Option<&str>) -> WikiLink {
+        WikiLink { target: target.to_string(), label: label.map(str::to_string) }
+    }
+
+    #[test]
+    fn parse_finds_links_with_and_without_labels() {
+        let links = parse_wiki_links("See [[Trip to Lisbon]] and [[ Packing list | what to bring ]], then [[x|]].");
+        assert_eq!(
+            links,
+            vec![link("Trip to Lisbon", None), link("Packing list", Some("what to bring")), link("x", None)]
+        );
+    }
+
+    #[test]
+    fn parse_skips_links_that_span_lines_nest_or_are_empty() {
+        assert!(parse_wiki_links("[[Trip\nto Lisbon]] [[]] [[ |label]] [[unclosed").is_empty());
+        assert_eq!(parse_wiki_links("[[outer [[Inner]]"), vec![link("Inner", None)]);
+    }
+
+    #[test]
+    fn rewrite_replaces_targets_and_keeps_labels() {
+        let content = "Back from [[Trip to Lisbon]] ([[trip to lisbon|the trip]]) before [[Packing list]].";
+        let rewritten = rewrite_wiki_links(content, |l| {
+            (normalize_link_title(&l.target) == "trip to lisbon").then(|| "Lisbon 2024".to_string())
+        });
+        assert_eq!(rewritten, "Back from [[Lisbon 2024]] ([[Lisbon 2024|the trip]]) before [[Packing list]].");
+    }
+
+    #[test]
+    fn rewrite_leaves_other_text_untouched() {
+        let content = "Multi-line [[not\na link]] and [[outer [[Inner]] with [[unclosed";
+        let rewritten = rewrite_wiki_links(content, |l| Some(format!("{}!", l.target)));
+        assert_eq!(rewritten, "Multi-line [[not\na link]] and [[outer [[Inner!]] with [[unclosed");
+        assert_eq!(rewrite_wiki_links(content, |_| None), content);
+    }
+
+    #[test]
+    fn titles_normalize_case_and_whitespace() {
+        assert_eq!(normalize_link_title("  Trip   to\tLisbon "), "trip to lisbon");
+        assert_eq!(dangling_link_key("Trip  To Lisbon"), "?trip to lisbon");
+        assert_eq!(link_title_pk("t", "u", "Trip to Lisbon"), link_title_pk("t", "u", " trip  TO lisbon"));
+        assert_ne!(link_title_pk("t", "u", "Trip to Lisbon"), link_title_pk("t", "u", "Trip to Porto"));
+        assert!(link_title_pk("t", "u", "Trip to Lisbon").starts_with("USER#t#u#TITLE#"));
+    }
+
+    #[test]
+    fn resolver_prefers_ids_then_the_oldest_title() {
+        let resolver = LinkResolver::from_entries([
+            ("id-1".to_string(), "Trip to Lisbon".to_string()),
+            ("id-2".to_string(), "trip to lisbon".to_string()),
+        ]);
+        assert_eq!(resolver.resolve(&link("id-2", None)), "id-2");
+        assert_eq!(resolver.resolve(&link("TRIP TO LISBON", None)), "id-1");
+        assert_eq!(resolver.resolve(&link("Trip to Porto", None)), "?trip to porto");
+    }
+
+    #[test]
+    fn resolve_all_drops_duplicates_and_self_links() {
+        let resolver = LinkResolver::from_entries([
+            ("id-1".to_string(), "Trip to Lisbon".to_string()),
+            ("id-2".to_string(), "Packing list".to_string()),
+        ]);
+        let keys: Vec<String> = resolver
+            .resolve_all("id-2", "[[Trip to Lisbon]] [[id-1|again]] [[Packing list]] [[Porto]] [[porto]]")
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec!["id-1", "?porto"]);
+    }
+
+    #[test]
+    fn link_items_store_both_directions() {
+        let items = link_items("t", "u", "src", "dst", Some("Trip"));
+        let sks: Vec<&str> = items.iter().map(|item| item["link_sk"].as_s().unwrap().as_str()).collect();
+        assert_eq!(sks, vec!["OUT#src#dst", "IN#dst#src"]);
+        assert!(items.iter().all(|item| item["target_id"].as_s().unwrap() == "dst"));
+
+        let dangling = link_items("t", "u", "src", "?porto", None);
+        assert!(dangling.iter().all(|item| !item.contains_key("target_id") && !item.contains_key("target_title")));
+    }
+}
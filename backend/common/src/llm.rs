@@ -0,0 +1,948 @@
+// Language model providers shared by the AI features
+//
+// Every service that talks to a hosted model goes through `LlmProvider`, so
+// provider selection, default models and the HTTP details live in one place.
+// `llm_provider_from_env` reads the shared configuration:
+//
+// - AI_PROVIDER: openai, anthropic, openai-compatible, ollama, llamacpp,
+//   mock, local or none, or a comma-separated failover chain such as
+//   `anthropic,openai,local` (see llm_resilience). `local` is the offline
+//   sentiment analyzer, which has no language model behind it, so it can only
+//   end a chain. Each service passes the provider it uses when AI_PROVIDER
+//   is unset: openai for analysis and tag suggestions, none for prompts.
+// - AI_MODEL: overrides the first provider's model
+// - AI_TIMEOUT_SECS: time allowed for one request
+// - OPENAI_API_KEY, OPENAI_MODEL, OPENAI_BASE_URL
+// - ANTHROPIC_API_KEY, ANTHROPIC_MODEL, ANTHROPIC_BASE_URL
+// - AI_BASE_URL, AI_API_KEY: endpoint of an OpenAI-compatible server, such as
+//   a self-hosted Ollama or llama.cpp server that keeps journal text in-house
+// - AI_JSON_MODE: response_format (default) or prompt, for servers whose
+//   models can't be constrained to JSON output
+// - AI_MOCK_RESPONSES: JSON array of replies for the scripted mock provider
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{http_client, send_with_retry, FailoverProvider, JournalError, RetryPolicy};
+
+pub const DEFAULT_OPENAI_MODEL: &str = "gpt-4o";
+pub const DEFAULT_ANTHROPIC_MODEL: &str = "claude-3-haiku-20240307";
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+pub const DEFAULT_LLAMACPP_BASE_URL: &str = "http://localhost:8080/v1";
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+// Self-hosted models may have to be loaded first; still inside the 60s AI function timeout
+pub const DEFAULT_SELF_HOSTED_TIMEOUT_SECS: u64 = 55;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// Appended to the system prompt when JSON can't be requested natively
+const JSON_INSTRUCTION: &str = "Respond with only valid JSON, without markdown code fences or any other text.";
+
+/// One completion request: a system instruction and the user's text
+#[derive(Debug, Clone)]
+pub struct LlmRequest {
+    pub system: String,
+    pub prompt: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// The reply must be a JSON object
+    pub json: bool,
+}
+
+impl LlmRequest {
+    // System prompt that asks for JSON in words
+    fn system_with_json_instruction(&self) -> String {
+        format!("{}\n\n{}", self.system, JSON_INSTRUCTION)
+    }
+}
+
+/// Tokens a provider counted for a completion; zero when it doesn't report them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+        }
+    }
+}
+
+/// Text returned by a provider, with who produced it and what it cost
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub text: String,
+    pub provider: String,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+pub type LlmFuture<'a> = Pin<Box<dyn Future<Output = Result<LlmResponse, JournalError>> + Send + 'a>>;
+
+/// A hosted or scripted language model
+pub trait LlmProvider: Send + Sync {
+    /// Name recorded alongside results, e.g. "openai"
+    fn name(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a>;
+}
+
+/// The JSON value in a model's reply. Models often wrap it in a markdown code
+/// fence or a sentence of prose; this finds the fenced block, or failing that
+/// the first balanced object or array.
+pub fn extract_json(text: &str) -> Option<&str> {
+    let trimmed = text.trim();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        if let Some(value) = balanced_json(trimmed) {
+            return Some(value);
+        }
+    }
+
+    // ```json ... ``` or a bare ``` ... ``` fence
+    if let Some(start) = trimmed.find("```") {
+        let after_fence = &trimmed[start + 3..];
+        let body_start = after_fence.find('\n').map(|i| i + 1).unwrap_or(0);
+        let body = &after_fence[body_start..];
+        if let Some(end) = body.find("```") {
+            if let Some(value) = balanced_json(body[..end].trim()) {
+                return Some(value);
+            }
+        }
+    }
+
+    let start = trimmed.find(['{', '['])?;
+    balanced_json(&trimmed[start..])
+}
+
+// The object or array the text starts with, up to its closing bracket
+fn balanced_json(text: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(&text[..=i]);
+                }
+            }
+            _ if depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse the JSON in a model's reply
+pub fn parse_json_reply<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    let json = extract_json(text).ok_or_else(|| "the reply contains no JSON".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+/// A structured completion that failed. `spent` is the last reply received,
+/// with the usage of every reply before the failure, so the tokens can still
+/// be recorded.
+#[derive(Debug)]
+pub struct StructuredError {
+    pub error: JournalError,
+    pub spent: Option<LlmResponse>,
+}
+
+impl std::fmt::Display for StructuredError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl From<StructuredError> for JournalError {
+    fn from(e: StructuredError) -> Self {
+        e.error
+    }
+}
+
+/// Complete a JSON request and parse the reply into `T`, checked by `validate`.
+/// A reply that doesn't parse or validate gets one repair attempt: the model
+/// is shown its reply and what was wrong with it. The returned usage covers
+/// both attempts, on failure too.
+pub async fn complete_structured<T, F>(
+    provider: &dyn LlmProvider,
+    request: &LlmRequest,
+    validate: F,
+) -> Result<(T, LlmResponse), StructuredError>
+where
+    T: DeserializeOwned,
+    F: Fn(T) -> Result<T, String>,
+{
+    let response = provider
+        .complete(request)
+        .await
+        .map_err(|error| StructuredError { error, spent: None })?;
+    let problem = match parse_json_reply(&response.text).and_then(&validate) {
+        Ok(value) => return Ok((value, response)),
+        Err(problem) => problem,
+    };
+
+    tracing::warn!("{} reply did not match the schema ({}), asking for a repair", response.provider, problem);
+    let repair = LlmRequest {
+        system: request.system.clone(),
+        prompt: format!(
+            "{}\n\nYour previous reply could not be used: {}\n\nPrevious reply:\n{}\n\n\
+             Reply again with only the corrected JSON.",
+            request.prompt, problem, response.text
+        ),
+        json: true,
+        ..request.clone()
+    };
+    let first_usage = response.usage;
+    let mut response = match provider.complete(&repair).await {
+        Ok(repaired) => repaired,
+        Err(error) => return Err(StructuredError { error, spent: Some(response) }),
+    };
+    response.usage = response.usage + first_usage;
+    match parse_json_reply(&response.text).and_then(&validate) {
+        Ok(value) => Ok((value, response)),
+        Err(problem) => Err(StructuredError {
+            error: JournalError::ExternalApiError(format!(
+                "{} reply did not match the schema after a repair attempt: {}",
+                response.provider, problem
+            )),
+            spent: Some(response),
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProviderKind {
+    OpenAi,
+    Anthropic,
+    /// Any server speaking the OpenAI chat completions API
+    OpenAiCompatible,
+    Mock,
+    /// Offline lexicon analysis only; no language model
+    Local,
+    /// AI features are turned off
+    Disabled,
+}
+
+impl LlmProviderKind {
+    /// Parse one AI_PROVIDER name; unset means OpenAI
+    pub fn parse(value: Option<&str>) -> Result<Self, JournalError> {
+        let value = value.map(|v| v.trim().to_lowercase()).unwrap_or_default();
+        match value.as_str() {
+            "" | "openai" => Ok(LlmProviderKind::OpenAi),
+            // rust-bert is not available on Lambda ARM64; the lexicon analyzer stands in for it
+            "local" | "rustbert" => Ok(LlmProviderKind::Local),
+            "anthropic" => Ok(LlmProviderKind::Anthropic),
+            "openai-compatible" | "openai_compatible" | "ollama" | "llamacpp" | "llama.cpp" => {
+                Ok(LlmProviderKind::OpenAiCompatible)
+            }
+            "mock" => Ok(LlmProviderKind::Mock),
+            "none" => Ok(LlmProviderKind::Disabled),
+            other => Err(JournalError::ConfigurationError(format!(
+                "Unknown AI_PROVIDER '{}'. Supported values: openai, anthropic, openai-compatible, ollama, llamacpp, mock, local, none",
+                other
+            ))),
+        }
+    }
+
+    /// Name of the provider as written in AI_PROVIDER
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LlmProviderKind::OpenAi => "openai",
+            LlmProviderKind::Anthropic => "anthropic",
+            LlmProviderKind::OpenAiCompatible => "openai-compatible",
+            LlmProviderKind::Mock => "mock",
+            LlmProviderKind::Local => "local",
+            LlmProviderKind::Disabled => "none",
+        }
+    }
+
+    /// Providers named by an AI_PROVIDER chain, in order, with their names;
+    /// just `default` if it is unset or empty. `none` must stand alone, and
+    /// nothing can follow `local`.
+    pub fn parse_chain(value: Option<&str>, default: LlmProviderKind) -> Result<Vec<(String, Self)>, JournalError> {
+        let names: Vec<String> = value
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if names.is_empty() {
+            return Ok(vec![(default.as_str().to_string(), default)]);
+        }
+
+        let mut chain = Vec::new();
+        for name in names {
+            let kind = LlmProviderKind::parse(Some(&name))?;
+            if chain.last().is_some_and(|(_, last)| *last == LlmProviderKind::Local) {
+                return Err(JournalError::ConfigurationError(format!(
+                    "AI_PROVIDER lists '{}' after local, which always answers",
+                    name
+                )));
+            }
+            chain.push((name, kind));
+        }
+        if chain.len() > 1 && chain.iter().any(|(_, kind)| *kind == LlmProviderKind::Disabled) {
+            return Err(JournalError::ConfigurationError("AI_PROVIDER=none can't be part of a chain".into()));
+        }
+        Ok(chain)
+    }
+
+    /// Providers selected by AI_PROVIDER, in order, or `default` if it is unset
+    pub fn chain_from_env(default: LlmProviderKind) -> Result<Vec<(String, Self)>, JournalError> {
+        LlmProviderKind::parse_chain(std::env::var("AI_PROVIDER").ok().as_deref(), default)
+    }
+
+    /// First provider selected by AI_PROVIDER, or `default` if it is unset
+    pub fn from_env(default: LlmProviderKind) -> Result<Self, JournalError> {
+        Ok(LlmProviderKind::chain_from_env(default)?[0].1)
+    }
+}
+
+/// How a JSON reply is asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonMode {
+    /// `response_format: json_object`, falling back to an instruction if the server rejects it
+    ResponseFormat,
+    /// Only an instruction in the system prompt, for models without JSON mode
+    Prompt,
+}
+
+impl JsonMode {
+    /// Parse AI_JSON_MODE; unset means response_format
+    pub fn parse(value: Option<&str>) -> Result<Self, JournalError> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("response_format") => Ok(JsonMode::ResponseFormat),
+            Some("prompt") => Ok(JsonMode::Prompt),
+            Some(other) => Err(JournalError::ConfigurationError(format!(
+                "Unknown AI_JSON_MODE '{}'. Supported values: response_format, prompt",
+                other
+            ))),
+        }
+    }
+}
+
+/// Provider settings resolved from the environment
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    pub kind: LlmProviderKind,
+    /// Name recorded with results: the AI_PROVIDER value, e.g. "ollama"
+    pub name: String,
+    pub model: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub timeout: Duration,
+    pub json_mode: JsonMode,
+    pub retry: RetryPolicy,
+}
+
+impl LlmConfig {
+    /// Configuration of the first provider selected by AI_PROVIDER, or of
+    /// `default` if it is unset
+    pub fn from_env(default: LlmProviderKind) -> Result<Self, JournalError> {
+        let (name, kind) = LlmProviderKind::chain_from_env(default)?.remove(0);
+        LlmConfig::for_provider(&name, kind, true)
+    }
+
+    /// Configuration of one provider of the chain; `primary` if it comes first
+    pub fn for_provider(name: &str, kind: LlmProviderKind, primary: bool) -> Result<Self, JournalError> {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+
+        let (model, base_url, api_key) = match kind {
+            LlmProviderKind::OpenAi => (
+                env("OPENAI_MODEL").unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string()),
+                env("OPENAI_BASE_URL").unwrap_or_else(|| DEFAULT_OPENAI_BASE_URL.to_string()),
+                Some(env("OPENAI_API_KEY").ok_or_else(|| {
+                    JournalError::ConfigurationError("OPENAI_API_KEY environment variable is not set".into())
+                })?),
+            ),
+            LlmProviderKind::Anthropic => (
+                env("ANTHROPIC_MODEL").unwrap_or_else(|| DEFAULT_ANTHROPIC_MODEL.to_string()),
+                env("ANTHROPIC_BASE_URL").unwrap_or_else(|| DEFAULT_ANTHROPIC_BASE_URL.to_string()),
+                Some(env("ANTHROPIC_API_KEY").ok_or_else(|| {
+                    JournalError::ConfigurationError("ANTHROPIC_API_KEY environment variable is not set".into())
+                })?),
+            ),
+            LlmProviderKind::OpenAiCompatible => (
+                env("AI_MODEL").ok_or_else(|| {
+                    JournalError::ConfigurationError("AI_MODEL must be set for an OpenAI-compatible provider".into())
+                })?,
+                env("AI_BASE_URL")
+                    .or_else(|| match name {
+                        "ollama" => Some(DEFAULT_OLLAMA_BASE_URL.to_string()),
+                        "llamacpp" | "llama.cpp" => Some(DEFAULT_LLAMACPP_BASE_URL.to_string()),
+                        _ => None,
+                    })
+                    .ok_or_else(|| {
+                        JournalError::ConfigurationError(
+                            "AI_BASE_URL must be set for an OpenAI-compatible provider".into(),
+                        )
+                    })?,
+                env("AI_API_KEY"),
+            ),
+            LlmProviderKind::Mock => ("mock".to_string(), String::new(), None),
+            LlmProviderKind::Local => {
+                return Err(JournalError::ConfigurationError(
+                    "AI_PROVIDER=local analyzes text offline and has no language model".into(),
+                ))
+            }
+            LlmProviderKind::Disabled => {
+                return Err(JournalError::ConfigurationError(
+                    "AI service is not configured. Set AI_PROVIDER environment variable.".into(),
+                ))
+            }
+        };
+
+        let default_timeout = match kind {
+            LlmProviderKind::OpenAiCompatible => DEFAULT_SELF_HOSTED_TIMEOUT_SECS,
+            _ => DEFAULT_TIMEOUT_SECS,
+        };
+        let timeout = match env("AI_TIMEOUT_SECS") {
+            Some(value) => value.trim().parse::<u64>().ok().filter(|secs| *secs > 0).ok_or_else(|| {
+                JournalError::ConfigurationError(format!("Invalid AI_TIMEOUT_SECS '{}'", value))
+            })?,
+            None => default_timeout,
+        };
+
+        Ok(LlmConfig {
+            kind,
+            name: match kind {
+                LlmProviderKind::OpenAiCompatible => name.to_string(),
+                LlmProviderKind::Anthropic => "anthropic".to_string(),
+                LlmProviderKind::Mock => "mock".to_string(),
+                _ => "openai".to_string(),
+            },
+            model: env("AI_MODEL").filter(|_| primary).unwrap_or(model),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            timeout: Duration::from_secs(timeout),
+            json_mode: JsonMode::parse(env("AI_JSON_MODE").as_deref())?,
+            retry: RetryPolicy::from_env(),
+        })
+    }
+}
+
+/// The language model providers selected by AI_PROVIDER, chained for failover,
+/// with `default` standing in for an unset AI_PROVIDER. A provider that isn't
+/// configured is left out of the chain with a warning; it is an error only if
+/// no provider is left.
+pub fn llm_provider_from_env(default: LlmProviderKind) -> Result<Box<dyn LlmProvider>, JournalError> {
+    let mut providers: Vec<Box<dyn LlmProvider>> = Vec::new();
+    let mut first_error = None;
+
+    for (i, (name, kind)) in LlmProviderKind::chain_from_env(default)?.into_iter().enumerate() {
+        let provider: Result<Box<dyn LlmProvider>, JournalError> = match kind {
+            LlmProviderKind::OpenAi | LlmProviderKind::OpenAiCompatible => {
+                LlmConfig::for_provider(&name, kind, i == 0).map(|c| Box::new(OpenAiProvider::new(c)) as _)
+            }
+            LlmProviderKind::Anthropic => {
+                LlmConfig::for_provider(&name, kind, i == 0).map(|c| Box::new(AnthropicProvider::new(c)) as _)
+            }
+            LlmProviderKind::Mock => MockProvider::from_env().map(|p| Box::new(p) as _),
+            // Offline analysis is up to the caller
+            LlmProviderKind::Local => break,
+            LlmProviderKind::Disabled => {
+                return Err(JournalError::ConfigurationError(
+                    "AI service is not configured. Set AI_PROVIDER environment variable.".into(),
+                ))
+            }
+        };
+        match provider {
+            Ok(provider) => providers.push(provider),
+            Err(e) => {
+                tracing::warn!("Leaving AI provider {} out of the chain: {}", name, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    if providers.is_empty() {
+        return Err(first_error.unwrap_or_else(|| {
+            JournalError::ConfigurationError("AI_PROVIDER=local analyzes text offline and has no language model".into())
+        }));
+    }
+    Ok(Box::new(FailoverProvider::new(providers)))
+}
+
+// Error for a non-success status, carrying the provider's message
+fn status_error(provider: &str, status: reqwest::StatusCode, body: &str) -> JournalError {
+    JournalError::ExternalApiError(format!(
+        "{} API returned {}: {}",
+        provider,
+        status,
+        body.chars().take(500).collect::<String>()
+    ))
+}
+
+async fn check_status(
+    provider: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, JournalError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(status_error(provider, status, &body))
+}
+
+/// OpenAI chat completions, or any server speaking the same API
+pub struct OpenAiProvider {
+    config: LlmConfig,
+    // Set once the server has rejected `response_format`
+    json_mode_rejected: AtomicBool,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiMessage,
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+// Whether an error response is about `response_format` rather than the request as a whole
+fn rejects_json_mode(status: reqwest::StatusCode, body: &str) -> bool {
+    (status == reqwest::StatusCode::BAD_REQUEST || status == reqwest::StatusCode::UNPROCESSABLE_ENTITY)
+        && body.to_lowercase().contains("response_format")
+}
+
+impl OpenAiProvider {
+    pub fn new(config: LlmConfig) -> Self {
+        OpenAiProvider { config, json_mode_rejected: AtomicBool::new(false) }
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse, JournalError> {
+        let native_json = request.json
+            && self.config.json_mode == JsonMode::ResponseFormat
+            && !self.json_mode_rejected.load(Ordering::Relaxed);
+
+        let response = self.post(request, native_json).await?;
+        let status = response.status();
+        let response = if native_json && !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            if !rejects_json_mode(status, &body) {
+                return Err(status_error(&self.config.name, status, &body));
+            }
+            // Many self-hosted models have no JSON mode; ask in the prompt instead
+            tracing::warn!(
+                "{} model {} does not support response_format, asking for JSON in the prompt",
+                self.config.name,
+                self.config.model
+            );
+            self.json_mode_rejected.store(true, Ordering::Relaxed);
+            self.post(request, false).await?
+        } else {
+            response
+        };
+
+        let response: OpenAiResponse = check_status(&self.config.name, response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| {
+                JournalError::ExternalApiError(format!("Failed to parse {} response: {}", self.config.name, e))
+            })?;
+
+        let usage = response
+            .usage
+            .map(|u| TokenUsage { input_tokens: u.prompt_tokens, output_tokens: u.completion_tokens })
+            .unwrap_or_default();
+        let text = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .filter(|text| !text.trim().is_empty())
+            .ok_or_else(|| JournalError::ExternalApiError(format!("Empty response from {}", self.config.name)))?;
+
+        Ok(LlmResponse { text, provider: self.config.name.clone(), model: self.config.model.clone(), usage })
+    }
+
+    async fn post(&self, request: &LlmRequest, native_json: bool) -> Result<reqwest::Response, JournalError> {
+        let system = if request.json && !native_json {
+            request.system_with_json_instruction()
+        } else {
+            request.system.clone()
+        };
+        let mut body = serde_json::json!({
+            "model": self.config.model,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": request.prompt },
+            ],
+        });
+        if native_json {
+            body["response_format"] = serde_json::json!({ "type": "json_object" });
+        }
+
+        send_with_retry(&self.config.name, self.config.timeout, &self.config.retry, || {
+            let http_request = http_client()
+                .post(format!("{}/chat/completions", self.config.base_url))
+                .header("Content-Type", "application/json")
+                .json(&body);
+            match &self.config.api_key {
+                Some(api_key) => http_request.header("Authorization", format!("Bearer {}", api_key)),
+                None => http_request,
+            }
+        })
+        .await
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// Anthropic messages API
+pub struct AnthropicProvider {
+    config: LlmConfig,
+}
+
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicContent>,
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct AnthropicContent {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: LlmConfig) -> Self {
+        AnthropicProvider { config }
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse, JournalError> {
+        // No JSON mode on the messages API
+        let system = if request.json { request.system_with_json_instruction() } else { request.system.clone() };
+        let body = serde_json::json!({
+            "model": self.config.model,
+            "max_tokens": request.max_tokens,
+            "temperature": request.temperature,
+            "system": system,
+            "messages": [{ "role": "user", "content": request.prompt }],
+        });
+
+        let response = send_with_retry("Anthropic", self.config.timeout, &self.config.retry, || {
+            http_client()
+                .post(format!("{}/messages", self.config.base_url))
+                .header("x-api-key", self.config.api_key.as_deref().unwrap_or_default())
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .header("Content-Type", "application/json")
+                .json(&body)
+        })
+        .await?;
+        let response: AnthropicResponse = check_status("Anthropic", response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse Anthropic response: {}", e)))?;
+
+        // Text blocks in order; other block types carry no reply text
+        let text: String = response.content.into_iter().filter_map(|block| block.text).collect();
+        if text.trim().is_empty() {
+            return Err(JournalError::ExternalApiError("Empty response from Anthropic".into()));
+        }
+
+        let usage = response
+            .usage
+            .map(|u| TokenUsage { input_tokens: u.input_tokens, output_tokens: u.output_tokens })
+            .unwrap_or_default();
+
+        Ok(LlmResponse { text, provider: "anthropic".to_string(), model: self.config.model.clone(), usage })
+    }
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// What the mock provider does for one request
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    Text(String),
+    Error(String),
+}
+
+/// Provider that answers from a fixed script, for running the services offline.
+/// Replies are used in order and every request is kept for inspection.
+pub struct MockProvider {
+    replies: Mutex<VecDeque<MockReply>>,
+    requests: Mutex<Vec<LlmRequest>>,
+}
+
+impl MockProvider {
+    pub fn new(replies: Vec<MockReply>) -> Self {
+        MockProvider { replies: Mutex::new(replies.into()), requests: Mutex::new(Vec::new()) }
+    }
+
+    /// Script from AI_MOCK_RESPONSES, a JSON array of reply strings
+    pub fn from_env() -> Result<Self, JournalError> {
+        let script = std::env::var("AI_MOCK_RESPONSES").unwrap_or_else(|_| "[]".to_string());
+        let replies: Vec<String> = serde_json::from_str(&script)
+            .map_err(|e| JournalError::ConfigurationError(format!("Invalid AI_MOCK_RESPONSES: {}", e)))?;
+        Ok(MockProvider::new(replies.into_iter().map(MockReply::Text).collect()))
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<LlmRequest> {
+        self.requests.lock().map(|requests| requests.clone()).unwrap_or_default()
+    }
+
+    fn reply(&self, request: &LlmRequest) -> Result<LlmResponse, JournalError> {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(request.clone());
+        }
+        let reply = self.replies.lock().ok().and_then(|mut replies| replies.pop_front());
+        match reply {
+            Some(MockReply::Text(text)) => {
+                Ok(LlmResponse {
+                    text,
+                    provider: "mock".to_string(),
+                    model: "mock".to_string(),
+                    usage: TokenUsage::default(),
+                })
+            }
+            Some(MockReply::Error(message)) => Err(JournalError::ExternalApiError(message)),
+            None => Err(JournalError::ExternalApiError("Mock provider has no replies left".into())),
+        }
+    }
+}
+
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        "mock"
+    }
+
+    fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a> {
+        let result = self.reply(request);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Mood {
+        mood: String,
+        score: f32,
+    }
+
+    fn validate_mood(mood: Mood) -> Result<Mood, String> {
+        if (-1.0..=1.0).contains(&mood.score) {
+            Ok(mood)
+        } else {
+            Err(format!("score {} is outside -1..1", mood.score))
+        }
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            system: "Rate the mood.".to_string(),
+            prompt: "A quiet, happy day.".to_string(),
+            temperature: 0.0,
+            max_tokens: 100,
+            json: true,
+        }
+    }
+
+    fn mock(replies: &[&str]) -> MockProvider {
+        MockProvider::new(replies.iter().map(|r| MockReply::Text(r.to_string())).collect())
+    }
+
+    #[test]
+    fn extract_json_finds_bare_fenced_and_embedded_values() {
+        assert_eq!(extract_json(r#"  {"a": 1}  "#), Some(r#"{"a": 1}"#));
+        assert_eq!(extract_json("[1, 2]"), Some("[1, 2]"));
+        assert_eq!(extract_json("```json\n{\"a\": 1}\n```"), Some(r#"{"a": 1}"#));
+        assert_eq!(extract_json("Here you go:\n```\n{\"a\": 1}\n```\nAnything else?"), Some(r#"{"a": 1}"#));
+        assert_eq!(extract_json(r#"Sure! {"a": {"b": [1]}} Hope that helps."#), Some(r#"{"a": {"b": [1]}}"#));
+    }
+
+    #[test]
+    fn extract_json_rejects_text_without_a_complete_value() {
+        assert_eq!(extract_json("no json here"), None);
+        assert_eq!(extract_json(r#"{"a": 1"#), None);
+        assert_eq!(extract_json("```json\n\n```"), None);
+    }
+
+    #[test]
+    fn balanced_json_stops_at_the_closing_bracket() {
+        assert_eq!(balanced_json(r#"{"a": 1} and more {"b": 2}"#), Some(r#"{"a": 1}"#));
+        assert_eq!(balanced_json("[[1], [2]], 3"), Some("[[1], [2]]"));
+    }
+
+    #[test]
+    fn balanced_json_ignores_brackets_in_strings() {
+        assert_eq!(balanced_json(r#"{"a": "}{]"} tail"#), Some(r#"{"a": "}{]"}"#));
+        assert_eq!(balanced_json(r#"{"a": "say \"}\""} tail"#), Some(r#"{"a": "say \"}\""}"#));
+    }
+
+    #[test]
+    fn balanced_json_needs_a_leading_bracket() {
+        assert_eq!(balanced_json(r#"x {"a": 1}"#), None);
+        assert_eq!(balanced_json("} {}"), None);
+        assert_eq!(balanced_json("{"), None);
+    }
+
+    #[test]
+    fn parse_json_reply_reads_the_value_in_the_reply() {
+        let mood: Mood = parse_json_reply("```json\n{\"mood\": \"calm\", \"score\": 0.5}\n```").unwrap();
+        assert_eq!(mood, Mood { mood: "calm".to_string(), score: 0.5 });
+    }
+
+    #[test]
+    fn parse_json_reply_explains_what_is_wrong() {
+        assert_eq!(parse_json_reply::<Mood>("I feel calm").unwrap_err(), "the reply contains no JSON");
+        let error = parse_json_reply::<Mood>(r#"{"mood": "calm"}"#).unwrap_err();
+        assert!(error.contains("missing field `score`"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn complete_structured_uses_a_valid_reply() {
+        let provider = mock(&[r#"{"mood": "calm", "score": 0.5}"#]);
+        let (mood, response) = complete_structured(&provider, &request(), validate_mood).await.unwrap();
+        assert_eq!(mood.mood, "calm");
+        assert_eq!(response.provider, "mock");
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn complete_structured_repairs_an_invalid_reply() {
+        let provider = mock(&[r#"{"mood": "calm", "score": 7}"#, r#"{"mood": "calm", "score": 0.7}"#]);
+        let (mood, _) = complete_structured(&provider, &request(), validate_mood).await.unwrap();
+        assert_eq!(mood.score, 0.7);
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].prompt.starts_with("A quiet, happy day."));
+        assert!(requests[1].prompt.contains("score 7 is outside -1..1"));
+        assert!(requests[1].prompt.contains(r#"{"mood": "calm", "score": 7}"#));
+    }
+
+    #[tokio::test]
+    async fn complete_structured_reports_the_spent_reply_when_the_repair_fails() {
+        let provider = mock(&["not json", "still not json"]);
+        let error = complete_structured(&provider, &request(), validate_mood).await.unwrap_err();
+        assert!(error.to_string().contains("after a repair attempt"), "{}", error);
+        assert_eq!(error.spent.map(|spent| spent.text), Some("still not json".to_string()));
+
+        let provider = mock(&["not json"]);
+        let error = complete_structured(&provider, &request(), validate_mood).await.unwrap_err();
+        assert!(error.to_string().contains("no replies left"), "{}", error);
+        assert_eq!(error.spent.map(|spent| spent.text), Some("not json".to_string()));
+    }
+
+    #[test]
+    fn provider_chain_keeps_the_configured_order() {
+        let chain = LlmProviderKind::parse_chain(Some("anthropic, mock,local"), LlmProviderKind::OpenAi).unwrap();
+        let kinds: Vec<LlmProviderKind> = chain.into_iter().map(|(_, kind)| kind).collect();
+        assert_eq!(kinds, vec![LlmProviderKind::Anthropic, LlmProviderKind::Mock, LlmProviderKind::Local]);
+    }
+
+    #[test]
+    fn provider_chain_falls_back_to_the_service_default() {
+        for value in [None, Some(""), Some(" , ")] {
+            let chain = LlmProviderKind::parse_chain(value, LlmProviderKind::Disabled).unwrap();
+            assert_eq!(chain, vec![("none".to_string(), LlmProviderKind::Disabled)]);
+            let chain = LlmProviderKind::parse_chain(value, LlmProviderKind::OpenAi).unwrap();
+            assert_eq!(chain, vec![("openai".to_string(), LlmProviderKind::OpenAi)]);
+        }
+        // An explicit value wins over the default
+        let chain = LlmProviderKind::parse_chain(Some("ollama"), LlmProviderKind::Disabled).unwrap();
+        assert_eq!(chain, vec![("ollama".to_string(), LlmProviderKind::OpenAiCompatible)]);
+    }
+
+    #[test]
+    fn provider_chain_rejects_invalid_chains() {
+        for value in ["local,openai", "none,openai", "openai,none", "gpt"] {
+            assert!(LlmProviderKind::parse_chain(Some(value), LlmProviderKind::OpenAi).is_err(), "{}", value);
+        }
+    }
+}
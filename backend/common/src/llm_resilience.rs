@@ -0,0 +1,487 @@
+// Retries, circuit breaking and failover for language model providers
+//
+// Every provider request goes through `send_with_retry`: timeouts, connection
+// failures, 429 and 5xx responses are retried with exponential backoff and
+// jitter, waiting as long as a Retry-After header asks when there is one.
+//
+// Providers are chained by `FailoverProvider` in the order AI_PROVIDER lists
+// them (e.g. `anthropic,openai,local`). Each provider has a circuit breaker:
+// after AI_BREAKER_THRESHOLD consecutive failures it is skipped for
+// AI_BREAKER_COOLDOWN_SECS, then given one trial request. Breaker state lives
+// for the life of the Lambda container, so a provider that is down is not
+// retried on every invocation.
+//
+// - AI_MAX_RETRIES: retries per request after the first attempt
+// - AI_RETRY_BASE_MS: backoff before the first retry, doubled each time
+// - AI_BREAKER_THRESHOLD, AI_BREAKER_COOLDOWN_SECS
+// - AI_TOTAL_TIMEOUT_SECS: time allowed for the whole chain
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::{JournalError, LlmFuture, LlmProvider, LlmRequest};
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BASE_MS: u64 = 500;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+// A provider asking us to wait longer than this is treated as unavailable
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(20);
+const DEFAULT_BREAKER_THRESHOLD: u32 = 3;
+const DEFAULT_BREAKER_COOLDOWN_SECS: u64 = 60;
+// Inside the 60s AI function timeout, leaving time to save the analysis
+const DEFAULT_TOTAL_TIMEOUT_SECS: u64 = 50;
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static BREAKERS: OnceLock<Mutex<HashMap<String, BreakerState>>> = OnceLock::new();
+
+/// HTTP client shared by all providers; timeouts are set per request
+pub(crate) fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(reqwest::Client::new)
+}
+
+fn env_number<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!("Ignoring invalid {} '{}'", key, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// How often and how patiently a failed request is retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        RetryPolicy {
+            max_retries: env_number("AI_MAX_RETRIES", DEFAULT_MAX_RETRIES),
+            base_delay: Duration::from_millis(env_number("AI_RETRY_BASE_MS", DEFAULT_RETRY_BASE_MS)),
+        }
+    }
+
+    // Exponential backoff with jitter: a random wait between half and all of
+    // base * 2^attempt, capped
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_RETRY_DELAY);
+        let millis = delay.as_millis() as u64;
+        Duration::from_millis(rand::random_range(millis / 2..=millis))
+    }
+}
+
+// Statuses worth trying again: rate limits, timeouts and server errors
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Wait requested by a Retry-After header, in seconds or as an HTTP date
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Send a request built by `build`, retrying per the policy. The last
+/// response is returned even if unsuccessful, so the caller can report it.
+pub(crate) async fn send_with_retry<F>(
+    provider: &str,
+    timeout: Duration,
+    policy: &RetryPolicy,
+    build: F,
+) -> Result<reqwest::Response, JournalError>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        let wait = match build().timeout(timeout).send().await {
+            Ok(response) if !is_retryable(response.status()) || attempt >= policy.max_retries => {
+                return Ok(response)
+            }
+            Ok(response) => match retry_after(&response) {
+                Some(wait) if wait > MAX_RETRY_AFTER => return Ok(response),
+                Some(wait) => {
+                    tracing::warn!("{} returned {}, retrying after {:?}", provider, response.status(), wait);
+                    wait
+                }
+                None => {
+                    let wait = policy.backoff(attempt);
+                    tracing::warn!("{} returned {}, retrying in {:?}", provider, response.status(), wait);
+                    wait
+                }
+            },
+            Err(e) if (e.is_timeout() || e.is_connect()) && attempt < policy.max_retries => {
+                let wait = policy.backoff(attempt);
+                tracing::warn!("{} request failed ({}), retrying in {:?}", provider, e, wait);
+                wait
+            }
+            Err(e) if e.is_timeout() => {
+                return Err(JournalError::ExternalApiError(format!(
+                    "{} API request timed out after {}s",
+                    provider,
+                    timeout.as_secs()
+                )))
+            }
+            Err(e) => return Err(JournalError::ExternalApiError(format!("{} API request failed: {}", provider, e))),
+        };
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// When a failing provider is taken out of the chain, and for how long
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerPolicy {
+    pub threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl BreakerPolicy {
+    pub fn from_env() -> Self {
+        BreakerPolicy {
+            threshold: env_number("AI_BREAKER_THRESHOLD", DEFAULT_BREAKER_THRESHOLD).max(1),
+            cooldown: Duration::from_secs(env_number("AI_BREAKER_COOLDOWN_SECS", DEFAULT_BREAKER_COOLDOWN_SECS)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+fn breakers() -> std::sync::MutexGuard<'static, HashMap<String, BreakerState>> {
+    BREAKERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether the provider's circuit lets a request through. Once the cooldown
+/// has passed a trial request is allowed; failing it reopens the circuit.
+pub fn circuit_allows(provider: &str) -> bool {
+    breakers()
+        .get(provider)
+        .and_then(|state| state.open_until)
+        .is_none_or(|until| Instant::now() >= until)
+}
+
+pub fn record_provider_success(provider: &str) {
+    breakers().remove(provider);
+}
+
+pub fn record_provider_failure(provider: &str, policy: &BreakerPolicy) {
+    let mut breakers = breakers();
+    let state = breakers.entry(provider.to_string()).or_default();
+    state.consecutive_failures += 1;
+    if state.consecutive_failures >= policy.threshold {
+        tracing::warn!(
+            "Opening circuit for {} after {} consecutive failures",
+            provider,
+            state.consecutive_failures
+        );
+        state.open_until = Some(Instant::now() + policy.cooldown);
+    }
+}
+
+/// Providers tried in order until one answers, skipping those whose circuit
+/// is open. The response names the provider that answered.
+pub struct FailoverProvider {
+    providers: Vec<Box<dyn LlmProvider>>,
+    breaker: BreakerPolicy,
+    total_timeout: Duration,
+}
+
+impl FailoverProvider {
+    pub fn new(providers: Vec<Box<dyn LlmProvider>>) -> Self {
+        FailoverProvider {
+            providers,
+            breaker: BreakerPolicy::from_env(),
+            total_timeout: Duration::from_secs(env_number("AI_TOTAL_TIMEOUT_SECS", DEFAULT_TOTAL_TIMEOUT_SECS)),
+        }
+    }
+
+    async fn complete_in_order(&self, request: &LlmRequest) -> Result<crate::LlmResponse, JournalError> {
+        let mut failures = Vec::new();
+        for provider in &self.providers {
+            let name = provider.name();
+            if !circuit_allows(name) {
+                failures.push(format!("{}: circuit open", name));
+                continue;
+            }
+            match provider.complete(request).await {
+                Ok(response) => {
+                    record_provider_success(name);
+                    return Ok(response);
+                }
+                Err(e) => {
+                    tracing::warn!("AI provider {} failed: {}", name, e);
+                    record_provider_failure(name, &self.breaker);
+                    failures.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        Err(JournalError::ExternalApiError(format!("All AI providers failed: {}", failures.join("; "))))
+    }
+}
+
+impl LlmProvider for FailoverProvider {
+    fn name(&self) -> &str {
+        self.providers.first().map(|p| p.name()).unwrap_or("none")
+    }
+
+    fn model(&self) -> &str {
+        self.providers.first().map(|p| p.model()).unwrap_or("none")
+    }
+
+    fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a> {
+        Box::pin(async move {
+            tokio::time::timeout(self.total_timeout, self.complete_in_order(request))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(JournalError::ExternalApiError(format!(
+                        "AI providers did not answer within {}s",
+                        self.total_timeout.as_secs()
+                    )))
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LlmResponse, MockProvider, MockReply, TokenUsage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // A provider with its own name, so breaker state is not shared between tests
+    struct NamedProvider {
+        name: &'static str,
+        answers: bool,
+        calls: AtomicUsize,
+    }
+
+    impl NamedProvider {
+        fn new(name: &'static str, answers: bool) -> Self {
+            NamedProvider { name, answers, calls: AtomicUsize::new(0) }
+        }
+    }
+
+    impl LlmProvider for NamedProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn model(&self) -> &str {
+            "test"
+        }
+
+        fn complete<'a>(&'a self, _request: &'a LlmRequest) -> LlmFuture<'a> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let result = if self.answers {
+                Ok(LlmResponse {
+                    text: "{}".to_string(),
+                    provider: self.name.to_string(),
+                    model: "test".to_string(),
+                    usage: TokenUsage::default(),
+                })
+            } else {
+                Err(JournalError::ExternalApiError(format!("{} is down", self.name)))
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    // Lets a test look at a provider after handing it to a FailoverProvider
+    impl LlmProvider for Arc<NamedProvider> {
+        fn name(&self) -> &str {
+            self.as_ref().name()
+        }
+
+        fn model(&self) -> &str {
+            self.as_ref().model()
+        }
+
+        fn complete<'a>(&'a self, request: &'a LlmRequest) -> LlmFuture<'a> {
+            self.as_ref().complete(request)
+        }
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            system: "Rate the mood.".to_string(),
+            prompt: "A quiet, happy day.".to_string(),
+            temperature: 0.0,
+            max_tokens: 100,
+            json: true,
+        }
+    }
+
+    fn failover(providers: Vec<Box<dyn LlmProvider>>, breaker: BreakerPolicy) -> FailoverProvider {
+        FailoverProvider { providers, breaker, total_timeout: Duration::from_secs(5) }
+    }
+
+    // HTTP server answering its nth request with the nth response, then the
+    // last one. Returns its URL and a count of requests served.
+    async fn scripted_server(responses: &'static [&'static str]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind test server");
+        let url = format!("http://{}", listener.local_addr().expect("test server address"));
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut chunk = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&chunk[..n]),
+                    }
+                }
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let response = responses[n.min(responses.len() - 1)];
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, served)
+    }
+
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const BUSY_FOR_A_MINUTE: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 60\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(100) };
+        for _ in 0..50 {
+            let first = policy.backoff(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100), "{:?}", first);
+            let third = policy.backoff(2);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400), "{:?}", third);
+            for attempt in [7, 40] {
+                let capped = policy.backoff(attempt);
+                assert!(capped >= MAX_RETRY_DELAY / 2 && capped <= MAX_RETRY_DELAY, "{:?}", capped);
+            }
+        }
+    }
+
+    #[test]
+    fn only_rate_limits_timeouts_and_server_errors_are_retried() {
+        for status in [429, 408, 500, 502, 503, 504] {
+            assert!(is_retryable(reqwest::StatusCode::from_u16(status).unwrap()), "{}", status);
+        }
+        for status in [200, 400, 401, 403, 404, 422] {
+            assert!(!is_retryable(reqwest::StatusCode::from_u16(status).unwrap()), "{}", status);
+        }
+    }
+
+    #[test]
+    fn circuit_opens_after_the_threshold_and_closes_on_success() {
+        let policy = BreakerPolicy { threshold: 2, cooldown: Duration::from_secs(60) };
+        let name = "test-breaker-threshold";
+        assert!(circuit_allows(name));
+        record_provider_failure(name, &policy);
+        assert!(circuit_allows(name));
+        record_provider_failure(name, &policy);
+        assert!(!circuit_allows(name));
+        record_provider_success(name);
+        assert!(circuit_allows(name));
+    }
+
+    #[test]
+    fn circuit_allows_a_trial_after_the_cooldown() {
+        let policy = BreakerPolicy { threshold: 1, cooldown: Duration::from_millis(50) };
+        let name = "test-breaker-cooldown";
+        record_provider_failure(name, &policy);
+        assert!(!circuit_allows(name));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(circuit_allows(name));
+        // A failed trial reopens the circuit straight away
+        record_provider_failure(name, &policy);
+        assert!(!circuit_allows(name));
+    }
+
+    #[tokio::test]
+    async fn failover_uses_the_next_provider_that_answers() {
+        let providers: Vec<Box<dyn LlmProvider>> = vec![
+            Box::new(MockProvider::new(vec![MockReply::Error("unavailable".to_string())])),
+            Box::new(MockProvider::new(vec![MockReply::Text(r#"{"mood": "calm"}"#.to_string())])),
+        ];
+        let provider = failover(providers, BreakerPolicy { threshold: 1000, cooldown: Duration::from_secs(60) });
+        let response = provider.complete(&request()).await.unwrap();
+        assert_eq!(response.text, r#"{"mood": "calm"}"#);
+    }
+
+    #[tokio::test]
+    async fn failover_skips_providers_with_an_open_circuit() {
+        let down = Arc::new(NamedProvider::new("test-failover-down", false));
+        let up = Arc::new(NamedProvider::new("test-failover-up", true));
+        let provider = failover(
+            vec![Box::new(down.clone()), Box::new(up.clone())],
+            BreakerPolicy { threshold: 1, cooldown: Duration::from_secs(60) },
+        );
+
+        assert_eq!(provider.complete(&request()).await.unwrap().provider, "test-failover-up");
+        assert_eq!(provider.complete(&request()).await.unwrap().provider, "test-failover-up");
+        assert_eq!(down.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(up.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failover_reports_every_failure() {
+        let provider = failover(
+            vec![Box::new(NamedProvider::new("test-all-down-a", false)), Box::new(NamedProvider::new("test-all-down-b", false))],
+            BreakerPolicy { threshold: 1000, cooldown: Duration::from_secs(60) },
+        );
+        let error = provider.complete(&request()).await.unwrap_err().to_string();
+        assert!(error.contains("test-all-down-a is down") && error.contains("test-all-down-b is down"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn send_with_retry_retries_server_errors() {
+        let (url, served) = scripted_server(&[UNAVAILABLE, UNAVAILABLE, OK]).await;
+        let policy = RetryPolicy { max_retries: 2, base_delay: Duration::from_millis(1) };
+        let response = send_with_retry("test", Duration::from_secs(5), &policy, || http_client().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn send_with_retry_returns_the_last_response_when_retries_run_out() {
+        let (url, served) = scripted_server(&[UNAVAILABLE]).await;
+        let policy = RetryPolicy { max_retries: 1, base_delay: Duration::from_millis(1) };
+        let response = send_with_retry("test", Duration::from_secs(5), &policy, || http_client().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn send_with_retry_gives_up_on_a_long_retry_after() {
+        let (url, served) = scripted_server(&[BUSY_FOR_A_MINUTE, OK]).await;
+        let policy = RetryPolicy { max_retries: 2, base_delay: Duration::from_millis(1) };
+        let response = send_with_retry("test", Duration::from_secs(5), &policy, || http_client().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,274 @@
+// Markdown handling for entry content
+//
+// Entries are stored as raw CommonMark with the GitHub extensions people
+// expect (tables, strikethrough, task lists). Everything that needs another
+// form of the content goes through this module so all clients and exports
+// agree on it:
+//
+//   render_html  sanitized HTML: no scripts, event handlers or unsafe URLs
+//   plain_text   readable text without markup, for word counts and search
+//   outline      the heading structure, with the anchors render_html emits
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// One heading of an entry's outline
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineHeading {
+    /// 1 for `#`, up to 6
+    pub level: u8,
+    pub text: String,
+    /// Id of the heading element in the rendered HTML
+    pub anchor: String,
+}
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+// Parse the content and give every heading a unique, GitHub-style anchor
+fn parse_with_anchors(markdown: &str) -> (Vec<Event<'_>>, Vec<OutlineHeading>) {
+    let mut events: Vec<Event> = Parser::new_ext(markdown, markdown_options()).collect();
+    let mut outline = Vec::new();
+    let mut used: HashMap<String, usize> = HashMap::new();
+
+    let mut i = 0;
+    while i < events.len() {
+        if let Event::Start(Tag::Heading { level, .. }) = &events[i] {
+            let level = *level;
+            let mut text = String::new();
+            let mut end = i + 1;
+            while end < events.len() && !matches!(events[end], Event::End(TagEnd::Heading(_))) {
+                if let Event::Text(t) | Event::Code(t) = &events[end] {
+                    text.push_str(t);
+                }
+                end += 1;
+            }
+
+            let slug = slugify(&text);
+            let count = used.entry(slug.clone()).or_insert(0);
+            let anchor = if *count == 0 { slug } else { format!("{}-{}", slug, count) };
+            *count += 1;
+
+            events[i] = Event::Start(Tag::Heading {
+                level,
+                id: Some(CowStr::from(anchor.clone())),
+                classes: Vec::new(),
+                attrs: Vec::new(),
+            });
+            outline.push(OutlineHeading { level: level as u8, text: text.trim().to_string(), anchor });
+            i = end;
+        }
+        i += 1;
+    }
+
+    (events, outline)
+}
+
+// Lowercase words joined by hyphens; headings without any become "section"
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "section".to_string() } else { slug.to_string() }
+}
+
+// Allow-list for rendered HTML. Raw HTML in the content passes through the
+// same filter, so only the markup the renderer itself produces survives.
+fn sanitizer() -> ammonia::Builder<'static> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["checked"])
+        // Task list checkboxes only: any other input becomes one too
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "");
+    for heading in ["h1", "h2", "h3", "h4", "h5", "h6"] {
+        builder.add_tag_attributes(heading, ["id"]);
+    }
+    builder
+}
+
+/// Render entry content to sanitized HTML
+pub fn render_html(markdown: &str) -> String {
+    let (events, _) = parse_with_anchors(markdown);
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    sanitizer().clean(&html).to_string()
+}
+
+/// Headings of the content, in document order
+pub fn outline(markdown: &str) -> Vec<OutlineHeading> {
+    parse_with_anchors(markdown).1
+}
+
+/// Text of the content without markup. Blocks are separated by newlines;
+/// link targets, images and raw HTML are left out, link and image text kept.
+pub fn plain_text(markdown: &str) -> String {
+    fn end_line(text: &mut String) {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+
+    let mut text = String::new();
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::End(TagEnd::TableCell) => text.push(' '),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::BlockQuote(_)
+                | TagEnd::CodeBlock
+                | TagEnd::Item
+                | TagEnd::TableHead
+                | TagEnd::TableRow,
+            )
+            | Event::Rule => end_line(&mut text),
+            _ => {}
+        }
+    }
+
+    text.trim().to_string()
+}
+
+/// Lowercased title and plain text of an entry, stored for substring search
+pub fn search_text(title: &str, markdown: &str) -> String {
+    format!("{}\n{}", title, plain_text(markdown)).to_lowercase()
+}
+
+/// Shift every heading down by `levels` (capped at `######`) so the content
+/// nests under a heading of its own, as in exports that title each entry
+pub fn demote_headings(markdown: &str, levels: usize) -> String {
+    let mut edits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+
+    for (event, range) in Parser::new_ext(markdown, markdown_options()).into_offset_iter() {
+        let Event::Start(Tag::Heading { level, .. }) = event else {
+            continue;
+        };
+        let new_level = (level as usize + levels).min(6);
+        let source = &markdown[range.clone()];
+        let indent = source.len() - source.trim_start().len();
+
+        if source[indent..].starts_with('#') {
+            // ATX heading: widen the marker
+            let start = range.start + indent;
+            let marker = source[indent..].chars().take_while(|c| *c == '#').count();
+            edits.push((start..start + marker, "#".repeat(new_level)));
+        } else {
+            // Setext heading: rewrite as a single ATX line, dropping the
+            // underline. Continuation lines repeat any container prefix
+            // (such as `> `), which must not end up in the title.
+            let line_start = markdown[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            let prefix = markdown[line_start..range.start].trim();
+            let source = source.trim_end();
+            let lines: Vec<&str> = source.lines().collect();
+            let title = lines[..lines.len().saturating_sub(1)]
+                .iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    let line = line.trim_start();
+                    if i == 0 { line } else { line.strip_prefix(prefix).unwrap_or(line) }.trim()
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let end = range.start + source.len();
+            edits.push((range.start..end, format!("{} {}", "#".repeat(new_level), title)));
+        }
+    }
+
+    let mut result = markdown.to_string();
+    for (range, replacement) in edits.into_iter().rev() {
+        result.replace_range(range, &replacement);
+    }
+    result
+}
+
+/// Escape text for use in HTML built around rendered content
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_html_strips_scripts_and_event_handlers() {
+        let html = render_html("Hi <script>alert(1)</script>\n\n<img src=\"cat.png\" onerror=\"alert(2)\">");
+        assert!(!html.contains("<script") && !html.contains("alert(1)"), "{}", html);
+        assert!(!html.contains("onerror") && !html.contains("alert(2)"), "{}", html);
+        assert!(html.contains("<img src=\"cat.png\">"), "{}", html);
+    }
+
+    #[test]
+    fn render_html_keeps_only_safe_links() {
+        let html = render_html("[click](javascript:alert(1)) <a href=\"javascript:alert(2)\">me</a>");
+        assert!(!html.contains("javascript"), "{}", html);
+        assert!(html.contains("click") && html.contains("me"), "{}", html);
+
+        let html = render_html("[site](https://example.com)");
+        assert_eq!(
+            html,
+            "<p><a href=\"https://example.com\" rel=\"noopener noreferrer nofollow\">site</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn render_html_only_renders_inputs_as_disabled_checkboxes() {
+        let html = render_html("- [x] done\n- [ ] todo");
+        assert_eq!(html.matches("type=\"checkbox\"").count(), 2, "{}", html);
+        assert_eq!(html.matches("checked").count(), 1, "{}", html);
+
+        let html = render_html("<input type=\"text\" name=\"password\" value=\"secret\">");
+        assert!(html.contains("type=\"checkbox\"") && html.contains("disabled"), "{}", html);
+        assert!(!html.contains("text") && !html.contains("secret") && !html.contains("name="), "{}", html);
+    }
+
+    #[test]
+    fn render_html_gives_headings_unique_anchors() {
+        let html = render_html("# Day one\n\n## Day one\n\n### !!!");
+        assert!(html.contains("<h1 id=\"day-one\">"), "{}", html);
+        assert!(html.contains("<h2 id=\"day-one-1\">"), "{}", html);
+        assert!(html.contains("<h3 id=\"section\">"), "{}", html);
+    }
+
+    #[test]
+    fn demote_headings_widens_atx_markers_up_to_six() {
+        assert_eq!(demote_headings("# Title\n\ntext\n\n##### Deep", 2), "### Title\n\ntext\n\n###### Deep");
+        assert_eq!(demote_headings("  ## Indented", 1), "  ### Indented");
+        assert_eq!(demote_headings("not # a heading", 1), "not # a heading");
+    }
+
+    #[test]
+    fn demote_headings_rewrites_setext_headings_as_one_line() {
+        assert_eq!(demote_headings("Title\n=====\n\ntext", 1), "## Title\n\ntext");
+        assert_eq!(demote_headings("First line\nsecond *line*\n---", 2), "#### First line second *line*");
+        assert_eq!(demote_headings("> Quote one\n> quote two\n> ===", 1), "> ## Quote one quote two");
+        assert_eq!(demote_headings("- Item a\n  item b\n  ---", 1), "- ### Item a item b");
+    }
+}
@@ -0,0 +1,468 @@
+// Structured mood: a primary emotion from a fixed taxonomy, optional secondary
+// emotions, an intensity and an energy level
+//
+// Stored on the entry as a map so it can be filtered and charted:
+//
+//   mood = M { primary_emotion: S, secondary_emotions: L of S, intensity: N, energy: N }
+//
+// Entries written before moods were structured hold a free-text string in
+// `mood`. Those are parsed on read with `Mood::from_legacy`; the
+// `migrate_moods` tool rewrites them in place so filters match them too.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::JournalError;
+
+pub const INTENSITY_MIN: u8 = 1;
+pub const INTENSITY_MAX: u8 = 5;
+pub const ENERGY_MIN: u8 = 1;
+pub const ENERGY_MAX: u8 = 5;
+/// Intensity assumed when none is given
+pub const DEFAULT_INTENSITY: u8 = 3;
+pub const MAX_SECONDARY_EMOTIONS: usize = 3;
+
+/// Whether an emotion is pleasant, unpleasant or neither
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Valence {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+impl Valence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Valence::Positive => "positive",
+            Valence::Negative => "negative",
+            Valence::Neutral => "neutral",
+        }
+    }
+}
+
+/// Emotions a mood can be built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Emotion {
+    Joy,
+    Gratitude,
+    Love,
+    Pride,
+    Hope,
+    Excitement,
+    Contentment,
+    Calm,
+    Surprise,
+    Nostalgia,
+    Confusion,
+    Boredom,
+    Indifference,
+    Tiredness,
+    Sadness,
+    Loneliness,
+    Disappointment,
+    Anxiety,
+    Fear,
+    Stress,
+    Frustration,
+    Anger,
+    Guilt,
+    Shame,
+}
+
+impl Emotion {
+    pub const ALL: [Emotion; 24] = [
+        Emotion::Joy,
+        Emotion::Gratitude,
+        Emotion::Love,
+        Emotion::Pride,
+        Emotion::Hope,
+        Emotion::Excitement,
+        Emotion::Contentment,
+        Emotion::Calm,
+        Emotion::Surprise,
+        Emotion::Nostalgia,
+        Emotion::Confusion,
+        Emotion::Boredom,
+        Emotion::Indifference,
+        Emotion::Tiredness,
+        Emotion::Sadness,
+        Emotion::Loneliness,
+        Emotion::Disappointment,
+        Emotion::Anxiety,
+        Emotion::Fear,
+        Emotion::Stress,
+        Emotion::Frustration,
+        Emotion::Anger,
+        Emotion::Guilt,
+        Emotion::Shame,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Emotion::Joy => "joy",
+            Emotion::Gratitude => "gratitude",
+            Emotion::Love => "love",
+            Emotion::Pride => "pride",
+            Emotion::Hope => "hope",
+            Emotion::Excitement => "excitement",
+            Emotion::Contentment => "contentment",
+            Emotion::Calm => "calm",
+            Emotion::Surprise => "surprise",
+            Emotion::Nostalgia => "nostalgia",
+            Emotion::Confusion => "confusion",
+            Emotion::Boredom => "boredom",
+            Emotion::Indifference => "indifference",
+            Emotion::Tiredness => "tiredness",
+            Emotion::Sadness => "sadness",
+            Emotion::Loneliness => "loneliness",
+            Emotion::Disappointment => "disappointment",
+            Emotion::Anxiety => "anxiety",
+            Emotion::Fear => "fear",
+            Emotion::Stress => "stress",
+            Emotion::Frustration => "frustration",
+            Emotion::Anger => "anger",
+            Emotion::Guilt => "guilt",
+            Emotion::Shame => "shame",
+        }
+    }
+
+    pub fn valence(&self) -> Valence {
+        match self {
+            Emotion::Joy
+            | Emotion::Gratitude
+            | Emotion::Love
+            | Emotion::Pride
+            | Emotion::Hope
+            | Emotion::Excitement
+            | Emotion::Contentment
+            | Emotion::Calm => Valence::Positive,
+            Emotion::Surprise
+            | Emotion::Nostalgia
+            | Emotion::Confusion
+            | Emotion::Boredom
+            | Emotion::Indifference => Valence::Neutral,
+            Emotion::Tiredness
+            | Emotion::Sadness
+            | Emotion::Loneliness
+            | Emotion::Disappointment
+            | Emotion::Anxiety
+            | Emotion::Fear
+            | Emotion::Stress
+            | Emotion::Frustration
+            | Emotion::Anger
+            | Emotion::Guilt
+            | Emotion::Shame => Valence::Negative,
+        }
+    }
+
+    /// Parse an emotion name or one of the everyday words people use for it
+    /// ("happy", "worried", ...)
+    pub fn parse(word: &str) -> Option<Emotion> {
+        let word = word.trim().to_lowercase();
+        Emotion::ALL
+            .iter()
+            .copied()
+            .find(|e| e.as_str() == word)
+            .or_else(|| EMOTION_WORDS.iter().find(|(w, _)| *w == word).map(|(_, e)| *e))
+    }
+}
+
+impl fmt::Display for Emotion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Everyday mood words mapped onto the taxonomy, used for search and for
+// migrating free-text moods
+const EMOTION_WORDS: &[(&str, Emotion)] = &[
+    ("happy", Emotion::Joy),
+    ("joyful", Emotion::Joy),
+    ("great", Emotion::Joy),
+    ("cheerful", Emotion::Joy),
+    ("grateful", Emotion::Gratitude),
+    ("thankful", Emotion::Gratitude),
+    ("blessed", Emotion::Gratitude),
+    ("loved", Emotion::Love),
+    ("loving", Emotion::Love),
+    ("proud", Emotion::Pride),
+    ("accomplished", Emotion::Pride),
+    ("hopeful", Emotion::Hope),
+    ("optimistic", Emotion::Hope),
+    ("excited", Emotion::Excitement),
+    ("amazing", Emotion::Excitement),
+    ("energetic", Emotion::Excitement),
+    ("content", Emotion::Contentment),
+    ("good", Emotion::Contentment),
+    ("fine", Emotion::Contentment),
+    ("satisfied", Emotion::Contentment),
+    ("relaxed", Emotion::Calm),
+    ("peaceful", Emotion::Calm),
+    ("serene", Emotion::Calm),
+    ("surprised", Emotion::Surprise),
+    ("shocked", Emotion::Surprise),
+    ("nostalgic", Emotion::Nostalgia),
+    ("confused", Emotion::Confusion),
+    ("uncertain", Emotion::Confusion),
+    ("bored", Emotion::Boredom),
+    ("neutral", Emotion::Indifference),
+    ("meh", Emotion::Indifference),
+    ("okay", Emotion::Indifference),
+    ("ok", Emotion::Indifference),
+    ("tired", Emotion::Tiredness),
+    ("exhausted", Emotion::Tiredness),
+    ("sleepy", Emotion::Tiredness),
+    ("drained", Emotion::Tiredness),
+    ("sad", Emotion::Sadness),
+    ("unhappy", Emotion::Sadness),
+    ("down", Emotion::Sadness),
+    ("depressed", Emotion::Sadness),
+    ("blue", Emotion::Sadness),
+    ("lonely", Emotion::Loneliness),
+    ("isolated", Emotion::Loneliness),
+    ("disappointed", Emotion::Disappointment),
+    ("anxious", Emotion::Anxiety),
+    ("worried", Emotion::Anxiety),
+    ("nervous", Emotion::Anxiety),
+    ("scared", Emotion::Fear),
+    ("afraid", Emotion::Fear),
+    ("stressed", Emotion::Stress),
+    ("overwhelmed", Emotion::Stress),
+    ("frustrated", Emotion::Frustration),
+    ("annoyed", Emotion::Frustration),
+    ("irritated", Emotion::Frustration),
+    ("angry", Emotion::Anger),
+    ("mad", Emotion::Anger),
+    ("furious", Emotion::Anger),
+    ("guilty", Emotion::Guilt),
+    ("ashamed", Emotion::Shame),
+    ("embarrassed", Emotion::Shame),
+];
+
+/// A self-reported mood
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mood {
+    pub primary_emotion: Emotion,
+    #[serde(default)]
+    pub secondary_emotions: Vec<Emotion>,
+    /// How strongly the primary emotion is felt, INTENSITY_MIN to INTENSITY_MAX
+    pub intensity: u8,
+    /// Physical energy, ENERGY_MIN (drained) to ENERGY_MAX (energized)
+    pub energy: Option<u8>,
+}
+
+/// Mood as accepted by the API: either the structured form, or a free-text
+/// mood from older clients that is mapped onto the taxonomy
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum MoodInput {
+    Text(String),
+    Structured {
+        primary_emotion: String,
+        #[serde(default)]
+        secondary_emotions: Vec<String>,
+        intensity: Option<i64>,
+        energy: Option<i64>,
+    },
+}
+
+impl MoodInput {
+    /// Validate the input and turn it into a mood
+    pub fn into_mood(self) -> Result<Mood, JournalError> {
+        let unknown = |name: &str| {
+            JournalError::ValidationError(format!(
+                "Unknown emotion '{}'; expected one of: {}",
+                name,
+                Emotion::ALL.iter().map(Emotion::as_str).collect::<Vec<_>>().join(", ")
+            ))
+        };
+
+        match self {
+            MoodInput::Text(text) => Mood::from_legacy(&text).ok_or_else(|| unknown(text.trim())),
+            MoodInput::Structured { primary_emotion, secondary_emotions, intensity, energy } => {
+                let primary_emotion = Emotion::parse(&primary_emotion).ok_or_else(|| unknown(&primary_emotion))?;
+                let secondary_emotions = secondary_emotions
+                    .iter()
+                    .map(|name| Emotion::parse(name).ok_or_else(|| unknown(name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let in_range = |value: i64, min: u8, max: u8, name: &str| {
+                    u8::try_from(value)
+                        .ok()
+                        .filter(|v| (min..=max).contains(v))
+                        .ok_or_else(|| {
+                            JournalError::ValidationError(format!("{} must be from {} to {}", name, min, max))
+                        })
+                };
+
+                let mood = Mood {
+                    primary_emotion,
+                    secondary_emotions,
+                    intensity: match intensity {
+                        Some(i) => in_range(i, INTENSITY_MIN, INTENSITY_MAX, "Intensity")?,
+                        None => DEFAULT_INTENSITY,
+                    },
+                    energy: energy.map(|e| in_range(e, ENERGY_MIN, ENERGY_MAX, "Energy")).transpose()?,
+                };
+                mood.validate()?;
+                Ok(mood)
+            }
+        }
+    }
+}
+
+impl Mood {
+    pub fn validate(&self) -> Result<(), JournalError> {
+        if !(INTENSITY_MIN..=INTENSITY_MAX).contains(&self.intensity) {
+            return Err(JournalError::ValidationError(format!(
+                "Intensity must be from {} to {}",
+                INTENSITY_MIN, INTENSITY_MAX
+            )));
+        }
+        if self.energy.is_some_and(|e| !(ENERGY_MIN..=ENERGY_MAX).contains(&e)) {
+            return Err(JournalError::ValidationError(format!(
+                "Energy must be from {} to {}",
+                ENERGY_MIN, ENERGY_MAX
+            )));
+        }
+        if self.secondary_emotions.len() > MAX_SECONDARY_EMOTIONS {
+            return Err(JournalError::ValidationError(format!(
+                "At most {} secondary emotions are allowed",
+                MAX_SECONDARY_EMOTIONS
+            )));
+        }
+        for (i, emotion) in self.secondary_emotions.iter().enumerate() {
+            if *emotion == self.primary_emotion || self.secondary_emotions[..i].contains(emotion) {
+                return Err(JournalError::ValidationError(format!(
+                    "Emotion '{}' is listed more than once",
+                    emotion
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn valence(&self) -> Valence {
+        self.primary_emotion.valence()
+    }
+
+    /// Map a free-text mood such as "very happy" or "tired but grateful" onto
+    /// the taxonomy. The first recognised emotion becomes the primary one and
+    /// intensity words ("slightly", "very", "extremely") set the intensity.
+    pub fn from_legacy(text: &str) -> Option<Mood> {
+        let lower = text.to_lowercase();
+        let words: Vec<&str> = lower
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        let mut emotions: Vec<Emotion> = Vec::new();
+        for word in &words {
+            if let Some(emotion) = Emotion::parse(word) {
+                if !emotions.contains(&emotion) {
+                    emotions.push(emotion);
+                }
+            }
+        }
+        if emotions.is_empty() {
+            return None;
+        }
+
+        let has_any = |list: &[&str]| words.iter().any(|w| list.contains(w));
+        let intensity = if has_any(&["extremely", "incredibly", "totally", "completely"]) {
+            5
+        } else if has_any(&["very", "really", "so", "super"]) {
+            4
+        } else if has_any(&["slightly", "bit", "little", "somewhat", "kinda"]) {
+            2
+        } else {
+            DEFAULT_INTENSITY
+        };
+
+        let energy = if has_any(&["exhausted", "drained", "sleepy"]) {
+            Some(ENERGY_MIN)
+        } else if has_any(&["energetic", "energized", "pumped"]) {
+            Some(ENERGY_MAX)
+        } else {
+            None
+        };
+
+        Some(Mood {
+            primary_emotion: emotions[0],
+            secondary_emotions: emotions.into_iter().skip(1).take(MAX_SECONDARY_EMOTIONS).collect(),
+            intensity,
+            energy,
+        })
+    }
+
+    pub fn to_attribute(&self) -> AttributeValue {
+        let mut map = HashMap::new();
+        map.insert("primary_emotion".to_string(), AttributeValue::S(self.primary_emotion.as_str().to_string()));
+        if !self.secondary_emotions.is_empty() {
+            map.insert(
+                "secondary_emotions".to_string(),
+                AttributeValue::L(
+                    self.secondary_emotions.iter().map(|e| AttributeValue::S(e.as_str().to_string())).collect(),
+                ),
+            );
+        }
+        map.insert("intensity".to_string(), AttributeValue::N(self.intensity.to_string()));
+        if let Some(energy) = self.energy {
+            map.insert("energy".to_string(), AttributeValue::N(energy.to_string()));
+        }
+        AttributeValue::M(map)
+    }
+
+    /// Read a stored mood, parsing legacy free-text moods
+    pub fn from_attribute(value: &AttributeValue) -> Option<Mood> {
+        match value {
+            AttributeValue::S(text) => Mood::from_legacy(text),
+            AttributeValue::M(map) => {
+                let primary_emotion = map.get("primary_emotion").and_then(|v| v.as_s().ok()).and_then(|s| Emotion::parse(s))?;
+                let get_n = |key: &str| map.get(key).and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<u8>().ok());
+
+                Some(Mood {
+                    primary_emotion,
+                    secondary_emotions: map
+                        .get("secondary_emotions")
+                        .and_then(|v| v.as_l().ok())
+                        .map(|list| list.iter().filter_map(|e| e.as_s().ok()).filter_map(|s| Emotion::parse(s)).collect())
+                        .unwrap_or_default(),
+                    intensity: get_n("intensity").unwrap_or(DEFAULT_INTENSITY),
+                    energy: get_n("energy"),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Mood {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}/{})", self.primary_emotion, self.intensity, INTENSITY_MAX)?;
+        if !self.secondary_emotions.is_empty() {
+            let others: Vec<&str> = self.secondary_emotions.iter().map(Emotion::as_str).collect();
+            write!(f, ", also {}", others.join(", "))?;
+        }
+        if let Some(energy) = self.energy {
+            write!(f, "; energy {}/{}", energy, ENERGY_MAX)?;
+        }
+        Ok(())
+    }
+}
+
+/// Mood of an entry item, if it has one
+pub fn entry_mood(item: &HashMap<String, AttributeValue>) -> Option<Mood> {
+    item.get("mood").and_then(Mood::from_attribute)
+}
+
+/// Filter expression matching entries that felt `emotion`, as the primary or a
+/// secondary emotion, binding `:mood`
+pub fn mood_filter_expression(emotion: Emotion, expression_values: &mut HashMap<String, AttributeValue>) -> String {
+    expression_values.insert(":mood".to_string(), AttributeValue::S(emotion.as_str().to_string()));
+    "(mood.primary_emotion = :mood OR contains(mood.secondary_emotions, :mood))".to_string()
+}
@@ -0,0 +1,143 @@
+// Notebooks: separate journals that each hold part of a user's entries
+//
+// Every entry belongs to exactly one notebook through its `notebook_id`
+// attribute. Each user has an implicit default notebook with the fixed id
+// "default"; entries written before notebooks existed carry no notebook_id and
+// belong to it. The default notebook is only stored once its settings are
+// changed, and it can never be deleted.
+//
+// Notebooks are keyed like categories: (id, tenant_id), listed per user through
+// the UserIndex GSI on (tenant_id, user_id).
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::JournalError;
+
+/// Id of the notebook that holds entries without an explicit notebook
+pub const DEFAULT_NOTEBOOK_ID: &str = "default";
+
+// Stored id prefix of a user's default notebook
+const DEFAULT_ITEM_PREFIX: &str = "default#";
+
+/// A notebook and its per-notebook settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notebook {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Template offered when writing a new entry in this notebook
+    pub default_template_id: Option<String>,
+    /// Tags applied to new entries that are created without tags
+    pub default_tags: Vec<String>,
+    /// Whether entries in this notebook are sent for AI analysis
+    pub ai_enabled: bool,
+    /// Archived notebooks stay readable but accept no new entries
+    pub archived: bool,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl Notebook {
+    /// The implicit notebook every user starts with
+    pub fn default_notebook() -> Self {
+        Notebook {
+            id: DEFAULT_NOTEBOOK_ID.to_string(),
+            name: "Journal".to_string(),
+            description: None,
+            default_template_id: None,
+            default_tags: Vec::new(),
+            ai_enabled: true,
+            archived: false,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+pub fn notebooks_table() -> String {
+    std::env::var("NOTEBOOKS_TABLE").unwrap_or_else(|_| "reflekt-notebooks".to_string())
+}
+
+/// Notebook an entry item belongs to
+pub fn entry_notebook_id(item: &HashMap<String, AttributeValue>) -> &str {
+    item.get("notebook_id")
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_NOTEBOOK_ID)
+}
+
+/// Filter expression selecting entries of one notebook, binding `:notebook_id`.
+/// Entries without a notebook_id belong to the default notebook.
+pub fn notebook_filter_expression(
+    notebook_id: &str,
+    expression_values: &mut HashMap<String, AttributeValue>,
+) -> String {
+    expression_values.insert(":notebook_id".to_string(), AttributeValue::S(notebook_id.to_string()));
+    if notebook_id == DEFAULT_NOTEBOOK_ID {
+        "(attribute_not_exists(notebook_id) OR notebook_id = :notebook_id)".to_string()
+    } else {
+        "notebook_id = :notebook_id".to_string()
+    }
+}
+
+pub fn item_to_notebook(item: &HashMap<String, AttributeValue>) -> Notebook {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+    let get_bool = |key: &str| item.get(key).and_then(|v| v.as_bool().ok()).copied();
+
+    Notebook {
+        id: get_s("id")
+            .map(|id| if id.starts_with(DEFAULT_ITEM_PREFIX) { DEFAULT_NOTEBOOK_ID.to_string() } else { id })
+            .unwrap_or_default(),
+        name: get_s("name").unwrap_or_default(),
+        description: get_s("description"),
+        default_template_id: get_s("default_template_id"),
+        default_tags: item.get("default_tags").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default(),
+        ai_enabled: get_bool("ai_enabled").unwrap_or(true),
+        archived: get_bool("archived").unwrap_or(false),
+        created_at: get_s("created_at"),
+        updated_at: get_s("updated_at"),
+    }
+}
+
+/// Load one of the user's notebooks. The default notebook is returned with
+/// its initial settings when it has never been stored.
+pub async fn get_notebook(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    notebook_id: &str,
+) -> Result<Option<Notebook>, JournalError> {
+    let item_id = notebook_item_id(user_id, notebook_id);
+
+    let response = client
+        .get_item()
+        .table_name(notebooks_table())
+        .key("id", AttributeValue::S(item_id))
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to fetch notebook: {}", e)))?;
+
+    match response.item {
+        Some(item) if item.get("user_id").and_then(|v| v.as_s().ok()).map(String::as_str) == Some(user_id) => {
+            Ok(Some(item_to_notebook(&item)))
+        }
+        Some(_) => Ok(None),
+        None if notebook_id == DEFAULT_NOTEBOOK_ID => Ok(Some(Notebook::default_notebook())),
+        None => Ok(None),
+    }
+}
+
+/// Key under which a notebook is stored. Notebook ids are UUIDs except for
+/// the default notebook, whose stored id is scoped to the user because ids
+/// only need to be unique within a tenant.
+pub fn notebook_item_id(user_id: &str, notebook_id: &str) -> String {
+    if notebook_id == DEFAULT_NOTEBOOK_ID {
+        format!("{}{}", DEFAULT_ITEM_PREFIX, user_id)
+    } else {
+        notebook_id.to_string()
+    }
+}
@@ -0,0 +1,545 @@
+// Versioned prompt templates for AI analysis
+//
+// The prompts sent to language models are named, versioned templates kept in
+// the prompt templates table (PROMPT_TEMPLATES_TABLE), so they can be changed,
+// compared and rolled back without a deploy:
+//
+//   scope          template_key
+//   GLOBAL         <name>#V#<version>   one version: system, prompt, description, created_at
+//   GLOBAL         <name>#ACTIVE        the version in use and an optional candidate
+//   TENANT#<id>    the same             a tenant's own versions and choice, overriding GLOBAL
+//
+// Versions are numbered from 1 within their scope and never changed once
+// written. A tenant with an ACTIVE item uses its own versions; other tenants
+// use the global ones. An ACTIVE item can send `candidate_percent` of entries
+// to `candidate_version` for A/B tests, assigned by a hash of the entry id so
+// re-analysis stays on the same arm. Without an ACTIVE item, or when the
+// table can't be read, the built-in version 0 is used.
+//
+// Templates hold {{variable}} placeholders: the entry's title, content,
+// created_at, tags and word_count, and the user's language and tone.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::{JournalError, ANALYSIS_SYSTEM_PROMPT};
+
+/// Template for entry analyses by the AI service
+pub const ANALYSIS_TEMPLATE: &str = "entry-analysis";
+pub const GLOBAL_TEMPLATE_SCOPE: &str = "GLOBAL";
+/// Variables available to analysis templates
+pub const ANALYSIS_TEMPLATE_VARIABLES: &[&str] =
+    &["title", "content", "created_at", "tags", "word_count", "language", "tone"];
+/// Tones a user can ask insights to be written in
+pub const INSIGHT_TONES: &[&str] = &["gentle", "encouraging", "direct", "neutral"];
+pub const DEFAULT_INSIGHT_TONE: &str = "gentle";
+pub const DEFAULT_TEMPLATE_LANGUAGE: &str = "en";
+pub const MAX_TEMPLATE_LENGTH: usize = 8000;
+const TEMPLATE_NAMES: &[&str] = &[ANALYSIS_TEMPLATE];
+
+fn templates_table() -> String {
+    std::env::var("PROMPT_TEMPLATES_TABLE").unwrap_or_else(|_| "reflekt-prompt-templates".to_string())
+}
+
+/// Scope holding a tenant's own templates
+pub fn tenant_template_scope(tenant_id: &str) -> String {
+    format!("TENANT#{}", tenant_id)
+}
+
+fn version_key(name: &str, version: u32) -> String {
+    // Zero-padded so versions sort numerically
+    format!("{}#V#{:06}", name, version)
+}
+
+fn active_key(name: &str) -> String {
+    format!("{}#ACTIVE", name)
+}
+
+/// Whether a template name is one the services use
+pub fn is_known_template(name: &str) -> bool {
+    TEMPLATE_NAMES.contains(&name)
+}
+
+/// One version of a prompt template
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: u32,
+    /// "builtin", "global" or "tenant"
+    pub source: String,
+    pub system: String,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl PromptTemplate {
+    fn from_item(item: &HashMap<String, AttributeValue>, source: &str) -> Option<Self> {
+        let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+        Some(PromptTemplate {
+            name: get_s("name")?,
+            version: item.get("version").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok())?,
+            source: source.to_string(),
+            system: get_s("system")?,
+            prompt: get_s("prompt")?,
+            description: get_s("description"),
+            created_at: get_s("created_at"),
+        })
+    }
+
+    /// Render the system prompt and user prompt with the given variables
+    pub fn render(&self, variables: &HashMap<&str, String>) -> (String, String) {
+        (render_template(&self.system, variables), render_template(&self.prompt, variables))
+    }
+}
+
+/// The built-in version 0 of a template, used when no version is active
+pub fn builtin_template(name: &str) -> Option<PromptTemplate> {
+    match name {
+        ANALYSIS_TEMPLATE => Some(PromptTemplate {
+            name: ANALYSIS_TEMPLATE.to_string(),
+            version: 0,
+            source: "builtin".to_string(),
+            system: format!(
+                "{} Write the insights and reflections in the language with code \"{{{{language}}}}\", \
+                 in a {{{{tone}}}} tone.",
+                ANALYSIS_SYSTEM_PROMPT
+            ),
+            prompt: "Title: {{title}}\n\nContent: {{content}}".to_string(),
+            description: Some("Built-in analysis prompt".to_string()),
+            created_at: None,
+        }),
+        _ => None,
+    }
+}
+
+/// Names of the {{variable}} placeholders in a template, in order
+pub fn template_variables(text: &str) -> Vec<&str> {
+    let mut variables = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        variables.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    variables
+}
+
+/// Replace {{variable}} placeholders; unknown variables render as nothing
+pub fn render_template(text: &str, variables: &HashMap<&str, String>) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        if let Some(value) = variables.get(after[..end].trim()) {
+            rendered.push_str(value);
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Check a new template version before it is stored
+pub fn validate_template(name: &str, system: &str, prompt: &str) -> Result<(), JournalError> {
+    if !is_known_template(name) {
+        return Err(JournalError::ValidationError(format!("Unknown template: {}", name)));
+    }
+    if system.trim().is_empty() || prompt.trim().is_empty() {
+        return Err(JournalError::ValidationError("system and prompt must not be empty".into()));
+    }
+    if system.len() + prompt.len() > MAX_TEMPLATE_LENGTH {
+        return Err(JournalError::ValidationError(format!(
+            "Templates are limited to {} characters",
+            MAX_TEMPLATE_LENGTH
+        )));
+    }
+    for variable in template_variables(system).into_iter().chain(template_variables(prompt)) {
+        if !ANALYSIS_TEMPLATE_VARIABLES.contains(&variable) {
+            return Err(JournalError::ValidationError(format!("Unknown template variable: {}", variable)));
+        }
+    }
+    if !template_variables(prompt).contains(&"content") {
+        return Err(JournalError::ValidationError("prompt must include {{content}}".into()));
+    }
+    Ok(())
+}
+
+/// The version a scope uses, and the candidate being tested against it
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateRollout {
+    pub version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_version: Option<u32>,
+    pub candidate_percent: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+impl TemplateRollout {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let get_n = |key: &str| item.get(key).and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<u32>().ok());
+        Some(TemplateRollout {
+            version: get_n("version")?,
+            candidate_version: get_n("candidate_version"),
+            candidate_percent: get_n("candidate_percent").unwrap_or(0).min(100) as u8,
+            updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).cloned(),
+        })
+    }
+
+    /// The version for an entry: the candidate for `candidate_percent` of
+    /// assignment keys, the active version for the rest
+    pub fn version_for(&self, name: &str, assignment_key: &str) -> u32 {
+        match self.candidate_version {
+            Some(candidate) if rollout_bucket(name, assignment_key) < self.candidate_percent => candidate,
+            _ => self.version,
+        }
+    }
+}
+
+// Stable bucket in 0..100 for an assignment key
+fn rollout_bucket(name: &str, assignment_key: &str) -> u8 {
+    let digest = Sha256::digest(format!("{}#{}", name, assignment_key).as_bytes());
+    (u16::from_be_bytes([digest[0], digest[1]]) % 100) as u8
+}
+
+/// One stored version of a template
+pub async fn get_template_version(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+    version: u32,
+) -> Result<Option<PromptTemplate>, JournalError> {
+    let response = client
+        .get_item()
+        .table_name(templates_table())
+        .key("scope", AttributeValue::S(scope.to_string()))
+        .key("template_key", AttributeValue::S(version_key(name, version)))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to load prompt template: {}", e)))?;
+
+    let source = if scope == GLOBAL_TEMPLATE_SCOPE { "global" } else { "tenant" };
+    Ok(response.item().and_then(|item| PromptTemplate::from_item(item, source)))
+}
+
+/// A scope's rollout of a template, if it has one
+pub async fn get_template_rollout(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+) -> Result<Option<TemplateRollout>, JournalError> {
+    let response = client
+        .get_item()
+        .table_name(templates_table())
+        .key("scope", AttributeValue::S(scope.to_string()))
+        .key("template_key", AttributeValue::S(active_key(name)))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to load prompt template rollout: {}", e)))?;
+
+    Ok(response.item().and_then(TemplateRollout::from_item))
+}
+
+/// All stored versions of a template in a scope, oldest first
+pub async fn list_template_versions(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+) -> Result<Vec<PromptTemplate>, JournalError> {
+    let source = if scope == GLOBAL_TEMPLATE_SCOPE { "global" } else { "tenant" };
+    let mut templates = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(templates_table())
+            .key_condition_expression("#scope = :scope AND begins_with(template_key, :prefix)")
+            .expression_attribute_names("#scope", "scope")
+            .expression_attribute_values(":scope", AttributeValue::S(scope.to_string()))
+            .expression_attribute_values(":prefix", AttributeValue::S(format!("{}#V#", name)))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to list prompt templates: {}", e)))?;
+
+        templates.extend(response.items().iter().filter_map(|item| PromptTemplate::from_item(item, source)));
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(templates)
+}
+
+/// Store a new version of a template in a scope, numbered after the latest
+pub async fn create_template_version(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+    system: &str,
+    prompt: &str,
+    description: Option<&str>,
+) -> Result<PromptTemplate, JournalError> {
+    validate_template(name, system, prompt)?;
+
+    let version = list_template_versions(client, scope, name)
+        .await?
+        .iter()
+        .map(|t| t.version)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut put = client
+        .put_item()
+        .table_name(templates_table())
+        .item("scope", AttributeValue::S(scope.to_string()))
+        .item("template_key", AttributeValue::S(version_key(name, version)))
+        .item("name", AttributeValue::S(name.to_string()))
+        .item("version", AttributeValue::N(version.to_string()))
+        .item("system", AttributeValue::S(system.to_string()))
+        .item("prompt", AttributeValue::S(prompt.to_string()))
+        .item("created_at", AttributeValue::S(now.clone()))
+        // Versions are immutable; a concurrent create gets the same number
+        .condition_expression("attribute_not_exists(template_key)");
+    if let Some(description) = description {
+        put = put.item("description", AttributeValue::S(description.to_string()));
+    }
+
+    if let Err(e) = put.send().await {
+        let e = e.into_service_error();
+        if e.is_conditional_check_failed_exception() {
+            return Err(JournalError::ValidationError(format!(
+                "Version {} of {} was created concurrently; try again",
+                version, name
+            )));
+        }
+        return Err(JournalError::DatabaseError(format!("Failed to save prompt template: {}", e)));
+    }
+
+    let source = if scope == GLOBAL_TEMPLATE_SCOPE { "global" } else { "tenant" };
+    Ok(PromptTemplate {
+        name: name.to_string(),
+        version,
+        source: source.to_string(),
+        system: system.to_string(),
+        prompt: prompt.to_string(),
+        description: description.map(String::from),
+        created_at: Some(now),
+    })
+}
+
+/// Choose the version a scope uses, and optionally a candidate to test.
+/// Both must exist in the scope.
+pub async fn set_template_rollout(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+    version: u32,
+    candidate: Option<(u32, u8)>,
+) -> Result<TemplateRollout, JournalError> {
+    if !is_known_template(name) {
+        return Err(JournalError::ValidationError(format!("Unknown template: {}", name)));
+    }
+    if let Some((_, percent)) = candidate {
+        if percent > 100 {
+            return Err(JournalError::ValidationError("candidate_percent must be between 0 and 100".into()));
+        }
+    }
+    for version in std::iter::once(version).chain(candidate.map(|(v, _)| v)) {
+        if get_template_version(client, scope, name, version).await?.is_none() {
+            return Err(JournalError::NotFoundError(format!("Version {} of {} not found", version, name)));
+        }
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut put = client
+        .put_item()
+        .table_name(templates_table())
+        .item("scope", AttributeValue::S(scope.to_string()))
+        .item("template_key", AttributeValue::S(active_key(name)))
+        .item("version", AttributeValue::N(version.to_string()))
+        .item("updated_at", AttributeValue::S(now.clone()));
+    if let Some((candidate_version, percent)) = candidate {
+        put = put
+            .item("candidate_version", AttributeValue::N(candidate_version.to_string()))
+            .item("candidate_percent", AttributeValue::N(percent.to_string()));
+    }
+    put.send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to save prompt template rollout: {}", e)))?;
+
+    Ok(TemplateRollout {
+        version,
+        candidate_version: candidate.map(|(v, _)| v),
+        candidate_percent: candidate.map(|(_, p)| p).unwrap_or(0),
+        updated_at: Some(now),
+    })
+}
+
+/// Remove a scope's rollout; a tenant then goes back to the global templates
+pub async fn delete_template_rollout(
+    client: &DynamoDbClient,
+    scope: &str,
+    name: &str,
+) -> Result<(), JournalError> {
+    client
+        .delete_item()
+        .table_name(templates_table())
+        .key("scope", AttributeValue::S(scope.to_string()))
+        .key("template_key", AttributeValue::S(active_key(name)))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to delete prompt template rollout: {}", e)))?;
+    Ok(())
+}
+
+/// The template version to use for one entry of a tenant. A template that
+/// can't be loaded falls back to the built-in version rather than failing.
+pub async fn resolve_template(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    name: &str,
+    assignment_key: &str,
+) -> Result<PromptTemplate, JournalError> {
+    let builtin = builtin_template(name)
+        .ok_or_else(|| JournalError::ConfigurationError(format!("Unknown template: {}", name)))?;
+
+    let tenant_scope = tenant_template_scope(tenant_id);
+    let (tenant_rollout, global_rollout) = tokio::join!(
+        get_template_rollout(client, &tenant_scope, name),
+        get_template_rollout(client, GLOBAL_TEMPLATE_SCOPE, name)
+    );
+    let rollout = match (tenant_rollout, global_rollout) {
+        (Ok(Some(rollout)), _) => Some((tenant_scope.as_str(), rollout)),
+        (Ok(None), Ok(Some(rollout))) => Some((GLOBAL_TEMPLATE_SCOPE, rollout)),
+        (Ok(None), Ok(None)) => None,
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::warn!("Failed to load {} rollout, using the built-in template: {}", name, e);
+            None
+        }
+    };
+
+    let Some((scope, rollout)) = rollout else {
+        return Ok(builtin);
+    };
+    let version = rollout.version_for(name, assignment_key);
+    match get_template_version(client, scope, name, version).await {
+        Ok(Some(template)) => Ok(template),
+        Ok(None) => {
+            tracing::warn!("Version {} of {} is missing from {}, using the built-in template", version, name, scope);
+            Ok(builtin)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load version {} of {}, using the built-in template: {}", version, name, e);
+            Ok(builtin)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollout(candidate_version: Option<u32>, candidate_percent: u8) -> TemplateRollout {
+        TemplateRollout { version: 1, candidate_version, candidate_percent, updated_at: None }
+    }
+
+    fn entry_ids() -> Vec<String> {
+        (0..1000).map(|i| format!("entry-{}", i)).collect()
+    }
+
+    #[test]
+    fn variables_are_listed_in_order() {
+        assert_eq!(
+            template_variables("{{title}}: {{ content }} ({{tags}}) {{unclosed"),
+            vec!["title", "content", "tags"]
+        );
+        assert!(template_variables("no placeholders").is_empty());
+    }
+
+    #[test]
+    fn render_fills_known_variables_and_drops_unknown_ones() {
+        let variables = HashMap::from([("title", "Walk".to_string()), ("content", "By the {{river}}".to_string())]);
+        assert_eq!(
+            render_template("{{title}} - {{ content }} {{mood}}!", &variables),
+            "Walk - By the {{river}} !"
+        );
+        assert_eq!(render_template("Open {{title", &variables), "Open {{title");
+    }
+
+    #[test]
+    fn builtin_template_renders_language_and_tone() {
+        let template = builtin_template(ANALYSIS_TEMPLATE).unwrap();
+        let variables = HashMap::from([
+            ("title", "Walk".to_string()),
+            ("content", "By the river".to_string()),
+            ("language", "de".to_string()),
+            ("tone", "direct".to_string()),
+        ]);
+        let (system, prompt) = template.render(&variables);
+        assert!(system.contains("language with code \"de\", in a direct tone"));
+        assert_eq!(prompt, "Title: Walk\n\nContent: By the river");
+        assert!(validate_template(ANALYSIS_TEMPLATE, &template.system, &template.prompt).is_ok());
+        assert!(builtin_template("unknown").is_none());
+    }
+
+    #[test]
+    fn validate_rejects_bad_templates() {
+        let valid = |system: &str, prompt: &str| validate_template(ANALYSIS_TEMPLATE, system, prompt).is_ok();
+
+        assert!(valid("Be {{tone}}.", "{{title}}\n{{content}}"));
+        assert!(!valid("Be kind.", "{{title}} only"));
+        assert!(!valid("Be {{mood}}.", "{{content}}"));
+        assert!(!valid("  ", "{{content}}"));
+        assert!(!valid("Be kind.", &format!("{{{{content}}}}{}", "x".repeat(MAX_TEMPLATE_LENGTH))));
+        assert!(validate_template("other-template", "Be kind.", "{{content}}").is_err());
+    }
+
+    #[test]
+    fn rollout_without_candidate_uses_the_active_version() {
+        assert!(entry_ids().iter().all(|id| rollout(None, 50).version_for(ANALYSIS_TEMPLATE, id) == 1));
+        assert!(entry_ids().iter().all(|id| rollout(Some(2), 0).version_for(ANALYSIS_TEMPLATE, id) == 1));
+        assert!(entry_ids().iter().all(|id| rollout(Some(2), 100).version_for(ANALYSIS_TEMPLATE, id) == 2));
+    }
+
+    #[test]
+    fn rollout_sends_about_the_candidate_percent_to_the_candidate() {
+        let candidates = entry_ids()
+            .iter()
+            .filter(|id| rollout(Some(2), 20).version_for(ANALYSIS_TEMPLATE, id) == 2)
+            .count();
+        assert!((150..=250).contains(&candidates), "{} of 1000 entries got the candidate", candidates);
+    }
+
+    #[test]
+    fn rollout_assignment_is_stable_and_grows_with_the_percent() {
+        for id in entry_ids() {
+            let bucket = rollout_bucket(ANALYSIS_TEMPLATE, &id);
+            assert!(bucket < 100);
+            assert_eq!(bucket, rollout_bucket(ANALYSIS_TEMPLATE, &id));
+
+            // An entry on the candidate stays there as the percent increases
+            if rollout(Some(2), 10).version_for(ANALYSIS_TEMPLATE, &id) == 2 {
+                assert_eq!(rollout(Some(2), 50).version_for(ANALYSIS_TEMPLATE, &id), 2);
+            }
+        }
+    }
+}
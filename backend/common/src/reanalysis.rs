@@ -0,0 +1,64 @@
+// Re-analysis of entries and content hashing for AI insights
+//
+// When the AI service analyzes an entry it stores `analysis_hash`, a SHA-256
+// of the title and content it analyzed. Events for an entry whose text still
+// hashes the same are skipped, so edits to tags, mood or flags don't pay for
+// another model call. AnalysisRequested events with `force` set bypass that
+// check; they come from POST /entries/{id}/insights/regenerate. The backfill
+// tool sends unforced AnalysisRequested events for entries that were never
+// analyzed or whose text changed since.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::{attribute_strings, entry_notebook_id, publish_event, JournalError};
+
+/// Event asking the AI service to analyze an existing entry
+pub const ANALYSIS_REQUESTED: &str = "AnalysisRequested";
+/// analysis_status of an entry the AI service has analyzed
+pub const ANALYSIS_STATUS_ANALYZED: &str = "analyzed";
+/// analysis_status of an entry waiting for a requested re-analysis
+pub const ANALYSIS_STATUS_PENDING: &str = "pending";
+
+/// Hash of the text an analysis is based on, as lowercase hex
+pub fn analysis_content_hash(title: &str, content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(title.as_bytes());
+    // Keeps "ab" + "c" apart from "a" + "bc"
+    hasher.update([0u8]);
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn item_str<'a>(item: &'a HashMap<String, AttributeValue>, key: &str) -> &'a str {
+    item.get(key).and_then(|v| v.as_s().ok()).map(String::as_str).unwrap_or_default()
+}
+
+/// Whether an entry item lacks an analysis of its current text
+pub fn needs_analysis(item: &HashMap<String, AttributeValue>) -> bool {
+    item_str(item, "analysis_status") != ANALYSIS_STATUS_ANALYZED
+        || item_str(item, "analysis_hash") != analysis_content_hash(item_str(item, "title"), item_str(item, "content"))
+}
+
+/// Publish AnalysisRequested for an entry item. `ai_enabled` is the entry's
+/// notebook setting; `force` re-analyzes even unchanged text.
+pub async fn request_analysis(
+    item: &HashMap<String, AttributeValue>,
+    ai_enabled: bool,
+    force: bool,
+) -> Result<(), JournalError> {
+    let detail = serde_json::json!({
+        "entry_id": item_str(item, "id"),
+        "tenant_id": item_str(item, "tenant_id"),
+        "user_id": item_str(item, "user_id"),
+        "title": item_str(item, "title"),
+        "content": item_str(item, "content"),
+        "notebook_id": entry_notebook_id(item),
+        "ai_enabled": ai_enabled,
+        "force": force,
+        "tags": attribute_strings(item.get("tags")),
+        "created_at": item_str(item, "created_at"),
+    });
+    publish_event(ANALYSIS_REQUESTED, detail).await
+}
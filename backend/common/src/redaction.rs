@@ -0,0 +1,630 @@
+// Redaction of personal details before journal text is sent to AI providers
+//
+// Emails, phone numbers, street addresses, card numbers (Luhn-checked), IBANs
+// (mod-97-checked) and names the user configures are replaced with
+// placeholders such as [EMAIL_1] or [NAME_2]. Placeholders are stable within a
+// `Redactor`: the same value always gets the same placeholder, across the title
+// and content alike, so the model can still tell people apart. `restore` maps
+// placeholders in the model's reply back to the original text before it is
+// saved.
+//
+// Whether a user's text is redacted depends on AI_REDACTION:
+//
+// - auto (default): for users whose privacy_level setting is "strict"
+// - always, never
+//
+// Names come from the user's redaction_names setting.
+
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use std::collections::HashMap;
+
+use crate::{get_privacy_settings, JournalError, PrivacySettings};
+
+/// Most names a user can ask to have redacted
+pub const MAX_REDACTION_NAMES: usize = 100;
+/// Longest name that can be redacted, in characters
+pub const MAX_REDACTION_NAME_LENGTH: usize = 100;
+
+/// Tells the model how to treat placeholders; appended to the system prompt
+pub const REDACTION_INSTRUCTION: &str = "Personal details in the entry have been replaced with placeholders \
+    in square brackets, such as [NAME_1] or [EMAIL_1]. When you refer to them, use the placeholders exactly \
+    as written.";
+
+// Street types that follow the street name ("221B Baker Street")
+const STREET_SUFFIXES: &[&str] = &[
+    "street", "st", "avenue", "ave", "road", "rd", "lane", "ln", "drive", "dr", "boulevard", "blvd",
+    "way", "court", "ct", "place", "pl", "terrace", "close", "crescent", "square", "sq", "parkway",
+    "highway", "hwy",
+];
+// Endings of street names written before the house number ("Hauptstraße 5")
+const STREET_ENDINGS: &[&str] = &[
+    "straße", "strasse", "str", "weg", "gasse", "platz", "allee", "straat", "laan", "gatan", "vej",
+];
+// Capitalized street-name words allowed between a house number and the suffix
+const MAX_STREET_NAME_WORDS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PiiKind {
+    Email,
+    Phone,
+    Address,
+    Card,
+    Iban,
+    Name,
+}
+
+impl PiiKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "EMAIL",
+            PiiKind::Phone => "PHONE",
+            PiiKind::Address => "ADDRESS",
+            PiiKind::Card => "CARD",
+            PiiKind::Iban => "IBAN",
+            PiiKind::Name => "NAME",
+        }
+    }
+}
+
+/// A personal detail found in text, as a byte range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiiMatch {
+    pub kind: PiiKind,
+    pub start: usize,
+    pub end: usize,
+    // Normalized value, so different spellings of one value share a placeholder
+    key: String,
+}
+
+/// Replaces personal details with placeholders and maps them back
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    names: Vec<String>,
+    placeholders: HashMap<(PiiKind, String), String>,
+    // Placeholder and the text it replaced, in order of first appearance
+    originals: Vec<(String, String)>,
+    counts: HashMap<PiiKind, usize>,
+}
+
+impl Redactor {
+    /// A redactor that also replaces the given names
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut names: Vec<String> = names
+            .into_iter()
+            .map(|n| n.as_ref().split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|n| n.chars().count() >= 2)
+            .collect();
+        // Longest first, so "Anna Smith" wins over "Anna"
+        names.sort_by_cached_key(|n| (std::cmp::Reverse(n.chars().count()), n.to_lowercase()));
+        names.dedup_by(|a, b| a.to_lowercase() == b.to_lowercase());
+
+        Redactor { names, ..Default::default() }
+    }
+
+    /// Text with every personal detail replaced by its placeholder
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+        for found in find_pii(text, &self.names) {
+            redacted.push_str(&text[last..found.start]);
+            redacted.push_str(&self.placeholder(&found, &text[found.start..found.end]));
+            last = found.end;
+        }
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+
+    /// Text with placeholders put back to what they replaced
+    pub fn restore(&self, text: &str) -> String {
+        self.originals
+            .iter()
+            .fold(text.to_string(), |restored, (placeholder, original)| restored.replace(placeholder, original))
+    }
+
+    /// Number of distinct values replaced so far
+    pub fn redacted_count(&self) -> usize {
+        self.originals.len()
+    }
+
+    fn placeholder(&mut self, found: &PiiMatch, original: &str) -> String {
+        let key = (found.kind, found.key.clone());
+        if let Some(placeholder) = self.placeholders.get(&key) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(found.kind).or_insert(0);
+        *count += 1;
+        let placeholder = format!("[{}_{}]", found.kind.label(), count);
+        self.placeholders.insert(key, placeholder.clone());
+        self.originals.push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+}
+
+/// Personal details in the text, in order and without overlaps. Earlier
+/// detectors win where matches overlap.
+pub fn find_pii(text: &str, names: &[String]) -> Vec<PiiMatch> {
+    let mut found: Vec<PiiMatch> = Vec::new();
+    let mut add = |candidates: Vec<PiiMatch>| {
+        for candidate in candidates {
+            if !found.iter().any(|f| candidate.start < f.end && f.start < candidate.end) {
+                found.push(candidate);
+            }
+        }
+    };
+
+    add(find_emails(text));
+    add(find_ibans(text));
+    add(find_numbers(text));
+    add(find_addresses(text));
+    add(find_names(text, names));
+
+    found.sort_by_key(|f| f.start);
+    found
+}
+
+fn boundary_before(text: &str, at: usize) -> bool {
+    text[..at].chars().next_back().is_none_or(|c| !c.is_alphanumeric())
+}
+
+fn boundary_after(text: &str, at: usize) -> bool {
+    text[at..].chars().next().is_none_or(|c| !c.is_alphanumeric())
+}
+
+fn find_emails(text: &str) -> Vec<PiiMatch> {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || "._%+-".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '-';
+
+    let mut emails = Vec::new();
+    for (at, _) in text.match_indices('@') {
+        let local_len: usize = text[..at].chars().rev().take_while(|c| is_local(*c)).map(char::len_utf8).sum();
+        let local = text[at - local_len..at].trim_start_matches('.');
+        let domain_len: usize = text[at + 1..].chars().take_while(|c| is_domain(*c)).map(char::len_utf8).sum();
+        let domain = text[at + 1..at + 1 + domain_len].trim_end_matches(['.', '-']);
+
+        let tld = domain.rsplit('.').next().unwrap_or_default();
+        if local.is_empty() || !domain.contains('.') || tld.len() < 2 || !tld.chars().all(|c| c.is_ascii_alphabetic()) {
+            continue;
+        }
+
+        let start = at - local.len();
+        let end = at + 1 + domain.len();
+        emails.push(PiiMatch {
+            kind: PiiKind::Email,
+            start,
+            end,
+            key: text[start..end].to_lowercase(),
+        });
+    }
+    emails
+}
+
+fn find_ibans(text: &str) -> Vec<PiiMatch> {
+    let mut ibans = Vec::new();
+    let mut search_from = 0;
+
+    for (start, _) in text.char_indices() {
+        if start < search_from || !boundary_before(text, start) {
+            continue;
+        }
+        let head = text.as_bytes().get(start..start + 4);
+        let Some(head) = head else { break };
+        if !(head[0].is_ascii_uppercase() && head[1].is_ascii_uppercase() && head[2].is_ascii_digit() && head[3].is_ascii_digit()) {
+            continue;
+        }
+
+        // Alphanumeric groups separated by single spaces; the IBAN may end
+        // at any group boundary
+        let mut compact = String::new();
+        let mut ends = Vec::new();
+        let bytes = text.as_bytes();
+        let mut i = start;
+        while i < bytes.len() && compact.len() < 34 {
+            let b = bytes[i];
+            if b.is_ascii_alphanumeric() {
+                compact.push(b.to_ascii_uppercase() as char);
+                i += 1;
+                if boundary_after(text, i) {
+                    ends.push((compact.clone(), i));
+                }
+            } else if b == b' ' && bytes.get(i + 1).is_some_and(|n| n.is_ascii_alphanumeric()) {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        let valid = ends
+            .into_iter()
+            .rev()
+            .find(|(compact, end)| compact.len() >= 15 && boundary_after(text, *end) && iban_checksum_valid(compact));
+        if let Some((compact, end)) = valid {
+            ibans.push(PiiMatch { kind: PiiKind::Iban, start, end, key: compact });
+            search_from = end;
+        }
+    }
+    ibans
+}
+
+// ISO 13616: the number with its first four characters moved to the end and
+// letters as 10-35 leaves remainder 1 modulo 97
+fn iban_checksum_valid(compact: &str) -> bool {
+    let rearranged = compact[4..].chars().chain(compact[..4].chars());
+    let mut remainder: u32 = 0;
+    for c in rearranged {
+        let Some(value) = c.to_digit(36) else { return false };
+        remainder = if value < 10 { (remainder * 10 + value) % 97 } else { (remainder * 100 + value) % 97 };
+    }
+    remainder == 1
+}
+
+// A run of digits with the separators people write numbers with
+struct NumberRun {
+    start: usize,
+    end: usize,
+    digits: String,
+    groups: Vec<usize>,
+    international: bool,
+}
+
+fn number_runs(text: &str) -> Vec<NumberRun> {
+    let mut runs = Vec::new();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let opens = c.is_ascii_digit() || ((c == '+' || c == '(') && chars.get(i + 1).is_some_and(|(_, n)| n.is_ascii_digit()));
+        if !opens || !boundary_before(text, start) {
+            i += 1;
+            continue;
+        }
+
+        let mut run = NumberRun { start, end: start, digits: String::new(), groups: Vec::new(), international: c == '+' };
+        let mut group = 0;
+        let mut separators = 0;
+        while i < chars.len() {
+            let (at, c) = chars[i];
+            if c.is_ascii_digit() {
+                run.digits.push(c);
+                group += 1;
+                separators = 0;
+                run.end = at + 1;
+            } else if matches!(c, ' ' | '-' | '(' | ')' | '+') && separators < 2 && (c != '+' || run.digits.is_empty()) {
+                if group > 0 {
+                    run.groups.push(group);
+                    group = 0;
+                }
+                separators += 1;
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        if group > 0 {
+            run.groups.push(group);
+        }
+
+        if !run.digits.is_empty() && boundary_after(text, run.end) {
+            runs.push(run);
+        }
+    }
+    runs
+}
+
+// Card numbers, then phone numbers, from digit runs
+fn find_numbers(text: &str) -> Vec<PiiMatch> {
+    let mut numbers = Vec::new();
+    for run in number_runs(text) {
+        let count = run.digits.len();
+        let kind = if !run.international && (13..=19).contains(&count) && luhn_valid(&run.digits) {
+            PiiKind::Card
+        } else if is_phone_number(&run) {
+            PiiKind::Phone
+        } else {
+            continue;
+        };
+
+        let key = if run.international { format!("+{}", run.digits) } else { run.digits };
+        numbers.push(PiiMatch { kind, start: run.start, end: run.end, key });
+    }
+    numbers
+}
+
+fn is_phone_number(run: &NumberRun) -> bool {
+    let count = run.digits.len();
+    // Dates such as 2024-03-01 or 01/03/2024 followed by more digits
+    let date_like = run.groups.len() >= 3
+        && ((run.groups[0] == 4 && run.groups[1] <= 2 && run.groups[2] <= 2)
+            || (run.groups[0] <= 2 && run.groups[1] <= 2 && run.groups[2] == 4));
+
+    if run.international {
+        (8..=15).contains(&count)
+    } else if date_like {
+        false
+    } else if (10..=12).contains(&count) {
+        // Bare digit strings are often ids or timestamps; national numbers
+        // written without separators start with a trunk 0
+        run.groups.len() > 1 || run.digits.starts_with('0')
+    } else {
+        // Local numbers such as 555-0142
+        run.groups == [3, 4]
+    }
+}
+
+fn luhn_valid(digits: &str) -> bool {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum % 10 == 0
+}
+
+// Words as byte ranges: runs of alphanumeric characters
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    words
+}
+
+// "12", "221B"
+fn is_house_number(word: &str) -> bool {
+    let digits = word.chars().take_while(|c| c.is_ascii_digit()).count();
+    (1..=5).contains(&digits) && word.chars().count() - digits <= 1 && word.chars().skip(digits).all(|c| c.is_alphabetic())
+}
+
+fn is_capitalized(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+fn find_addresses(text: &str) -> Vec<PiiMatch> {
+    let words = words(text);
+    let word = |i: usize| &text[words[i].0..words[i].1];
+    // Words i and j are written next to each other, apart from spacing and an
+    // abbreviation's period
+    let adjacent = |i: usize, j: usize| text[words[i].1..words[j].0].trim_start_matches('.').trim().is_empty();
+
+    let mut addresses = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let mut span = None;
+
+        // House number, capitalized street name, street type
+        if is_house_number(word(i)) {
+            for j in i + 2..=(i + MAX_STREET_NAME_WORDS + 1).min(words.len() - 1) {
+                if !adjacent(j - 1, j) || !(i + 1..j).all(|k| is_capitalized(word(k))) || !adjacent(i, i + 1) {
+                    break;
+                }
+                if STREET_SUFFIXES.contains(&word(j).to_lowercase().as_str()) {
+                    span = Some((i, j));
+                    break;
+                }
+            }
+        }
+
+        // Street name ending in a street type, then the house number
+        if span.is_none() && i + 1 < words.len() && is_capitalized(word(i)) && is_house_number(word(i + 1)) && adjacent(i, i + 1) {
+            let lower = word(i).to_lowercase();
+            if STREET_ENDINGS.iter().any(|ending| lower.ends_with(ending)) {
+                span = Some((i, i + 1));
+            }
+        }
+
+        match span {
+            Some((first, last)) => {
+                let (start, end) = (words[first].0, words[last].1);
+                let key = text[start..end].split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+                addresses.push(PiiMatch { kind: PiiKind::Address, start, end, key });
+                i = last + 1;
+            }
+            None => i += 1,
+        }
+    }
+    addresses
+}
+
+// Length in bytes of `needle` at the start of `haystack`, ignoring case
+fn match_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    let mut hay = haystack.char_indices();
+    for n in needle.chars() {
+        let (_, h) = hay.next()?;
+        if !h.to_lowercase().eq(n.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(hay.next().map(|(i, _)| i).unwrap_or(haystack.len()))
+}
+
+fn find_names(text: &str, names: &[String]) -> Vec<PiiMatch> {
+    let mut found = Vec::new();
+    for name in names {
+        for (start, _) in text.char_indices() {
+            if !boundary_before(text, start) {
+                continue;
+            }
+            if let Some(len) = match_ignore_case(&text[start..], name) {
+                if boundary_after(text, start + len) {
+                    found.push(PiiMatch { kind: PiiKind::Name, start, end: start + len, key: name.to_lowercase() });
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Whether text sent to AI providers is redacted, from AI_REDACTION
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionMode {
+    /// Only for users with a strict privacy level
+    Auto,
+    Always,
+    Never,
+}
+
+impl RedactionMode {
+    pub fn from_env() -> Result<Self, JournalError> {
+        match std::env::var("AI_REDACTION").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "auto" => Ok(RedactionMode::Auto),
+            "always" => Ok(RedactionMode::Always),
+            "never" => Ok(RedactionMode::Never),
+            other => Err(JournalError::ConfigurationError(format!(
+                "Unknown AI_REDACTION '{}': expected auto, always or never",
+                other
+            ))),
+        }
+    }
+
+    /// The redactor for text written by a user with these settings, or None
+    /// when it is sent as written
+    pub fn redactor(&self, settings: &PrivacySettings) -> Option<Redactor> {
+        match self {
+            RedactionMode::Never => None,
+            RedactionMode::Auto if !settings.is_strict() => None,
+            _ => Some(Redactor::new(&settings.redaction_names)),
+        }
+    }
+}
+
+/// The redactor for a user's text, or None when it is sent as written. If the
+/// settings can't be read in auto mode the text is redacted, without names.
+pub async fn redactor_for_user(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<Option<Redactor>, JournalError> {
+    let mode = RedactionMode::from_env()?;
+    if mode == RedactionMode::Never {
+        return Ok(None);
+    }
+
+    match get_privacy_settings(client, tenant_id, user_id).await {
+        Ok(settings) => Ok(mode.redactor(&settings)),
+        Err(e) => {
+            tracing::warn!("Redacting text for user {} without their settings: {}", user_id, e);
+            Ok(Some(Redactor::new(Vec::<String>::new())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_replaces_details_with_stable_placeholders() {
+        let mut redactor = Redactor::new(["Anna Smith", "Ben"]);
+        let redacted = redactor.redact(
+            "Lunch with anna smith and Ben. Anna Smith wrote from anna@example.com, \
+             Ben from ben.jones@mail.example.org.",
+        );
+        assert_eq!(
+            redacted,
+            "Lunch with [NAME_1] and [NAME_2]. [NAME_1] wrote from [EMAIL_1], [NAME_2] from [EMAIL_2]."
+        );
+        assert_eq!(redactor.redacted_count(), 4);
+    }
+
+    #[test]
+    fn redact_keeps_placeholders_across_texts() {
+        let mut redactor = Redactor::new(["Anna"]);
+        assert_eq!(redactor.redact("Dinner with Anna"), "Dinner with [NAME_1]");
+        assert_eq!(redactor.redact("Anna cooked"), "[NAME_1] cooked");
+        assert_eq!(redactor.redacted_count(), 1);
+    }
+
+    #[test]
+    fn redact_only_matches_whole_names() {
+        let mut redactor = Redactor::new(["Ann"]);
+        assert_eq!(redactor.redact("Annual review with Ann"), "Annual review with [NAME_1]");
+    }
+
+    #[test]
+    fn redact_checks_card_and_iban_checksums() {
+        let mut redactor = Redactor::new(Vec::<String>::new());
+        assert_eq!(redactor.redact("Paid with 4111 1111 1111 1111."), "Paid with [CARD_1].");
+        assert_eq!(redactor.redact("Sent to GB82 WEST 1234 5698 7654 32."), "Sent to [IBAN_1].");
+        assert_eq!(redactor.redact("Order 4111 1111 1111 1112 arrived."), "Order 4111 1111 1111 1112 arrived.");
+    }
+
+    #[test]
+    fn redact_finds_phone_numbers_but_not_dates_or_ids() {
+        let mut redactor = Redactor::new(Vec::<String>::new());
+        assert_eq!(
+            redactor.redact("Call +44 20 7946 0958 or 555-0142 before 2024-03-01 about order 1712345678."),
+            "Call [PHONE_1] or [PHONE_2] before 2024-03-01 about order 1712345678."
+        );
+        // Another spelling of the same number keeps its placeholder
+        assert_eq!(redactor.redact("Or try +44 (20) 7946-0958."), "Or try [PHONE_1].");
+    }
+
+    #[test]
+    fn redact_finds_addresses_written_either_way() {
+        let mut redactor = Redactor::new(Vec::<String>::new());
+        assert_eq!(
+            redactor.redact("Moved from 221B Baker Street to Hauptstraße 5, near 12 Elm St."),
+            "Moved from [ADDRESS_1] to [ADDRESS_2], near [ADDRESS_3]."
+        );
+        assert_eq!(redactor.redact("I ran 5 miles on Main Street."), "I ran 5 miles on Main Street.");
+    }
+
+    #[test]
+    fn redact_prefers_the_earlier_detector_where_matches_overlap() {
+        let mut redactor = Redactor::new(["Anna"]);
+        assert_eq!(redactor.redact("Anna: anna@example.com"), "[NAME_1]: [EMAIL_1]");
+        assert_eq!(redactor.redact("ANNA@EXAMPLE.COM"), "[EMAIL_1]");
+    }
+
+    #[test]
+    fn redact_prefers_the_longest_name() {
+        let mut redactor = Redactor::new(["Anna", "anna smith", " Anna  Smith ", "A"]);
+        assert_eq!(redactor.redact("Anna Smith and Anna"), "[NAME_1] and [NAME_2]");
+        assert_eq!(redactor.redact("A day with Anna"), "A day with [NAME_2]");
+    }
+
+    #[test]
+    fn restore_puts_the_original_text_back() {
+        let original = "Anna Smith moved to 221 Baker Street; write to anna@example.com.";
+        let mut redactor = Redactor::new(["Anna Smith"]);
+        let redacted = redactor.redact(original);
+        assert!(!redacted.contains("Anna") && !redacted.contains("Baker") && !redacted.contains('@'), "{}", redacted);
+        assert_eq!(redactor.restore(&redacted), original);
+        assert_eq!(
+            redactor.restore("You seem glad [NAME_1] is settling in."),
+            "You seem glad Anna Smith is settling in."
+        );
+    }
+
+    #[test]
+    fn restore_leaves_unknown_placeholders() {
+        let redactor = Redactor::new(["Anna"]);
+        assert_eq!(redactor.restore("Say hi to [NAME_1]"), "Say hi to [NAME_1]");
+    }
+
+    #[test]
+    fn redaction_mode_follows_the_privacy_level() {
+        let strict = PrivacySettings { privacy_level: Some("strict".to_string()), ..Default::default() };
+        assert!(RedactionMode::Auto.redactor(&strict).is_some());
+        assert!(RedactionMode::Auto.redactor(&PrivacySettings::default()).is_none());
+        assert!(RedactionMode::Always.redactor(&PrivacySettings::default()).is_some());
+        assert!(RedactionMode::Never.redactor(&strict).is_none());
+    }
+}
@@ -0,0 +1,418 @@
+// Daily per-user entry rollups
+//
+// Calendar heatmaps need one number per day for a whole year, which is far too
+// many entries to read on every request. Each write to an entry therefore also
+// adjusts a rollup item for the day it was created on, in the user's timezone:
+//
+//   rollup_pk = USER#<tenant_id>#<user_id>   (same value as timeline_pk)
+//   day       = YYYY-MM-DD
+//
+// Counters are only ever changed with ADD so concurrent writers never lose
+// updates. Mood counts are stored as one numeric attribute per primary emotion
+// (`mood#<emotion>`), because ADD cannot create entries inside a map attribute.
+//
+// Days are bucketed with the timezone in effect at write time. After a user
+// changes timezone, run the rebuild_daily_rollups tool to re-bucket history.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{entry_mood, timeline_pk, JournalError};
+
+/// Prefix of the per-mood counter attributes on a rollup item
+pub const MOOD_ATTR_PREFIX: &str = "mood#";
+
+/// Change to apply to a single day's rollup
+#[derive(Debug, Default, Clone)]
+pub struct RollupDelta {
+    pub entries: i64,
+    pub words: i64,
+    pub sentiment_sum: f64,
+    pub sentiment_count: i64,
+    pub moods: HashMap<String, i64>,
+}
+
+impl RollupDelta {
+    /// Contribution of one entry item, added (`sign` = 1) or removed (`sign` = -1)
+    pub fn for_entry(item: &HashMap<String, AttributeValue>, sign: i64) -> Self {
+        let words = item
+            .get("word_count")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<i64>().ok())
+            .unwrap_or(0);
+        let sentiment = item
+            .get("sentiment_score")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok());
+
+        let mut moods = HashMap::new();
+        if let Some(mood) = entry_mood(item) {
+            moods.insert(mood.primary_emotion.as_str().to_string(), sign);
+        }
+
+        RollupDelta {
+            entries: sign,
+            words: words * sign,
+            sentiment_sum: sentiment.unwrap_or(0.0) * sign as f64,
+            sentiment_count: if sentiment.is_some() { sign } else { 0 },
+            moods,
+        }
+    }
+
+    /// Net change when an entry item is replaced by a new version of itself
+    pub fn between(old: &HashMap<String, AttributeValue>, new: &HashMap<String, AttributeValue>) -> Self {
+        let mut delta = Self::for_entry(old, -1);
+        delta.merge(&Self::for_entry(new, 1));
+        delta
+    }
+
+    /// Accumulate another delta for the same day into this one
+    pub fn merge(&mut self, other: &RollupDelta) {
+        self.entries += other.entries;
+        self.words += other.words;
+        self.sentiment_sum += other.sentiment_sum;
+        self.sentiment_count += other.sentiment_count;
+        for (mood, count) in &other.moods {
+            *self.moods.entry(mood.clone()).or_insert(0) += count;
+        }
+        self.moods.retain(|_, count| *count != 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+            && self.words == 0
+            && self.sentiment_sum == 0.0
+            && self.sentiment_count == 0
+            && self.moods.is_empty()
+    }
+}
+
+/// Aggregated activity for one day
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyRollup {
+    pub date: String,
+    pub entry_count: i64,
+    pub word_count: i64,
+    pub dominant_mood: Option<String>,
+    pub average_sentiment: Option<f64>,
+}
+
+impl DailyRollup {
+    pub fn from_delta(day: NaiveDate, delta: &RollupDelta) -> Self {
+        DailyRollup {
+            date: day.to_string(),
+            entry_count: delta.entries,
+            word_count: delta.words,
+            dominant_mood: dominant_mood(delta.moods.iter().map(|(mood, count)| (mood.as_str(), *count))),
+            average_sentiment: (delta.sentiment_count > 0)
+                .then(|| delta.sentiment_sum / delta.sentiment_count as f64),
+        }
+    }
+}
+
+/// Bucket entry items by local day without touching the rollups table
+pub fn rollups_from_entries(items: &[HashMap<String, AttributeValue>], tz: Tz) -> BTreeMap<NaiveDate, RollupDelta> {
+    let mut days: BTreeMap<NaiveDate, RollupDelta> = BTreeMap::new();
+
+    for item in items {
+        let Some(day) = item.get("created_at").and_then(|v| v.as_s().ok()).and_then(|c| local_day(c, tz)) else {
+            continue;
+        };
+        days.entry(day).or_default().merge(&RollupDelta::for_entry(item, 1));
+    }
+
+    days
+}
+
+// Most frequent mood; ties go to the alphabetically first mood so results are stable
+fn dominant_mood<'a>(moods: impl Iterator<Item = (&'a str, i64)>) -> Option<String> {
+    moods
+        .filter(|(_, count)| *count > 0)
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(mood, _)| mood.to_string())
+}
+
+/// Parse an IANA timezone name, falling back to UTC for missing or unknown zones
+pub fn parse_timezone(name: Option<&str>) -> Tz {
+    name.and_then(|n| n.parse::<Tz>().ok()).unwrap_or(Tz::UTC)
+}
+
+/// Calendar day of an RFC 3339 timestamp in the given timezone
+pub fn local_day(created_at: &str, tz: Tz) -> Option<NaiveDate> {
+    created_at
+        .parse::<DateTime<Utc>>()
+        .ok()
+        .map(|ts| ts.with_timezone(&tz).date_naive())
+}
+
+/// Look up the timezone stored in the user's settings
+pub async fn get_user_timezone(client: &DynamoDbClient, tenant_id: &str, user_id: &str) -> Tz {
+    let settings_table = std::env::var("SETTINGS_TABLE").unwrap_or_else(|_| "reflekt-settings".to_string());
+
+    let result = client
+        .get_item()
+        .table_name(settings_table)
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .projection_expression("timezone")
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => parse_timezone(
+            response
+                .item()
+                .and_then(|item| item.get("timezone"))
+                .and_then(|v| v.as_s().ok())
+                .map(|s| s.as_str()),
+        ),
+        Err(e) => {
+            tracing::warn!("Failed to load timezone for user {}: {}", user_id, e);
+            Tz::UTC
+        }
+    }
+}
+
+/// Apply a delta to the rollup of the day an entry item was created on
+pub async fn apply_entry_rollup(
+    client: &DynamoDbClient,
+    item: &HashMap<String, AttributeValue>,
+    delta: &RollupDelta,
+) -> Result<(), JournalError> {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok());
+
+    let (Some(tenant_id), Some(user_id), Some(created_at)) =
+        (get_s("tenant_id"), get_s("user_id"), get_s("created_at"))
+    else {
+        return Err(JournalError::ValidationError("Entry is missing rollup attributes".into()));
+    };
+
+    let tz = get_user_timezone(client, tenant_id, user_id).await;
+    let day = local_day(created_at, tz)
+        .ok_or_else(|| JournalError::ValidationError(format!("Invalid created_at: {}", created_at)))?;
+
+    apply_rollup_delta(client, tenant_id, user_id, day, delta).await
+}
+
+/// Atomically add a delta to one day's rollup item
+pub async fn apply_rollup_delta(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    day: NaiveDate,
+    delta: &RollupDelta,
+) -> Result<(), JournalError> {
+    if delta.is_empty() {
+        return Ok(());
+    }
+
+    let table_name = std::env::var("ROLLUPS_TABLE").unwrap_or_else(|_| "reflekt-daily-rollups".to_string());
+
+    let mut update_expression =
+        "ADD entry_count :entries, word_count :words, sentiment_sum :sentiment_sum, sentiment_count :sentiment_count"
+            .to_string();
+    let mut request = client
+        .update_item()
+        .table_name(table_name)
+        .key("rollup_pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .key("day", AttributeValue::S(day.to_string()))
+        .expression_attribute_values(":entries", AttributeValue::N(delta.entries.to_string()))
+        .expression_attribute_values(":words", AttributeValue::N(delta.words.to_string()))
+        .expression_attribute_values(":sentiment_sum", AttributeValue::N(delta.sentiment_sum.to_string()))
+        .expression_attribute_values(":sentiment_count", AttributeValue::N(delta.sentiment_count.to_string()));
+
+    for (i, (mood, count)) in delta.moods.iter().enumerate() {
+        update_expression.push_str(&format!(", #mood{} :mood{}", i, i));
+        request = request
+            .expression_attribute_names(format!("#mood{}", i), format!("{}{}", MOOD_ATTR_PREFIX, mood))
+            .expression_attribute_values(format!(":mood{}", i), AttributeValue::N(count.to_string()));
+    }
+
+    request
+        .update_expression(update_expression)
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to update daily rollup: {}", e)))?;
+
+    Ok(())
+}
+
+/// Full rollup item for a day, used when rollups are rebuilt from scratch
+pub fn rollup_item(
+    tenant_id: &str,
+    user_id: &str,
+    day: NaiveDate,
+    delta: &RollupDelta,
+) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("rollup_pk".to_string(), AttributeValue::S(timeline_pk(tenant_id, user_id)));
+    item.insert("day".to_string(), AttributeValue::S(day.to_string()));
+    item.insert("entry_count".to_string(), AttributeValue::N(delta.entries.to_string()));
+    item.insert("word_count".to_string(), AttributeValue::N(delta.words.to_string()));
+    item.insert("sentiment_sum".to_string(), AttributeValue::N(delta.sentiment_sum.to_string()));
+    item.insert("sentiment_count".to_string(), AttributeValue::N(delta.sentiment_count.to_string()));
+    for (mood, count) in &delta.moods {
+        item.insert(format!("{}{}", MOOD_ATTR_PREFIX, mood), AttributeValue::N(count.to_string()));
+    }
+    item
+}
+
+/// Read a user's rollups for an inclusive date range
+pub async fn query_rollups(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<DailyRollup>, JournalError> {
+    let table_name = std::env::var("ROLLUPS_TABLE").unwrap_or_else(|_| "reflekt-daily-rollups".to_string());
+    let mut rollups = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(&table_name)
+            .key_condition_expression("rollup_pk = :pk AND #day BETWEEN :start AND :end")
+            .expression_attribute_names("#day", "day")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .expression_attribute_values(":start", AttributeValue::S(start.to_string()))
+            .expression_attribute_values(":end", AttributeValue::S(end.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query daily rollups: {}", e)))?;
+
+        rollups.extend(
+            response
+                .items()
+                .iter()
+                .map(item_to_rollup)
+                .filter(|rollup| rollup.entry_count > 0),
+        );
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(rollups)
+}
+
+/// Convert a rollup item to its public summary
+pub fn item_to_rollup(item: &HashMap<String, AttributeValue>) -> DailyRollup {
+    let get_n = |key: &str| {
+        item.get(key)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    };
+
+    let sentiment_count = get_n("sentiment_count");
+    let dominant_mood = dominant_mood(item.iter().filter_map(|(key, value)| {
+        let mood = key.strip_prefix(MOOD_ATTR_PREFIX)?;
+        let count = value.as_n().ok()?.parse::<i64>().ok()?;
+        Some((mood, count))
+    }));
+
+    DailyRollup {
+        date: item.get("day").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
+        entry_count: get_n("entry_count") as i64,
+        word_count: get_n("word_count") as i64,
+        dominant_mood,
+        average_sentiment: (sentiment_count > 0.0).then(|| get_n("sentiment_sum") / sentiment_count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Emotion, Mood};
+
+    fn entry(created_at: &str, words: i64, sentiment: Option<f64>, mood: Option<Emotion>) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::from([
+            ("created_at".to_string(), AttributeValue::S(created_at.to_string())),
+            ("word_count".to_string(), AttributeValue::N(words.to_string())),
+        ]);
+        if let Some(sentiment) = sentiment {
+            item.insert("sentiment_score".to_string(), AttributeValue::N(sentiment.to_string()));
+        }
+        if let Some(primary_emotion) = mood {
+            let mood = Mood { primary_emotion, secondary_emotions: Vec::new(), intensity: 3, energy: None };
+            item.insert("mood".to_string(), mood.to_attribute());
+        }
+        item
+    }
+
+    fn day(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn local_day_is_the_day_in_the_users_timezone() {
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let tokyo: Tz = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(local_day("2024-03-10T03:30:00Z", Tz::UTC), Some(day("2024-03-10")));
+        assert_eq!(local_day("2024-03-10T03:30:00Z", new_york), Some(day("2024-03-09")));
+        assert_eq!(local_day("2024-03-09T20:00:00Z", tokyo), Some(day("2024-03-10")));
+        assert_eq!(local_day("2024-03-10T01:00:00+05:00", Tz::UTC), Some(day("2024-03-09")));
+        assert_eq!(local_day("yesterday", Tz::UTC), None);
+    }
+
+    #[test]
+    fn parse_timezone_falls_back_to_utc() {
+        assert_eq!(parse_timezone(Some("Europe/Berlin")), Tz::Europe__Berlin);
+        assert_eq!(parse_timezone(Some("Mars/Olympus")), Tz::UTC);
+        assert_eq!(parse_timezone(None), Tz::UTC);
+    }
+
+    #[test]
+    fn rollups_from_entries_buckets_by_local_day() {
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let items = vec![
+            entry("2024-03-09T15:00:00Z", 100, Some(0.5), Some(Emotion::Joy)),
+            // Late evening in New York, already the next day in UTC
+            entry("2024-03-10T03:30:00Z", 50, Some(-0.1), Some(Emotion::Joy)),
+            entry("2024-03-10T18:00:00Z", 20, None, Some(Emotion::Calm)),
+            entry("not a timestamp", 999, None, None),
+        ];
+
+        let days = rollups_from_entries(&items, new_york);
+        assert_eq!(days.keys().copied().collect::<Vec<_>>(), vec![day("2024-03-09"), day("2024-03-10")]);
+
+        let first = DailyRollup::from_delta(day("2024-03-09"), &days[&day("2024-03-09")]);
+        assert_eq!((first.entry_count, first.word_count), (2, 150));
+        assert_eq!(first.dominant_mood.as_deref(), Some("joy"));
+        assert!((first.average_sentiment.unwrap() - 0.2).abs() < 1e-9);
+
+        let second = DailyRollup::from_delta(day("2024-03-10"), &days[&day("2024-03-10")]);
+        assert_eq!((second.entry_count, second.word_count), (1, 20));
+        assert_eq!(second.dominant_mood.as_deref(), Some("calm"));
+        assert_eq!(second.average_sentiment, None);
+
+        let in_utc = rollups_from_entries(&items, Tz::UTC);
+        assert_eq!(in_utc[&day("2024-03-10")].entries, 2);
+    }
+
+    #[test]
+    fn delta_between_versions_keeps_only_the_change() {
+        let old = entry("2024-03-09T15:00:00Z", 100, Some(0.5), Some(Emotion::Joy));
+        let new = entry("2024-03-09T15:00:00Z", 120, Some(0.5), Some(Emotion::Calm));
+        let delta = RollupDelta::between(&old, &new);
+        assert_eq!((delta.entries, delta.words, delta.sentiment_count), (0, 20, 0));
+        assert_eq!(delta.moods, HashMap::from([("joy".to_string(), -1), ("calm".to_string(), 1)]));
+        assert!(RollupDelta::between(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn dominant_mood_breaks_ties_alphabetically() {
+        let moods = [("joy", 2), ("calm", 2), ("anger", 1), ("sadness", 0)];
+        assert_eq!(dominant_mood(moods.into_iter()), Some("calm".to_string()));
+        assert_eq!(dominant_mood([("joy", 0), ("calm", -1)].into_iter()), None);
+    }
+}
@@ -0,0 +1,331 @@
+// Offline sentiment and emotion analysis
+//
+// A VADER-style lexicon scorer that needs no model or network access. Each
+// word with a known valence (-4 to 4) contributes to its sentence's total:
+// boosters ("very") and dampeners ("slightly") in the three preceding words
+// shift it, a negation in the same window flips and weakens it, and words in
+// capitals are emphasized. Within a sentence, words after "but" count more
+// than the words before it; exclamation marks strengthen the whole sentence.
+// The total is normalized into a compound score in [-1, 1].
+//
+// Emotions come from the mood taxonomy: words that name or imply an emotion
+// ("worried", "grateful") add to that emotion's share unless negated.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{extract_keywords_with_corpus, Emotion, Valence};
+
+// Compound scores closer to zero than this are neutral
+const NEUTRAL_THRESHOLD: f32 = 0.05;
+// Normalization constant: total / sqrt(total^2 + alpha)
+const NORMALIZATION_ALPHA: f32 = 15.0;
+const BOOSTER_INCREMENT: f32 = 0.293;
+const NEGATION_SCALAR: f32 = -0.74;
+const CAPS_INCREMENT: f32 = 0.733;
+const EXCLAMATION_INCREMENT: f32 = 0.292;
+const MAX_EXCLAMATIONS: usize = 4;
+const BUT_BEFORE_WEIGHT: f32 = 0.5;
+const BUT_AFTER_WEIGHT: f32 = 1.5;
+// Preceding words searched for boosters and negations, with their decay
+const WINDOW_DECAY: [f32; 3] = [1.0, 0.95, 0.9];
+
+/// Word valences on a -4 (most negative) to 4 (most positive) scale
+const LEXICON: &[(&str, f32)] = &[
+    ("abandoned", -2.4), ("able", 0.9), ("accomplished", 2.1), ("aching", -1.6),
+    ("afraid", -2.0), ("alive", 1.6), ("alone", -1.0), ("amazing", 2.8),
+    ("angry", -2.3), ("annoyed", -1.6), ("annoying", -1.8), ("anxious", -2.0),
+    ("anxiety", -2.0), ("appreciate", 1.9), ("appreciated", 2.0), ("ashamed", -2.1),
+    ("awesome", 3.1), ("awful", -2.9), ("awkward", -1.1), ("bad", -2.5),
+    ("beautiful", 2.9), ("best", 3.2), ("better", 1.9), ("bitter", -1.8),
+    ("blessed", 2.7), ("bored", -1.1), ("boring", -1.3), ("brave", 2.2),
+    ("bright", 1.5), ("broken", -2.2), ("burned", -1.4), ("burnout", -2.2),
+    ("calm", 1.3), ("care", 1.7), ("cared", 1.8), ("celebrate", 2.7),
+    ("celebrated", 2.4), ("cheerful", 2.5), ("cherish", 2.3), ("comfort", 1.5),
+    ("comfortable", 1.6), ("confident", 2.2), ("confused", -1.3), ("content", 1.5),
+    ("cried", -1.6), ("cry", -2.1), ("crying", -2.1), ("curious", 1.3),
+    ("dead", -3.3), ("defeated", -2.1), ("delighted", 2.9), ("depressed", -2.7),
+    ("depressing", -2.5), ("desperate", -2.0), ("devastated", -3.0), ("disappointed", -1.9),
+    ("disappointing", -2.2), ("disaster", -3.1), ("disgusted", -2.4), ("down", -0.8),
+    ("drained", -1.7), ("dread", -2.4), ("dreading", -2.4), ("easy", 1.9),
+    ("embarrassed", -1.5), ("empty", -1.7), ("encouraged", 1.9), ("energized", 2.0),
+    ("enjoy", 2.2), ("enjoyed", 2.3), ("excellent", 2.7), ("excited", 2.3),
+    ("exciting", 2.2), ("exhausted", -1.9), ("exhausting", -1.5), ("fail", -2.5),
+    ("failed", -2.3), ("failure", -2.3), ("fantastic", 2.6), ("fear", -2.2),
+    ("fine", 0.8), ("fought", -1.3), ("free", 2.3), ("frustrated", -2.1),
+    ("frustrating", -1.9), ("fun", 2.3), ("furious", -2.7), ("glad", 2.0),
+    ("good", 1.9), ("grateful", 2.0), ("gratitude", 2.2), ("great", 3.1),
+    ("grief", -2.2), ("guilty", -1.8), ("happiness", 2.6), ("happy", 2.7),
+    ("hard", -0.4), ("hate", -2.7), ("hated", -3.2), ("healthy", 1.7),
+    ("heartbroken", -3.0), ("helpful", 1.8), ("helpless", -2.1), ("hope", 1.9),
+    ("hopeful", 2.3), ("hopeless", -2.0), ("horrible", -2.5), ("hurt", -2.4),
+    ("hurting", -2.3), ("ill", -1.8), ("inspired", 2.2), ("inspiring", 2.3),
+    ("insecure", -1.8), ("irritated", -1.8), ("isolated", -1.3), ("joy", 2.8),
+    ("joyful", 2.9), ("laugh", 2.6), ("laughed", 2.0),
+    ("laughing", 2.2), ("lonely", -1.5), ("lost", -1.3), ("love", 3.2),
+    ("loved", 2.9), ("lovely", 2.8), ("loving", 2.9), ("lucky", 1.8),
+    ("mad", -2.2), ("meaningful", 1.8), ("mess", -1.5), ("miserable", -2.2),
+    ("miss", -0.6), ("missed", -1.2), ("motivated", 1.9), ("nervous", -1.1),
+    ("nice", 1.8), ("overwhelmed", -1.5), ("overwhelming", -1.4), ("pain", -2.3),
+    ("painful", -2.4), ("panic", -2.3), ("peace", 2.5), ("peaceful", 2.2),
+    ("perfect", 2.7), ("pleasant", 2.3), ("pleased", 1.9), ("positive", 2.6),
+    ("problem", -1.7), ("problems", -1.7), ("productive", 1.7), ("progress", 1.8),
+    ("proud", 2.1), ("regret", -1.8), ("regretted", -1.6), ("rejected", -2.1),
+    ("relaxed", 2.2), ("relaxing", 2.1), ("relief", 2.1), ("relieved", 1.9),
+    ("rested", 1.3), ("restless", -1.1), ("ruined", -2.4), ("sad", -2.1),
+    ("sadness", -1.9), ("safe", 1.9), ("satisfied", 1.8), ("scared", -1.9),
+    ("scary", -2.2), ("shame", -2.1), ("sick", -2.3), ("smile", 1.5),
+    ("smiled", 2.5), ("smiling", 2.3), ("sore", -1.5), ("sorry", -0.3),
+    ("strong", 2.3), ("struggle", -1.3), ("struggled", -1.5), ("struggling", -1.6),
+    ("stuck", -1.0), ("stress", -1.8), ("stressed", -1.4), ("stressful", -2.3),
+    ("succeed", 2.2), ("success", 2.7), ("successful", 2.8), ("suffering", -2.1),
+    ("sunny", 1.5), ("support", 1.7), ("supported", 1.9), ("supportive", 2.0),
+    ("terrible", -2.1), ("terrified", -3.0), ("thank", 1.5), ("thankful", 2.7),
+    ("thanks", 1.9), ("tired", -1.9), ("trust", 2.3), ("ugly", -2.3),
+    ("uncomfortable", -1.6), ("unhappy", -1.8), ("upset", -1.6), ("useless", -1.8),
+    ("warm", 0.9), ("weak", -1.9), ("welcome", 2.0), ("wonderful", 2.7),
+    ("worried", -1.2), ("worry", -1.9), ("worrying", -1.4), ("worse", -2.1),
+    ("worst", -3.1), ("worthless", -1.9), ("wrong", -2.1), ("yay", 2.4),
+];
+
+/// Words that strengthen (positive) or weaken (negative) the word after them
+const BOOSTERS: &[(&str, f32)] = &[
+    ("absolutely", BOOSTER_INCREMENT), ("completely", BOOSTER_INCREMENT),
+    ("deeply", BOOSTER_INCREMENT), ("especially", BOOSTER_INCREMENT),
+    ("extremely", BOOSTER_INCREMENT), ("genuinely", BOOSTER_INCREMENT),
+    ("hugely", BOOSTER_INCREMENT), ("incredibly", BOOSTER_INCREMENT),
+    ("particularly", BOOSTER_INCREMENT), ("quite", BOOSTER_INCREMENT),
+    ("really", BOOSTER_INCREMENT), ("so", BOOSTER_INCREMENT),
+    ("super", BOOSTER_INCREMENT), ("totally", BOOSTER_INCREMENT),
+    ("truly", BOOSTER_INCREMENT), ("utterly", BOOSTER_INCREMENT),
+    ("very", BOOSTER_INCREMENT), ("almost", -BOOSTER_INCREMENT),
+    ("barely", -BOOSTER_INCREMENT), ("hardly", -BOOSTER_INCREMENT),
+    ("kinda", -BOOSTER_INCREMENT), ("little", -BOOSTER_INCREMENT),
+    ("partly", -BOOSTER_INCREMENT), ("slightly", -BOOSTER_INCREMENT),
+    ("somewhat", -BOOSTER_INCREMENT), ("sorta", -BOOSTER_INCREMENT),
+];
+
+const NEGATIONS: &[&str] = &[
+    "not", "no", "never", "none", "nobody", "nothing", "neither", "nor", "nowhere", "without",
+    "cannot", "cant", "dont", "didnt", "doesnt", "isnt", "wasnt", "werent", "arent", "wont",
+    "wouldnt", "couldnt", "shouldnt", "havent", "hasnt", "hadnt", "aint",
+];
+
+// Words that imply an emotion without naming it, beyond the mood words
+const EMOTION_CUES: &[(&str, Emotion)] = &[
+    ("appreciate", Emotion::Gratitude),
+    ("appreciated", Emotion::Gratitude),
+    ("thanks", Emotion::Gratitude),
+    ("thank", Emotion::Gratitude),
+    ("laughed", Emotion::Joy),
+    ("laughing", Emotion::Joy),
+    ("fun", Emotion::Joy),
+    ("delighted", Emotion::Joy),
+    ("celebrated", Emotion::Pride),
+    ("inspired", Emotion::Hope),
+    ("motivated", Emotion::Hope),
+    ("relieved", Emotion::Calm),
+    ("relief", Emotion::Calm),
+    ("peace", Emotion::Calm),
+    ("cried", Emotion::Sadness),
+    ("crying", Emotion::Sadness),
+    ("grief", Emotion::Sadness),
+    ("heartbroken", Emotion::Sadness),
+    ("alone", Emotion::Loneliness),
+    ("missed", Emotion::Loneliness),
+    ("worry", Emotion::Anxiety),
+    ("worrying", Emotion::Anxiety),
+    ("panic", Emotion::Anxiety),
+    ("dread", Emotion::Anxiety),
+    ("dreading", Emotion::Anxiety),
+    ("terrified", Emotion::Fear),
+    ("scary", Emotion::Fear),
+    ("stressful", Emotion::Stress),
+    ("burnout", Emotion::Stress),
+    ("frustrating", Emotion::Frustration),
+    ("annoying", Emotion::Frustration),
+    ("stuck", Emotion::Frustration),
+    ("hate", Emotion::Anger),
+    ("hated", Emotion::Anger),
+    ("regret", Emotion::Guilt),
+    ("sorry", Emotion::Guilt),
+    ("exhausting", Emotion::Tiredness),
+    ("burned", Emotion::Tiredness),
+];
+
+/// Share of the detected emotional language that points at one emotion
+#[derive(Debug, Clone, Serialize)]
+pub struct EmotionScore {
+    pub emotion: Emotion,
+    pub score: f32,
+}
+
+/// Result of the offline analyzer
+#[derive(Debug, Clone, Serialize)]
+pub struct LexiconAnalysis {
+    pub sentiment: Valence,
+    /// Compound score from -1.0 (very negative) to 1.0 (very positive)
+    pub score: f32,
+    /// How much sentiment-bearing language the score rests on, from 0.0
+    /// (none: the text gave nothing to score) to 1.0
+    pub confidence: f32,
+    /// Emotions found, strongest first; shares sum to 1.0
+    pub emotions: Vec<EmotionScore>,
+    pub keywords: Vec<String>,
+    /// Number of words that carried sentiment
+    pub matched_words: usize,
+}
+
+/// Analyze text with the sentiment lexicon
+pub fn analyze_lexicon(text: &str, max_keywords: usize) -> LexiconAnalysis {
+    let lexicon: HashMap<&str, f32> = LEXICON.iter().copied().collect();
+    let boosters: HashMap<&str, f32> = BOOSTERS.iter().copied().collect();
+    let text = text.replace(['\u{2019}', '\u{2018}'], "'");
+    // Capitals only stand out in text that isn't shouted throughout
+    let mixed_case = text.chars().any(|c| c.is_lowercase());
+
+    let mut total = 0.0f32;
+    let mut matched_words = 0;
+    let mut emotion_weights: HashMap<Emotion, f32> = HashMap::new();
+
+    for (sentence, exclamations) in sentences(&text) {
+        let words: Vec<&str> = sentence
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|w| w.trim_matches('\''))
+            .filter(|w| !w.is_empty())
+            .collect();
+        let lower: Vec<String> = words.iter().map(|w| w.to_lowercase().replace('\'', "")).collect();
+        let but_index = lower.iter().position(|w| w == "but");
+
+        let mut sentence_total = 0.0f32;
+        for (i, word) in lower.iter().enumerate() {
+            if boosters.contains_key(word.as_str()) {
+                continue;
+            }
+            let base = lexicon.get(word.as_str()).copied();
+            let emotion = Emotion::parse(word)
+                .or_else(|| EMOTION_CUES.iter().find(|(cue, _)| cue == word).map(|(_, e)| *e));
+            if base.is_none() && emotion.is_none() {
+                continue;
+            }
+
+            let mut valence = base.unwrap_or(0.0);
+            let sign = valence.signum();
+            if base.is_some() && mixed_case && words[i].chars().count() > 1 && words[i].chars().all(|c| !c.is_lowercase()) {
+                valence += CAPS_INCREMENT * sign;
+            }
+
+            let mut negated = false;
+            for (distance, decay) in WINDOW_DECAY.iter().enumerate() {
+                let Some(previous) = i.checked_sub(distance + 1).map(|j| lower[j].as_str()) else {
+                    break;
+                };
+                if let Some(&boost) = boosters.get(previous) {
+                    valence += boost * decay * sign;
+                }
+                if NEGATIONS.contains(&previous) || is_contraction(previous) {
+                    negated = true;
+                }
+            }
+
+            // A negated emotion word ("not worried") says nothing about which emotion was felt
+            if let (Some(emotion), false) = (emotion, negated) {
+                *emotion_weights.entry(emotion).or_insert(0.0) += valence.abs().max(1.0);
+            }
+
+            if base.is_none() {
+                continue;
+            }
+            matched_words += 1;
+            if negated {
+                valence *= NEGATION_SCALAR;
+            }
+            match but_index {
+                Some(b) if i < b => valence *= BUT_BEFORE_WEIGHT,
+                Some(b) if i > b => valence *= BUT_AFTER_WEIGHT,
+                _ => {}
+            }
+            sentence_total += valence;
+        }
+
+        if sentence_total != 0.0 {
+            let emphasis = exclamations.min(MAX_EXCLAMATIONS) as f32 * EXCLAMATION_INCREMENT;
+            sentence_total += emphasis * sentence_total.signum();
+        }
+        total += sentence_total;
+    }
+
+    let score = (total / (total * total + NORMALIZATION_ALPHA).sqrt()).clamp(-1.0, 1.0);
+    let sentiment = if score >= NEUTRAL_THRESHOLD {
+        Valence::Positive
+    } else if score <= -NEUTRAL_THRESHOLD {
+        Valence::Negative
+    } else {
+        Valence::Neutral
+    };
+
+    let emotion_total: f32 = emotion_weights.values().sum();
+    let mut emotions: Vec<EmotionScore> = emotion_weights
+        .into_iter()
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(emotion, weight)| EmotionScore { emotion, score: round2(weight / emotion_total) })
+        .collect();
+    emotions.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.emotion.as_str().cmp(b.emotion.as_str()))
+    });
+
+    LexiconAnalysis {
+        sentiment,
+        score: round2(score),
+        confidence: round2(matched_words as f32 / (matched_words as f32 + 3.0)),
+        emotions,
+        keywords: extract_keywords_with_corpus(&text, None, max_keywords),
+        matched_words,
+    }
+}
+
+// Sentences of the text with the number of exclamation marks ending each
+fn sentences(text: &str) -> Vec<(&str, usize)> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?' | ';' | '\n') {
+            continue;
+        }
+        let mut end = i + c.len_utf8();
+        let mut exclamations = usize::from(c == '!');
+        while let Some(&(j, next)) = chars.peek() {
+            if !matches!(next, '.' | '!' | '?') {
+                break;
+            }
+            exclamations += usize::from(next == '!');
+            end = j + next.len_utf8();
+            chars.next();
+        }
+        result.push((&text[start..i], exclamations));
+        start = end;
+    }
+    if start < text.len() {
+        result.push((&text[start..], 0));
+    }
+    result
+}
+
+// Contractions such as "didn't" with the apostrophe already removed
+fn is_contraction(word: &str) -> bool {
+    word.strip_suffix("nt").is_some_and(|stem| {
+        matches!(
+            stem,
+            "do" | "did" | "does" | "is" | "was" | "were" | "are" | "wo" | "would" | "could" | "should"
+                | "have" | "has" | "had" | "ca" | "must" | "need"
+        )
+    })
+}
+
+fn round2(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
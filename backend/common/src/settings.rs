@@ -1,13 +1,19 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use crate::JournalError;
 
+/// privacy_level value under which text sent to AI providers is redacted
+pub const STRICT_PRIVACY_LEVEL: &str = "strict";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserSettings {
     pub tenant_id: String,
     pub user_id: String,
     pub theme: Option<String>,
     pub date_format: Option<String>,
+    pub timezone: Option<String>,
     pub notification_preferences: Option<NotificationPreferences>,
     pub display_preferences: Option<DisplayPreferences>,
 }
@@ -44,4 +50,88 @@ pub async fn get_user_settings(
         .map_err(|e| JournalError::InternalError(format!("Failed to parse settings: {}", e)))?;
         
     Ok(response)
-} 
\ No newline at end of file
+} 
+
+/// The settings that decide whether a user's entries are analyzed, how their
+/// text is redacted, how insights are written and whether they are shown
+#[derive(Debug, Clone)]
+pub struct PrivacySettings {
+    pub ai_insights_enabled: bool,
+    pub privacy_level: Option<String>,
+    pub redaction_names: Vec<String>,
+    pub show_insights: bool,
+    pub language: Option<String>,
+    pub insight_tone: Option<String>,
+}
+
+// Users without stored settings get the defaults the settings service returns
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        PrivacySettings {
+            ai_insights_enabled: true,
+            privacy_level: None,
+            redaction_names: Vec::new(),
+            show_insights: true,
+            language: None,
+            insight_tone: None,
+        }
+    }
+}
+
+impl PrivacySettings {
+    pub fn is_strict(&self) -> bool {
+        self.privacy_level
+            .as_deref()
+            .is_some_and(|level| level.trim().eq_ignore_ascii_case(STRICT_PRIVACY_LEVEL))
+    }
+}
+
+/// Read a string list stored as a list or a string set
+pub fn attribute_strings(value: Option<&AttributeValue>) -> Vec<String> {
+    match value {
+        Some(AttributeValue::Ss(values)) => values.clone(),
+        Some(AttributeValue::L(values)) => values.iter().filter_map(|v| v.as_s().ok().cloned()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Load the fields of the user's settings that shape AI analysis
+pub async fn get_privacy_settings(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<PrivacySettings, JournalError> {
+    let settings_table = std::env::var("SETTINGS_TABLE").unwrap_or_else(|_| "reflekt-settings".to_string());
+
+    let response = client
+        .get_item()
+        .table_name(settings_table)
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .projection_expression(
+            "ai_insights_enabled, privacy_level, redaction_names, display_preferences.show_insights, #language, insight_tone",
+        )
+        .expression_attribute_names("#language", "language")
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to load privacy settings: {}", e)))?;
+
+    let Some(item) = response.item() else {
+        return Ok(PrivacySettings::default());
+    };
+
+    let get_bool = |value: Option<&AttributeValue>| value.and_then(|v| v.as_bool().ok()).copied();
+    Ok(PrivacySettings {
+        ai_insights_enabled: get_bool(item.get("ai_insights_enabled")).unwrap_or(true),
+        privacy_level: item.get("privacy_level").and_then(|v| v.as_s().ok()).cloned(),
+        redaction_names: attribute_strings(item.get("redaction_names")),
+        show_insights: get_bool(
+            item.get("display_preferences")
+                .and_then(|v| v.as_m().ok())
+                .and_then(|prefs| prefs.get("show_insights")),
+        )
+        .unwrap_or(true),
+        language: item.get("language").and_then(|v| v.as_s().ok()).cloned(),
+        insight_tone: item.get("insight_tone").and_then(|v| v.as_s().ok()).cloned(),
+    })
+}
@@ -0,0 +1,431 @@
+// Persistent per-user tag statistics
+//
+// Instead of re-reading every entry to count tags, each entry write applies the
+// difference between the entry's old and new tag sets to a stats partition:
+//
+//   stats_pk = USER#<tenant_id>#<user_id>   (same value as timeline_pk)
+//   stats_sk = TAG#<tag>                    count, total_count, first_used, last_used
+//   stats_sk = PAIR#<tag_a>\t<tag_b>        count of entries carrying both tags
+//
+// `count` is the number of entries tagged with exactly this tag; `total_count`
+// also includes entries tagged with any of its children, with each entry
+// counted once per ancestor. Pairs are ordered (tag_a < tag_b) and joined with
+// a tab, which normalized tags can never contain. first_used/last_used record
+// when the tag was applied to an entry.
+
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{normalize_tags, tag_ancestors, timeline_pk, JournalError};
+
+const TAG_SK_PREFIX: &str = "TAG#";
+const PAIR_SK_PREFIX: &str = "PAIR#";
+// Tag pair counters updated at once by one stats change
+const PAIR_UPDATE_CONCURRENCY: usize = 16;
+
+/// Change to a user's tag statistics caused by one or more entry writes
+#[derive(Debug, Default, Clone)]
+pub struct TagStatsDelta {
+    pub direct: HashMap<String, i64>,
+    pub total: HashMap<String, i64>,
+    pub pairs: HashMap<(String, String), i64>,
+}
+
+impl TagStatsDelta {
+    /// Net change when an entry's tags go from `old` to `new` (either may be empty)
+    pub fn between<A, B>(old: &[A], new: &[B]) -> Self
+    where
+        A: AsRef<str>,
+        B: AsRef<str>,
+    {
+        let mut delta = TagStatsDelta::default();
+        delta.add_entry(&normalize_tags(old.iter().map(|t| t.as_ref())), -1);
+        delta.add_entry(&normalize_tags(new.iter().map(|t| t.as_ref())), 1);
+        delta.direct.retain(|_, n| *n != 0);
+        delta.total.retain(|_, n| *n != 0);
+        delta.pairs.retain(|_, n| *n != 0);
+        delta
+    }
+
+    fn add_entry(&mut self, tags: &[String], sign: i64) {
+        let mut rolled_up = BTreeSet::new();
+
+        for tag in tags {
+            *self.direct.entry(tag.clone()).or_insert(0) += sign;
+            rolled_up.extend(tag_ancestors(tag));
+            rolled_up.insert(tag.clone());
+        }
+        for tag in rolled_up {
+            *self.total.entry(tag).or_insert(0) += sign;
+        }
+
+        // tags are sorted, so (a, b) with a < b
+        for (i, a) in tags.iter().enumerate() {
+            for b in &tags[i + 1..] {
+                *self.pairs.entry((a.clone(), b.clone())).or_insert(0) += sign;
+            }
+        }
+    }
+
+    /// Accumulate another delta into this one
+    pub fn merge(&mut self, other: TagStatsDelta) {
+        for (tag, n) in other.direct {
+            *self.direct.entry(tag).or_insert(0) += n;
+        }
+        for (tag, n) in other.total {
+            *self.total.entry(tag).or_insert(0) += n;
+        }
+        for (pair, n) in other.pairs {
+            *self.pairs.entry(pair).or_insert(0) += n;
+        }
+        self.direct.retain(|_, n| *n != 0);
+        self.total.retain(|_, n| *n != 0);
+        self.pairs.retain(|_, n| *n != 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.direct.is_empty() && self.total.is_empty() && self.pairs.is_empty()
+    }
+}
+
+/// Statistics for one tag
+#[derive(Debug, Clone, Serialize)]
+pub struct TagStat {
+    pub tag: String,
+    pub count: i64,
+    pub total_count: i64,
+    pub first_used: Option<String>,
+    pub last_used: Option<String>,
+}
+
+/// All tag statistics for one user
+#[derive(Debug, Clone, Default)]
+pub struct TagStats {
+    pub tags: Vec<TagStat>,
+    pub pairs: HashMap<(String, String), i64>,
+}
+
+impl TagStats {
+    /// Tags most often used together with `tag`, most frequent first
+    pub fn related(&self, tag: &str) -> Vec<(String, i64)> {
+        let mut related: Vec<(String, i64)> = self
+            .pairs
+            .iter()
+            .filter_map(|((a, b), count)| {
+                if a == tag {
+                    Some((b.clone(), *count))
+                } else if b == tag {
+                    Some((a.clone(), *count))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        related.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        related
+    }
+}
+
+fn stats_table() -> String {
+    std::env::var("TAG_STATS_TABLE").unwrap_or_else(|_| "reflekt-tag-stats".to_string())
+}
+
+fn tag_sk(tag: &str) -> String {
+    format!("{}{}", TAG_SK_PREFIX, tag)
+}
+
+fn pair_sk(a: &str, b: &str) -> String {
+    format!("{}{}\t{}", PAIR_SK_PREFIX, a, b)
+}
+
+/// Apply a delta to the user's stored statistics. `used_at` becomes last_used
+/// (and first_used if the tag is new) for every tag whose direct count grows.
+pub async fn apply_tag_stats_delta(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    delta: &TagStatsDelta,
+    used_at: &str,
+) -> Result<(), JournalError> {
+    if delta.is_empty() {
+        return Ok(());
+    }
+
+    let table_name = stats_table();
+    let pk = timeline_pk(tenant_id, user_id);
+
+    let tags: BTreeSet<&String> = delta.direct.keys().chain(delta.total.keys()).collect();
+    for tag in tags {
+        let direct = delta.direct.get(tag).copied().unwrap_or(0);
+        let total = delta.total.get(tag).copied().unwrap_or(0);
+
+        let mut request = client
+            .update_item()
+            .table_name(&table_name)
+            .key("stats_pk", AttributeValue::S(pk.clone()))
+            .key("stats_sk", AttributeValue::S(tag_sk(tag)))
+            .expression_attribute_values(":tag", AttributeValue::S(tag.clone()))
+            .expression_attribute_values(":direct", AttributeValue::N(direct.to_string()))
+            .expression_attribute_values(":total", AttributeValue::N(total.to_string()))
+            .return_values(ReturnValue::AllNew);
+
+        request = if direct > 0 {
+            request
+                .update_expression(
+                    "SET tag = :tag, last_used = :used_at, first_used = if_not_exists(first_used, :used_at) \
+                     ADD #count :direct, total_count :total",
+                )
+                .expression_attribute_values(":used_at", AttributeValue::S(used_at.to_string()))
+        } else {
+            request.update_expression("SET tag = :tag ADD #count :direct, total_count :total")
+        };
+
+        let response = request
+            .expression_attribute_names("#count", "count")
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to update tag stats: {}", e)))?;
+
+        let get_n = |key: &str| {
+            response
+                .attributes()
+                .and_then(|a| a.get(key))
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse::<i64>().ok())
+                .unwrap_or(0)
+        };
+        if get_n("count") <= 0 && get_n("total_count") <= 0 {
+            delete_if_unused(client, &table_name, &pk, &tag_sk(tag), "#count <= :zero AND total_count <= :zero").await?;
+        }
+    }
+
+    // Pair counters are independent, so a batch of them is updated at a time
+    let pairs: Vec<(String, String, i64)> =
+        delta.pairs.iter().map(|((a, b), count)| (a.clone(), b.clone(), *count)).collect();
+    for batch in pairs.chunks(PAIR_UPDATE_CONCURRENCY) {
+        let mut updates = tokio::task::JoinSet::new();
+        for (a, b, count) in batch.iter().cloned() {
+            updates.spawn(apply_pair_delta(client.clone(), table_name.clone(), pk.clone(), a, b, count));
+        }
+        while let Some(result) = updates.join_next().await {
+            result.map_err(|e| JournalError::InternalError(format!("Tag pair update failed: {}", e)))??;
+        }
+    }
+
+    Ok(())
+}
+
+async fn apply_pair_delta(
+    client: DynamoDbClient,
+    table_name: String,
+    pk: String,
+    a: String,
+    b: String,
+    count: i64,
+) -> Result<(), JournalError> {
+    let response = client
+        .update_item()
+        .table_name(&table_name)
+        .key("stats_pk", AttributeValue::S(pk.clone()))
+        .key("stats_sk", AttributeValue::S(pair_sk(&a, &b)))
+        .update_expression("SET tag_a = :a, tag_b = :b ADD #count :count")
+        .expression_attribute_names("#count", "count")
+        .expression_attribute_values(":a", AttributeValue::S(a.clone()))
+        .expression_attribute_values(":b", AttributeValue::S(b.clone()))
+        .expression_attribute_values(":count", AttributeValue::N(count.to_string()))
+        .return_values(ReturnValue::UpdatedNew)
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to update tag pair stats: {}", e)))?;
+
+    let remaining = response
+        .attributes()
+        .and_then(|a| a.get("count"))
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<i64>().ok())
+        .unwrap_or(0);
+    if remaining <= 0 {
+        delete_if_unused(&client, &table_name, &pk, &pair_sk(&a, &b), "#count <= :zero").await?;
+    }
+    Ok(())
+}
+
+/// Move a tag's first_used back to `first_used` if that is earlier, for a tag
+/// that took over the entries of older tags (a rename or merge)
+pub async fn backdate_first_used(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    tag: &str,
+    first_used: &str,
+) -> Result<(), JournalError> {
+    let result = client
+        .update_item()
+        .table_name(stats_table())
+        .key("stats_pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .key("stats_sk", AttributeValue::S(tag_sk(tag)))
+        .update_expression("SET first_used = :first_used")
+        .condition_expression(
+            "attribute_exists(stats_sk) AND (attribute_not_exists(first_used) OR first_used > :first_used)",
+        )
+        .expression_attribute_values(":first_used", AttributeValue::S(first_used.to_string()))
+        .send()
+        .await;
+
+    if let Err(e) = result {
+        let e = e.into_service_error();
+        // Already as early, or the tag is gone again
+        if e.is_conditional_check_failed_exception() {
+            return Ok(());
+        }
+        return Err(JournalError::DatabaseError(format!("Failed to update tag first use: {}", e)));
+    }
+    Ok(())
+}
+
+// Drop a stats item once nothing references it; the condition guards against a
+// concurrent increment landing between the update and the delete
+async fn delete_if_unused(
+    client: &DynamoDbClient,
+    table_name: &str,
+    pk: &str,
+    sk: &str,
+    condition: &str,
+) -> Result<(), JournalError> {
+    let result = client
+        .delete_item()
+        .table_name(table_name)
+        .key("stats_pk", AttributeValue::S(pk.to_string()))
+        .key("stats_sk", AttributeValue::S(sk.to_string()))
+        .condition_expression(condition)
+        .expression_attribute_names("#count", "count")
+        .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+        .send()
+        .await;
+
+    if let Err(e) = result {
+        tracing::debug!("Kept tag stats item {}: {}", sk, e);
+    }
+    Ok(())
+}
+
+/// Read all tag statistics for a user
+pub async fn get_tag_stats(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<TagStats, JournalError> {
+    let table_name = stats_table();
+    let mut stats = TagStats::default();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(&table_name)
+            .key_condition_expression("stats_pk = :pk")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query tag stats: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            let get_n = |key: &str| {
+                item.get(key)
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|n| n.parse::<i64>().ok())
+                    .unwrap_or(0)
+            };
+            let sk = get_s("stats_sk").unwrap_or_default();
+
+            if sk.starts_with(TAG_SK_PREFIX) {
+                if get_n("total_count") <= 0 {
+                    continue;
+                }
+                stats.tags.push(TagStat {
+                    tag: get_s("tag").unwrap_or_else(|| sk[TAG_SK_PREFIX.len()..].to_string()),
+                    count: get_n("count"),
+                    total_count: get_n("total_count"),
+                    first_used: get_s("first_used"),
+                    last_used: get_s("last_used"),
+                });
+            } else if sk.starts_with(PAIR_SK_PREFIX) {
+                if let (Some(a), Some(b)) = (get_s("tag_a"), get_s("tag_b")) {
+                    let count = get_n("count");
+                    if count > 0 {
+                        stats.pairs.insert((a, b), count);
+                    }
+                }
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Stored items for a user's statistics built from scratch, used by the rebuild tool.
+/// `entries` are (tags, created_at) pairs; first/last used come from created_at.
+pub fn tag_stats_items<S: AsRef<str>>(
+    tenant_id: &str,
+    user_id: &str,
+    entries: &[(Vec<S>, String)],
+) -> Vec<HashMap<String, AttributeValue>> {
+    let pk = timeline_pk(tenant_id, user_id);
+    let empty: [&str; 0] = [];
+    let mut delta = TagStatsDelta::default();
+    let mut used: HashMap<String, (String, String)> = HashMap::new();
+
+    for (tags, created_at) in entries {
+        let entry_delta = TagStatsDelta::between(&empty, tags);
+        for tag in entry_delta.direct.keys() {
+            let span = used.entry(tag.clone()).or_insert_with(|| (created_at.clone(), created_at.clone()));
+            if *created_at < span.0 {
+                span.0 = created_at.clone();
+            }
+            if *created_at > span.1 {
+                span.1 = created_at.clone();
+            }
+        }
+        delta.merge(entry_delta);
+    }
+
+    let mut items = Vec::new();
+
+    for (tag, total) in &delta.total {
+        let mut item = HashMap::new();
+        item.insert("stats_pk".to_string(), AttributeValue::S(pk.clone()));
+        item.insert("stats_sk".to_string(), AttributeValue::S(tag_sk(tag)));
+        item.insert("tag".to_string(), AttributeValue::S(tag.clone()));
+        item.insert(
+            "count".to_string(),
+            AttributeValue::N(delta.direct.get(tag).copied().unwrap_or(0).to_string()),
+        );
+        item.insert("total_count".to_string(), AttributeValue::N(total.to_string()));
+        if let Some((first, last)) = used.get(tag) {
+            item.insert("first_used".to_string(), AttributeValue::S(first.clone()));
+            item.insert("last_used".to_string(), AttributeValue::S(last.clone()));
+        }
+        items.push(item);
+    }
+
+    for ((a, b), count) in &delta.pairs {
+        let mut item = HashMap::new();
+        item.insert("stats_pk".to_string(), AttributeValue::S(pk.clone()));
+        item.insert("stats_sk".to_string(), AttributeValue::S(pair_sk(a, b)));
+        item.insert("tag_a".to_string(), AttributeValue::S(a.clone()));
+        item.insert("tag_b".to_string(), AttributeValue::S(b.clone()));
+        item.insert("count".to_string(), AttributeValue::N(count.to_string()));
+        items.push(item);
+    }
+
+    items
+}
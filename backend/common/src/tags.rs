@@ -0,0 +1,79 @@
+// Tag normalization and hierarchy helpers
+//
+// Tags are stored lowercased with surrounding and repeated whitespace removed,
+// so "Work", "work" and "work " are the same tag. A `/` separates levels of a
+// hierarchical tag: `work/meetings` is a child of `work`, and counts for a
+// child roll up into each of its ancestors.
+
+use std::collections::BTreeSet;
+
+use crate::JournalError;
+
+/// Separator between levels of a hierarchical tag
+pub const TAG_SEPARATOR: char = '/';
+
+/// Most tags one entry can carry. Tag statistics count every pair of an
+/// entry's tags, so this also bounds the pair updates per entry write.
+pub const MAX_TAGS_PER_ENTRY: usize = 20;
+
+/// Canonical form of a tag, or None if nothing is left after trimming
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let segments: Vec<String> = tag
+        .split(TAG_SEPARATOR)
+        .map(|segment| segment.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join(&TAG_SEPARATOR.to_string()))
+    }
+}
+
+/// Normalize a list of tags, dropping empty ones and duplicates (sorted)
+pub fn normalize_tags<I, S>(tags: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    tags.into_iter()
+        .filter_map(|tag| normalize_tag(tag.as_ref()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Reject a normalized tag list longer than MAX_TAGS_PER_ENTRY
+pub fn check_tag_count(tags: &[String]) -> Result<(), JournalError> {
+    if tags.len() > MAX_TAGS_PER_ENTRY {
+        return Err(JournalError::ValidationError(format!(
+            "An entry can have at most {} tags",
+            MAX_TAGS_PER_ENTRY
+        )));
+    }
+    Ok(())
+}
+
+/// Ancestors of a normalized tag from the root down: `a/b/c` -> [`a`, `a/b`]
+pub fn tag_ancestors(tag: &str) -> Vec<String> {
+    tag.match_indices(TAG_SEPARATOR)
+        .map(|(i, _)| tag[..i].to_string())
+        .collect()
+}
+
+/// Whether a normalized tag is `root` itself or one of its descendants
+pub fn tag_is_within(tag: &str, root: &str) -> bool {
+    tag == root
+        || (tag.len() > root.len() && tag.starts_with(root) && tag[root.len()..].starts_with(TAG_SEPARATOR))
+}
+
+/// Move a tag from under `from` to under `to`, keeping any child path.
+/// Returns None when the tag is not within `from`.
+pub fn reparent_tag(tag: &str, from: &str, to: &str) -> Option<String> {
+    if tag_is_within(tag, from) {
+        Some(format!("{}{}", to, &tag[from.len()..]))
+    } else {
+        None
+    }
+}
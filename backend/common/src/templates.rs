@@ -0,0 +1,259 @@
+// Entry templates: a Markdown skeleton plus typed fields
+//
+// A template's `body` pre-fills the content of new entries. Its fields are
+// filled in alongside the text and stored on the entry as structured data:
+//
+//   template_id  = <template id>
+//   field_values = M { <field key>: N (rating) | L of S (checked items) | S (short text) }
+//
+// Values are validated against the template when an entry is written, so
+// analytics can chart a field over time without re-checking types.
+//
+// Templates are keyed like notebooks: (id, tenant_id), listed per user through
+// the UserIndex GSI on (tenant_id, user_id).
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use crate::JournalError;
+
+pub const RATING_MIN: i64 = 1;
+pub const RATING_MAX: i64 = 10;
+pub const SHORT_TEXT_MAX_LENGTH: usize = 280;
+
+/// Kind of value a template field holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldType {
+    /// Whole number from RATING_MIN to RATING_MAX
+    Rating,
+    /// Subset of the listed items that were checked off
+    Checklist { items: Vec<String> },
+    /// Single line of text up to SHORT_TEXT_MAX_LENGTH characters
+    ShortText,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateField {
+    /// Stable identifier used as the key in an entry's fields
+    pub key: String,
+    pub label: String,
+    #[serde(flatten)]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Markdown skeleton for the entry content
+    pub body: String,
+    pub fields: Vec<TemplateField>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+pub fn templates_table() -> String {
+    std::env::var("TEMPLATES_TABLE").unwrap_or_else(|_| "reflekt-templates".to_string())
+}
+
+/// Check a template's field definitions: keys must be unique identifiers and
+/// checklists need at least one distinct item
+pub fn validate_template_fields(fields: &[TemplateField]) -> Result<(), JournalError> {
+    let mut keys = HashSet::new();
+
+    for field in fields {
+        let valid_key = !field.key.is_empty()
+            && field.key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_key {
+            return Err(JournalError::ValidationError(format!(
+                "Invalid field key '{}': use letters, digits, '_' or '-'",
+                field.key
+            )));
+        }
+        if !keys.insert(field.key.as_str()) {
+            return Err(JournalError::ValidationError(format!("Duplicate field key '{}'", field.key)));
+        }
+        if let FieldType::Checklist { items } = &field.field_type {
+            let distinct: HashSet<&String> = items.iter().collect();
+            if items.is_empty() || distinct.len() != items.len() || items.iter().any(|i| i.trim().is_empty()) {
+                return Err(JournalError::ValidationError(format!(
+                    "Checklist '{}' needs one or more distinct, non-empty items",
+                    field.key
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate submitted field values against a template and convert them to the
+/// stored map. Unknown keys and values of the wrong type are rejected.
+pub fn field_values_to_attribute(
+    template: &EntryTemplate,
+    values: &serde_json::Map<String, Value>,
+) -> Result<HashMap<String, AttributeValue>, JournalError> {
+    if let Some(unknown) = values.keys().find(|k| !template.fields.iter().any(|f| &f.key == *k)) {
+        return Err(JournalError::ValidationError(format!("Unknown field '{}'", unknown)));
+    }
+
+    let mut stored = HashMap::new();
+    for field in &template.fields {
+        let value = match values.get(&field.key) {
+            Some(Value::Null) | None => {
+                if field.required {
+                    return Err(JournalError::ValidationError(format!("Field '{}' is required", field.key)));
+                }
+                continue;
+            }
+            Some(value) => value,
+        };
+
+        let invalid = |expected: &str| {
+            JournalError::ValidationError(format!("Field '{}' must be {}", field.key, expected))
+        };
+
+        let attribute = match &field.field_type {
+            FieldType::Rating => {
+                let rating = value
+                    .as_i64()
+                    .filter(|r| (RATING_MIN..=RATING_MAX).contains(r))
+                    .ok_or_else(|| invalid(&format!("a whole number from {} to {}", RATING_MIN, RATING_MAX)))?;
+                AttributeValue::N(rating.to_string())
+            }
+            FieldType::Checklist { items } => {
+                let checked = value.as_array().ok_or_else(|| invalid("a list of checked items"))?;
+                let mut seen = HashSet::new();
+                let mut list = Vec::new();
+                for item in checked {
+                    let item = item
+                        .as_str()
+                        .filter(|i| items.iter().any(|known| known == i))
+                        .ok_or_else(|| invalid("a list of the checklist's items"))?;
+                    if seen.insert(item) {
+                        list.push(AttributeValue::S(item.to_string()));
+                    }
+                }
+                AttributeValue::L(list)
+            }
+            FieldType::ShortText => {
+                let text = value
+                    .as_str()
+                    .map(str::trim)
+                    .filter(|t| t.chars().count() <= SHORT_TEXT_MAX_LENGTH && !t.contains('\n'))
+                    .ok_or_else(|| invalid(&format!("a single line of at most {} characters", SHORT_TEXT_MAX_LENGTH)))?;
+                AttributeValue::S(text.to_string())
+            }
+        };
+        stored.insert(field.key.clone(), attribute);
+    }
+
+    Ok(stored)
+}
+
+/// Stored field values of an entry as JSON, for API responses
+pub fn field_values_to_json(fields: &HashMap<String, AttributeValue>) -> serde_json::Map<String, Value> {
+    fields
+        .iter()
+        .filter_map(|(key, value)| {
+            let json = match value {
+                AttributeValue::N(n) => n.parse::<i64>().ok().map(Value::from)?,
+                AttributeValue::S(s) => Value::String(s.clone()),
+                AttributeValue::L(items) => Value::Array(
+                    items.iter().filter_map(|i| i.as_s().ok()).map(|s| Value::String(s.clone())).collect(),
+                ),
+                _ => return None,
+            };
+            Some((key.clone(), json))
+        })
+        .collect()
+}
+
+/// Numeric value of a stored field for charting: the rating itself, or the
+/// share of checklist items checked. Short text has no numeric value.
+/// Checked items since removed from the template don't count.
+pub fn field_numeric_value(field: &TemplateField, value: &AttributeValue) -> Option<f64> {
+    match (&field.field_type, value) {
+        (FieldType::Rating, AttributeValue::N(n)) => n.parse().ok(),
+        (FieldType::Checklist { items }, AttributeValue::L(checked)) if !items.is_empty() => {
+            let checked: HashSet<&String> = checked
+                .iter()
+                .filter_map(|v| v.as_s().ok())
+                .filter(|item| items.contains(item))
+                .collect();
+            Some((checked.len() as f64 / items.len() as f64).min(1.0))
+        }
+        _ => None,
+    }
+}
+
+pub fn item_to_template(item: &HashMap<String, AttributeValue>) -> EntryTemplate {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+
+    EntryTemplate {
+        id: get_s("id").unwrap_or_default(),
+        name: get_s("name").unwrap_or_default(),
+        description: get_s("description"),
+        body: get_s("body").unwrap_or_default(),
+        // Field definitions are stored as a JSON document
+        fields: get_s("fields")
+            .and_then(|f| serde_json::from_str(&f).ok())
+            .unwrap_or_default(),
+        created_at: get_s("created_at"),
+        updated_at: get_s("updated_at"),
+    }
+}
+
+pub fn template_to_item(tenant_id: &str, user_id: &str, template: &EntryTemplate) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("id".to_string(), AttributeValue::S(template.id.clone()));
+    item.insert("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string()));
+    item.insert("user_id".to_string(), AttributeValue::S(user_id.to_string()));
+    item.insert("name".to_string(), AttributeValue::S(template.name.clone()));
+    item.insert("body".to_string(), AttributeValue::S(template.body.clone()));
+    item.insert(
+        "fields".to_string(),
+        AttributeValue::S(serde_json::to_string(&template.fields).unwrap_or_else(|_| "[]".to_string())),
+    );
+
+    if let Some(description) = &template.description {
+        item.insert("description".to_string(), AttributeValue::S(description.clone()));
+    }
+    if let Some(created_at) = &template.created_at {
+        item.insert("created_at".to_string(), AttributeValue::S(created_at.clone()));
+    }
+    if let Some(updated_at) = &template.updated_at {
+        item.insert("updated_at".to_string(), AttributeValue::S(updated_at.clone()));
+    }
+
+    item
+}
+
+/// Load one of the user's templates
+pub async fn get_template(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    template_id: &str,
+) -> Result<Option<EntryTemplate>, JournalError> {
+    let response = client
+        .get_item()
+        .table_name(templates_table())
+        .key("id", AttributeValue::S(template_id.to_string()))
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to fetch template: {}", e)))?;
+
+    Ok(response
+        .item
+        .filter(|item| item.get("user_id").and_then(|v| v.as_s().ok()).map(String::as_str) == Some(user_id))
+        .map(|item| item_to_template(&item)))
+}
@@ -0,0 +1,102 @@
+// Offline stand-ins for DynamoDB and AI providers in tests
+//
+// `EmptyJsonServer` answers every HTTP request with `{}`. As a DynamoDB
+// endpoint it behaves like empty tables: reads find nothing and writes
+// succeed. As an AI provider endpoint it never gives a usable reply, so a
+// failover chain moves on to its next provider.
+//
+// Tests that set AI configuration in the environment hold `lock_env` while
+// they run, since the environment is shared by every test in the binary.
+
+use aws_sdk_dynamodb::config::retry::RetryConfig;
+use aws_sdk_dynamodb::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, MutexGuard};
+
+const EMPTY_JSON_RESPONSE: &[u8] =
+    b"HTTP/1.1 200 OK\r\nContent-Type: application/x-amz-json-1.0\r\nContent-Length: 2\r\n\r\n{}";
+
+static ENV_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Serializes tests that change the environment
+pub async fn lock_env() -> MutexGuard<'static, ()> {
+    ENV_LOCK.lock().await
+}
+
+/// Configure the AI features to use the scripted mock provider. `providers`
+/// is the AI_PROVIDER chain and `replies` the mock's script.
+pub fn set_mock_ai(providers: &str, replies: &[&str]) {
+    std::env::set_var("AI_PROVIDER", providers);
+    std::env::set_var("AI_MOCK_RESPONSES", serde_json::to_string(replies).unwrap_or_default());
+    std::env::set_var("AI_MAX_RETRIES", "0");
+    // High enough that failures in one test never open a circuit for another
+    std::env::set_var("AI_BREAKER_THRESHOLD", "1000");
+    for key in ["AI_MODEL", "AI_REDACTION", "AI_QUOTA_ACTION", "ANTHROPIC_API_KEY", "ANTHROPIC_BASE_URL"] {
+        std::env::remove_var(key);
+    }
+}
+
+/// HTTP server on a local port that answers every request with `{}`
+pub struct EmptyJsonServer {
+    pub url: String,
+}
+
+impl EmptyJsonServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind test server");
+        let url = format!("http://{}", listener.local_addr().expect("test server address"));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream));
+            }
+        });
+        EmptyJsonServer { url }
+    }
+
+    /// DynamoDB client for this server, with test credentials and no retries
+    pub fn dynamo_client(&self) -> DynamoDbClient {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .endpoint_url(&self.url)
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .retry_config(RetryConfig::disabled())
+            .build();
+        DynamoDbClient::from_conf(config)
+    }
+}
+
+// Answer each request on a kept-alive connection until the client closes it
+async fn serve_connection(mut stream: TcpStream) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let Some(header_end) = buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4) else {
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+            continue;
+        };
+
+        let headers = String::from_utf8_lossy(&buffer[..header_end]).to_lowercase();
+        let body_length: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0);
+        while buffer.len() < header_end + body_length {
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+
+        buffer.drain(..header_end + body_length);
+        if stream.write_all(EMPTY_JSON_RESPONSE).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// Text statistics for entry content
+//
+// Metrics are computed on the Markdown-stripped text (see `plain_text`) with
+// Unicode word and sentence segmentation (UAX #29), so markup isn't counted
+// and scripts written without spaces are: every CJK ideograph or kana counts
+// as one word. They are stored on the entry item on every write:
+//
+//   word_count, character_count, sentence_count, reading_time_minutes,
+//   readability_score (only for text that is mostly in Latin script)
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::plain_text;
+
+/// Reading speed for space-separated scripts
+pub const WORDS_PER_MINUTE: f64 = 230.0;
+/// Reading speed for Chinese and Japanese, in characters
+pub const CJK_CHARACTERS_PER_MINUTE: f64 = 500.0;
+
+// Share of Latin-script words needed before a readability score is given
+const READABILITY_MIN_LATIN_SHARE: f64 = 0.8;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextMetrics {
+    pub word_count: i64,
+    /// Grapheme clusters, not counting whitespace
+    pub character_count: i64,
+    pub sentence_count: i64,
+    /// Estimated reading time, rounded up; 0 only for empty text
+    pub reading_time_minutes: i64,
+    /// Flesch reading ease from 0 (very hard) to 100 (very easy)
+    pub readability_score: Option<f64>,
+}
+
+impl TextMetrics {
+    /// Metrics of Markdown content, ignoring markup
+    pub fn from_markdown(markdown: &str) -> Self {
+        Self::from_text(&plain_text(markdown))
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        let words: Vec<&str> = text.unicode_words().collect();
+
+        let cjk_characters: usize = words
+            .iter()
+            .filter(|w| w.chars().any(is_cjk))
+            .map(|w| w.chars().filter(|c| is_cjk(*c)).count())
+            .sum();
+        let other_words = words.iter().filter(|w| !w.chars().any(is_cjk)).count();
+
+        let minutes = other_words as f64 / WORDS_PER_MINUTE + cjk_characters as f64 / CJK_CHARACTERS_PER_MINUTE;
+        let reading_time_minutes = if words.is_empty() { 0 } else { (minutes.ceil() as i64).max(1) };
+
+        let sentence_count = text
+            .unicode_sentences()
+            .filter(|s| s.unicode_words().next().is_some())
+            .count();
+
+        TextMetrics {
+            word_count: words.len() as i64,
+            character_count: text.graphemes(true).filter(|g| !g.trim().is_empty()).count() as i64,
+            sentence_count: sentence_count as i64,
+            reading_time_minutes,
+            readability_score: flesch_reading_ease(&words, sentence_count),
+        }
+    }
+
+    /// Metrics stored on an entry item, if it has them
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let get_n = |key: &str| item.get(key).and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<f64>().ok());
+
+        // Entries written before the full set existed only carry word_count
+        get_n("character_count")?;
+
+        Some(TextMetrics {
+            word_count: get_n("word_count").unwrap_or(0.0) as i64,
+            character_count: get_n("character_count").unwrap_or(0.0) as i64,
+            sentence_count: get_n("sentence_count").unwrap_or(0.0) as i64,
+            reading_time_minutes: get_n("reading_time_minutes").unwrap_or(0.0) as i64,
+            readability_score: get_n("readability_score"),
+        })
+    }
+
+    /// Attributes to store; readability_score is absent when there is no score
+    pub fn to_attributes(&self) -> Vec<(&'static str, AttributeValue)> {
+        let mut attributes = vec![
+            ("word_count", AttributeValue::N(self.word_count.to_string())),
+            ("character_count", AttributeValue::N(self.character_count.to_string())),
+            ("sentence_count", AttributeValue::N(self.sentence_count.to_string())),
+            ("reading_time_minutes", AttributeValue::N(self.reading_time_minutes.to_string())),
+        ];
+        if let Some(score) = self.readability_score {
+            attributes.push(("readability_score", AttributeValue::N(score.to_string())));
+        }
+        attributes
+    }
+}
+
+// Chinese ideographs and Japanese kana, which are written without spaces
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+            | 0x31F0..=0x31FF   // Katakana phonetic extensions
+            | 0x3400..=0x4DBF   // CJK Extension A
+            | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+            | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+            | 0xFF66..=0xFF9F   // Halfwidth Katakana
+            | 0x20000..=0x2FA1F // CJK Extensions B and later
+    )
+}
+
+// Letters of the Latin script, up to Latin Extended-B
+fn is_latin_word(word: &str) -> bool {
+    word.chars().all(|c| c.is_alphabetic() && (c as u32) <= 0x024F)
+}
+
+// Flesch reading ease over the Latin-script words. Other scripts have no
+// comparable syllable structure, so text that is mostly non-Latin gets none.
+fn flesch_reading_ease(words: &[&str], sentences: usize) -> Option<f64> {
+    let latin: Vec<&str> = words.iter().copied().filter(|w| is_latin_word(w)).collect();
+    if latin.is_empty() || sentences == 0 || (latin.len() as f64) < words.len() as f64 * READABILITY_MIN_LATIN_SHARE {
+        return None;
+    }
+
+    let syllables: usize = latin.iter().map(|w| count_syllables(w)).sum();
+    let words_per_sentence = latin.len() as f64 / sentences as f64;
+    let syllables_per_word = syllables as f64 / latin.len() as f64;
+
+    let score = 206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word;
+    Some((score.clamp(0.0, 100.0) * 10.0).round() / 10.0)
+}
+
+// Vowel groups, less a silent final "e"; every word has at least one syllable
+fn count_syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let is_vowel = |c: char| "aeiouyàáâäèéêëìíîïòóôöùúûü".contains(c);
+
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+
+    if count > 1 && word.ends_with('e') && !word.ends_with("le") && !word.ends_with("ee") {
+        count -= 1;
+    }
+    count.max(1)
+}
@@ -0,0 +1,71 @@
+// Time capsules: entries sealed until a chosen date
+//
+// A time capsule is an entry with an `unlock_at` timestamp. Until that moment
+// it is sealed: reads return a placeholder instead of its content, search,
+// analytics and link resolution skip it, and it cannot be edited.
+//
+// Sealed capsules also carry `capsule_pk = SEALED`, which puts them in the
+// sparse CapsuleIndex GSI on (capsule_pk, unlock_at). The unlock job queries it
+// for capsules that are due, publishes TimeCapsuleUnlocked and removes
+// capsule_pk, so every capsule is announced once. Until then the capsule's
+// mood, words and tags are kept out of the calendar rollups and tag
+// statistics; the unlock job adds them.
+//
+// Timestamps are stored as RFC 3339 UTC with second precision, so they compare
+// correctly as strings in key conditions and filters.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use std::collections::HashMap;
+
+use crate::JournalError;
+
+pub const CAPSULE_INDEX: &str = "CapsuleIndex";
+/// capsule_pk value of capsules that have not been announced as unlocked
+pub const CAPSULE_SEALED: &str = "SEALED";
+
+/// Stored form of an unlock time
+pub fn format_unlock_at(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Parse a requested unlock time: an RFC 3339 timestamp, or a date that
+/// unlocks at midnight UTC. It must lie in the future.
+pub fn parse_unlock_at(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, JournalError> {
+    let unlock_at = DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        })
+        .map_err(|_| JournalError::ValidationError("unlock_at must be an RFC 3339 timestamp or YYYY-MM-DD".into()))?;
+
+    if unlock_at <= now {
+        return Err(JournalError::ValidationError("unlock_at must be in the future".into()));
+    }
+    Ok(unlock_at)
+}
+
+/// Unlock time of an entry item, if it is a time capsule
+pub fn entry_unlock_at(item: &HashMap<String, AttributeValue>) -> Option<DateTime<Utc>> {
+    item.get("unlock_at")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Whether an entry item is a time capsule that is still sealed
+pub fn is_sealed(item: &HashMap<String, AttributeValue>, now: DateTime<Utc>) -> bool {
+    entry_unlock_at(item).is_some_and(|unlock_at| unlock_at > now)
+}
+
+/// Whether an entry item is a capsule the unlock job hasn't processed yet,
+/// so it isn't counted in rollups and tag statistics
+pub fn awaiting_unlock(item: &HashMap<String, AttributeValue>) -> bool {
+    item.contains_key("capsule_pk")
+}
+
+/// Filter expression excluding sealed time capsules, binding `:now`
+pub fn unsealed_filter_expression(expression_values: &mut HashMap<String, AttributeValue>) -> String {
+    expression_values.insert(":now".to_string(), AttributeValue::S(format_unlock_at(Utc::now())));
+    "(attribute_not_exists(unlock_at) OR unlock_at <= :now)".to_string()
+}
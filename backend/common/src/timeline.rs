@@ -0,0 +1,81 @@
+// Timeline key design for the entries table
+//
+// Entries are keyed by (id, tenant_id), which is fine for point reads but means
+// date-range views have to read a user's whole partition through UserIndex and
+// filter afterwards. Every entry therefore also carries a composite key that is
+// projected into the TimelineIndex GSI:
+//
+//   timeline_pk = USER#<tenant_id>#<user_id>
+//   timeline_sk = TS#<created_at>#<entry_id>
+//
+// created_at is always written as an RFC 3339 UTC timestamp, so lexical order on
+// timeline_sk is chronological order and date ranges become key conditions.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use std::collections::HashMap;
+
+use crate::JournalError;
+
+/// Name of the GSI keyed on (timeline_pk, timeline_sk)
+pub const TIMELINE_INDEX: &str = "TimelineIndex";
+
+/// Partition key shared by all entries of one user
+pub fn timeline_pk(tenant_id: &str, user_id: &str) -> String {
+    format!("USER#{}#{}", tenant_id, user_id)
+}
+
+/// Sort key for a single entry; the entry id keeps keys unique within a timestamp
+pub fn timeline_sk(created_at: &str, entry_id: &str) -> String {
+    format!("TS#{}#{}", created_at, entry_id)
+}
+
+/// Inclusive sort key bounds for a date range.
+///
+/// Either bound may be a full RFC 3339 timestamp or a bare `YYYY-MM-DD` date.
+/// The upper bound is suffixed with `~` (which sorts after every character used
+/// in timestamps and ids) so that an end date covers the whole day.
+pub fn timeline_bounds(start: Option<&str>, end: Option<&str>) -> (String, String) {
+    let lower = format!("TS#{}", start.unwrap_or(""));
+    let upper = format!("TS#{}~", end.unwrap_or(""));
+    (lower, upper)
+}
+
+/// Query a user's entries in chronological order, following pagination until
+/// the whole range has been read.
+pub async fn query_timeline(
+    client: &DynamoDbClient,
+    table_name: &str,
+    tenant_id: &str,
+    user_id: &str,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Vec<HashMap<String, AttributeValue>>, JournalError> {
+    let (lower, upper) = timeline_bounds(start, end);
+    let mut items = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(table_name)
+            .index_name(TIMELINE_INDEX)
+            .key_condition_expression("timeline_pk = :pk AND timeline_sk BETWEEN :lower AND :upper")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .expression_attribute_values(":lower", AttributeValue::S(lower.clone()))
+            .expression_attribute_values(":upper", AttributeValue::S(upper.clone()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query timeline: {}", e)))?;
+
+        items.extend(response.items().iter().cloned());
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(items)
+}
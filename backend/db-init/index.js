@@ -58,6 +58,11 @@ async function createDynamoDBTables() {
         { AttributeName: 'tenant_id', AttributeType: 'S' },
         { AttributeName: 'user_id', AttributeType: 'S' },
         { AttributeName: 'created_at', AttributeType: 'S' },
+        { AttributeName: 'timeline_pk', AttributeType: 'S' },
+        { AttributeName: 'timeline_sk', AttributeType: 'S' },
+        { AttributeName: 'capsule_pk', AttributeType: 'S' },
+        { AttributeName: 'unlock_at', AttributeType: 'S' },
+        { AttributeName: 'title_pk', AttributeType: 'S' },
       ],
       GlobalSecondaryIndexes: [
         {
@@ -88,6 +93,48 @@ async function createDynamoDBTables() {
             WriteCapacityUnits: 5,
           },
         },
+        {
+          IndexName: 'TimelineIndex',
+          KeySchema: [
+            { AttributeName: 'timeline_pk', KeyType: 'HASH' },
+            { AttributeName: 'timeline_sk', KeyType: 'RANGE' },
+          ],
+          Projection: {
+            ProjectionType: 'ALL',
+          },
+          ProvisionedThroughput: {
+            ReadCapacityUnits: 5,
+            WriteCapacityUnits: 5,
+          },
+        },
+        {
+          IndexName: 'CapsuleIndex',
+          KeySchema: [
+            { AttributeName: 'capsule_pk', KeyType: 'HASH' },
+            { AttributeName: 'unlock_at', KeyType: 'RANGE' },
+          ],
+          Projection: {
+            ProjectionType: 'ALL',
+          },
+          ProvisionedThroughput: {
+            ReadCapacityUnits: 5,
+            WriteCapacityUnits: 5,
+          },
+        },
+        {
+          IndexName: 'TitleIndex',
+          KeySchema: [
+            { AttributeName: 'title_pk', KeyType: 'HASH' },
+            { AttributeName: 'timeline_sk', KeyType: 'RANGE' },
+          ],
+          Projection: {
+            ProjectionType: 'KEYS_ONLY',
+          },
+          ProvisionedThroughput: {
+            ReadCapacityUnits: 5,
+            WriteCapacityUnits: 5,
+          },
+        },
       ],
       BillingMode: 'PROVISIONED',
       ProvisionedThroughput: {
@@ -128,6 +175,72 @@ async function createDynamoDBTables() {
         WriteCapacityUnits: 5,
       },
     },
+    {
+      TableName: 'reflekt-notebooks',
+      KeySchema: [
+        { AttributeName: 'id', KeyType: 'HASH' },
+        { AttributeName: 'tenant_id', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'id', AttributeType: 'S' },
+        { AttributeName: 'tenant_id', AttributeType: 'S' },
+        { AttributeName: 'user_id', AttributeType: 'S' },
+      ],
+      GlobalSecondaryIndexes: [
+        {
+          IndexName: 'UserIndex',
+          KeySchema: [
+            { AttributeName: 'tenant_id', KeyType: 'HASH' },
+            { AttributeName: 'user_id', KeyType: 'RANGE' },
+          ],
+          Projection: {
+            ProjectionType: 'ALL',
+          },
+          ProvisionedThroughput: {
+            ReadCapacityUnits: 5,
+            WriteCapacityUnits: 5,
+          },
+        },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-templates',
+      KeySchema: [
+        { AttributeName: 'id', KeyType: 'HASH' },
+        { AttributeName: 'tenant_id', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'id', AttributeType: 'S' },
+        { AttributeName: 'tenant_id', AttributeType: 'S' },
+        { AttributeName: 'user_id', AttributeType: 'S' },
+      ],
+      GlobalSecondaryIndexes: [
+        {
+          IndexName: 'UserIndex',
+          KeySchema: [
+            { AttributeName: 'tenant_id', KeyType: 'HASH' },
+            { AttributeName: 'user_id', KeyType: 'RANGE' },
+          ],
+          Projection: {
+            ProjectionType: 'ALL',
+          },
+          ProvisionedThroughput: {
+            ReadCapacityUnits: 5,
+            WriteCapacityUnits: 5,
+          },
+        },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
     {
       TableName: 'reflekt-insights',
       KeySchema: [
@@ -177,6 +290,102 @@ async function createDynamoDBTables() {
         WriteCapacityUnits: 5,
       },
     },
+    {
+      TableName: 'reflekt-daily-rollups',
+      KeySchema: [
+        { AttributeName: 'rollup_pk', KeyType: 'HASH' },
+        { AttributeName: 'day', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'rollup_pk', AttributeType: 'S' },
+        { AttributeName: 'day', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-tag-stats',
+      KeySchema: [
+        { AttributeName: 'stats_pk', KeyType: 'HASH' },
+        { AttributeName: 'stats_sk', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'stats_pk', AttributeType: 'S' },
+        { AttributeName: 'stats_sk', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-entry-links',
+      KeySchema: [
+        { AttributeName: 'links_pk', KeyType: 'HASH' },
+        { AttributeName: 'link_sk', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'links_pk', AttributeType: 'S' },
+        { AttributeName: 'link_sk', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-goals',
+      KeySchema: [
+        { AttributeName: 'goals_pk', KeyType: 'HASH' },
+        { AttributeName: 'goal_sk', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'goals_pk', AttributeType: 'S' },
+        { AttributeName: 'goal_sk', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-ai-usage',
+      KeySchema: [
+        { AttributeName: 'tenant_id', KeyType: 'HASH' },
+        { AttributeName: 'usage_key', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'tenant_id', AttributeType: 'S' },
+        { AttributeName: 'usage_key', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
+    {
+      TableName: 'reflekt-prompt-templates',
+      KeySchema: [
+        { AttributeName: 'scope', KeyType: 'HASH' },
+        { AttributeName: 'template_key', KeyType: 'RANGE' },
+      ],
+      AttributeDefinitions: [
+        { AttributeName: 'scope', AttributeType: 'S' },
+        { AttributeName: 'template_key', AttributeType: 'S' },
+      ],
+      BillingMode: 'PROVISIONED',
+      ProvisionedThroughput: {
+        ReadCapacityUnits: 5,
+        WriteCapacityUnits: 5,
+      },
+    },
   ];
   
   // Create each table
@@ -0,0 +1,174 @@
+// Backfills AI insights for a tenant's entries
+//
+// Insights are produced when entries are written. Entries written before AI
+// analysis was enabled, or while it was failing or out of quota, have none,
+// and edits made while analysis was off leave stale ones. This tool scans a
+// tenant's entries and publishes AnalysisRequested for every entry without an
+// analysis of its current text, skipping sealed time capsules and notebooks
+// with AI analysis turned off. The AI service applies the user's opt-out and
+// the tenant's quota as usual.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev EVENT_BUS_NAME=reflekt-journal-events-dev \
+//     cargo run --release --bin backfill_insights -- --tenant <tenant_id> \
+//       [--user <user_id>] [--rate <events per second>] [--checkpoint <file>] [--dry-run]
+//
+// Events are published at no more than --rate per second (1 by default) to
+// spread the load on the AI provider. After each page of the scan the
+// position is saved to the checkpoint file (backfill-insights-<tenant_id>.json
+// by default); running the same command again resumes from there, and the
+// file is removed once the scan completes. The run stops early, keeping its
+// checkpoint, if the tenant's AI quota is used up with AI_QUOTA_ACTION=block.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{
+    ai_quota_exceeded, chrono, entry_notebook_id, get_dynamo_client, get_notebook, is_sealed,
+    lambda_runtime::Error, needs_analysis, request_analysis, serde_json, QuotaAction,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+// The scan position is a table key of string attributes
+fn load_checkpoint(path: &str) -> Result<Option<HashMap<String, AttributeValue>>, Error> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    let key: HashMap<String, String> = serde_json::from_str(&contents)
+        .map_err(|e| Error::from(format!("Invalid checkpoint file {}: {}", path, e)))?;
+    Ok(Some(key.into_iter().map(|(k, v)| (k, AttributeValue::S(v))).collect()))
+}
+
+fn save_checkpoint(path: &str, key: &HashMap<String, AttributeValue>) -> Result<(), Error> {
+    let key: HashMap<&String, &String> = key.iter().filter_map(|(k, v)| v.as_s().ok().map(|v| (k, v))).collect();
+    std::fs::write(path, serde_json::to_string(&key)?)
+        .map_err(|e| Error::from(format!("Failed to write checkpoint file {}: {}", path, e)))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let Some(tenant_id) = arg_value(&args, "--tenant") else {
+        return Err(Error::from("--tenant <tenant_id> is required"));
+    };
+    let only_user = arg_value(&args, "--user");
+    let rate: f64 = match arg_value(&args, "--rate") {
+        Some(rate) => rate
+            .parse()
+            .ok()
+            .filter(|r: &f64| r.is_finite() && *r > 0.0)
+            .ok_or_else(|| Error::from("--rate must be a positive number"))?,
+        None => 1.0,
+    };
+    let checkpoint_path = arg_value(&args, "--checkpoint")
+        .map(String::from)
+        .unwrap_or_else(|| format!("backfill-insights-{}.json", tenant_id));
+
+    let dynamo_client = get_dynamo_client().await;
+    let entries_table = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let interval = Duration::from_secs_f64(1.0 / rate);
+
+    let mut start_key = load_checkpoint(&checkpoint_path)?;
+    if start_key.is_some() {
+        tracing::info!("Resuming from checkpoint {}", checkpoint_path);
+    }
+    tracing::info!("Backfilling insights for tenant {} at {}/s (dry run: {})", tenant_id, rate, dry_run);
+
+    // Whether each (user, notebook) has AI analysis enabled
+    let mut notebooks_ai_enabled: HashMap<(String, String), bool> = HashMap::new();
+    let (mut scanned, mut requested, mut current, mut excluded) = (0, 0, 0, 0);
+
+    loop {
+        if !dry_run && ai_quota_exceeded(&dynamo_client, tenant_id).await == Some(QuotaAction::Block) {
+            tracing::warn!("Tenant {} has used its AI quota; stopping. Run again to resume.", tenant_id);
+            return Ok(());
+        }
+
+        let mut scan = dynamo_client
+            .scan()
+            .table_name(&entries_table)
+            .expression_attribute_values(":tenant_id", AttributeValue::S(tenant_id.to_string()))
+            .set_exclusive_start_key(start_key);
+        scan = match only_user {
+            Some(user_id) => scan
+                .filter_expression("tenant_id = :tenant_id AND user_id = :user_id")
+                .expression_attribute_values(":user_id", AttributeValue::S(user_id.to_string())),
+            None => scan.filter_expression("tenant_id = :tenant_id"),
+        };
+        let response = scan
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        let now = chrono::Utc::now();
+        for item in response.items() {
+            scanned += 1;
+            if !needs_analysis(item) {
+                current += 1;
+                continue;
+            }
+            if is_sealed(item, now) {
+                excluded += 1;
+                continue;
+            }
+
+            let user_id = item.get("user_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+            let notebook_id = entry_notebook_id(item).to_string();
+            let ai_enabled = match notebooks_ai_enabled.get(&(user_id.clone(), notebook_id.clone())) {
+                Some(enabled) => *enabled,
+                None => {
+                    let enabled = get_notebook(&dynamo_client, tenant_id, &user_id, &notebook_id)
+                        .await?
+                        .is_none_or(|n| n.ai_enabled);
+                    notebooks_ai_enabled.insert((user_id, notebook_id), enabled);
+                    enabled
+                }
+            };
+            if !ai_enabled {
+                excluded += 1;
+                continue;
+            }
+
+            requested += 1;
+            if dry_run {
+                continue;
+            }
+            request_analysis(item, true, false).await?;
+            tokio::time::sleep(interval).await;
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => {
+                if !dry_run {
+                    save_checkpoint(&checkpoint_path, key)?;
+                }
+                start_key = Some(key.clone());
+            }
+            _ => break,
+        }
+    }
+
+    if !dry_run {
+        let _ = std::fs::remove_file(&checkpoint_path);
+    }
+    tracing::info!(
+        "Done: {} entries scanned, {} analyses requested, {} already current, {} sealed or in notebooks without AI",
+        scanned,
+        requested,
+        current,
+        excluded
+    );
+    Ok(())
+}
@@ -0,0 +1,124 @@
+// One-off migration that backfills timeline keys on existing entries
+//
+// Entries written before TimelineIndex existed have no timeline_pk/timeline_sk
+// attributes and are therefore invisible to date-ordered queries. This tool scans
+// the entries table and sets both keys on every item that is missing them.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev cargo run --release --bin backfill_timeline_keys -- [--dry-run] [--resume <id>:<tenant_id>]
+//
+// Updates are conditional on the keys not existing yet, so the tool is safe to
+// re-run. The last scanned key is logged after every page; pass it to --resume
+// to continue an interrupted run.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{get_dynamo_client, lambda_runtime::Error, timeline_pk, timeline_sk};
+use std::collections::HashMap;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let mut start_key = args
+        .iter()
+        .position(|a| a == "--resume")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|key| parse_resume_key(key));
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    tracing::info!("Backfilling timeline keys on {} (dry run: {})", table_name, dry_run);
+
+    let mut scanned = 0;
+    let mut updated = 0;
+    let mut skipped = 0;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&table_name)
+            .filter_expression("attribute_not_exists(timeline_pk)")
+            .projection_expression("id, tenant_id, user_id, created_at")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        scanned += response.scanned_count();
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok());
+
+            let (Some(id), Some(tenant_id), Some(user_id), Some(created_at)) =
+                (get_s("id"), get_s("tenant_id"), get_s("user_id"), get_s("created_at"))
+            else {
+                tracing::warn!("Skipping malformed entry: {:?}", item.get("id"));
+                skipped += 1;
+                continue;
+            };
+
+            if dry_run {
+                tracing::info!("Would update entry {} in tenant {}", id, tenant_id);
+                updated += 1;
+                continue;
+            }
+
+            let result = dynamo_client
+                .update_item()
+                .table_name(&table_name)
+                .key("id", AttributeValue::S(id.clone()))
+                .key("tenant_id", AttributeValue::S(tenant_id.clone()))
+                .update_expression("SET timeline_pk = :pk, timeline_sk = :sk")
+                .condition_expression("attribute_exists(id) AND attribute_not_exists(timeline_pk)")
+                .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+                .expression_attribute_values(":sk", AttributeValue::S(timeline_sk(created_at, id)))
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => updated += 1,
+                Err(e) => {
+                    // A failed condition means another writer got there first
+                    tracing::warn!("Skipping entry {}: {}", id, e);
+                    skipped += 1;
+                }
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => {
+                tracing::info!(
+                    "Progress: scanned {}, updated {}, skipped {}; resume with --resume {}",
+                    scanned,
+                    updated,
+                    skipped,
+                    format_resume_key(key)
+                );
+                start_key = Some(key.clone());
+            }
+            _ => break,
+        }
+    }
+
+    tracing::info!("Done: scanned {}, updated {}, skipped {}", scanned, updated, skipped);
+    Ok(())
+}
+
+fn parse_resume_key(key: &str) -> Option<HashMap<String, AttributeValue>> {
+    let (id, tenant_id) = key.split_once(':')?;
+    let mut start_key = HashMap::new();
+    start_key.insert("id".to_string(), AttributeValue::S(id.to_string()));
+    start_key.insert("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string()));
+    Some(start_key)
+}
+
+fn format_resume_key(key: &HashMap<String, AttributeValue>) -> String {
+    let get_s = |k: &str| key.get(k).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+    format!("{}:{}", get_s("id"), get_s("tenant_id"))
+}
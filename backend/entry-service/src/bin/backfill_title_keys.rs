@@ -0,0 +1,128 @@
+// One-off migration that backfills title keys on existing entries
+//
+// Wiki links are resolved by title through TitleIndex. Entries written before
+// it existed have no title_pk attribute, so links to them by title stay
+// dangling. This tool scans the entries table and sets title_pk on every item
+// that is missing it, leaving out time capsules that haven't been unlocked yet.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev cargo run --release --bin backfill_title_keys -- [--dry-run] [--resume <id>:<tenant_id>]
+//
+// Updates are conditional on the key not existing yet and the title being the
+// one read, so the tool is safe to re-run. The last scanned key is logged after
+// every page; pass it to --resume to continue an interrupted run. Run
+// rebuild_entry_links afterwards to resolve links written to these titles.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{get_dynamo_client, lambda_runtime::Error, link_title_pk};
+use std::collections::HashMap;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let mut start_key = args
+        .iter()
+        .position(|a| a == "--resume")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|key| parse_resume_key(key));
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    tracing::info!("Backfilling title keys on {} (dry run: {})", table_name, dry_run);
+
+    let mut scanned = 0;
+    let mut updated = 0;
+    let mut skipped = 0;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&table_name)
+            .filter_expression(
+                "attribute_not_exists(title_pk) AND attribute_exists(title) AND attribute_not_exists(capsule_pk)",
+            )
+            .projection_expression("id, tenant_id, user_id, title")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        scanned += response.scanned_count();
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok());
+
+            let (Some(id), Some(tenant_id), Some(user_id), Some(title)) =
+                (get_s("id"), get_s("tenant_id"), get_s("user_id"), get_s("title"))
+            else {
+                tracing::warn!("Skipping malformed entry: {:?}", item.get("id"));
+                skipped += 1;
+                continue;
+            };
+
+            if dry_run {
+                tracing::info!("Would update entry {} in tenant {}", id, tenant_id);
+                updated += 1;
+                continue;
+            }
+
+            let result = dynamo_client
+                .update_item()
+                .table_name(&table_name)
+                .key("id", AttributeValue::S(id.clone()))
+                .key("tenant_id", AttributeValue::S(tenant_id.clone()))
+                .update_expression("SET title_pk = :title_pk")
+                .condition_expression("attribute_exists(id) AND attribute_not_exists(title_pk) AND title = :title")
+                .expression_attribute_values(":title_pk", AttributeValue::S(link_title_pk(tenant_id, user_id, title)))
+                .expression_attribute_values(":title", AttributeValue::S(title.clone()))
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => updated += 1,
+                Err(e) => {
+                    // A failed condition means the entry was edited or deleted meanwhile
+                    tracing::warn!("Skipping entry {}: {}", id, e);
+                    skipped += 1;
+                }
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => {
+                tracing::info!(
+                    "Progress: scanned {}, updated {}, skipped {}; resume with --resume {}",
+                    scanned,
+                    updated,
+                    skipped,
+                    format_resume_key(key)
+                );
+                start_key = Some(key.clone());
+            }
+            _ => break,
+        }
+    }
+
+    tracing::info!("Done: scanned {}, updated {}, skipped {}", scanned, updated, skipped);
+    Ok(())
+}
+
+fn parse_resume_key(key: &str) -> Option<HashMap<String, AttributeValue>> {
+    let (id, tenant_id) = key.split_once(':')?;
+    let mut start_key = HashMap::new();
+    start_key.insert("id".to_string(), AttributeValue::S(id.to_string()));
+    start_key.insert("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string()));
+    Some(start_key)
+}
+
+fn format_resume_key(key: &HashMap<String, AttributeValue>) -> String {
+    let get_s = |k: &str| key.get(k).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+    format!("{}:{}", get_s("id"), get_s("tenant_id"))
+}
@@ -0,0 +1,116 @@
+// Migrates free-text moods to the structured mood format
+//
+// Entries written before moods were structured store `mood` as a string. They
+// are parsed on read, but DynamoDB filters only match the structured map, so
+// this tool rewrites every string mood that maps onto the emotion taxonomy.
+// Moods that can't be mapped are left untouched and listed.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev \
+//     cargo run --release --bin migrate_moods -- [--dry-run] [--user <tenant_id>:<user_id>]
+//
+// Calendar rollups count moods by primary emotion; run rebuild_daily_rollups
+// afterwards so counters recorded under the old free-text names are replaced.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{get_dynamo_client, lambda_runtime::Error, Mood};
+use std::collections::HashMap;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let only_user = args
+        .iter()
+        .position(|a| a == "--user")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|u| u.split_once(':'))
+        .map(|(tenant_id, user_id)| (tenant_id.to_string(), user_id.to_string()));
+
+    let dynamo_client = get_dynamo_client().await;
+    let entries_table = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    tracing::info!("Migrating moods in {} (dry run: {})", entries_table, dry_run);
+
+    let mut migrated = 0;
+    let mut unmapped: HashMap<String, usize> = HashMap::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&entries_table)
+            .projection_expression("id, tenant_id, user_id, mood")
+            .filter_expression("attribute_type(mood, :string)")
+            .expression_attribute_values(":string", AttributeValue::S("S".to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            let (Some(id), Some(tenant_id), Some(user_id), Some(legacy)) =
+                (get_s("id"), get_s("tenant_id"), get_s("user_id"), get_s("mood"))
+            else {
+                continue;
+            };
+
+            if only_user.as_ref().is_some_and(|(t, u)| *t != tenant_id || *u != user_id) {
+                continue;
+            }
+
+            let Some(mood) = Mood::from_legacy(&legacy) else {
+                *unmapped.entry(legacy.trim().to_lowercase()).or_insert(0) += 1;
+                continue;
+            };
+
+            migrated += 1;
+            if dry_run {
+                tracing::info!("Entry {}: '{}' -> {}", id, legacy, mood);
+                continue;
+            }
+
+            // Only replace the string that was read, in case the entry changed since
+            let result = dynamo_client
+                .update_item()
+                .table_name(&entries_table)
+                .key("id", AttributeValue::S(id.clone()))
+                .key("tenant_id", AttributeValue::S(tenant_id))
+                .update_expression("SET mood = :mood")
+                .condition_expression("mood = :legacy")
+                .expression_attribute_values(":mood", mood.to_attribute())
+                .expression_attribute_values(":legacy", AttributeValue::S(legacy))
+                .send()
+                .await;
+
+            if let Err(e) = result {
+                tracing::warn!("Failed to migrate mood of entry {}: {}", id, e);
+                migrated -= 1;
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    let mut unmapped: Vec<(String, usize)> = unmapped.into_iter().collect();
+    unmapped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    for (text, count) in &unmapped {
+        tracing::info!("Unmapped mood '{}' on {} entries", text, count);
+    }
+
+    tracing::info!(
+        "Done: {} moods migrated, {} entries with unmapped moods left unchanged",
+        migrated,
+        unmapped.iter().map(|(_, count)| count).sum::<usize>()
+    );
+    Ok(())
+}
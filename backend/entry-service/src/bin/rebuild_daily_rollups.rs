@@ -0,0 +1,142 @@
+// Rebuilds the daily rollups used by the calendar heatmap
+//
+// Rollups are maintained incrementally on every entry write, but entries that
+// predate the rollups table have never been counted, and a timezone change
+// leaves history bucketed in the old zone. This tool recomputes every rollup
+// from the entries themselves and replaces what is stored.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev ROLLUPS_TABLE=reflekt-daily-rollups-dev \
+//     cargo run --release --bin rebuild_daily_rollups -- [--dry-run] [--user <tenant_id>:<user_id>]
+//
+// Run it while writes are quiet: an entry written between reading a user's
+// entries and replacing their rollups is not counted until the next rebuild.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{
+    get_dynamo_client, get_user_timezone, lambda_runtime::Error, rollup_item, rollups_from_entries,
+    timeline_pk,
+};
+use std::collections::{HashMap, HashSet};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let only_user = args
+        .iter()
+        .position(|a| a == "--user")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|u| u.split_once(':'))
+        .map(|(tenant_id, user_id)| (tenant_id.to_string(), user_id.to_string()));
+
+    let dynamo_client = get_dynamo_client().await;
+    let entries_table = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let rollups_table = std::env::var("ROLLUPS_TABLE").unwrap_or_else(|_| "reflekt-daily-rollups".to_string());
+
+    tracing::info!("Rebuilding daily rollups from {} (dry run: {})", entries_table, dry_run);
+
+    // Group every entry by owner
+    let mut entries_by_user: HashMap<(String, String), Vec<HashMap<String, AttributeValue>>> = HashMap::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&entries_table)
+            .projection_expression("tenant_id, user_id, created_at, word_count, sentiment_score, mood")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            let (Some(tenant_id), Some(user_id)) = (get_s("tenant_id"), get_s("user_id")) else {
+                continue;
+            };
+
+            let owner = (tenant_id, user_id);
+            if only_user.as_ref().is_some_and(|u| *u != owner) {
+                continue;
+            }
+            entries_by_user.entry(owner).or_default().push(item.clone());
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    let mut days_written = 0;
+    let mut days_removed = 0;
+
+    for ((tenant_id, user_id), items) in &entries_by_user {
+        let tz = get_user_timezone(&dynamo_client, tenant_id, user_id).await;
+        let days = rollups_from_entries(items, tz);
+        let fresh_days: HashSet<String> = days.keys().map(|d| d.to_string()).collect();
+
+        tracing::info!("User {}: {} entries over {} days ({})", user_id, items.len(), days.len(), tz.name());
+
+        if dry_run {
+            days_written += days.len();
+            continue;
+        }
+
+        // Drop rollups for days that no longer have entries (e.g. after a timezone change)
+        let existing = dynamo_client
+            .query()
+            .table_name(&rollups_table)
+            .key_condition_expression("rollup_pk = :pk")
+            .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+            .projection_expression("#day")
+            .expression_attribute_names("#day", "day")
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to query rollups: {}", e)))?;
+
+        for item in existing.items() {
+            let Some(day) = item.get("day").and_then(|v| v.as_s().ok()) else {
+                continue;
+            };
+            if fresh_days.contains(day) {
+                continue;
+            }
+
+            dynamo_client
+                .delete_item()
+                .table_name(&rollups_table)
+                .key("rollup_pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+                .key("day", AttributeValue::S(day.clone()))
+                .send()
+                .await
+                .map_err(|e| Error::from(format!("Failed to delete rollup: {}", e)))?;
+            days_removed += 1;
+        }
+
+        for (day, delta) in &days {
+            dynamo_client
+                .put_item()
+                .table_name(&rollups_table)
+                .set_item(Some(rollup_item(tenant_id, user_id, *day, delta)))
+                .send()
+                .await
+                .map_err(|e| Error::from(format!("Failed to write rollup: {}", e)))?;
+            days_written += 1;
+        }
+    }
+
+    tracing::info!(
+        "Done: {} users, {} days written, {} stale days removed",
+        entries_by_user.len(),
+        days_written,
+        days_removed
+    );
+    Ok(())
+}
@@ -0,0 +1,165 @@
+// Rebuilds the wiki link graph from the entries table
+//
+// Links are maintained on every entry write. Entries written before the links
+// table existed were never parsed, so this tool re-reads every user's entries,
+// resolves their `[[...]]` links and replaces the stored partition.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev LINKS_TABLE=reflekt-entry-links-dev \
+//     cargo run --release --bin rebuild_entry_links -- [--dry-run] [--user <tenant_id>:<user_id>]
+//
+// Run it while writes are quiet: an entry edited between reading the entries
+// and replacing the links keeps stale links until it is saved again.
+
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, PutRequest, WriteRequest};
+use journal_common::{get_dynamo_client, lambda_runtime::Error, link_items, timeline_pk, LinkResolver};
+use std::collections::HashMap;
+
+// (created_at, id, title, content) of one entry
+type EntryText = (String, String, String, String);
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let only_user = args
+        .iter()
+        .position(|a| a == "--user")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|u| u.split_once(':'))
+        .map(|(tenant_id, user_id)| (tenant_id.to_string(), user_id.to_string()));
+
+    let dynamo_client = get_dynamo_client().await;
+    let entries_table = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let links_table = std::env::var("LINKS_TABLE").unwrap_or_else(|_| "reflekt-entry-links".to_string());
+
+    tracing::info!("Rebuilding entry links from {} (dry run: {})", entries_table, dry_run);
+
+    let mut entries_by_user: HashMap<(String, String), Vec<EntryText>> = HashMap::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&entries_table)
+            .projection_expression("tenant_id, user_id, id, title, content, created_at")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            let (Some(tenant_id), Some(user_id), Some(id)) = (get_s("tenant_id"), get_s("user_id"), get_s("id")) else {
+                continue;
+            };
+
+            let owner = (tenant_id, user_id);
+            if only_user.as_ref().is_some_and(|u| *u != owner) {
+                continue;
+            }
+
+            entries_by_user.entry(owner).or_default().push((
+                get_s("created_at").unwrap_or_default(),
+                id,
+                get_s("title").unwrap_or_default(),
+                get_s("content").unwrap_or_default(),
+            ));
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    let mut links_written = 0;
+
+    for ((tenant_id, user_id), entries) in entries_by_user.iter_mut() {
+        // Oldest first, so duplicate titles resolve the same way as on write
+        entries.sort();
+        let resolver = LinkResolver::from_entries(entries.iter().map(|(_, id, title, _)| (id.clone(), title.clone())));
+
+        let mut items = Vec::new();
+        for (_, id, _, content) in entries.iter() {
+            for (key, link) in resolver.resolve_all(id, content) {
+                let dangling_title = key.starts_with('?').then_some(link.target.as_str());
+                items.extend(link_items(tenant_id, user_id, id, &key, dangling_title));
+            }
+        }
+        tracing::info!("User {}: {} entries, {} links", user_id, entries.len(), items.len() / 2);
+        links_written += items.len() / 2;
+
+        if dry_run {
+            continue;
+        }
+
+        // Clear the existing partition, then write the fresh items
+        let mut deletes = Vec::new();
+        let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let response = dynamo_client
+                .query()
+                .table_name(&links_table)
+                .key_condition_expression("links_pk = :pk")
+                .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+                .projection_expression("links_pk, link_sk")
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| Error::from(format!("Failed to query entry links: {}", e)))?;
+
+            for key in response.items() {
+                let request = DeleteRequest::builder()
+                    .set_key(Some(key.clone()))
+                    .build()
+                    .map_err(|e| Error::from(format!("Failed to build delete request: {}", e)))?;
+                deletes.push(WriteRequest::builder().delete_request(request).build());
+            }
+
+            match response.last_evaluated_key() {
+                Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+                _ => break,
+            }
+        }
+
+        let mut puts = Vec::new();
+        for item in items {
+            let request = PutRequest::builder()
+                .set_item(Some(item))
+                .build()
+                .map_err(|e| Error::from(format!("Failed to build put request: {}", e)))?;
+            puts.push(WriteRequest::builder().put_request(request).build());
+        }
+
+        // Deletes go first so a key present in both ends up with the fresh item
+        for requests in [deletes, puts] {
+            // BatchWriteItem accepts at most 25 requests
+            for chunk in requests.chunks(25) {
+                let mut pending = chunk.to_vec();
+                while !pending.is_empty() {
+                    let response = dynamo_client
+                        .batch_write_item()
+                        .request_items(&links_table, pending)
+                        .send()
+                        .await
+                        .map_err(|e| Error::from(format!("Failed to write entry links: {}", e)))?;
+
+                    pending = response
+                        .unprocessed_items()
+                        .and_then(|u| u.get(&links_table))
+                        .cloned()
+                        .unwrap_or_default();
+                }
+            }
+        }
+    }
+
+    tracing::info!("Done: {} users, {} links written", entries_by_user.len(), links_written);
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// Rebuilds the per-user tag statistics from the entries table
+//
+// Tag stats are maintained incrementally on every entry write. Entries written
+// before the stats table existed were never counted, so this tool recomputes
+// every user's counts, first/last used dates and co-occurrence pairs from
+// scratch and replaces the stored partition.
+//
+// Usage:
+//   ENTRIES_TABLE=reflekt-entries-dev TAG_STATS_TABLE=reflekt-tag-stats-dev \
+//     cargo run --release --bin rebuild_tag_stats -- [--dry-run] [--user <tenant_id>:<user_id>]
+//
+// Run it while writes are quiet: a tag change made between reading a user's
+// entries and replacing their stats is lost until the next rebuild.
+
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, PutRequest, WriteRequest};
+use journal_common::{get_dynamo_client, lambda_runtime::Error, tag_stats_items, timeline_pk};
+use std::collections::HashMap;
+
+// (tags, created_at) of each tagged entry
+type TaggedEntries = Vec<(Vec<String>, String)>;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let only_user = args
+        .iter()
+        .position(|a| a == "--user")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|u| u.split_once(':'))
+        .map(|(tenant_id, user_id)| (tenant_id.to_string(), user_id.to_string()));
+
+    let dynamo_client = get_dynamo_client().await;
+    let entries_table = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let stats_table = std::env::var("TAG_STATS_TABLE").unwrap_or_else(|_| "reflekt-tag-stats".to_string());
+
+    tracing::info!("Rebuilding tag stats from {} (dry run: {})", entries_table, dry_run);
+
+    // Tagged entries grouped by owner
+    let mut entries_by_user: HashMap<(String, String), TaggedEntries> = HashMap::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&entries_table)
+            .projection_expression("tenant_id, user_id, created_at, tags")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| Error::from(format!("Failed to scan entries: {}", e)))?;
+
+        for item in response.items() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+            let (Some(tenant_id), Some(user_id)) = (get_s("tenant_id"), get_s("user_id")) else {
+                continue;
+            };
+
+            let owner = (tenant_id, user_id);
+            if only_user.as_ref().is_some_and(|u| *u != owner) {
+                continue;
+            }
+
+            let tags = item.get("tags").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default();
+            entries_by_user
+                .entry(owner)
+                .or_default()
+                .push((tags, get_s("created_at").unwrap_or_default()));
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    let mut items_written = 0;
+
+    for ((tenant_id, user_id), entries) in &entries_by_user {
+        let items = tag_stats_items(tenant_id, user_id, entries);
+        tracing::info!("User {}: {} entries, {} stats items", user_id, entries.len(), items.len());
+
+        if dry_run {
+            items_written += items.len();
+            continue;
+        }
+
+        // Clear the existing partition, then write the fresh items
+        let mut deletes = Vec::new();
+        let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let response = dynamo_client
+                .query()
+                .table_name(&stats_table)
+                .key_condition_expression("stats_pk = :pk")
+                .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+                .projection_expression("stats_pk, stats_sk")
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| Error::from(format!("Failed to query tag stats: {}", e)))?;
+
+            for key in response.items() {
+                let request = DeleteRequest::builder()
+                    .set_key(Some(key.clone()))
+                    .build()
+                    .map_err(|e| Error::from(format!("Failed to build delete request: {}", e)))?;
+                deletes.push(WriteRequest::builder().delete_request(request).build());
+            }
+
+            match response.last_evaluated_key() {
+                Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+                _ => break,
+            }
+        }
+
+        let mut puts = Vec::new();
+        for item in items {
+            let request = PutRequest::builder()
+                .set_item(Some(item))
+                .build()
+                .map_err(|e| Error::from(format!("Failed to build put request: {}", e)))?;
+            puts.push(WriteRequest::builder().put_request(request).build());
+        }
+        items_written += puts.len();
+
+        // Deletes go first so a key present in both ends up with the fresh item
+        for requests in [deletes, puts] {
+            // BatchWriteItem accepts at most 25 requests
+            for chunk in requests.chunks(25) {
+                let mut pending = chunk.to_vec();
+                while !pending.is_empty() {
+                    let response = dynamo_client
+                        .batch_write_item()
+                        .request_items(&stats_table, pending)
+                        .send()
+                        .await
+                        .map_err(|e| Error::from(format!("Failed to write tag stats: {}", e)))?;
+
+                    pending = response
+                        .unprocessed_items()
+                        .and_then(|u| u.get(&stats_table))
+                        .cloned()
+                        .unwrap_or_default();
+                }
+            }
+        }
+    }
+
+    tracing::info!("Done: {} users, {} stats items written", entries_by_user.len(), items_written);
+    Ok(())
+}
@@ -0,0 +1,196 @@
+// Calendar and heatmap views
+//
+// GET /entries/calendar?year=YYYY&month=M returns one summary per day of the
+// month, computed from the entries themselves. Without `month` it returns the
+// year-level heatmap, which is read from the daily rollups table so it never
+// touches individual entries. Days are bucketed in the user's timezone from
+// settings; `timezone=<IANA name>` overrides it for a single month request.
+// The heatmap always uses the settings timezone, since the rollups were
+// bucketed in it when the entries were written.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use journal_common::chrono_tz::Tz;
+use journal_common::{
+    error_response, extract_tenant_context, get_dynamo_client, get_user_timezone, json_response,
+    lambda_runtime::Error, query_rollups, query_timeline, rollups_from_entries,
+    DailyRollup, JournalError,
+};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct MonthCalendar {
+    year: i32,
+    month: u32,
+    timezone: String,
+    days: Vec<DailyRollup>,
+}
+
+#[derive(Debug, Serialize)]
+struct YearHeatmap {
+    year: i32,
+    timezone: String,
+    total_entries: i64,
+    total_words: i64,
+    days: Vec<DailyRollup>,
+}
+
+// GET /entries/calendar
+pub(crate) async fn get_calendar(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let params = &event.query_string_parameters;
+    let year = match params.first("year").map(|y| y.parse::<i32>()) {
+        Some(Ok(year)) => year,
+        None => Utc::now().year(),
+        Some(Err(_)) => return Ok(error_response(400, &JournalError::ValidationError("Invalid year".into()))),
+    };
+    let month = match params.first("month").map(|m| m.parse::<u32>()) {
+        Some(Ok(month)) if (1..=12).contains(&month) => Some(month),
+        None => None,
+        Some(_) => return Ok(error_response(400, &JournalError::ValidationError("Month must be between 1 and 12".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+
+    let tz_override = match params.first("timezone").map(|name| (name, name.parse::<Tz>())) {
+        Some((_, Ok(tz))) => Some(tz),
+        None => None,
+        Some((name, Err(_))) => return Ok(error_response(400, &JournalError::ValidationError(format!("Unknown timezone: {}", name)))),
+    };
+    // Rollups can't be re-bucketed per request, so only a month honours the override
+    let tz = match (month, tz_override) {
+        (Some(_), Some(tz)) => tz,
+        _ => get_user_timezone(&dynamo_client, &claims.tenant_id, &claims.sub).await,
+    };
+
+    let result = match month {
+        Some(month) => month_calendar(&dynamo_client, &claims.tenant_id, &claims.sub, year, month, tz)
+            .await
+            .map(|calendar| json_response(200, &calendar)),
+        None => year_heatmap(&dynamo_client, &claims.tenant_id, &claims.sub, year, tz)
+            .await
+            .map(|heatmap| json_response(200, &heatmap)),
+    };
+
+    match result {
+        Ok(response) => Ok(response),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+async fn month_calendar(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    year: i32,
+    month: u32,
+    tz: Tz,
+) -> Result<MonthCalendar, JournalError> {
+    let invalid = || JournalError::ValidationError("Invalid calendar month".into());
+    let first_day = NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?;
+    let next_month = first_day.checked_add_months(Months::new(1)).ok_or_else(invalid)?;
+
+    // Start of the local day at both ends of the month, expressed in UTC like created_at
+    let to_utc = |day: NaiveDate| start_of_day(tz, day).map(|ts| ts.to_rfc3339()).ok_or_else(invalid);
+    let start = to_utc(first_day)?;
+    let end = to_utc(next_month)?;
+
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let items = query_timeline(client, &table_name, tenant_id, user_id, Some(&start), Some(&end)).await?;
+    let mut by_day = rollups_from_entries(&items, tz);
+
+    // Every day of the month is present so the client can lay out the grid directly
+    let days = first_day
+        .iter_days()
+        .take_while(|day| *day < next_month)
+        .map(|day| DailyRollup::from_delta(day, &by_day.remove(&day).unwrap_or_default()))
+        .collect();
+
+    Ok(MonthCalendar {
+        year,
+        month,
+        timezone: tz.name().to_string(),
+        days,
+    })
+}
+
+// First valid local time of a day in UTC. Usually midnight, but where a DST
+// change skips midnight the day starts when the gap ends.
+fn start_of_day(tz: Tz, day: NaiveDate) -> Option<DateTime<Utc>> {
+    let midnight = day.and_hms_opt(0, 0, 0)?;
+    (0..24 * 60)
+        .map(|minute| midnight + Duration::minutes(minute))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|ts| ts.with_timezone(&Utc))
+}
+
+async fn year_heatmap(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    year: i32,
+    tz: Tz,
+) -> Result<YearHeatmap, JournalError> {
+    let invalid = || JournalError::ValidationError("Invalid calendar year".into());
+    let start = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid)?;
+    let end = NaiveDate::from_ymd_opt(year, 12, 31).ok_or_else(invalid)?;
+
+    // Only days with activity are returned
+    let days = query_rollups(client, tenant_id, user_id, start, end).await?;
+
+    Ok(YearHeatmap {
+        year,
+        timezone: tz.name().to_string(),
+        total_entries: days.iter().map(|d| d.entry_count).sum(),
+        total_words: days.iter().map(|d| d.word_count).sum(),
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use journal_common::test_util::EmptyJsonServer;
+
+    fn start(tz: &str, day: &str) -> String {
+        let tz: Tz = tz.parse().unwrap();
+        start_of_day(tz, day.parse().unwrap()).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        assert_eq!(start("UTC", "2024-03-01"), "2024-03-01T00:00:00+00:00");
+        assert_eq!(start("Europe/Berlin", "2024-03-01"), "2024-02-29T23:00:00+00:00");
+        assert_eq!(start("Asia/Kolkata", "2024-05-01"), "2024-04-30T18:30:00+00:00");
+        assert_eq!(start("America/New_York", "2024-11-01"), "2024-11-01T04:00:00+00:00");
+    }
+
+    #[test]
+    fn days_without_a_midnight_start_when_the_gap_ends() {
+        // Clocks in Santiago jump from 00:00 to 01:00 on these days
+        assert_eq!(start("America/Santiago", "2024-09-08"), "2024-09-08T04:00:00+00:00");
+        assert_eq!(start("America/Sao_Paulo", "2018-11-04"), "2018-11-04T03:00:00+00:00");
+        // The day after runs from the usual midnight
+        assert_eq!(start("America/Santiago", "2024-09-09"), "2024-09-09T03:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn month_calendar_lists_every_day_in_the_requested_timezone() {
+        let server = EmptyJsonServer::start().await;
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let calendar = month_calendar(&server.dynamo_client(), "tenant-1", "user-1", 2024, 2, tz).await.unwrap();
+        assert_eq!(calendar.timezone, "Europe/Berlin");
+        assert_eq!(calendar.days.len(), 29);
+        assert_eq!(calendar.days.first().map(|d| d.date.as_str()), Some("2024-02-01"));
+        assert_eq!(calendar.days.last().map(|d| d.date.as_str()), Some("2024-02-29"));
+        assert!(calendar.days.iter().all(|d| d.entry_count == 0));
+    }
+}
@@ -0,0 +1,86 @@
+// Pinning, favoriting and archiving entries
+//
+// PUT /entries/{id}/pin|favorite|archive sets the flag and DELETE on the same
+// path clears it. Flags are not edits: they leave updated_at alone and can be
+// changed on sealed time capsules too.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use journal_common::{
+    error_response, extract_tenant_context, get_dynamo_client, json_response, lambda_runtime::Error, EntryFlag,
+    JournalError,
+};
+
+use crate::item_to_entry;
+
+pub(crate) async fn set_entry_flag(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Path like /entries/{id}/pin
+    let path = event.path.clone().unwrap_or_default();
+    let parts: Vec<&str> = path.split('/').collect();
+    let Some(flag) = parts.get(3).and_then(|segment| EntryFlag::from_path_segment(segment)) else {
+        return Ok(error_response(404, &JournalError::NotFoundError("Route not found".into())));
+    };
+    let entry_id = match event.path_parameters.get("id").cloned().or_else(|| parts.get(2).map(|id| id.to_string())) {
+        Some(id) if !id.is_empty() => id,
+        _ => return Ok(error_response(400, &JournalError::ValidationError("Missing entry ID".into()))),
+    };
+    let value = event.http_method.as_str() == "PUT";
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    // Verify ownership
+    let result = dynamo_client
+        .get_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .projection_expression("user_id")
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => match response.item {
+            Some(item) if item.get("user_id").and_then(|v| v.as_s().ok()) == Some(&claims.sub) => {}
+            Some(_) => return Ok(error_response(403, &JournalError::AuthorizationError("Not authorized to update this entry".into()))),
+            None => return Ok(error_response(404, &JournalError::NotFoundError("Entry not found".into()))),
+        },
+        Err(e) => return Ok(error_response(500, &JournalError::DatabaseError(format!("Failed to fetch entry: {}", e)))),
+    }
+
+    // Cleared flags are removed so the attribute only exists while set
+    let attribute = flag.attribute();
+    let mut update = dynamo_client
+        .update_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .condition_expression("attribute_exists(id)")
+        .return_values(ReturnValue::AllNew);
+    update = if value {
+        update
+            .update_expression(format!("SET {0} = :{0}", attribute))
+            .expression_attribute_values(format!(":{}", attribute), AttributeValue::Bool(true))
+    } else {
+        update.update_expression(format!("REMOVE {}", attribute))
+    };
+
+    match update.send().await {
+        Ok(response) => {
+            let item = response.attributes.unwrap_or_default();
+            Ok(json_response(200, &item_to_entry(&item)))
+        }
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to update entry {}: {}", entry_id, e)),
+        )),
+    }
+}
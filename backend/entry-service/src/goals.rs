@@ -0,0 +1,671 @@
+// Recurring journaling goals: CRUD, progress history and evaluation
+//
+// Goals are evaluated against the entries table, not rollups, so tag goals see
+// each entry's tags. After every entry write the period containing the entry
+// is recounted for each of the user's active goals. The first time an entry
+// write brings a period to its target a GoalCompleted event is published,
+// which the gamification service turns into points. Creating or retargeting a
+// goal records progress but never announces it. Tag renames and merges carry
+// over to tag goals, and the periods of the retagged entries are recounted.
+// Time capsules count from the time the unlock job processes them.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::chrono::{self, Duration, NaiveDate};
+use journal_common::chrono_tz::Tz;
+use journal_common::{
+    awaiting_unlock, delete_goal_items, error_response, extract_tenant_context, get_dynamo_client, get_goal,
+    get_period_progress, get_user_timezone, goal_streak, goal_to_item, goals_table, json_response,
+    lambda_runtime::Error, list_goals, local_day, progress_to_item, publish_event, query_goal_progress,
+    query_timeline, serde_json, uuid::Uuid, Goal, GoalMetric, GoalPeriod, GoalProgress, JournalError,
+    MAX_ACTIVE_GOALS,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+
+// Periods of history returned by default and at most
+const DEFAULT_HISTORY_PERIODS: usize = 12;
+const MAX_HISTORY_PERIODS: usize = 366;
+
+#[derive(Debug, Deserialize)]
+struct CreateGoalInput {
+    name: String,
+    #[serde(flatten)]
+    metric: GoalMetric,
+    target: i64,
+    period: GoalPeriod,
+    active: Option<bool>,
+}
+
+// Metric and period are fixed once a goal has history
+#[derive(Debug, Deserialize)]
+struct UpdateGoalInput {
+    name: Option<String>,
+    target: Option<i64>,
+    active: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct GoalWithProgress {
+    #[serde(flatten)]
+    goal: Goal,
+    current: GoalProgress,
+}
+
+#[derive(Debug, Serialize)]
+struct GoalProgressResponse {
+    goal: Goal,
+    current_streak: i64,
+    completed_periods: usize,
+    /// Oldest period first, including periods nothing was written in
+    history: Vec<GoalProgress>,
+}
+
+// Goal id from a /goals/{id} or /goals/{id}/progress path
+fn path_goal_id(event: &ApiGatewayProxyRequest) -> Option<String> {
+    event
+        .path_parameters
+        .get("id")
+        .cloned()
+        .or_else(|| event.path.as_deref().and_then(|p| p.split('/').nth(2)).map(str::to_string))
+        .filter(|id| !id.is_empty())
+}
+
+// Today in the user's timezone
+fn local_today(tz: Tz) -> NaiveDate {
+    chrono::Utc::now().with_timezone(&tz).date_naive()
+}
+
+// GET /goals - the user's goals with their progress in the current period
+pub(crate) async fn list_user_goals(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let goals = match list_goals(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(goals) => goals,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let today = local_today(get_user_timezone(&dynamo_client, &claims.tenant_id, &claims.sub).await);
+    let mut response = Vec::with_capacity(goals.len());
+    for goal in goals {
+        let (start, _) = goal.period.bounds(today);
+        let recorded = get_period_progress(&dynamo_client, &claims.tenant_id, &claims.sub, &goal.id, start).await;
+        let current = match recorded {
+            Ok(progress) => progress.unwrap_or_else(|| GoalProgress::empty(&goal, start)),
+            Err(e) => return Ok(error_response(500, &e)),
+        };
+        response.push(GoalWithProgress { goal, current });
+    }
+
+    Ok(json_response(200, &response))
+}
+
+// POST /goals
+pub(crate) async fn create_goal(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let input: CreateGoalInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let mut goal = Goal {
+        id: Uuid::new_v4().to_string(),
+        name: input.name,
+        metric: input.metric,
+        target: input.target,
+        period: input.period,
+        active: input.active.unwrap_or(true),
+        created_at: Some(timestamp.clone()),
+        updated_at: Some(timestamp),
+    };
+    if let Err(e) = goal.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    let dynamo_client = get_dynamo_client().await;
+    if goal.active {
+        if let Err(e) = check_active_goal_limit(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+            return Ok(error_response(400, &e));
+        }
+    }
+    if let Err(e) = save_goal(&dynamo_client, &claims.tenant_id, &claims.sub, &goal).await {
+        return Ok(error_response(500, &e));
+    }
+
+    // Entries already written this period count, but a new goal isn't
+    // announced as completed for them
+    if goal.active {
+        let mut evaluator = GoalEvaluator::new(&dynamo_client, &claims.tenant_id, &claims.sub).await;
+        let today = local_today(evaluator.tz);
+        if let Err(e) = evaluator.evaluate(&goal, today, false).await {
+            tracing::warn!("Failed to evaluate goal {}: {}", goal.id, e);
+        }
+    }
+
+    Ok(json_response(201, &goal))
+}
+
+// PUT /goals/{id} - rename, retarget, pause or resume a goal
+pub(crate) async fn update_goal(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(goal_id) = path_goal_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing goal ID".into())));
+    };
+
+    let input: UpdateGoalInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let mut goal = match get_goal(&dynamo_client, &claims.tenant_id, &claims.sub, &goal_id).await {
+        Ok(Some(goal)) => goal,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Goal not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    if let Some(name) = input.name {
+        goal.name = name;
+    }
+    if let Some(target) = input.target {
+        goal.target = target;
+    }
+    if let Some(active) = input.active {
+        if active && !goal.active {
+            if let Err(e) = check_active_goal_limit(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+                return Ok(error_response(400, &e));
+            }
+        }
+        goal.active = active;
+    }
+    if let Err(e) = goal.validate() {
+        return Ok(error_response(400, &e));
+    }
+    goal.updated_at = Some(chrono::Utc::now().to_rfc3339());
+
+    if let Err(e) = save_goal(&dynamo_client, &claims.tenant_id, &claims.sub, &goal).await {
+        return Ok(error_response(500, &e));
+    }
+
+    // A new target applies to the current period straight away, but meeting
+    // it by editing the goal isn't announced
+    if goal.active {
+        let mut evaluator = GoalEvaluator::new(&dynamo_client, &claims.tenant_id, &claims.sub).await;
+        let today = local_today(evaluator.tz);
+        if let Err(e) = evaluator.evaluate(&goal, today, false).await {
+            tracing::warn!("Failed to evaluate goal {}: {}", goal.id, e);
+        }
+    }
+
+    Ok(json_response(200, &goal))
+}
+
+// DELETE /goals/{id} - the goal and its history
+pub(crate) async fn delete_goal(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(goal_id) = path_goal_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing goal ID".into())));
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match get_goal(&dynamo_client, &claims.tenant_id, &claims.sub, &goal_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Goal not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    }
+
+    match delete_goal_items(&dynamo_client, &claims.tenant_id, &claims.sub, &goal_id).await {
+        Ok(()) => Ok(json_response(200, &serde_json::json!({ "message": "Goal deleted successfully" }))),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// GET /goals/{id}/progress?periods=N - the last N periods, current one included
+pub(crate) async fn get_goal_progress(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(goal_id) = path_goal_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing goal ID".into())));
+    };
+    let periods = event
+        .query_string_parameters
+        .first("periods")
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_HISTORY_PERIODS)
+        .clamp(1, MAX_HISTORY_PERIODS);
+
+    let dynamo_client = get_dynamo_client().await;
+    let goal = match get_goal(&dynamo_client, &claims.tenant_id, &claims.sub, &goal_id).await {
+        Ok(Some(goal)) => goal,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Goal not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let tz = get_user_timezone(&dynamo_client, &claims.tenant_id, &claims.sub).await;
+    let today = local_today(tz);
+
+    // Period starts from the requested depth up to the current period, but not
+    // before the period the goal was created in
+    let created_start = goal
+        .created_at
+        .as_deref()
+        .and_then(|created_at| local_day(created_at, tz))
+        .map(|day| goal.period.bounds(day).0);
+    let mut starts = vec![goal.period.bounds(today).0];
+    while starts.len() < periods {
+        let previous = goal.period.previous_start(starts[starts.len() - 1]);
+        if created_start.is_some_and(|created| previous < created) {
+            break;
+        }
+        starts.push(previous);
+    }
+    starts.reverse();
+
+    // The streak may reach further back than the history shown
+    let from = created_start.unwrap_or(starts[0]);
+    let recorded = match query_goal_progress(&dynamo_client, &claims.tenant_id, &claims.sub, &goal.id, from).await {
+        Ok(progress) => progress,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let mut by_start: HashMap<&str, &GoalProgress> = recorded.iter().map(|p| (p.period_start.as_str(), p)).collect();
+    let history: Vec<GoalProgress> = starts
+        .iter()
+        .map(|start| {
+            by_start
+                .remove(start.to_string().as_str())
+                .cloned()
+                .unwrap_or_else(|| GoalProgress::empty(&goal, *start))
+        })
+        .collect();
+
+    let response = GoalProgressResponse {
+        current_streak: goal_streak(&goal, &recorded, today),
+        completed_periods: recorded.iter().filter(|p| p.completed).count(),
+        goal,
+        history,
+    };
+    Ok(json_response(200, &response))
+}
+
+// Errors if the user already has the most active goals allowed
+async fn check_active_goal_limit(client: &DynamoDbClient, tenant_id: &str, user_id: &str) -> Result<(), JournalError> {
+    let active = list_goals(client, tenant_id, user_id).await?.iter().filter(|goal| goal.active).count();
+    if active >= MAX_ACTIVE_GOALS {
+        return Err(JournalError::ValidationError(format!(
+            "You can have at most {} active goals",
+            MAX_ACTIVE_GOALS
+        )));
+    }
+    Ok(())
+}
+
+async fn save_goal(client: &DynamoDbClient, tenant_id: &str, user_id: &str, goal: &Goal) -> Result<(), JournalError> {
+    client
+        .put_item()
+        .table_name(goals_table())
+        .set_item(Some(goal_to_item(tenant_id, user_id, goal)))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to save goal: {}", e)))?;
+    Ok(())
+}
+
+// Whether an entry item counts towards a period: created between two local
+// days, inclusive, and not a capsule the unlock job has yet to count
+fn counts_towards(item: &HashMap<String, AttributeValue>, tz: Tz, (start, end): (NaiveDate, NaiveDate)) -> bool {
+    !awaiting_unlock(item)
+        && item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|created_at| local_day(created_at, tz))
+            .is_some_and(|day| day >= start && day <= end)
+}
+
+// Progress to record for a period now at `value`, or None while nothing was
+// recorded and nothing reached. A completion time, once recorded, is kept.
+fn next_progress(
+    goal: &Goal,
+    (start, end): (NaiveDate, NaiveDate),
+    value: i64,
+    previous: Option<GoalProgress>,
+) -> Option<GoalProgress> {
+    if previous.is_none() && value == 0 {
+        return None;
+    }
+
+    let completed = value >= goal.target;
+    let completed_at = previous
+        .and_then(|p| p.completed_at)
+        .or_else(|| completed.then(|| chrono::Utc::now().to_rfc3339()));
+    Some(GoalProgress {
+        period_start: start.to_string(),
+        period_end: end.to_string(),
+        value,
+        target: goal.target,
+        completed,
+        completed_at,
+    })
+}
+
+// Recounts goals of one user; entry items of a period are loaded once for
+// all goals sharing the period
+struct GoalEvaluator<'a> {
+    client: &'a DynamoDbClient,
+    tenant_id: &'a str,
+    user_id: &'a str,
+    tz: Tz,
+    periods: HashMap<(NaiveDate, NaiveDate), Vec<HashMap<String, AttributeValue>>>,
+}
+
+impl<'a> GoalEvaluator<'a> {
+    async fn new(client: &'a DynamoDbClient, tenant_id: &'a str, user_id: &'a str) -> Self {
+        GoalEvaluator {
+            client,
+            tenant_id,
+            user_id,
+            tz: get_user_timezone(client, tenant_id, user_id).await,
+            periods: HashMap::new(),
+        }
+    }
+
+    async fn period_entries(
+        &mut self,
+        (start, end): (NaiveDate, NaiveDate),
+    ) -> Result<&[HashMap<String, AttributeValue>], JournalError> {
+        let items = match self.periods.entry((start, end)) {
+            Entry::Occupied(cached) => cached.into_mut(),
+            Entry::Vacant(slot) => {
+                // Timeline keys are UTC, so read a day either side and keep the local days
+                let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+                let from = (start - Duration::days(1)).to_string();
+                let to = (end + Duration::days(1)).to_string();
+                let items =
+                    query_timeline(self.client, &table_name, self.tenant_id, self.user_id, Some(&from), Some(&to))
+                        .await?;
+
+                let tz = self.tz;
+                slot.insert(items.into_iter().filter(|item| counts_towards(item, tz, (start, end))).collect())
+            }
+        };
+        Ok(items)
+    }
+
+    // Recount a goal for the period containing `day` and store the result.
+    // Completion is published the first time the period reaches its target,
+    // by whichever write records completed_at.
+    async fn evaluate(&mut self, goal: &Goal, day: NaiveDate, announce: bool) -> Result<(), JournalError> {
+        let (start, end) = goal.period.bounds(day);
+
+        // Periods that ended before the goal existed aren't tracked
+        let created_day = goal.created_at.as_deref().and_then(|created_at| local_day(created_at, self.tz));
+        if created_day.is_some_and(|created| end < created) {
+            return Ok(());
+        }
+
+        let items = self.period_entries((start, end)).await?;
+        let value = goal.metric.value(&items.iter().collect::<Vec<_>>());
+
+        let previous = get_period_progress(self.client, self.tenant_id, self.user_id, &goal.id, start).await?;
+        let previous_completed_at = previous.as_ref().and_then(|p| p.completed_at.clone());
+        let Some(progress) = next_progress(goal, (start, end), value, previous) else {
+            return Ok(());
+        };
+        let first_completion = progress.completed && previous_completed_at.is_none();
+
+        let mut put = self
+            .client
+            .put_item()
+            .table_name(goals_table())
+            .set_item(Some(progress_to_item(self.tenant_id, self.user_id, &goal.id, &progress)));
+        // Unless completion was already recorded, a concurrent write may record
+        // it first; that write keeps its completed_at and announces instead
+        if previous_completed_at.is_none() {
+            put = put.condition_expression("attribute_not_exists(completed_at)");
+        }
+        if let Err(e) = put.send().await {
+            let e = e.into_service_error();
+            if e.is_conditional_check_failed_exception() {
+                return Ok(());
+            }
+            return Err(JournalError::DatabaseError(format!("Failed to save goal progress: {}", e)));
+        }
+
+        if first_completion && announce {
+            let event_detail = serde_json::json!({
+                "goal_id": goal.id,
+                "tenant_id": self.tenant_id,
+                "user_id": self.user_id,
+                "name": goal.name,
+                "metric": goal.metric.as_str(),
+                "period": goal.period.as_str(),
+                "period_start": progress.period_start,
+                "period_end": progress.period_end,
+                "target": goal.target,
+                "value": value,
+            });
+            publish_event("GoalCompleted", event_detail).await?;
+        }
+
+        Ok(())
+    }
+}
+
+// Recount the user's active goals for the period an entry item was created in
+pub(crate) async fn evaluate_goals_after_write(
+    client: &DynamoDbClient,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<(), JournalError> {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok());
+
+    let (Some(tenant_id), Some(user_id), Some(created_at)) =
+        (get_s("tenant_id"), get_s("user_id"), get_s("created_at"))
+    else {
+        return Err(JournalError::ValidationError("Entry is missing goal attributes".into()));
+    };
+
+    evaluate_goals_for_entries(client, tenant_id, user_id, &[created_at.as_str()]).await
+}
+
+// Recount the user's active goals for every period containing one of the
+// entries created at `created_ats`. Each period is recounted once per goal.
+pub(crate) async fn evaluate_goals_for_entries(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    created_ats: &[&str],
+) -> Result<(), JournalError> {
+    let goals: Vec<Goal> = list_goals(client, tenant_id, user_id)
+        .await?
+        .into_iter()
+        .filter(|goal| goal.active)
+        .collect();
+    if goals.is_empty() || created_ats.is_empty() {
+        return Ok(());
+    }
+
+    let mut evaluator = GoalEvaluator::new(client, tenant_id, user_id).await;
+    let days = created_ats
+        .iter()
+        .map(|created_at| {
+            local_day(created_at, evaluator.tz)
+                .ok_or_else(|| JournalError::ValidationError(format!("Invalid created_at: {}", created_at)))
+        })
+        .collect::<Result<BTreeSet<NaiveDate>, _>>()?;
+
+    for goal in &goals {
+        let starts: BTreeSet<NaiveDate> = days.iter().map(|day| goal.period.bounds(*day).0).collect();
+        for start in starts {
+            evaluator.evaluate(goal, start, true).await?;
+        }
+    }
+    Ok(())
+}
+
+// Carry a tag rename or merge over to the user's tag goals, using the mapping
+// applied to their entries. A goal whose tag was deleted keeps it.
+pub(crate) async fn rename_goal_tags<F>(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    map_tag: F,
+) -> Result<(), JournalError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let now = chrono::Utc::now().to_rfc3339();
+    for mut goal in list_goals(client, tenant_id, user_id).await? {
+        let GoalMetric::TaggedEntries { tag } = &mut goal.metric else {
+            continue;
+        };
+        match map_tag(tag) {
+            Some(new_tag) if new_tag != *tag => *tag = new_tag,
+            _ => continue,
+        }
+        goal.updated_at = Some(now.clone());
+        save_goal(client, tenant_id, user_id, &goal).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use journal_common::CAPSULE_SEALED;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn daily_goal(target: i64) -> Goal {
+        Goal {
+            id: "goal-1".into(),
+            name: "Write daily".into(),
+            metric: GoalMetric::Entries,
+            target,
+            period: GoalPeriod::Daily,
+            active: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn period() -> (NaiveDate, NaiveDate) {
+        (date("2024-06-10"), date("2024-06-10"))
+    }
+
+    fn entry_created(created_at: &str) -> HashMap<String, AttributeValue> {
+        HashMap::from([("created_at".to_string(), AttributeValue::S(created_at.to_string()))])
+    }
+
+    #[test]
+    fn nothing_is_recorded_for_an_untouched_period() {
+        assert!(next_progress(&daily_goal(2), period(), 0, None).is_none());
+    }
+
+    #[test]
+    fn progress_below_target_is_not_completed() {
+        let progress = next_progress(&daily_goal(2), period(), 1, None).unwrap();
+        assert_eq!(progress.period_start, "2024-06-10");
+        assert_eq!(progress.period_end, "2024-06-10");
+        assert_eq!((progress.value, progress.target), (1, 2));
+        assert!(!progress.completed);
+        assert!(progress.completed_at.is_none());
+    }
+
+    #[test]
+    fn reaching_the_target_records_the_completion_time() {
+        let progress = next_progress(&daily_goal(2), period(), 2, None).unwrap();
+        assert!(progress.completed);
+        assert!(progress.completed_at.is_some());
+    }
+
+    #[test]
+    fn completion_time_survives_later_edits() {
+        let goal = daily_goal(2);
+        let first = next_progress(&goal, period(), 2, None).unwrap();
+        let completed_at = first.completed_at.clone();
+
+        // Dropping below the target keeps the period's first completion
+        let dropped = next_progress(&goal, period(), 1, Some(first)).unwrap();
+        assert!(!dropped.completed);
+        assert_eq!(dropped.completed_at, completed_at);
+
+        // Reaching it again isn't a new completion
+        let again = next_progress(&goal, period(), 3, Some(dropped)).unwrap();
+        assert!(again.completed);
+        assert_eq!(again.completed_at, completed_at);
+    }
+
+    #[test]
+    fn recorded_progress_is_updated_back_to_zero() {
+        let goal = daily_goal(2);
+        let first = next_progress(&goal, period(), 1, None).unwrap();
+        let emptied = next_progress(&goal, period(), 0, Some(first)).unwrap();
+        assert_eq!(emptied.value, 0);
+        assert!(!emptied.completed);
+    }
+
+    #[test]
+    fn entries_belong_to_the_local_day_they_were_written() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let june_10 = period();
+
+        // 02:00 UTC on the 11th is still the evening of the 10th in New York
+        assert!(counts_towards(&entry_created("2024-06-11T02:00:00+00:00"), tz, june_10));
+        assert!(!counts_towards(&entry_created("2024-06-10T02:00:00+00:00"), tz, june_10));
+        assert!(counts_towards(&entry_created("2024-06-10T02:00:00+00:00"), Tz::UTC, june_10));
+        assert!(!counts_towards(&HashMap::new(), tz, june_10));
+    }
+
+    #[test]
+    fn capsules_count_once_unlocked() {
+        let mut capsule = entry_created("2024-06-10T12:00:00+00:00");
+        capsule.insert("unlock_at".to_string(), AttributeValue::S("2025-01-01T00:00:00Z".to_string()));
+        capsule.insert("capsule_pk".to_string(), AttributeValue::S(CAPSULE_SEALED.to_string()));
+        assert!(!counts_towards(&capsule, Tz::UTC, period()));
+
+        // The unlock job removes capsule_pk once it has counted the entry
+        capsule.remove("capsule_pk");
+        assert!(counts_towards(&capsule, Tz::UTC, period()));
+    }
+}
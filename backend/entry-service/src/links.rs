@@ -0,0 +1,439 @@
+// Wiki links between entries: keeping the link graph in step with entry writes,
+// plus the backlinks and graph endpoints.
+//
+// Links are re-parsed whenever an entry's content changes. Titles matter too:
+// a new or renamed entry picks up dangling `[[Title]]` links written before it
+// existed, and a rename rewrites `[[Old title]]` in linking entries so their
+// text keeps pointing at it. When an entry is deleted, links to it become
+// dangling again and `[[<id>]]` references are rewritten to its title so the
+// linking text stays readable.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::{
+    apply_entry_rollup, chrono, dangling_link_key, error_response, extract_tenant_context,
+    get_all_links, get_dynamo_client, get_incoming_links, json_response, lambda_runtime::Error,
+    normalize_link_title, parse_wiki_links, query_entry_titles, resolve_link_targets, retarget_links,
+    rewrite_wiki_links, search_text, set_entry_links, JournalError, LinkResolver, RollupDelta,
+    TextMetrics, WikiLink,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::goals;
+
+// Characters of context shown on each side of a link in a backlink excerpt
+const EXCERPT_CONTEXT: usize = 80;
+
+#[derive(Debug, Serialize)]
+struct Backlink {
+    entry_id: String,
+    title: String,
+    created_at: String,
+    excerpt: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphNode {
+    id: String,
+    title: String,
+    // No entry exists for this title yet
+    missing: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphEdge {
+    source: String,
+    target: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+fn entries_table() -> String {
+    std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string())
+}
+
+fn get_s(item: &HashMap<String, AttributeValue>, key: &str) -> Option<String> {
+    item.get(key).and_then(|v| v.as_s().ok()).cloned()
+}
+
+// Update the link graph after an entry was created (old_item is None) or updated
+pub(crate) async fn sync_links_after_write(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    old_item: Option<&HashMap<String, AttributeValue>>,
+    new_item: &HashMap<String, AttributeValue>,
+) -> Result<(), JournalError> {
+    let entry_id = get_s(new_item, "id").unwrap_or_default();
+    let title = get_s(new_item, "title").unwrap_or_default();
+    let content = get_s(new_item, "content").unwrap_or_default();
+    let old_title = old_item.and_then(|item| get_s(item, "title"));
+    let old_content = old_item.and_then(|item| get_s(item, "content"));
+
+    // Outgoing links; a new entry without links has nothing stored yet
+    if old_content.as_deref() != Some(content.as_str()) {
+        let links = parse_wiki_links(&content);
+        if !links.is_empty() || old_item.is_some() {
+            let resolver = if links.is_empty() {
+                LinkResolver::default()
+            } else {
+                resolve_link_targets(client, &entries_table(), tenant_id, user_id, &links).await?
+            };
+            let targets: Vec<(String, String)> = resolver
+                .resolve_all(&entry_id, &content)
+                .into_iter()
+                .map(|(key, link)| (key, link.target))
+                .collect();
+            set_entry_links(client, tenant_id, user_id, &entry_id, &targets).await?;
+        }
+    }
+
+    let title_changed = match old_title.as_deref() {
+        Some(old) => normalize_link_title(old) != normalize_link_title(&title),
+        None => true,
+    };
+    if !title_changed {
+        return Ok(());
+    }
+
+    // Links written before this title existed now resolve to the entry
+    retarget_links(client, tenant_id, user_id, &dangling_link_key(&title), &entry_id, None).await?;
+
+    // Keep `[[Old title]]` references pointing here after a rename
+    if let Some(old_title) = old_title {
+        let old_key = normalize_link_title(&old_title);
+        for link in get_incoming_links(client, tenant_id, user_id, &entry_id).await? {
+            let result = rewrite_entry_content(client, tenant_id, user_id, &link.source_id, |l: &WikiLink| {
+                (normalize_link_title(&l.target) == old_key).then(|| title.clone())
+            })
+            .await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to repair links in entry {}: {}", link.source_id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Update the link graph after an entry was deleted
+pub(crate) async fn sync_links_after_delete(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<(), JournalError> {
+    let entry_id = get_s(item, "id").unwrap_or_default();
+    let title = get_s(item, "title").unwrap_or_default();
+
+    set_entry_links(client, tenant_id, user_id, &entry_id, &[]).await?;
+
+    // Links to the entry dangle on its title until another entry takes it
+    let incoming = retarget_links(client, tenant_id, user_id, &entry_id, &dangling_link_key(&title), Some(&title)).await?;
+    for link in incoming {
+        let result = rewrite_entry_content(client, tenant_id, user_id, &link.source_id, |l: &WikiLink| {
+            (l.target == entry_id).then(|| title.clone())
+        })
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to repair links in entry {}: {}", link.source_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Rewrite link targets in another entry's content. The write is conditional on
+// the content being unchanged since it was read, so a concurrent edit wins.
+// A rewrite counts as an edit of that entry: it bumps updated_at and publishes
+// EntryUpdated.
+async fn rewrite_entry_content<F>(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    entry_id: &str,
+    map_target: F,
+) -> Result<(), JournalError>
+where
+    F: Fn(&WikiLink) -> Option<String>,
+{
+    let table_name = entries_table();
+
+    let response = client
+        .get_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.to_string()))
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to fetch entry: {}", e)))?;
+
+    let Some(item) = response.item else {
+        return Ok(());
+    };
+    if get_s(&item, "user_id").as_deref() != Some(user_id) {
+        return Ok(());
+    }
+
+    let content = get_s(&item, "content").unwrap_or_default();
+    let rewritten = rewrite_wiki_links(&content, map_target);
+    if rewritten == content {
+        return Ok(());
+    }
+
+    // Link labels are part of the text, so the metrics move with the content
+    let metrics = TextMetrics::from_markdown(&rewritten);
+    let mut update_expression =
+        "SET content = :content, search_text = :search_text, updated_at = :updated_at".to_string();
+    let mut request = client
+        .update_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.to_string()))
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .condition_expression("content = :old_content")
+        .expression_attribute_values(":content", AttributeValue::S(rewritten.clone()))
+        .expression_attribute_values(
+            ":search_text",
+            AttributeValue::S(search_text(&get_s(&item, "title").unwrap_or_default(), &rewritten)),
+        )
+        .expression_attribute_values(":updated_at", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .expression_attribute_values(":old_content", AttributeValue::S(content));
+    for (name, value) in metrics.to_attributes() {
+        update_expression.push_str(&format!(", {} = :{}", name, name));
+        request = request.expression_attribute_values(format!(":{}", name), value);
+    }
+    if metrics.readability_score.is_none() {
+        update_expression.push_str(" REMOVE readability_score");
+    }
+
+    let response = request
+        .update_expression(update_expression)
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew)
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to update entry content: {}", e)))?;
+
+    if let Some(updated) = response.attributes() {
+        apply_entry_rollup(client, updated, &RollupDelta::between(&item, updated)).await?;
+        goals::evaluate_goals_after_write(client, updated).await?;
+
+        // Announced like any other edit, so analysis and word counts follow it
+        if let Err(e) = crate::publish_entry_updated(client, &item, updated).await {
+            tracing::warn!("Failed to publish event: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+// GET /entries/{id}/backlinks - entries that link to this one, newest first
+pub(crate) async fn get_backlinks(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Extract entry_id from path like /entries/{id}/backlinks
+    let path = event.path.clone().unwrap_or_default();
+    let entry_id = match event.path_parameters.get("id").cloned().or_else(|| path.split('/').nth(2).map(str::to_string)) {
+        Some(id) if !id.is_empty() => id,
+        _ => return Ok(error_response(400, &JournalError::ValidationError("Missing entry ID".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = entries_table();
+
+    let result = dynamo_client
+        .get_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .send()
+        .await;
+
+    let entry = match result {
+        Ok(response) => match response.item {
+            Some(item) if get_s(&item, "user_id").as_deref() == Some(claims.sub.as_str()) => item,
+            Some(_) => return Ok(error_response(403, &JournalError::AuthorizationError("Not authorized to access this entry".into()))),
+            None => return Ok(error_response(404, &JournalError::NotFoundError("Entry not found".into()))),
+        },
+        Err(e) => return Ok(error_response(500, &JournalError::DatabaseError(format!("Failed to fetch entry: {}", e)))),
+    };
+    let title = get_s(&entry, "title").unwrap_or_default();
+
+    let incoming = match get_incoming_links(&dynamo_client, &claims.tenant_id, &claims.sub, &entry_id).await {
+        Ok(links) => links,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let source_ids: Vec<String> = incoming.into_iter().map(|l| l.source_id).collect();
+    let sources = match batch_get_entries(&dynamo_client, &table_name, &claims.tenant_id, &source_ids).await {
+        Ok(sources) => sources,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let mut backlinks: Vec<Backlink> = sources
+        .iter()
+        .filter(|item| get_s(item, "user_id").as_deref() == Some(claims.sub.as_str()))
+        .map(|item| Backlink {
+            entry_id: get_s(item, "id").unwrap_or_default(),
+            title: get_s(item, "title").unwrap_or_default(),
+            created_at: get_s(item, "created_at").unwrap_or_default(),
+            excerpt: get_s(item, "content").and_then(|c| link_excerpt(&c, &entry_id, &title)),
+        })
+        .collect();
+    backlinks.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    Ok(json_response(200, &backlinks))
+}
+
+// GET /entries/graph - nodes and edges of the user's link graph.
+// Entries without any links are left out unless `include_unlinked=true`.
+pub(crate) async fn get_link_graph(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let include_unlinked = event
+        .query_string_parameters
+        .first("include_unlinked")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    let dynamo_client = get_dynamo_client().await;
+
+    let links = match get_all_links(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(links) => links,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    let titles = match query_entry_titles(&dynamo_client, &entries_table(), &claims.tenant_id, &claims.sub).await {
+        Ok(titles) => titles,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    // Keyed by node id; BTreeMap keeps the response stable between calls
+    let mut nodes: BTreeMap<String, GraphNode> = BTreeMap::new();
+    let titles: HashMap<String, String> = titles.into_iter().collect();
+    let mut node_for = |key: &str, dangling_title: Option<&str>| {
+        nodes.entry(key.to_string()).or_insert_with(|| match titles.get(key) {
+            Some(title) => GraphNode { id: key.to_string(), title: title.clone(), missing: false },
+            None => GraphNode {
+                id: key.to_string(),
+                title: dangling_title.unwrap_or_else(|| key.trim_start_matches('?')).to_string(),
+                missing: true,
+            },
+        });
+    };
+
+    let mut edges = Vec::new();
+    for link in &links {
+        node_for(&link.source_id, None);
+        node_for(&link.target_key, link.target_title.as_deref());
+        edges.push(GraphEdge {
+            source: link.source_id.clone(),
+            target: link.target_key.clone(),
+        });
+    }
+
+    if include_unlinked {
+        for id in titles.keys() {
+            node_for(id, None);
+        }
+    }
+
+    Ok(json_response(200, &LinkGraph { nodes: nodes.into_values().collect(), edges }))
+}
+
+// Fetch entries by id, 100 keys per request
+async fn batch_get_entries(
+    client: &DynamoDbClient,
+    table_name: &str,
+    tenant_id: &str,
+    entry_ids: &[String],
+) -> Result<Vec<HashMap<String, AttributeValue>>, JournalError> {
+    let mut items = Vec::new();
+
+    for chunk in entry_ids.chunks(100) {
+        let keys: Vec<HashMap<String, AttributeValue>> = chunk
+            .iter()
+            .map(|id| {
+                HashMap::from([
+                    ("id".to_string(), AttributeValue::S(id.clone())),
+                    ("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string())),
+                ])
+            })
+            .collect();
+
+        let mut request = Some(
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .projection_expression("id, user_id, title, created_at, content")
+                .build()
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to build KeysAndAttributes: {}", e)))?,
+        );
+
+        while let Some(keys_attrs) = request.take() {
+            let result = client
+                .batch_get_item()
+                .request_items(table_name, keys_attrs)
+                .send()
+                .await
+                .map_err(|e| JournalError::DatabaseError(format!("Failed to batch get entries: {}", e)))?;
+
+            items.extend(result.responses().and_then(|r| r.get(table_name)).into_iter().flatten().cloned());
+            request = result
+                .unprocessed_keys()
+                .and_then(|u| u.get(table_name))
+                .filter(|k| !k.keys().is_empty())
+                .cloned();
+        }
+    }
+
+    Ok(items)
+}
+
+// Text around the first link to the entry, by id or by title
+fn link_excerpt(content: &str, entry_id: &str, title: &str) -> Option<String> {
+    let title_key = normalize_link_title(title);
+    let link = parse_wiki_links(content)
+        .into_iter()
+        .find(|l| l.target == entry_id || normalize_link_title(&l.target) == title_key)?;
+
+    let needle = format!("[[{}", link.target);
+    let position = content.find(&needle)?;
+
+    let start = content[..position]
+        .char_indices()
+        .rev()
+        .nth(EXCERPT_CONTEXT - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let end = content[position..]
+        .char_indices()
+        .nth(needle.chars().count() + EXCERPT_CONTEXT)
+        .map(|(i, _)| position + i)
+        .unwrap_or(content.len());
+
+    let mut excerpt = content[start..end].trim().to_string();
+    if start > 0 {
+        excerpt.insert(0, '…');
+    }
+    if end < content.len() {
+        excerpt.push('…');
+    }
+    Some(excerpt)
+}
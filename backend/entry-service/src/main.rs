@@ -1,13 +1,31 @@
 use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
 use aws_sdk_dynamodb::types::AttributeValue;
 use journal_common::{
-    base64, chrono, error_response, extract_tenant_context, get_dynamo_client,
-    json_response, lambda_runtime::{run, service_fn, Error, LambdaEvent},
-    publish_event, serde_json, uuid::Uuid, JournalError,
+    apply_entry_rollup, apply_tag_stats_delta, awaiting_unlock, base64, check_tag_count, chrono, demote_headings, entry_flag, entry_mood,
+    entry_notebook_id, error_response, escape_html, extract_tenant_context, field_values_to_attribute,
+    field_values_to_json, flag_filter_expression, format_unlock_at, get_dynamo_client, get_notebook,
+    get_privacy_settings, get_tag_stats, get_template, is_sealed, json_response, lambda_runtime::{run, service_fn, Error, LambdaEvent},
+    link_title_pk, mood_filter_expression, normalize_tags, notebook_filter_expression, outline, parse_unlock_at,
+    plain_text, publish_event, query_timeline, render_html, search_text, serde_json, timeline_bounds,
+    timeline_pk, timeline_sk, unsealed_filter_expression, uuid::Uuid, ArchivedFilter, Emotion, EntryFlag,
+    JournalError, Mood, MoodInput, OutlineHeading, RollupDelta, TagStatsDelta, TextMetrics, CAPSULE_SEALED,
+    DEFAULT_NOTEBOOK_ID, INTENSITY_MAX, INTENSITY_MIN, TIMELINE_INDEX,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+mod calendar;
+mod flags;
+mod goals;
+mod links;
+mod notebooks;
+mod on_this_day;
+mod regenerate;
+mod suggest;
+mod tags;
+mod templates;
+mod time_capsules;
+
 // Entry model matching the frontend interface
 #[derive(Debug, Serialize, Deserialize)]
 struct Entry {
@@ -20,12 +38,41 @@ struct Entry {
     #[allow(dead_code)]
     tenant_id: String,
     user_id: String,
+    notebook_id: String,
     categories: Vec<String>,
     tags: Option<Vec<String>>,
-    mood: Option<String>,
+    mood: Option<Mood>,
     location: Option<String>,
-    word_count: Option<i32>,
+    #[serde(flatten)]
+    metrics: TextMetrics,
     sentiment_score: Option<f64>,
+    // "analyzed" or "skipped" once the AI service has handled the entry, with
+    // the reason when it was skipped
+    analysis_status: Option<String>,
+    analysis_skip_reason: Option<String>,
+    template_id: Option<String>,
+    fields: Option<serde_json::Map<String, serde_json::Value>>,
+    // Time capsules only: when the entry unlocks, and whether it is still sealed
+    unlock_at: Option<String>,
+    sealed: bool,
+    pinned: bool,
+    favorite: bool,
+    archived: bool,
+}
+
+// Title shown in place of a sealed time capsule's own
+const SEALED_TITLE: &str = "Sealed time capsule";
+
+// Entry with its content in a rendered form, for GET /entries/{id}?render=
+#[derive(Debug, Serialize)]
+struct RenderedEntry {
+    #[serde(flatten)]
+    entry: Entry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_text: Option<String>,
+    outline: Vec<OutlineHeading>,
 }
 
 // Input models for create/update
@@ -33,29 +80,41 @@ struct Entry {
 struct CreateEntryInput {
     title: String,
     content: String,
+    notebook_id: Option<String>,
     categories: Vec<String>,
     tags: Option<Vec<String>>,
-    mood: Option<String>,
+    mood: Option<MoodInput>,
     location: Option<String>,
+    template_id: Option<String>,
+    fields: Option<serde_json::Map<String, serde_json::Value>>,
+    // Seals the entry as a time capsule until this date
+    unlock_at: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct UpdateEntryInput {
     title: Option<String>,
     content: Option<String>,
+    notebook_id: Option<String>,
     categories: Option<Vec<String>>,
     tags: Option<Vec<String>>,
-    mood: Option<String>,
+    mood: Option<MoodInput>,
     location: Option<String>,
+    fields: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 // Query parameters for list/search
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct EntryQueryParams {
     category: Option<String>,
+    notebook_id: Option<String>,
     start_date: Option<String>,
     end_date: Option<String>,
     search_text: Option<String>,
+    pinned: Option<bool>,
+    favorite: Option<bool>,
+    #[serde(skip)]
+    archived: ArchivedFilter,
     limit: Option<i32>,
     next_token: Option<String>,
 }
@@ -68,6 +127,12 @@ struct SearchEntryParams {
     from_date: Option<String>,
     to_date: Option<String>,
     mood: Option<String>,
+    min_intensity: Option<i64>,
+    notebook_id: Option<String>,
+    pinned: Option<bool>,
+    favorite: Option<bool>,
+    #[serde(skip)]
+    archived: ArchivedFilter,
     sort_by: Option<String>,  // date_asc, date_desc, title_asc, title_desc
     limit: Option<i32>,
     page: Option<i32>,
@@ -77,14 +142,12 @@ struct SearchEntryParams {
 #[derive(Debug, Serialize)]
 struct TagCount {
     tag: String,
+    // Entries tagged with this tag or any of its children
     count: i32,
-}
-
-// Suggest tags request
-#[derive(Debug, Deserialize)]
-struct SuggestTagsRequest {
-    content: String,
-    existing_tags: Option<Vec<String>>,
+    // Entries tagged with exactly this tag
+    direct_count: i32,
+    first_used: Option<String>,
+    last_used: Option<String>,
 }
 
 // Export format enum
@@ -92,9 +155,77 @@ struct SuggestTagsRequest {
 enum ExportFormat {
     Json,
     Markdown,
+    Html,
     Pdf,
 }
 
+// Convert a DynamoDB item to an Entry; sealed time capsules become a placeholder
+fn item_to_entry(item: &HashMap<String, AttributeValue>) -> Entry {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+
+    let entry = Entry {
+        id: get_s("id").unwrap_or_default(),
+        title: get_s("title").unwrap_or_default(),
+        content: get_s("content").unwrap_or_default(),
+        created_at: get_s("created_at").unwrap_or_default(),
+        updated_at: get_s("updated_at").unwrap_or_default(),
+        tenant_id: get_s("tenant_id").unwrap_or_default(),
+        user_id: get_s("user_id").unwrap_or_default(),
+        notebook_id: entry_notebook_id(item).to_string(),
+        categories: item.get("categories").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default(),
+        tags: item.get("tags").and_then(|v| v.as_ss().ok()).cloned(),
+        mood: entry_mood(item),
+        location: get_s("location"),
+        // Entries written before the full metrics existed get them computed on read
+        metrics: TextMetrics::from_item(item)
+            .unwrap_or_else(|| TextMetrics::from_markdown(&get_s("content").unwrap_or_default())),
+        sentiment_score: item.get("sentiment_score").and_then(|v| v.as_n().ok().and_then(|n| n.parse().ok())),
+        analysis_status: get_s("analysis_status"),
+        analysis_skip_reason: get_s("analysis_skip_reason"),
+        template_id: get_s("template_id"),
+        fields: item.get("field_values").and_then(|v| v.as_m().ok()).map(field_values_to_json),
+        unlock_at: get_s("unlock_at"),
+        sealed: is_sealed(item, chrono::Utc::now()),
+        pinned: entry_flag(item, EntryFlag::Pinned),
+        favorite: entry_flag(item, EntryFlag::Favorite),
+        archived: entry_flag(item, EntryFlag::Archived),
+    };
+
+    if entry.sealed {
+        sealed_placeholder(entry)
+    } else {
+        entry
+    }
+}
+
+// A sealed time capsule with everything but its identity and dates hidden
+fn sealed_placeholder(entry: Entry) -> Entry {
+    Entry {
+        title: SEALED_TITLE.to_string(),
+        content: String::new(),
+        categories: Vec::new(),
+        tags: None,
+        mood: None,
+        location: None,
+        metrics: TextMetrics::default(),
+        sentiment_score: None,
+        template_id: None,
+        fields: None,
+        sealed: true,
+        ..entry
+    }
+}
+
+// Stored word count of an entry item, 0 when the attribute is absent
+fn item_word_count(item: &HashMap<String, AttributeValue>) -> i64 {
+    item.get("word_count").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0)
+}
+
+// Tags stored on an entry item, empty when the attribute is absent
+fn item_tags(item: &HashMap<String, AttributeValue>) -> Vec<String> {
+    item.get("tags").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default()
+}
+
 async fn create_entry(
     event: ApiGatewayProxyRequest,
 ) -> Result<ApiGatewayProxyResponse, Error> {
@@ -106,13 +237,77 @@ async fn create_entry(
     
     // Parse request body
     let body = event.body.ok_or_else(|| JournalError::ValidationError("Missing request body".into()))?;
-    let input: CreateEntryInput = match serde_json::from_str(&body) {
+    let mut input: CreateEntryInput = match serde_json::from_str(&body) {
         Ok(input) => input,
         Err(e) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
     };
     
     // Validate input
-    if input.title.is_empty() || input.content.is_empty() {
+    if input.title.is_empty() {
+        return Ok(error_response(400, &JournalError::ValidationError("Title and content are required".into())));
+    }
+    
+    // A time capsule is sealed from the moment it is written
+    let now = chrono::Utc::now();
+    let unlock_at = match input.unlock_at.as_deref().map(|u| parse_unlock_at(u, now)).transpose() {
+        Ok(unlock_at) => unlock_at.map(format_unlock_at),
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    
+    // Validate the mood against the emotion taxonomy
+    let mood = match input.mood.take().map(MoodInput::into_mood).transpose() {
+        Ok(mood) => mood,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    
+    // Resolve the notebook; archived notebooks take no new entries
+    let dynamo_client = get_dynamo_client().await;
+    let notebook_id = input.notebook_id.clone().unwrap_or_else(|| DEFAULT_NOTEBOOK_ID.to_string());
+    let notebook = match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, &notebook_id).await {
+        Ok(Some(notebook)) if notebook.archived => {
+            return Ok(error_response(400, &JournalError::ValidationError("Notebook is archived".into())));
+        }
+        Ok(Some(notebook)) => notebook,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Notebook not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+    
+    // Resolve the template; without an explicit one the notebook's default applies
+    let template = match (&input.template_id, &notebook.default_template_id) {
+        (Some(template_id), _) => match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, template_id).await {
+            Ok(Some(template)) => Some(template),
+            Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+            Err(e) => return Ok(error_response(500, &e)),
+        },
+        // A deleted default template is ignored rather than blocking writes
+        (None, Some(template_id)) => get_template(&dynamo_client, &claims.tenant_id, &claims.sub, template_id)
+            .await
+            .unwrap_or_default(),
+        (None, None) => None,
+    };
+    
+    // Field values are validated against the template. The notebook's default
+    // only pre-fills the content, so its required fields are enforced just
+    // when the client chose the template or sent field values.
+    let field_values = match (&template, &input.fields) {
+        (Some(_), None) if input.template_id.is_none() => HashMap::new(),
+        (Some(template), fields) => match field_values_to_attribute(template, &fields.clone().unwrap_or_default()) {
+            Ok(values) => values,
+            Err(e) => return Ok(error_response(400, &e)),
+        },
+        (None, Some(_)) => {
+            return Ok(error_response(400, &JournalError::ValidationError("Field values require a template".into())));
+        }
+        (None, None) => HashMap::new(),
+    };
+    
+    // Start from the template's skeleton when no content was written
+    if input.content.trim().is_empty() {
+        if let Some(template) = &template {
+            input.content = template.body.clone();
+        }
+    }
+    if input.content.trim().is_empty() {
         return Ok(error_response(400, &JournalError::ValidationError("Title and content are required".into())));
     }
     
@@ -121,20 +316,27 @@ async fn create_entry(
     let timestamp = chrono::Utc::now().to_rfc3339();
     
     // Prepare DynamoDB item
-    let dynamo_client = get_dynamo_client().await;
     let mut item = HashMap::new();
     
     item.insert("id".to_string(), AttributeValue::S(entry_id.clone()));
     item.insert("tenant_id".to_string(), AttributeValue::S(claims.tenant_id.clone()));
     item.insert("user_id".to_string(), AttributeValue::S(claims.sub.clone()));
+    item.insert("notebook_id".to_string(), AttributeValue::S(notebook.id.clone()));
     item.insert("title".to_string(), AttributeValue::S(input.title.clone()));
     item.insert("content".to_string(), AttributeValue::S(input.content.clone()));
     item.insert("created_at".to_string(), AttributeValue::S(timestamp.clone()));
     item.insert("updated_at".to_string(), AttributeValue::S(timestamp.clone()));
     
-    // Calculate word count
-    let word_count = input.content.split_whitespace().count() as i32;
-    item.insert("word_count".to_string(), AttributeValue::N(word_count.to_string()));
+    // Timeline keys for date-ordered queries through TimelineIndex
+    item.insert("timeline_pk".to_string(), AttributeValue::S(timeline_pk(&claims.tenant_id, &claims.sub)));
+    item.insert("timeline_sk".to_string(), AttributeValue::S(timeline_sk(&timestamp, &entry_id)));
+    
+    // Calculate text metrics and search text from the content without markup
+    let metrics = TextMetrics::from_markdown(&input.content);
+    for (name, value) in metrics.to_attributes() {
+        item.insert(name.to_string(), value);
+    }
+    item.insert("search_text".to_string(), AttributeValue::S(search_text(&input.title, &input.content)));
     
     // Add categories
     if !input.categories.is_empty() {
@@ -144,16 +346,24 @@ async fn create_entry(
         );
     }
     
-    // Add tags if present
-    if let Some(tags) = &input.tags {
+    // Add tags if present, in canonical form; otherwise the notebook's default tags
+    let tags = match &input.tags {
+        Some(tags) => Some(normalize_tags(tags)),
+        None if !notebook.default_tags.is_empty() => Some(notebook.default_tags.clone()),
+        None => None,
+    };
+    if let Some(tags) = &tags {
+        if let Err(e) = check_tag_count(tags) {
+            return Ok(error_response(400, &e));
+        }
         if !tags.is_empty() {
             item.insert("tags".to_string(), AttributeValue::Ss(tags.clone()));
         }
     }
     
     // Add mood if present
-    if let Some(mood) = &input.mood {
-        item.insert("mood".to_string(), AttributeValue::S(mood.clone()));
+    if let Some(mood) = &mood {
+        item.insert("mood".to_string(), mood.to_attribute());
     }
     
     // Add location if present
@@ -161,17 +371,64 @@ async fn create_entry(
         item.insert("location".to_string(), AttributeValue::S(location.clone()));
     }
     
+    // Add template and its structured field values
+    if let Some(template) = &template {
+        item.insert("template_id".to_string(), AttributeValue::S(template.id.clone()));
+        if !field_values.is_empty() {
+            item.insert("field_values".to_string(), AttributeValue::M(field_values.clone()));
+        }
+    }
+    
+    // Seal time capsules; capsule_pk lists them in CapsuleIndex until they are announced.
+    // Other entries can be linked to by title straight away.
+    if let Some(unlock_at) = &unlock_at {
+        item.insert("unlock_at".to_string(), AttributeValue::S(unlock_at.clone()));
+        item.insert("capsule_pk".to_string(), AttributeValue::S(CAPSULE_SEALED.to_string()));
+    } else {
+        item.insert(
+            "title_pk".to_string(),
+            AttributeValue::S(link_title_pk(&claims.tenant_id, &claims.sub, &input.title)),
+        );
+    }
+    
     // Save to DynamoDB
     let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
     
     match dynamo_client
         .put_item()
         .table_name(table_name)
-        .set_item(Some(item))
+        .set_item(Some(item.clone()))
         .send()
         .await
     {
         Ok(_) => {
+            // Count the entry towards its day in the calendar rollups and its
+            // tags' statistics; time capsules are counted once they unlock
+            if unlock_at.is_none() {
+                if let Err(e) = apply_entry_rollup(&dynamo_client, &item, &RollupDelta::for_entry(&item, 1)).await {
+                    tracing::warn!("Failed to update daily rollup: {}", e);
+                }
+
+                let tag_delta = TagStatsDelta::between(&[] as &[String], &item_tags(&item));
+                if let Err(e) = apply_tag_stats_delta(&dynamo_client, &claims.tenant_id, &claims.sub, &tag_delta, &timestamp).await {
+                    tracing::warn!("Failed to update tag stats: {}", e);
+                }
+            }
+
+            // Count the entry towards the user's goals; capsules count once they unlock
+            if unlock_at.is_none() {
+                if let Err(e) = goals::evaluate_goals_after_write(&dynamo_client, &item).await {
+                    tracing::warn!("Failed to update goal progress: {}", e);
+                }
+            }
+
+            // Time capsules are linked once they unlock
+            if unlock_at.is_none() {
+                if let Err(e) = links::sync_links_after_write(&dynamo_client, &claims.tenant_id, &claims.sub, None, &item).await {
+                    tracing::warn!("Failed to update entry links: {}", e);
+                }
+            }
+
             // Create the entry response
             let entry = Entry {
                 id: entry_id.clone(),
@@ -181,21 +438,38 @@ async fn create_entry(
                 updated_at: timestamp,
                 tenant_id: claims.tenant_id.clone(),
                 user_id: claims.sub.clone(),
+                notebook_id: notebook.id.clone(),
                 categories: input.categories,
-                tags: input.tags,
-                mood: input.mood,
+                tags,
+                mood,
                 location: input.location,
-                word_count: Some(word_count),
+                metrics,
                 sentiment_score: None,
+                analysis_status: None,
+                analysis_skip_reason: None,
+                template_id: template.as_ref().map(|t| t.id.clone()),
+                fields: template.as_ref().map(|_| field_values_to_json(&field_values)),
+                sealed: unlock_at.is_some(),
+                unlock_at,
+                pinned: false,
+                favorite: false,
+                archived: false,
             };
             
-            // Publish event for processing
+            // Publish event for processing; sealed capsules are analyzed once they unlock
             let event_detail = serde_json::json!({
                 "entry_id": entry_id,
                 "tenant_id": claims.tenant_id,
                 "user_id": claims.sub,
                 "title": entry.title,
                 "content": entry.content,
+                "notebook_id": notebook.id,
+                "ai_enabled": notebook.ai_enabled,
+                "sealed": entry.sealed,
+                "word_count": entry.metrics.word_count,
+                "tags": entry.tags,
+                "created_at": entry.created_at,
+                "unlock_at": entry.unlock_at,
             });
             
             if let Err(e) = publish_event("EntryCreated", event_detail).await {
@@ -203,6 +477,9 @@ async fn create_entry(
                 // Continue anyway - event publishing should not block the response
             }
             
+            if entry.sealed {
+                return Ok(json_response(201, &sealed_placeholder(entry)));
+            }
             Ok(json_response(201, &entry))
         }
         Err(e) => Ok(error_response(
@@ -253,29 +530,24 @@ async fn get_entry(
                 }
                 
                 // Convert DynamoDB item to Entry
-                let entry = Entry {
-                    id: item.get("id").unwrap().as_s().unwrap().clone(),
-                    title: item.get("title").unwrap().as_s().unwrap().clone(),
-                    content: item.get("content").unwrap().as_s().unwrap().clone(),
-                    created_at: item.get("created_at").unwrap().as_s().unwrap().clone(),
-                    updated_at: item.get("updated_at").unwrap().as_s().unwrap().clone(),
-                    tenant_id: item.get("tenant_id").unwrap().as_s().unwrap().clone(),
-                    user_id: user_id.clone(),
-                    categories: item
-                        .get("categories")
-                        .map(|v| v.as_ss().unwrap().clone())
-                        .unwrap_or_default(),
-                    tags: item.get("tags").map(|v| v.as_ss().unwrap().clone()),
-                    mood: item.get("mood").map(|v| v.as_s().unwrap().clone()),
-                    location: item.get("location").map(|v| v.as_s().unwrap().clone()),
-                    word_count: item.get("word_count").and_then(|v| v.as_n().ok().and_then(|n| n.parse::<i32>().ok())),
-                    sentiment_score: item
-                        .get("sentiment_score")
-                        .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
-                        .flatten(),
-                };
+                let entry = item_to_entry(&item);
                 
-                Ok(json_response(200, &entry))
+                // Optionally include the content rendered as sanitized HTML or plain text
+                match event.query_string_parameters.first("render") {
+                    None => Ok(json_response(200, &entry)),
+                    Some(render @ ("html" | "text")) => {
+                        let rendered = RenderedEntry {
+                            content_html: (render == "html").then(|| render_html(&entry.content)),
+                            content_text: (render == "text").then(|| plain_text(&entry.content)),
+                            outline: outline(&entry.content),
+                            entry,
+                        };
+                        Ok(json_response(200, &rendered))
+                    }
+                    Some(_) => Ok(error_response(400, &JournalError::ValidationError(
+                        "Invalid render. Supported values: html, text".into()
+                    ))),
+                }
             } else {
                 Ok(error_response(404, &JournalError::NotFoundError("Entry not found".into())))
             }
@@ -287,8 +559,12 @@ async fn get_entry(
     }
 }
 
+// List entries, newest first. Pinned entries come before all others: the first
+// page starts with every pinned entry matching the filters, and the paginated
+// query itself skips them. Views preset one of the flag filters.
 async fn list_entries(
     event: ApiGatewayProxyRequest,
+    view: Option<EntryFlag>,
 ) -> Result<ApiGatewayProxyResponse, Error> {
     // Extract tenant context
     let claims = match extract_tenant_context(&event.headers).await {
@@ -296,48 +572,109 @@ async fn list_entries(
         Err(e) => return Ok(error_response(401, &e)),
     };
     
+    let archived = match ArchivedFilter::parse(event.query_string_parameters.first("archived")) {
+        Ok(archived) => archived,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    
     // Parse query parameters directly from QueryMap
-    let query_params = EntryQueryParams {
+    let mut query_params = EntryQueryParams {
         category: event.query_string_parameters.first("category").map(String::from),
+        notebook_id: event.query_string_parameters.first("notebook_id").map(String::from),
         start_date: event.query_string_parameters.first("start_date").map(String::from),
         end_date: event.query_string_parameters.first("end_date").map(String::from),
         search_text: event.query_string_parameters.first("search_text").map(String::from),
+        pinned: event.query_string_parameters.first("pinned").and_then(|s| s.parse().ok()),
+        favorite: event.query_string_parameters.first("favorite").and_then(|s| s.parse().ok()),
+        archived,
         limit: event.query_string_parameters.first("limit").and_then(|s| s.parse().ok()),
         next_token: event.query_string_parameters.first("next_token").map(String::from),
     };
+    match view {
+        Some(EntryFlag::Pinned) => query_params.pinned = Some(true),
+        Some(EntryFlag::Favorite) => query_params.favorite = Some(true),
+        Some(EntryFlag::Archived) => query_params.archived = ArchivedFilter::Only,
+        None => {}
+    }
     
     // Prepare DynamoDB query
     let dynamo_client = get_dynamo_client().await;
     let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
     
-    // Query by GSI for user's entries
-    let mut query = dynamo_client
-        .query()
-        .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
-        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id))
-        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub));
+    // Query the user's timeline, newest first; the date range is part of the key condition
+    let (lower, upper) = timeline_bounds(query_params.start_date.as_deref(), query_params.end_date.as_deref());
+    let mut filter_values: HashMap<String, AttributeValue> = HashMap::new();
+    filter_values.insert(":pk".to_string(), AttributeValue::S(timeline_pk(&claims.tenant_id, &claims.sub)));
+    filter_values.insert(":lower".to_string(), AttributeValue::S(lower));
+    filter_values.insert(":upper".to_string(), AttributeValue::S(upper));
     
-    // Apply category filter if provided
+    // Apply category, notebook and flag filters if provided
+    let mut filter_parts: Vec<String> = Vec::new();
     if let Some(category) = &query_params.category {
-        query = query
-            .filter_expression("contains(categories, :category)")
-            .expression_attribute_values(":category", AttributeValue::S(category.clone()));
+        filter_parts.push("contains(categories, :category)".to_string());
+        filter_values.insert(":category".to_string(), AttributeValue::S(category.clone()));
     }
-    
-    // Apply date filters if provided
-    if let Some(start_date) = &query_params.start_date {
-        query = query
-            .filter_expression("created_at >= :start_date")
-            .expression_attribute_values(":start_date", AttributeValue::S(start_date.clone()));
+    if let Some(notebook_id) = &query_params.notebook_id {
+        filter_parts.push(notebook_filter_expression(notebook_id, &mut filter_values));
+    }
+    if let Some(favorite) = query_params.favorite {
+        filter_parts.push(flag_filter_expression(EntryFlag::Favorite, favorite, &mut filter_values));
+    }
+    if let Some(archived) = query_params.archived.filter_expression(&mut filter_values) {
+        filter_parts.push(archived);
     }
     
-    if let Some(end_date) = &query_params.end_date {
-        query = query
-            .filter_expression("created_at <= :end_date")
-            .expression_attribute_values(":end_date", AttributeValue::S(end_date.clone()));
+    // Pinned entries lead the first page unless the listing is about pinning itself
+    let pinned_first = query_params.pinned.is_none();
+    let mut pinned_items = Vec::new();
+    if pinned_first && query_params.next_token.is_none() {
+        let mut pinned_values = filter_values.clone();
+        let mut pinned_parts = filter_parts.clone();
+        pinned_parts.push(flag_filter_expression(EntryFlag::Pinned, true, &mut pinned_values));
+        
+        let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let response = dynamo_client
+                .query()
+                .table_name(&table_name)
+                .index_name(TIMELINE_INDEX)
+                .key_condition_expression("timeline_pk = :pk AND timeline_sk BETWEEN :lower AND :upper")
+                .filter_expression(pinned_parts.join(" AND "))
+                .set_expression_attribute_values(Some(pinned_values.clone()))
+                .scan_index_forward(false)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await;
+            
+            match response {
+                Ok(response) => {
+                    pinned_items.extend(response.items().iter().cloned());
+                    match response.last_evaluated_key() {
+                        Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+                        _ => break,
+                    }
+                }
+                Err(e) => return Ok(error_response(
+                    500,
+                    &JournalError::DatabaseError(format!("Failed to list pinned entries: {}", e)),
+                )),
+            }
+        }
     }
+    filter_parts.push(flag_filter_expression(
+        EntryFlag::Pinned,
+        query_params.pinned.unwrap_or(false),
+        &mut filter_values,
+    ));
+    
+    let mut query = dynamo_client
+        .query()
+        .table_name(&table_name)
+        .index_name(TIMELINE_INDEX)
+        .key_condition_expression("timeline_pk = :pk AND timeline_sk BETWEEN :lower AND :upper")
+        .filter_expression(filter_parts.join(" AND "))
+        .set_expression_attribute_values(Some(filter_values))
+        .scan_index_forward(false);
     
     // Apply pagination
     if let Some(limit) = query_params.limit {
@@ -361,34 +698,9 @@ async fn list_entries(
     // Execute query
     match query.send().await {
         Ok(response) => {
-            // Convert items to entries
+            // Convert items to entries, pinned ones first
             let items = response.items();
-            let entries: Vec<Entry> = items
-                .iter()
-                .map(|item| {
-                    Entry {
-                        id: item.get("id").unwrap().as_s().unwrap().clone(),
-                        title: item.get("title").unwrap().as_s().unwrap().clone(),
-                        content: item.get("content").unwrap().as_s().unwrap().clone(),
-                        created_at: item.get("created_at").unwrap().as_s().unwrap().clone(),
-                        updated_at: item.get("updated_at").unwrap().as_s().unwrap().clone(),
-                        tenant_id: item.get("tenant_id").unwrap().as_s().unwrap().clone(),
-                        user_id: item.get("user_id").unwrap().as_s().unwrap().clone(),
-                        categories: item
-                            .get("categories")
-                            .map(|v| v.as_ss().unwrap().clone())
-                            .unwrap_or_default(),
-                        tags: item.get("tags").map(|v| v.as_ss().unwrap().clone()),
-                        mood: item.get("mood").map(|v| v.as_s().unwrap().clone()),
-                        location: item.get("location").map(|v| v.as_s().unwrap().clone()),
-                        word_count: item.get("word_count").and_then(|v| v.as_n().ok().and_then(|n| n.parse::<i32>().ok())),
-                        sentiment_score: item
-                            .get("sentiment_score")
-                            .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
-                            .flatten(),
-                    }
-                })
-                .collect();
+            let entries: Vec<Entry> = pinned_items.iter().chain(items.iter()).map(item_to_entry).collect();
             
             // Prepare pagination info - convert DynamoDB key to serializable format
             use base64::Engine;
@@ -432,7 +744,7 @@ async fn update_entry(
     
     // Parse request body
     let body = event.body.ok_or_else(|| JournalError::ValidationError("Missing request body".into()))?;
-    let input: UpdateEntryInput = match serde_json::from_str(&body) {
+    let mut input: UpdateEntryInput = match serde_json::from_str(&body) {
         Ok(input) => input,
         Err(e) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
     };
@@ -449,7 +761,7 @@ async fn update_entry(
         .send()
         .await;
     
-    let _item = match result {
+    let existing_item = match result {
         Ok(response) => {
             match response.item {
                 Some(item) => {
@@ -474,6 +786,15 @@ async fn update_entry(
         )),
     };
     
+    // Time capsules can't be changed until they unlock
+    if is_sealed(&existing_item, chrono::Utc::now()) {
+        let unlock_at = existing_item.get("unlock_at").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+        return Ok(error_response(
+            403,
+            &JournalError::AuthorizationError(format!("Time capsule is sealed until {}", unlock_at)),
+        ));
+    }
+    
     // Build update expression
     let timestamp = chrono::Utc::now().to_rfc3339();
     let mut update_expression = "SET updated_at = :updated_at".to_string();
@@ -486,14 +807,52 @@ async fn update_entry(
     
     // Add title if present
     if let Some(title) = &input.title {
-        update_expression.push_str(", title = :title");
+        update_expression.push_str(", title = :title, title_pk = :title_pk");
         expression_values.insert(":title".to_string(), AttributeValue::S(title.clone()));
+        expression_values.insert(
+            ":title_pk".to_string(),
+            AttributeValue::S(link_title_pk(&claims.tenant_id, &claims.sub, title)),
+        );
     }
     
-    // Add content if present
+    // Add content if present, recomputing its text metrics
+    let mut remove_attributes: Vec<&str> = Vec::new();
     if let Some(content) = &input.content {
         update_expression.push_str(", content = :content");
         expression_values.insert(":content".to_string(), AttributeValue::S(content.clone()));
+        
+        let metrics = TextMetrics::from_markdown(content);
+        if metrics.readability_score.is_none() {
+            remove_attributes.push("readability_score");
+        }
+        for (name, value) in metrics.to_attributes() {
+            update_expression.push_str(&format!(", {} = :{}", name, name));
+            expression_values.insert(format!(":{}", name), value);
+        }
+    }
+    
+    // Keep the search text in step with the title and content
+    if input.title.is_some() || input.content.is_some() {
+        let get_existing = |key: &str| existing_item.get(key).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+        let title = input.title.clone().unwrap_or_else(|| get_existing("title"));
+        let content = input.content.clone().unwrap_or_else(|| get_existing("content"));
+        update_expression.push_str(", search_text = :search_text");
+        expression_values.insert(":search_text".to_string(), AttributeValue::S(search_text(&title, &content)));
+    }
+    
+    // Move to another notebook if requested
+    if let Some(notebook_id) = &input.notebook_id {
+        match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, notebook_id).await {
+            Ok(Some(notebook)) if notebook.archived && notebook.id != entry_notebook_id(&existing_item) => {
+                return Ok(error_response(400, &JournalError::ValidationError("Notebook is archived".into())));
+            }
+            Ok(Some(notebook)) => {
+                update_expression.push_str(", notebook_id = :notebook_id");
+                expression_values.insert(":notebook_id".to_string(), AttributeValue::S(notebook.id));
+            }
+            Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Notebook not found".into()))),
+            Err(e) => return Ok(error_response(500, &e)),
+        }
     }
     
     // Add categories if present
@@ -505,16 +864,28 @@ async fn update_entry(
         );
     }
     
-    // Add tags if present
+    // Add tags if present, in canonical form; an empty list clears them
     if let Some(tags) = &input.tags {
-        update_expression.push_str(", tags = :tags");
-        expression_values.insert(":tags".to_string(), AttributeValue::Ss(tags.clone()));
+        let tags = normalize_tags(tags);
+        if let Err(e) = check_tag_count(&tags) {
+            return Ok(error_response(400, &e));
+        }
+        if tags.is_empty() {
+            remove_attributes.push("tags");
+        } else {
+            update_expression.push_str(", tags = :tags");
+            expression_values.insert(":tags".to_string(), AttributeValue::Ss(tags));
+        }
     }
     
-    // Add mood if present
-    if let Some(mood) = &input.mood {
+    // Add mood if present, validated against the emotion taxonomy
+    if let Some(mood) = input.mood.take() {
+        let mood = match mood.into_mood() {
+            Ok(mood) => mood,
+            Err(e) => return Ok(error_response(400, &e)),
+        };
         update_expression.push_str(", mood = :mood");
-        expression_values.insert(":mood".to_string(), AttributeValue::S(mood.clone()));
+        expression_values.insert(":mood".to_string(), mood.to_attribute());
     }
     
     // Add location if present
@@ -523,6 +894,31 @@ async fn update_entry(
         expression_values.insert(":location".to_string(), AttributeValue::S(location.clone()));
     }
     
+    // Replace field values, validated against the entry's template
+    if let Some(fields) = &input.fields {
+        let template_id = match existing_item.get("template_id").and_then(|v| v.as_s().ok()) {
+            Some(id) => id,
+            None => return Ok(error_response(400, &JournalError::ValidationError("Field values require a template".into()))),
+        };
+        let template = match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, template_id).await {
+            Ok(Some(template)) => template,
+            Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+            Err(e) => return Ok(error_response(500, &e)),
+        };
+        match field_values_to_attribute(&template, fields) {
+            Ok(values) if values.is_empty() => remove_attributes.push("field_values"),
+            Ok(values) => {
+                update_expression.push_str(", field_values = :field_values");
+                expression_values.insert(":field_values".to_string(), AttributeValue::M(values));
+            }
+            Err(e) => return Ok(error_response(400, &e)),
+        }
+    }
+    
+    if !remove_attributes.is_empty() {
+        update_expression.push_str(&format!(" REMOVE {}", remove_attributes.join(", ")));
+    }
+    
     // Update entry
     match dynamo_client
         .update_item()
@@ -539,38 +935,34 @@ async fn update_entry(
             // Build updated entry
             let updated_item = response.attributes().unwrap();
             
-            let entry = Entry {
-                id: updated_item.get("id").unwrap().as_s().unwrap().clone(),
-                title: updated_item.get("title").unwrap().as_s().unwrap().clone(),
-                content: updated_item.get("content").unwrap().as_s().unwrap().clone(),
-                created_at: updated_item.get("created_at").unwrap().as_s().unwrap().clone(),
-                updated_at: updated_item.get("updated_at").unwrap().as_s().unwrap().clone(),
-                tenant_id: updated_item.get("tenant_id").unwrap().as_s().unwrap().clone(),
-                user_id: updated_item.get("user_id").unwrap().as_s().unwrap().clone(),
-                categories: updated_item
-                    .get("categories")
-                    .map(|v| v.as_ss().unwrap().clone())
-                    .unwrap_or_default(),
-                tags: updated_item.get("tags").map(|v| v.as_ss().unwrap().clone()),
-                mood: updated_item.get("mood").map(|v| v.as_s().unwrap().clone()),
-                location: updated_item.get("location").map(|v| v.as_s().unwrap().clone()),
-                word_count: updated_item.get("word_count").and_then(|v| v.as_n().ok().and_then(|n| n.parse::<i32>().ok())),
-                sentiment_score: updated_item
-                    .get("sentiment_score")
-                    .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
-                    .flatten(),
-            };
+            let entry = item_to_entry(updated_item);
             
-            // Publish event
-            let event_detail = serde_json::json!({
-                "entry_id": entry_id,
-                "tenant_id": claims.tenant_id,
-                "user_id": claims.sub,
-                "title": entry.title,
-                "content": entry.content,
-            });
+            // Move word count and mood changes into the calendar rollups. A
+            // capsule the unlock job hasn't reached yet is counted by it instead.
+            if !awaiting_unlock(&existing_item) {
+                let delta = RollupDelta::between(&existing_item, updated_item);
+                if let Err(e) = apply_entry_rollup(&dynamo_client, updated_item, &delta).await {
+                    tracing::warn!("Failed to update daily rollup: {}", e);
+                }
+                
+                // Tags removed by the update are subtracted as well as new ones added
+                let tag_delta = TagStatsDelta::between(&item_tags(&existing_item), &item_tags(updated_item));
+                if let Err(e) = apply_tag_stats_delta(&dynamo_client, &claims.tenant_id, &claims.sub, &tag_delta, &timestamp).await {
+                    tracing::warn!("Failed to update tag stats: {}", e);
+                }
+            }
+            
+            // Word count and tag changes can move goal progress either way
+            if let Err(e) = goals::evaluate_goals_after_write(&dynamo_client, updated_item).await {
+                tracing::warn!("Failed to update goal progress: {}", e);
+            }
             
-            if let Err(e) = publish_event("EntryUpdated", event_detail).await {
+            // Re-parse links and repair references to a renamed entry
+            if let Err(e) = links::sync_links_after_write(&dynamo_client, &claims.tenant_id, &claims.sub, Some(&existing_item), updated_item).await {
+                tracing::warn!("Failed to update entry links: {}", e);
+            }
+            
+            if let Err(e) = publish_entry_updated(&dynamo_client, &existing_item, updated_item).await {
                 tracing::warn!("Failed to publish event: {}", e);
             }
             
@@ -583,6 +975,37 @@ async fn update_entry(
     }
 }
 
+// Publish EntryUpdated for an edit of `existing_item` into `updated_item`,
+// honouring the notebook's AI setting
+async fn publish_entry_updated(
+    dynamo_client: &aws_sdk_dynamodb::Client,
+    existing_item: &HashMap<String, AttributeValue>,
+    updated_item: &HashMap<String, AttributeValue>,
+) -> Result<(), JournalError> {
+    let entry = item_to_entry(updated_item);
+    let ai_enabled = match get_notebook(dynamo_client, &entry.tenant_id, &entry.user_id, &entry.notebook_id).await {
+        Ok(notebook) => notebook.is_none_or(|n| n.ai_enabled),
+        Err(e) => {
+            tracing::warn!("Failed to load notebook: {}", e);
+            true
+        }
+    };
+    let event_detail = serde_json::json!({
+        "entry_id": entry.id,
+        "tenant_id": entry.tenant_id,
+        "user_id": entry.user_id,
+        "title": entry.title,
+        "content": entry.content,
+        "notebook_id": entry.notebook_id,
+        "ai_enabled": ai_enabled,
+        "word_count": entry.metrics.word_count,
+        "word_count_delta": item_word_count(updated_item) - item_word_count(existing_item),
+        "tags": entry.tags,
+        "created_at": entry.created_at,
+    });
+    publish_event("EntryUpdated", event_detail).await
+}
+
 async fn delete_entry(
     event: ApiGatewayProxyRequest,
 ) -> Result<ApiGatewayProxyResponse, Error> {
@@ -634,11 +1057,33 @@ async fn delete_entry(
                         .await
                     {
                         Ok(_) => {
+                            // Capsules not yet unlocked were never counted
+                            if !awaiting_unlock(&item) {
+                                if let Err(e) = apply_entry_rollup(&dynamo_client, &item, &RollupDelta::for_entry(&item, -1)).await {
+                                    tracing::warn!("Failed to update daily rollup: {}", e);
+                                }
+                                
+                                let tag_delta = TagStatsDelta::between(&item_tags(&item), &[] as &[String]);
+                                let now = chrono::Utc::now().to_rfc3339();
+                                if let Err(e) = apply_tag_stats_delta(&dynamo_client, &claims.tenant_id, &claims.sub, &tag_delta, &now).await {
+                                    tracing::warn!("Failed to update tag stats: {}", e);
+                                }
+                            }
+                            
+                            if let Err(e) = goals::evaluate_goals_after_write(&dynamo_client, &item).await {
+                                tracing::warn!("Failed to update goal progress: {}", e);
+                            }
+                            
+                            if let Err(e) = links::sync_links_after_delete(&dynamo_client, &claims.tenant_id, &claims.sub, &item).await {
+                                tracing::warn!("Failed to update entry links: {}", e);
+                            }
+                            
                             // Publish event
                             let event_detail = serde_json::json!({
                                 "entry_id": entry_id,
                                 "tenant_id": claims.tenant_id,
                                 "user_id": claims.sub,
+                                "word_count_delta": -item_word_count(&item),
                             });
                             
                             if let Err(e) = publish_event("EntryDeleted", event_detail).await {
@@ -745,6 +1190,14 @@ async fn get_entry_insights(
                     insights.insert("provider".to_string(), serde_json::Value::String(provider.clone()));
                 }
                 
+                // Extract the prompt template version the analysis was made with
+                if let Some(AttributeValue::S(template)) = item.get("prompt_template") {
+                    insights.insert("prompt_template".to_string(), serde_json::Value::String(template.clone()));
+                }
+                if let Some(version) = item.get("prompt_version").and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<u64>().ok()) {
+                    insights.insert("prompt_version".to_string(), serde_json::Value::from(version));
+                }
+                
                 // Extract created_at
                 if let Some(AttributeValue::S(created_at)) = item.get("created_at") {
                     insights.insert("created_at".to_string(), serde_json::Value::String(created_at.clone()));
@@ -776,6 +1229,11 @@ async fn search_entries(
         Err(e) => return Ok(error_response(401, &e)),
     };
 
+    let archived = match ArchivedFilter::parse(event.query_string_parameters.first("archived")) {
+        Ok(archived) => archived,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
     // Parse search parameters from query string
     let params = SearchEntryParams {
         text: event.query_string_parameters.first("text").map(String::from),
@@ -783,6 +1241,11 @@ async fn search_entries(
         from_date: event.query_string_parameters.first("from_date").map(String::from),
         to_date: event.query_string_parameters.first("to_date").map(String::from),
         mood: event.query_string_parameters.first("mood").map(String::from),
+        min_intensity: event.query_string_parameters.first("min_intensity").and_then(|s| s.parse().ok()),
+        notebook_id: event.query_string_parameters.first("notebook_id").map(String::from),
+        pinned: event.query_string_parameters.first("pinned").and_then(|s| s.parse().ok()),
+        favorite: event.query_string_parameters.first("favorite").and_then(|s| s.parse().ok()),
+        archived,
         sort_by: event.query_string_parameters.first("sort_by").map(String::from),
         limit: event.query_string_parameters.first("limit").and_then(|s| s.parse().ok()),
         page: event.query_string_parameters.first("page").and_then(|s| s.parse().ok()),
@@ -796,15 +1259,20 @@ async fn search_entries(
     let mut expression_values: HashMap<String, AttributeValue> = HashMap::new();
     let mut expression_names: HashMap<String, String> = HashMap::new();
 
-    // Add user filter (always required)
-    expression_values.insert(":tenant_id".to_string(), AttributeValue::S(claims.tenant_id.clone()));
-    expression_values.insert(":user_id".to_string(), AttributeValue::S(claims.sub.clone()));
+    // Restrict to the user's timeline; the date range is part of the key condition
+    let (lower, upper) = timeline_bounds(params.from_date.as_deref(), params.to_date.as_deref());
+    expression_values.insert(":pk".to_string(), AttributeValue::S(timeline_pk(&claims.tenant_id, &claims.sub)));
+    expression_values.insert(":lower".to_string(), AttributeValue::S(lower));
+    expression_values.insert(":upper".to_string(), AttributeValue::S(upper));
 
-    // Text search (searches in title and content)
+    // Text search over the lowercased plain text of title and content; entries
+    // written before search_text existed fall back to the raw fields
     if let Some(text) = &params.text {
         if !text.is_empty() {
             let search_lower = text.to_lowercase();
-            filter_parts.push("(contains(#title_lower, :search_text) OR contains(#content_lower, :search_text))".to_string());
+            filter_parts.push(
+                "(contains(search_text, :search_text) OR (attribute_not_exists(search_text) AND (contains(#title_lower, :search_text) OR contains(#content_lower, :search_text))))".to_string(),
+            );
             expression_values.insert(":search_text".to_string(), AttributeValue::S(search_lower));
             expression_names.insert("#title_lower".to_string(), "title".to_string());
             expression_names.insert("#content_lower".to_string(), "content".to_string());
@@ -813,36 +1281,52 @@ async fn search_entries(
 
     // Tags filter
     if let Some(tags_str) = &params.tags {
-        let tags: Vec<&str> = tags_str.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+        let tags = normalize_tags(tags_str.split(','));
         for (i, tag) in tags.iter().enumerate() {
             filter_parts.push(format!("contains(tags, :tag{})", i));
-            expression_values.insert(format!(":tag{}", i), AttributeValue::S(tag.to_string()));
+            expression_values.insert(format!(":tag{}", i), AttributeValue::S(tag.clone()));
         }
     }
 
-    // Date range filter
-    if let Some(from_date) = &params.from_date {
-        filter_parts.push("created_at >= :from_date".to_string());
-        expression_values.insert(":from_date".to_string(), AttributeValue::S(from_date.clone()));
+    // Mood filter: an emotion, or a word for one, felt as primary or secondary emotion
+    if let Some(mood) = &params.mood {
+        let Some(emotion) = Emotion::parse(mood) else {
+            return Ok(error_response(400, &JournalError::ValidationError(format!("Unknown emotion '{}'", mood))));
+        };
+        filter_parts.push(mood_filter_expression(emotion, &mut expression_values));
+    }
+    if let Some(min_intensity) = params.min_intensity {
+        let min_intensity = min_intensity.clamp(INTENSITY_MIN as i64, INTENSITY_MAX as i64);
+        filter_parts.push("mood.intensity >= :min_intensity".to_string());
+        expression_values.insert(":min_intensity".to_string(), AttributeValue::N(min_intensity.to_string()));
     }
 
-    if let Some(to_date) = &params.to_date {
-        filter_parts.push("created_at <= :to_date".to_string());
-        expression_values.insert(":to_date".to_string(), AttributeValue::S(to_date.clone()));
+    // Notebook filter
+    if let Some(notebook_id) = &params.notebook_id {
+        filter_parts.push(notebook_filter_expression(notebook_id, &mut expression_values));
     }
 
-    // Mood filter
-    if let Some(mood) = &params.mood {
-        filter_parts.push("mood = :mood".to_string());
-        expression_values.insert(":mood".to_string(), AttributeValue::S(mood.clone()));
+    // Pinned, favorite and archived filters; archived entries are left out by default
+    if let Some(pinned) = params.pinned {
+        filter_parts.push(flag_filter_expression(EntryFlag::Pinned, pinned, &mut expression_values));
+    }
+    if let Some(favorite) = params.favorite {
+        filter_parts.push(flag_filter_expression(EntryFlag::Favorite, favorite, &mut expression_values));
+    }
+    if let Some(archived) = params.archived.filter_expression(&mut expression_values) {
+        filter_parts.push(archived);
     }
 
-    // Build the query
+    // Sealed time capsules never match
+    filter_parts.push(unsealed_filter_expression(&mut expression_values));
+
+    // Build the query, newest first
     let mut query = dynamo_client
         .query()
         .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id");
+        .index_name(TIMELINE_INDEX)
+        .key_condition_expression("timeline_pk = :pk AND timeline_sk BETWEEN :lower AND :upper")
+        .scan_index_forward(false);
 
     // Add filter expression if there are filters
     if !filter_parts.is_empty() {
@@ -875,24 +1359,7 @@ async fn search_entries(
             let items = response.items();
 
             // Convert items to entries
-            let mut entries: Vec<Entry> = items
-                .iter()
-                .map(|item| Entry {
-                    id: item.get("id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    title: item.get("title").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    content: item.get("content").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    created_at: item.get("created_at").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    tenant_id: item.get("tenant_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    user_id: item.get("user_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    categories: item.get("categories").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default(),
-                    tags: item.get("tags").and_then(|v| v.as_ss().ok()).cloned(),
-                    mood: item.get("mood").and_then(|v| v.as_s().ok()).cloned(),
-                    location: item.get("location").and_then(|v| v.as_s().ok()).cloned(),
-                    word_count: item.get("word_count").and_then(|v| v.as_n().ok().and_then(|n| n.parse().ok())),
-                    sentiment_score: item.get("sentiment_score").and_then(|v| v.as_n().ok().and_then(|n| n.parse().ok())),
-                })
-                .collect();
+            let mut entries: Vec<Entry> = items.iter().map(item_to_entry).collect();
 
             // Apply sorting
             if let Some(sort_by) = &params.sort_by {
@@ -907,6 +1374,9 @@ async fn search_entries(
                 entries.sort_by(|a, b| b.created_at.cmp(&a.created_at)); // default: date_desc
             }
 
+            // Pinned entries first, keeping the chosen order within each group
+            entries.sort_by_key(|entry| !entry.pinned);
+
             // Apply pagination (skip to correct page)
             let start_index = ((page - 1) * limit) as usize;
             let paginated_entries: Vec<Entry> = entries.into_iter().skip(start_index).take(limit as usize).collect();
@@ -941,46 +1411,49 @@ async fn export_entries(
     let format = match format_str.to_lowercase().as_str() {
         "json" => ExportFormat::Json,
         "markdown" | "md" => ExportFormat::Markdown,
+        "html" => ExportFormat::Html,
         "pdf" => ExportFormat::Pdf,
         _ => return Ok(error_response(400, &JournalError::ValidationError(
-            "Invalid format. Supported formats: json, markdown, pdf".into()
+            "Invalid format. Supported formats: json, markdown, html, pdf".into()
         ))),
     };
 
-    // Fetch all entries for the user
+    // Everything, or only the favorites as a collection of their own
+    let collection = event.query_string_parameters.first("collection").unwrap_or("all");
+    let (collection_title, file_name, favorites_only) = match collection {
+        "all" => ("Journal Entries", "journal-entries", false),
+        "favorites" => ("Favorite Entries", "journal-favorites", true),
+        other => return Ok(error_response(400, &JournalError::ValidationError(format!(
+            "Invalid collection '{}'. Supported collections: all, favorites",
+            other
+        )))),
+    };
+
+    // Optional date range and notebook
+    let from_date = event.query_string_parameters.first("from_date");
+    let to_date = event.query_string_parameters.first("to_date");
+    let notebook_id = event.query_string_parameters.first("notebook_id");
+
+    // Fetch the user's entries in chronological order
     let dynamo_client = get_dynamo_client().await;
     let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
 
-    let result = dynamo_client
-        .query()
-        .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
-        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id.clone()))
-        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub.clone()))
-        .send()
-        .await;
+    let result = query_timeline(
+        &dynamo_client,
+        &table_name,
+        &claims.tenant_id,
+        &claims.sub,
+        from_date,
+        to_date,
+    ).await;
 
     match result {
-        Ok(response) => {
-            let items = response.items();
+        Ok(items) => {
             let entries: Vec<Entry> = items
                 .iter()
-                .map(|item| Entry {
-                    id: item.get("id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    title: item.get("title").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    content: item.get("content").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    created_at: item.get("created_at").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    tenant_id: item.get("tenant_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    user_id: item.get("user_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default(),
-                    categories: item.get("categories").and_then(|v| v.as_ss().ok()).cloned().unwrap_or_default(),
-                    tags: item.get("tags").and_then(|v| v.as_ss().ok()).cloned(),
-                    mood: item.get("mood").and_then(|v| v.as_s().ok()).cloned(),
-                    location: item.get("location").and_then(|v| v.as_s().ok()).cloned(),
-                    word_count: item.get("word_count").and_then(|v| v.as_n().ok().and_then(|n| n.parse().ok())),
-                    sentiment_score: item.get("sentiment_score").and_then(|v| v.as_n().ok().and_then(|n| n.parse().ok())),
-                })
+                .filter(|item| notebook_id.is_none_or(|id| entry_notebook_id(item) == id))
+                .filter(|item| !favorites_only || entry_flag(item, EntryFlag::Favorite))
+                .map(item_to_entry)
                 .collect();
 
             // Generate export content based on format
@@ -990,7 +1463,7 @@ async fn export_entries(
 
                     let mut headers = aws_lambda_events::http::HeaderMap::new();
                     headers.insert("content-type", "application/json".parse().unwrap());
-                    headers.insert("content-disposition", "attachment; filename=\"journal-entries.json\"".parse().unwrap());
+                    headers.insert("content-disposition", format!("attachment; filename=\"{}.json\"", file_name).parse().unwrap());
 
                     Ok(ApiGatewayProxyResponse {
                         status_code: 200,
@@ -1001,7 +1474,7 @@ async fn export_entries(
                     })
                 }
                 ExportFormat::Markdown => {
-                    let mut markdown = String::from("# Journal Entries\n\n");
+                    let mut markdown = format!("# {}\n\n", collection_title);
                     markdown.push_str(&format!("Exported on: {}\n\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
                     markdown.push_str("---\n\n");
 
@@ -1019,13 +1492,14 @@ async fn export_entries(
                             }
                         }
 
-                        markdown.push_str(&format!("{}\n\n", entry.content));
+                        // Nest the entry's own headings under its title
+                        markdown.push_str(&format!("{}\n\n", demote_headings(&entry.content, 2)));
                         markdown.push_str("---\n\n");
                     }
 
                     let mut headers = aws_lambda_events::http::HeaderMap::new();
                     headers.insert("content-type", "text/markdown; charset=utf-8".parse().unwrap());
-                    headers.insert("content-disposition", "attachment; filename=\"journal-entries.md\"".parse().unwrap());
+                    headers.insert("content-disposition", format!("attachment; filename=\"{}.md\"", file_name).parse().unwrap());
 
                     Ok(ApiGatewayProxyResponse {
                         status_code: 200,
@@ -1035,10 +1509,47 @@ async fn export_entries(
                         is_base64_encoded: false,
                     })
                 }
+                ExportFormat::Html => {
+                    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n", collection_title);
+                    html.push_str(&format!("<h1>{}</h1>\n", collection_title));
+                    html.push_str(&format!("<p>Exported on: {}</p>\n<hr>\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
+
+                    for entry in entries {
+                        html.push_str("<article>\n");
+                        html.push_str(&format!("<h2>{}</h2>\n", escape_html(&entry.title)));
+                        html.push_str(&format!("<p><strong>Date:</strong> {}</p>\n", escape_html(&entry.created_at)));
+
+                        if let Some(mood) = &entry.mood {
+                            html.push_str(&format!("<p><strong>Mood:</strong> {}</p>\n", escape_html(&mood.to_string())));
+                        }
+
+                        if let Some(tags) = &entry.tags {
+                            if !tags.is_empty() {
+                                html.push_str(&format!("<p><strong>Tags:</strong> {}</p>\n", escape_html(&tags.join(", "))));
+                            }
+                        }
+
+                        html.push_str(&render_html(&demote_headings(&entry.content, 2)));
+                        html.push_str("</article>\n<hr>\n");
+                    }
+                    html.push_str("</body>\n</html>\n");
+
+                    let mut headers = aws_lambda_events::http::HeaderMap::new();
+                    headers.insert("content-type", "text/html; charset=utf-8".parse().unwrap());
+                    headers.insert("content-disposition", format!("attachment; filename=\"{}.html\"", file_name).parse().unwrap());
+
+                    Ok(ApiGatewayProxyResponse {
+                        status_code: 200,
+                        headers,
+                        multi_value_headers: Default::default(),
+                        body: Some(aws_lambda_events::encodings::Body::Text(html)),
+                        is_base64_encoded: false,
+                    })
+                }
                 ExportFormat::Pdf => {
                     // For PDF, we return a simple text representation
                     // In production, you'd use a PDF generation library or service
-                    let mut content = String::from("JOURNAL ENTRIES EXPORT\n");
+                    let mut content = format!("{} EXPORT\n", collection_title.to_uppercase());
                     content.push_str(&format!("Exported: {}\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
                     content.push_str(&"=".repeat(50));
                     content.push_str("\n\n");
@@ -1058,7 +1569,7 @@ async fn export_entries(
                         }
 
                         content.push_str("\n");
-                        content.push_str(&entry.content);
+                        content.push_str(&plain_text(&entry.content));
                         content.push_str("\n\n");
                         content.push_str(&"-".repeat(50));
                         content.push_str("\n\n");
@@ -1066,7 +1577,7 @@ async fn export_entries(
 
                     let mut headers = aws_lambda_events::http::HeaderMap::new();
                     headers.insert("content-type", "text/plain; charset=utf-8".parse().unwrap());
-                    headers.insert("content-disposition", "attachment; filename=\"journal-entries.txt\"".parse().unwrap());
+                    headers.insert("content-disposition", format!("attachment; filename=\"{}.txt\"", file_name).parse().unwrap());
 
                     Ok(ApiGatewayProxyResponse {
                         status_code: 200,
@@ -1078,10 +1589,7 @@ async fn export_entries(
                 }
             }
         }
-        Err(e) => Ok(error_response(
-            500,
-            &JournalError::DatabaseError(format!("Failed to fetch entries for export: {}", e)),
-        )),
+        Err(e) => Ok(error_response(500, &e)),
     }
 }
 
@@ -1096,37 +1604,20 @@ async fn get_tags(
     };
 
     let dynamo_client = get_dynamo_client().await;
-    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
-
-    // Query all entries for the user to aggregate tags
-    let result = dynamo_client
-        .query()
-        .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
-        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id.clone()))
-        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub.clone()))
-        .projection_expression("tags")
-        .send()
-        .await;
-
-    match result {
-        Ok(response) => {
-            // Aggregate tag counts
-            let mut tag_counts: HashMap<String, i32> = HashMap::new();
-
-            for item in response.items() {
-                if let Some(tags) = item.get("tags").and_then(|v| v.as_ss().ok()) {
-                    for tag in tags {
-                        *tag_counts.entry(tag.clone()).or_insert(0) += 1;
-                    }
-                }
-            }
 
-            // Convert to TagCount structs
-            let mut tags: Vec<TagCount> = tag_counts
+    // Counts are maintained incrementally on every entry write
+    match get_tag_stats(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(stats) => {
+            let mut tags: Vec<TagCount> = stats
+                .tags
                 .into_iter()
-                .map(|(tag, count)| TagCount { tag, count })
+                .map(|stat| TagCount {
+                    tag: stat.tag,
+                    count: stat.total_count as i32,
+                    direct_count: stat.count as i32,
+                    first_used: stat.first_used,
+                    last_used: stat.last_used,
+                })
                 .collect();
 
             // Sort by count descending, then alphabetically
@@ -1136,157 +1627,139 @@ async fn get_tags(
 
             Ok(json_response(200, &tags))
         }
-        Err(e) => Ok(error_response(
-            500,
-            &JournalError::DatabaseError(format!("Failed to get tags: {}", e)),
-        )),
+        Err(e) => Ok(error_response(500, &e)),
     }
 }
 
-// Suggest tags based on content using simple keyword extraction
-async fn suggest_tags(
-    event: ApiGatewayProxyRequest,
-) -> Result<ApiGatewayProxyResponse, Error> {
-    // Extract tenant context
-    let claims = match extract_tenant_context(&event.headers).await {
-        Ok(claims) => claims,
-        Err(e) => return Ok(error_response(401, &e)),
-    };
+async fn handler(event: LambdaEvent<serde_json::Value>) -> Result<ApiGatewayProxyResponse, Error> {
+    let (payload, _context) = event.into_parts();
 
-    // Parse request body
-    let body = match &event.body {
-        Some(b) => b,
-        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
-    };
-
-    let request: SuggestTagsRequest = match serde_json::from_str(body) {
-        Ok(req) => req,
-        Err(e) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
-    };
-
-    let existing_tags: Vec<String> = request.existing_tags.unwrap_or_default();
-
-    // Get user's existing tags to suggest from their vocabulary
-    let dynamo_client = get_dynamo_client().await;
-    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
-
-    let result = dynamo_client
-        .query()
-        .table_name(table_name)
-        .index_name("UserIndex")
-        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
-        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id.clone()))
-        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub.clone()))
-        .projection_expression("tags")
-        .send()
-        .await;
-
-    // Collect all user's tags
-    let user_tags: Vec<String> = match result {
-        Ok(response) => {
-            let mut all_tags: Vec<String> = Vec::new();
-            for item in response.items() {
-                if let Some(tags) = item.get("tags").and_then(|v| v.as_ss().ok()) {
-                    all_tags.extend(tags.iter().cloned());
-                }
-            }
-            all_tags
-        }
-        Err(_) => Vec::new(),
-    };
-
-    // Extract keywords from content
-    let content_lower = request.content.to_lowercase();
-    let words: Vec<&str> = content_lower
-        .split(|c: char| !c.is_alphanumeric())
-        .filter(|w| w.len() > 3)
-        .collect();
-
-    // Common stop words to filter out
-    let stop_words: std::collections::HashSet<&str> = [
-        "the", "and", "for", "are", "but", "not", "you", "all", "can", "had",
-        "her", "was", "one", "our", "out", "day", "get", "has", "him", "his",
-        "how", "its", "may", "new", "now", "old", "see", "way", "who", "boy",
-        "did", "own", "say", "she", "too", "use", "that", "with", "have", "this",
-        "will", "your", "from", "they", "been", "call", "come", "each", "find",
-        "long", "make", "many", "more", "than", "time", "very", "when", "what",
-        "which", "would", "about", "could", "other", "their", "there", "these",
-        "think", "thought", "today", "really", "feeling", "feel", "just", "like",
-        "want", "know", "going", "things", "being", "something", "always",
-    ].iter().cloned().collect();
-
-    // Count word frequencies
-    let mut word_freq: HashMap<String, i32> = HashMap::new();
-    for word in words {
-        if !stop_words.contains(word) {
-            *word_freq.entry(word.to_string()).or_insert(0) += 1;
-        }
+    // Scheduled invocations carry a constant {"job": "..."} input
+    if let Some(job) = payload.get("job").and_then(|j| j.as_str()) {
+        return handle_scheduled_job(job).await;
     }
 
-    // Get top keywords
-    let mut keywords: Vec<(String, i32)> = word_freq.into_iter().collect();
-    keywords.sort_by(|a, b| b.1.cmp(&a.1));
-
-    let mut suggested_tags: Vec<String> = Vec::new();
-
-    // First, check if any user's existing tags match content
-    for user_tag in &user_tags {
-        let tag_lower = user_tag.to_lowercase();
-        if content_lower.contains(&tag_lower) && !existing_tags.contains(user_tag) && !suggested_tags.contains(user_tag) {
-            suggested_tags.push(user_tag.clone());
-            if suggested_tags.len() >= 5 {
-                break;
-            }
-        }
+    match serde_json::from_value::<ApiGatewayProxyRequest>(payload) {
+        Ok(request) => handle_http_request(request).await,
+        Err(_) => Ok(error_response(
+            400,
+            &JournalError::ValidationError("Unknown event type".into()),
+        )),
     }
+}
 
-    // Then add top keywords as suggestions
-    for (keyword, _) in keywords.iter().take(10) {
-        if !existing_tags.iter().any(|t| t.to_lowercase() == *keyword)
-            && !suggested_tags.iter().any(|t| t.to_lowercase() == *keyword)
-            && suggested_tags.len() < 5
-        {
-            suggested_tags.push(keyword.clone());
-        }
-    }
+async fn handle_scheduled_job(job: &str) -> Result<ApiGatewayProxyResponse, Error> {
+    tracing::info!("Running scheduled job: {}", job);
 
-    Ok(json_response(200, &suggested_tags))
+    match job {
+        "on-this-day" => match on_this_day::publish_on_this_day_events().await {
+            Ok(published) => Ok(json_response(200, &serde_json::json!({ "published": published }))),
+            Err(e) => Ok(error_response(500, &e)),
+        },
+        "time-capsules" => match time_capsules::publish_unlocked_capsules().await {
+            Ok(published) => Ok(json_response(200, &serde_json::json!({ "published": published }))),
+            Err(e) => Ok(error_response(500, &e)),
+        },
+        _ => Ok(error_response(
+            400,
+            &JournalError::ValidationError(format!("Unknown job: {}", job)),
+        )),
+    }
 }
 
-async fn handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+async fn handle_http_request(
+    request: ApiGatewayProxyRequest,
 ) -> Result<ApiGatewayProxyResponse, Error> {
     // Extract path and method for routing
-    let path = event.payload.path.as_deref().unwrap_or("");
-    let method = event.payload.http_method.as_str();
+    let path = request.path.clone().unwrap_or_default();
+    let method = request.http_method.as_str();
     
     tracing::info!("Handling request: {} {}", method, path);
     
     // Route the request to the appropriate handler
-    match (method, path) {
+    match (method, path.as_str()) {
         // Health check endpoint
-        ("GET", "/health") => health_check(event.payload).await,
+        ("GET", "/health") => health_check(request).await,
 
         // Search entries - must be before generic /entries/{id} route
-        ("GET", "/entries/search") => search_entries(event.payload).await,
+        ("GET", "/entries/search") => search_entries(request).await,
 
         // Export entries
-        ("GET", "/entries/export") => export_entries(event.payload).await,
+        ("GET", "/entries/export") => export_entries(request).await,
 
         // Get all tags with counts
-        ("GET", "/entries/tags") => get_tags(event.payload).await,
+        ("GET", "/entries/tags") => get_tags(request).await,
+
+        // Tag management
+        ("POST", "/entries/tags/rename") => tags::rename_tag(request).await,
+        ("POST", "/entries/tags/merge") => tags::merge_tags(request).await,
+        ("DELETE", p) if p.starts_with("/entries/tags/") => tags::delete_tag(request).await,
 
         // Suggest tags for content
-        ("POST", "/entries/suggest-tags") => suggest_tags(event.payload).await,
+        ("POST", "/entries/suggest-tags") => suggest::suggest_tags(request).await,
+
+        // Per-day summaries for calendar and heatmap views
+        ("GET", "/entries/calendar") => calendar::get_calendar(request).await,
+
+        // Entries from the same day in previous years and recent lookbacks
+        ("GET", "/entries/on-this-day") => on_this_day::get_on_this_day(request).await,
+
+        // Wiki link graph between entries
+        ("GET", "/entries/graph") => links::get_link_graph(request).await,
+
+        // Notebooks
+        ("GET", "/notebooks") => notebooks::list_notebooks(request).await,
+        ("POST", "/notebooks") => notebooks::create_notebook(request).await,
+        ("GET", p) if p.starts_with("/notebooks/") => notebooks::get_notebook_by_id(request).await,
+        ("PUT", p) if p.starts_with("/notebooks/") => notebooks::update_notebook(request).await,
+        ("DELETE", p) if p.starts_with("/notebooks/") => notebooks::delete_notebook(request).await,
+
+        // Recurring goals
+        ("GET", "/goals") => goals::list_user_goals(request).await,
+        ("POST", "/goals") => goals::create_goal(request).await,
+        ("GET", p) if p.starts_with("/goals/") && p.ends_with("/progress") => goals::get_goal_progress(request).await,
+        ("PUT", p) if p.starts_with("/goals/") => goals::update_goal(request).await,
+        ("DELETE", p) if p.starts_with("/goals/") => goals::delete_goal(request).await,
+
+        // Entry templates
+        ("GET", "/templates") => templates::list_templates(request).await,
+        ("POST", "/templates") => templates::create_template(request).await,
+        ("GET", p) if p.starts_with("/templates/") => templates::get_template_by_id(request).await,
+        ("PUT", p) if p.starts_with("/templates/") => templates::update_template(request).await,
+        ("DELETE", p) if p.starts_with("/templates/") => templates::delete_template(request).await,
 
         // Entry CRUD endpoints
-        ("POST", "/entries") => create_entry(event.payload).await,
-        ("GET", "/entries") => list_entries(event.payload).await,
+        ("POST", "/entries") => create_entry(request).await,
+        ("GET", "/entries") => list_entries(request, None).await,
+
+        // Views of pinned, favorite and archived entries
+        ("GET", "/entries/pinned") => list_entries(request, Some(EntryFlag::Pinned)).await,
+        ("GET", "/entries/favorites") => list_entries(request, Some(EntryFlag::Favorite)).await,
+        ("GET", "/entries/archived") => list_entries(request, Some(EntryFlag::Archived)).await,
+
+        // PUT|DELETE /entries/{id}/pin|favorite|archive - Set or clear a flag
+        ("PUT" | "DELETE", p)
+            if p.starts_with("/entries/")
+                && p.split('/').count() == 4
+                && p.rsplit('/').next().and_then(EntryFlag::from_path_segment).is_some() =>
+        {
+            flags::set_entry_flag(request).await
+        }
+
+        // GET /entries/{id}/backlinks - Entries linking to this one
+        ("GET", p) if p.starts_with("/entries/") && p.ends_with("/backlinks") && p.split('/').count() == 4 => {
+            links::get_backlinks(request).await
+        }
+
+        // POST /entries/{id}/insights/regenerate - Re-run AI analysis of an entry
+        ("POST", p) if p.starts_with("/entries/") && p.ends_with("/insights/regenerate") && p.split('/').count() == 5 => {
+            regenerate::regenerate_insights(request).await
+        }
 
         // GET /entries/{id}/insights - Get AI insights for a specific entry
         ("GET", p) if p.starts_with("/entries/") && p.ends_with("/insights") => {
             // Validate user has access to this entry
-            let claims = match extract_tenant_context(&event.payload.headers).await {
+            let claims = match extract_tenant_context(&request.headers).await {
                 Ok(claims) => claims,
                 Err(e) => return Ok(error_response(401, &e)),
             };
@@ -1323,6 +1796,15 @@ async fn handler(
                             return Ok(error_response(403, &JournalError::AuthorizationError("You do not have permission to access this entry's insights".into())));
                         }
 
+                        // Insights stay hidden while the user has turned them off
+                        match get_privacy_settings(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+                            Ok(settings) if !settings.show_insights => {
+                                return Ok(error_response(403, &JournalError::AuthorizationError("Insights are turned off in your display preferences".into())));
+                            }
+                            Ok(_) => {}
+                            Err(e) => return Ok(error_response(500, &e)),
+                        }
+
                         // Get insights for the entry
                         get_entry_insights(&dynamo_client, entry_id, &claims.tenant_id).await
                     } else {
@@ -1335,17 +1817,17 @@ async fn handler(
 
         // GET /entries/{id} - Get single entry
         ("GET", p) if p.starts_with("/entries/") && p.split('/').count() == 3 => {
-            get_entry(event.payload).await
+            get_entry(request).await
         }
 
         // PUT /entries/{id} - Update entry
         ("PUT", p) if p.starts_with("/entries/") && p.split('/').count() == 3 => {
-            update_entry(event.payload).await
+            update_entry(request).await
         }
 
         // DELETE /entries/{id} - Delete entry
         ("DELETE", p) if p.starts_with("/entries/") && p.split('/').count() == 3 => {
-            delete_entry(event.payload).await
+            delete_entry(request).await
         }
 
         // If no route matches, return 404
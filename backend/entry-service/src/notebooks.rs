@@ -0,0 +1,360 @@
+// Notebook CRUD endpoints
+//
+// Deleting a notebook moves its entries into another notebook (the default one
+// unless `move_to` is given) before the notebook itself is removed, so every
+// entry keeps belonging to exactly one notebook.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::{
+    check_tag_count, chrono, entry_notebook_id, error_response, extract_tenant_context, get_dynamo_client,
+    get_notebook, item_to_notebook, json_response, lambda_runtime::Error, normalize_tags,
+    notebook_item_id, notebooks_table, query_timeline, serde_json, uuid::Uuid, JournalError,
+    Notebook, DEFAULT_NOTEBOOK_ID,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+struct CreateNotebookInput {
+    name: String,
+    description: Option<String>,
+    default_template_id: Option<String>,
+    default_tags: Option<Vec<String>>,
+    ai_enabled: Option<bool>,
+    archived: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateNotebookInput {
+    name: Option<String>,
+    description: Option<String>,
+    default_template_id: Option<String>,
+    default_tags: Option<Vec<String>>,
+    ai_enabled: Option<bool>,
+    archived: Option<bool>,
+}
+
+fn notebook_to_item(tenant_id: &str, user_id: &str, notebook: &Notebook) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("id".to_string(), AttributeValue::S(notebook_item_id(user_id, &notebook.id)));
+    item.insert("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string()));
+    item.insert("user_id".to_string(), AttributeValue::S(user_id.to_string()));
+    item.insert("name".to_string(), AttributeValue::S(notebook.name.clone()));
+    item.insert("ai_enabled".to_string(), AttributeValue::Bool(notebook.ai_enabled));
+    item.insert("archived".to_string(), AttributeValue::Bool(notebook.archived));
+
+    if let Some(description) = &notebook.description {
+        item.insert("description".to_string(), AttributeValue::S(description.clone()));
+    }
+    if let Some(template_id) = &notebook.default_template_id {
+        item.insert("default_template_id".to_string(), AttributeValue::S(template_id.clone()));
+    }
+    // Empty sets are not allowed in DynamoDB
+    if !notebook.default_tags.is_empty() {
+        item.insert("default_tags".to_string(), AttributeValue::Ss(notebook.default_tags.clone()));
+    }
+    if let Some(created_at) = &notebook.created_at {
+        item.insert("created_at".to_string(), AttributeValue::S(created_at.clone()));
+    }
+    if let Some(updated_at) = &notebook.updated_at {
+        item.insert("updated_at".to_string(), AttributeValue::S(updated_at.clone()));
+    }
+
+    item
+}
+
+// Notebook id from a /notebooks/{id} path
+fn path_notebook_id(event: &ApiGatewayProxyRequest) -> Option<String> {
+    event
+        .path_parameters
+        .get("id")
+        .cloned()
+        .or_else(|| event.path.as_deref().and_then(|p| p.strip_prefix("/notebooks/")).map(str::to_string))
+        .filter(|id| !id.is_empty())
+}
+
+// GET /notebooks - all notebooks of the user, default first
+pub(crate) async fn list_notebooks(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let result = dynamo_client
+        .query()
+        .table_name(notebooks_table())
+        .index_name("UserIndex")
+        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
+        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => {
+            let mut notebooks: Vec<Notebook> = response.items().iter().map(item_to_notebook).collect();
+            if !notebooks.iter().any(|n| n.id == DEFAULT_NOTEBOOK_ID) {
+                notebooks.push(Notebook::default_notebook());
+            }
+            notebooks.sort_by(|a, b| {
+                (b.id == DEFAULT_NOTEBOOK_ID)
+                    .cmp(&(a.id == DEFAULT_NOTEBOOK_ID))
+                    .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            });
+
+            Ok(json_response(200, &notebooks))
+        }
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to list notebooks: {}", e)),
+        )),
+    }
+}
+
+// POST /notebooks
+pub(crate) async fn create_notebook(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let input: CreateNotebookInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    if input.name.trim().is_empty() {
+        return Ok(error_response(400, &JournalError::ValidationError("Name is required".into())));
+    }
+    let default_tags = normalize_tags(input.default_tags.unwrap_or_default());
+    if let Err(e) = check_tag_count(&default_tags) {
+        return Ok(error_response(400, &e));
+    }
+
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let notebook = Notebook {
+        id: Uuid::new_v4().to_string(),
+        name: input.name.trim().to_string(),
+        description: input.description,
+        default_template_id: input.default_template_id,
+        default_tags,
+        ai_enabled: input.ai_enabled.unwrap_or(true),
+        archived: input.archived.unwrap_or(false),
+        created_at: Some(timestamp.clone()),
+        updated_at: Some(timestamp),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match dynamo_client
+        .put_item()
+        .table_name(notebooks_table())
+        .set_item(Some(notebook_to_item(&claims.tenant_id, &claims.sub, &notebook)))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(201, &notebook)),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to save notebook: {}", e)),
+        )),
+    }
+}
+
+// GET /notebooks/{id}
+pub(crate) async fn get_notebook_by_id(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(notebook_id) = path_notebook_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing notebook ID".into())));
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, &notebook_id).await {
+        Ok(Some(notebook)) => Ok(json_response(200, &notebook)),
+        Ok(None) => Ok(error_response(404, &JournalError::NotFoundError("Notebook not found".into()))),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// PUT /notebooks/{id} - update name and settings; the default notebook is stored on first update
+pub(crate) async fn update_notebook(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(notebook_id) = path_notebook_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing notebook ID".into())));
+    };
+
+    let input: UpdateNotebookInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let mut notebook = match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, &notebook_id).await {
+        Ok(Some(notebook)) => notebook,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Notebook not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    if let Some(name) = input.name {
+        if name.trim().is_empty() {
+            return Ok(error_response(400, &JournalError::ValidationError("Name must not be empty".into())));
+        }
+        notebook.name = name.trim().to_string();
+    }
+    if let Some(description) = input.description {
+        notebook.description = Some(description).filter(|d| !d.is_empty());
+    }
+    if let Some(template_id) = input.default_template_id {
+        notebook.default_template_id = Some(template_id).filter(|t| !t.is_empty());
+    }
+    if let Some(tags) = input.default_tags {
+        let tags = normalize_tags(tags);
+        if let Err(e) = check_tag_count(&tags) {
+            return Ok(error_response(400, &e));
+        }
+        notebook.default_tags = tags;
+    }
+    if let Some(ai_enabled) = input.ai_enabled {
+        notebook.ai_enabled = ai_enabled;
+    }
+    if let Some(archived) = input.archived {
+        if archived && notebook.id == DEFAULT_NOTEBOOK_ID {
+            return Ok(error_response(400, &JournalError::ValidationError("The default notebook cannot be archived".into())));
+        }
+        notebook.archived = archived;
+    }
+
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    notebook.created_at.get_or_insert_with(|| timestamp.clone());
+    notebook.updated_at = Some(timestamp);
+
+    match dynamo_client
+        .put_item()
+        .table_name(notebooks_table())
+        .set_item(Some(notebook_to_item(&claims.tenant_id, &claims.sub, &notebook)))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(200, &notebook)),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to update notebook: {}", e)),
+        )),
+    }
+}
+
+// DELETE /notebooks/{id}?move_to={id} - move the notebook's entries, then delete it
+pub(crate) async fn delete_notebook(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(notebook_id) = path_notebook_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing notebook ID".into())));
+    };
+
+    if notebook_id == DEFAULT_NOTEBOOK_ID {
+        return Ok(error_response(400, &JournalError::ValidationError("The default notebook cannot be deleted".into())));
+    }
+
+    let move_to = event
+        .query_string_parameters
+        .first("move_to")
+        .unwrap_or(DEFAULT_NOTEBOOK_ID)
+        .to_string();
+    if move_to == notebook_id {
+        return Ok(error_response(400, &JournalError::ValidationError("Entries must move to a different notebook".into())));
+    }
+
+    let dynamo_client = get_dynamo_client().await;
+    for id in [&notebook_id, &move_to] {
+        match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError(format!("Notebook {} not found", id)))),
+            Err(e) => return Ok(error_response(500, &e)),
+        }
+    }
+
+    let moved = match move_entries(&dynamo_client, &claims.tenant_id, &claims.sub, &notebook_id, &move_to).await {
+        Ok(moved) => moved,
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    match dynamo_client
+        .delete_item()
+        .table_name(notebooks_table())
+        .key("id", AttributeValue::S(notebook_item_id(&claims.sub, &notebook_id)))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(200, &serde_json::json!({ "success": true, "moved_entries": moved }))),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to delete notebook: {}", e)),
+        )),
+    }
+}
+
+// Reassign every entry of one notebook to another
+async fn move_entries(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    from: &str,
+    to: &str,
+) -> Result<usize, JournalError> {
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let items = query_timeline(client, &table_name, tenant_id, user_id, None, None).await?;
+
+    let mut moved = 0;
+    for item in items.iter().filter(|item| entry_notebook_id(item) == from) {
+        let Some(entry_id) = item.get("id").and_then(|v| v.as_s().ok()) else {
+            continue;
+        };
+
+        client
+            .update_item()
+            .table_name(&table_name)
+            .key("id", AttributeValue::S(entry_id.clone()))
+            .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+            .update_expression("SET notebook_id = :to")
+            .condition_expression("user_id = :user_id")
+            .expression_attribute_values(":to", AttributeValue::S(to.to_string()))
+            .expression_attribute_values(":user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to move entry {}: {}", entry_id, e)))?;
+        moved += 1;
+    }
+
+    Ok(moved)
+}
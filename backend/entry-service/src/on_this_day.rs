@@ -0,0 +1,330 @@
+// "On this day" resurfacing of past entries
+//
+// GET /entries/on-this-day?date=YYYY-MM-DD returns the entries written on the same
+// month/day in previous years, plus fixed lookbacks one week, one month and six
+// months earlier. Each entry carries its insight summary from the insights table
+// when one exists and the user hasn't turned insights off. The same lookup backs the optional daily job that publishes
+// OnThisDay events for the notification path.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::chrono::{self, Datelike, Duration, Months, NaiveDate};
+use journal_common::{
+    error_response, extract_tenant_context, get_dynamo_client, get_privacy_settings, is_sealed, json_response,
+    lambda_runtime::Error, publish_event, query_timeline, serde_json, timeline_pk, JournalError,
+    TIMELINE_INDEX,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{item_to_entry, Entry};
+
+// Insight fields surfaced alongside a resurfaced entry
+#[derive(Debug, Serialize)]
+struct InsightSummary {
+    sentiment: Option<String>,
+    sentiment_score: Option<f64>,
+    insights: Option<String>,
+    reflections: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResurfacedEntry {
+    #[serde(flatten)]
+    entry: Entry,
+    insight: Option<InsightSummary>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResurfacedDay {
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    years_ago: Option<i32>,
+    entries: Vec<ResurfacedEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct OnThisDayResponse {
+    date: String,
+    previous_years: Vec<ResurfacedDay>,
+    one_week_ago: ResurfacedDay,
+    one_month_ago: ResurfacedDay,
+    six_months_ago: ResurfacedDay,
+}
+
+// GET /entries/on-this-day
+pub(crate) async fn get_on_this_day(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Default to today (UTC) when no date is given
+    let date = match event.query_string_parameters.first("date") {
+        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => return Ok(error_response(400, &JournalError::ValidationError(
+                "Invalid date. Expected format: YYYY-MM-DD".into()
+            ))),
+        },
+        None => chrono::Utc::now().date_naive(),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+
+    // Insights are left out when they're turned off, or if that can't be checked
+    let show_insights = match get_privacy_settings(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(settings) => settings.show_insights,
+        Err(e) => {
+            tracing::warn!("Hiding insights for user {}: {}", claims.sub, e);
+            false
+        }
+    };
+
+    match collect_on_this_day(&dynamo_client, &claims.tenant_id, &claims.sub, date).await {
+        Ok(mut response) => {
+            if !show_insights {
+                response.hide_insights();
+            }
+            Ok(json_response(200, &response))
+        }
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+impl OnThisDayResponse {
+    fn hide_insights(&mut self) {
+        let days = self
+            .previous_years
+            .iter_mut()
+            .chain([&mut self.one_week_ago, &mut self.one_month_ago, &mut self.six_months_ago]);
+        for day in days {
+            for entry in &mut day.entries {
+                entry.insight = None;
+            }
+        }
+    }
+}
+
+// Scheduled job: publish an OnThisDay event for every user with anniversary entries today
+pub(crate) async fn publish_on_this_day_events() -> Result<usize, JournalError> {
+    let dynamo_client = get_dynamo_client().await;
+    let settings_table = std::env::var("SETTINGS_TABLE").unwrap_or_else(|_| "reflekt-settings".to_string());
+    let today = chrono::Utc::now().date_naive();
+    let mut published = 0;
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    // The settings table holds one item per known user
+    loop {
+        let response = dynamo_client
+            .scan()
+            .table_name(&settings_table)
+            .projection_expression("tenant_id, user_id")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to scan users: {}", e)))?;
+
+        for item in response.items() {
+            let (Some(AttributeValue::S(tenant_id)), Some(AttributeValue::S(user_id))) =
+                (item.get("tenant_id"), item.get("user_id"))
+            else {
+                continue;
+            };
+
+            let on_this_day = match collect_on_this_day(&dynamo_client, tenant_id, user_id, today).await {
+                Ok(on_this_day) => on_this_day,
+                Err(e) => {
+                    tracing::warn!("Skipping on-this-day for user {}: {}", user_id, e);
+                    continue;
+                }
+            };
+
+            if on_this_day.previous_years.is_empty() {
+                continue;
+            }
+
+            let entries: Vec<serde_json::Value> = on_this_day
+                .previous_years
+                .iter()
+                .flat_map(|day| {
+                    day.entries.iter().map(move |e| serde_json::json!({
+                        "entry_id": e.entry.id,
+                        "title": e.entry.title,
+                        "created_at": e.entry.created_at,
+                        "years_ago": day.years_ago,
+                    }))
+                })
+                .collect();
+
+            let event_detail = serde_json::json!({
+                "tenant_id": tenant_id,
+                "user_id": user_id,
+                "date": on_this_day.date,
+                "entries": entries,
+            });
+
+            if let Err(e) = publish_event("OnThisDay", event_detail).await {
+                tracing::warn!("Failed to publish event: {}", e);
+            } else {
+                published += 1;
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(published)
+}
+
+async fn collect_on_this_day(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    date: NaiveDate,
+) -> Result<OnThisDayResponse, JournalError> {
+    let mut previous_years = Vec::new();
+
+    // Only look back as far as the user's first entry
+    if let Some(first_year) = earliest_entry_year(client, tenant_id, user_id).await? {
+        for year in (first_year..date.year()).rev() {
+            // Feb 29 only exists in leap years
+            let Some(day) = NaiveDate::from_ymd_opt(year, date.month(), date.day()) else {
+                continue;
+            };
+
+            let resurfaced = resurface_day(client, tenant_id, user_id, day, Some(date.year() - year)).await?;
+            if !resurfaced.entries.is_empty() {
+                previous_years.push(resurfaced);
+            }
+        }
+    }
+
+    let one_month = date.checked_sub_months(Months::new(1)).unwrap_or(date);
+    let six_months = date.checked_sub_months(Months::new(6)).unwrap_or(date);
+
+    Ok(OnThisDayResponse {
+        date: date.to_string(),
+        previous_years,
+        one_week_ago: resurface_day(client, tenant_id, user_id, date - Duration::days(7), None).await?,
+        one_month_ago: resurface_day(client, tenant_id, user_id, one_month, None).await?,
+        six_months_ago: resurface_day(client, tenant_id, user_id, six_months, None).await?,
+    })
+}
+
+async fn earliest_entry_year(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<Option<i32>, JournalError> {
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    let response = client
+        .query()
+        .table_name(table_name)
+        .index_name(TIMELINE_INDEX)
+        .key_condition_expression("timeline_pk = :pk")
+        .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .projection_expression("created_at")
+        .limit(1)
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to query timeline: {}", e)))?;
+
+    Ok(response
+        .items()
+        .first()
+        .and_then(|item| item.get("created_at"))
+        .and_then(|v| v.as_s().ok())
+        .and_then(|created_at| created_at.parse::<chrono::DateTime<chrono::Utc>>().ok())
+        .map(|created_at| created_at.year()))
+}
+
+async fn resurface_day(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    day: NaiveDate,
+    years_ago: Option<i32>,
+) -> Result<ResurfacedDay, JournalError> {
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let day_str = day.to_string();
+
+    let items = query_timeline(client, &table_name, tenant_id, user_id, Some(&day_str), Some(&day_str)).await?;
+    // Sealed time capsules aren't resurfaced
+    let now = chrono::Utc::now();
+    let entries: Vec<Entry> = items.iter().filter(|item| !is_sealed(item, now)).map(item_to_entry).collect();
+
+    let entry_ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+    let mut insights = get_insight_summaries(client, tenant_id, &entry_ids).await?;
+
+    let entries = entries
+        .into_iter()
+        .map(|entry| {
+            let insight = insights.remove(&entry.id);
+            ResurfacedEntry { entry, insight }
+        })
+        .collect();
+
+    Ok(ResurfacedDay {
+        date: day_str,
+        years_ago,
+        entries,
+    })
+}
+
+async fn get_insight_summaries(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    entry_ids: &[&str],
+) -> Result<HashMap<String, InsightSummary>, JournalError> {
+    let insights_table = std::env::var("INSIGHTS_TABLE").unwrap_or_else(|_| "reflekt-insights".to_string());
+    let mut summaries = HashMap::new();
+
+    // DynamoDB batch size limit is 100
+    for chunk in entry_ids.chunks(100) {
+        let keys: Vec<HashMap<String, AttributeValue>> = chunk
+            .iter()
+            .map(|entry_id| {
+                let mut key = HashMap::new();
+                key.insert("entry_id".to_string(), AttributeValue::S(entry_id.to_string()));
+                key.insert("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string()));
+                key
+            })
+            .collect();
+
+        let keys_attrs = KeysAndAttributes::builder()
+            .set_keys(Some(keys))
+            .build()
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to build KeysAndAttributes: {}", e)))?;
+
+        let result = client
+            .batch_get_item()
+            .request_items(insights_table.clone(), keys_attrs)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to batch get insights: {}", e)))?;
+
+        for item in result.responses().and_then(|r| r.get(&insights_table)).into_iter().flatten() {
+            let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned();
+
+            if let Some(entry_id) = get_s("entry_id") {
+                summaries.insert(entry_id, InsightSummary {
+                    sentiment: get_s("sentiment"),
+                    sentiment_score: item.get("sentiment_score").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()),
+                    insights: get_s("insights"),
+                    reflections: get_s("reflections"),
+                });
+            }
+        }
+    }
+
+    Ok(summaries)
+}
@@ -0,0 +1,143 @@
+// On-demand re-analysis of an entry
+//
+// POST /entries/{id}/insights/regenerate marks the entry's analysis as
+// pending and publishes a forced AnalysisRequested event, so the AI service
+// analyzes it again even if its text hasn't changed. The notebook's AI
+// setting, the user's opt-out and the tenant's quota still apply there. Each
+// entry can be regenerated once per cooldown period; a request whose event
+// can't be published leaves the entry as it was and doesn't count.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{
+    chrono, entry_notebook_id, error_response, extract_tenant_context, get_dynamo_client, get_notebook, is_sealed,
+    json_response, lambda_runtime::Error, request_analysis, serde_json, JournalError, ANALYSIS_STATUS_PENDING,
+};
+
+// Seconds before the same entry can be regenerated again
+const REGENERATE_COOLDOWN_SECS: i64 = 60;
+
+pub(crate) async fn regenerate_insights(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Path like /entries/{id}/insights/regenerate
+    let path = event.path.clone().unwrap_or_default();
+    let parts: Vec<&str> = path.split('/').collect();
+    let entry_id = match event.path_parameters.get("id").cloned().or_else(|| parts.get(2).map(|id| id.to_string())) {
+        Some(id) if !id.is_empty() => id,
+        _ => return Ok(error_response(400, &JournalError::ValidationError("Missing entry ID".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    // Verify ownership
+    let result = dynamo_client
+        .get_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .send()
+        .await;
+
+    let item = match result {
+        Ok(response) => match response.item {
+            Some(item) if item.get("user_id").and_then(|v| v.as_s().ok()) == Some(&claims.sub) => item,
+            Some(_) => return Ok(error_response(403, &JournalError::AuthorizationError("Not authorized to access this entry".into()))),
+            None => return Ok(error_response(404, &JournalError::NotFoundError("Entry not found".into()))),
+        },
+        Err(e) => return Ok(error_response(500, &JournalError::DatabaseError(format!("Failed to fetch entry: {}", e)))),
+    };
+
+    let now = chrono::Utc::now();
+    if is_sealed(&item, now) {
+        return Ok(error_response(
+            400,
+            &JournalError::ValidationError("Time capsules can't be analyzed while sealed".into()),
+        ));
+    }
+
+    // Timestamps share one format so they compare as strings
+    let format_time = |time: chrono::DateTime<chrono::Utc>| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let cutoff = now - chrono::Duration::seconds(REGENERATE_COOLDOWN_SECS);
+
+    let result = dynamo_client
+        .update_item()
+        .table_name(&table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .update_expression("SET analysis_status = :pending, analysis_requested_at = :now REMOVE analysis_skip_reason")
+        .condition_expression(
+            "attribute_exists(id) AND (attribute_not_exists(analysis_requested_at) OR analysis_requested_at <= :cutoff)",
+        )
+        .expression_attribute_values(":pending", AttributeValue::S(ANALYSIS_STATUS_PENDING.to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(format_time(now)))
+        .expression_attribute_values(":cutoff", AttributeValue::S(format_time(cutoff)))
+        .send()
+        .await;
+
+    if let Err(e) = result {
+        let e = e.into_service_error();
+        if e.is_conditional_check_failed_exception() {
+            return Ok(error_response(
+                429,
+                &JournalError::ValidationError(format!(
+                    "Insights for this entry can be regenerated once every {} seconds",
+                    REGENERATE_COOLDOWN_SECS
+                )),
+            ));
+        }
+        return Ok(error_response(500, &JournalError::DatabaseError(format!("Failed to update entry: {}", e))));
+    }
+
+    // The AI service records a skip if the notebook has AI analysis turned off
+    let ai_enabled = match get_notebook(&dynamo_client, &claims.tenant_id, &claims.sub, entry_notebook_id(&item)).await {
+        Ok(notebook) => notebook.is_none_or(|n| n.ai_enabled),
+        Err(e) => {
+            tracing::warn!("Failed to load notebook: {}", e);
+            true
+        }
+    };
+
+    if let Err(e) = request_analysis(&item, ai_enabled, true).await {
+        // Nothing will pick the entry up: put back its previous analysis
+        // state and lift the cooldown so the request can be retried
+        let mut restore = dynamo_client
+            .update_item()
+            .table_name(&table_name)
+            .key("id", AttributeValue::S(entry_id.clone()))
+            .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+            .condition_expression("analysis_requested_at = :now")
+            .expression_attribute_values(":now", AttributeValue::S(format_time(now)));
+        let mut set = Vec::new();
+        let mut remove = vec!["analysis_requested_at"];
+        for (attribute, placeholder) in [("analysis_status", ":status"), ("analysis_skip_reason", ":skip_reason")] {
+            match item.get(attribute) {
+                Some(value) => {
+                    set.push(format!("{} = {}", attribute, placeholder));
+                    restore = restore.expression_attribute_values(placeholder, value.clone());
+                }
+                None => remove.push(attribute),
+            }
+        }
+        let mut expression = format!("REMOVE {}", remove.join(", "));
+        if !set.is_empty() {
+            expression = format!("SET {} {}", set.join(", "), expression);
+        }
+        if let Err(restore_error) = restore.update_expression(expression).send().await {
+            tracing::warn!("Failed to reset analysis state of entry {}: {}", entry_id, restore_error);
+        }
+        return Ok(error_response(500, &e));
+    }
+
+    Ok(json_response(202, &serde_json::json!({
+        "entry_id": entry_id,
+        "analysis_status": ANALYSIS_STATUS_PENDING,
+    })))
+}
@@ -0,0 +1,441 @@
+// Tag suggestions ranked against the user's own writing
+//
+// Words in the submitted text are scored by TF-IDF against the user's most
+// recent entries, so words they use everywhere ("meeting", "because") sink and
+// distinctive ones rise. Candidates come from three places:
+//
+//   vocabulary - existing tags whose words (after stemming) appear in the text;
+//                matching words are folded into the tag instead of suggested raw
+//   keyword    - the best remaining terms, offered as new tags
+//   related    - tags that co-occur with tags already chosen or matched
+//
+// With `"mode": "ai"` the configured AI provider picks the tags instead, seeded
+// with the user's vocabulary; if it fails, or the user has turned off AI
+// insights, the local ranking is returned. The text is redacted first when the
+// user's privacy settings ask for it.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::{
+    ai_quota_exceeded, chrono, error_response, extract_tenant_context, get_dynamo_client, get_privacy_settings,
+    get_tag_stats, is_sealed, json_response, lambda_runtime::Error, llm_provider_from_env, normalize_tag,
+    normalize_tags, parse_json_reply, record_ai_usage, score_terms, serde_json, stem, timeline_pk, tokenize,
+    DocumentFrequencies, JournalError, LlmProviderKind, LlmRequest, RedactionMode, TagStats, REDACTION_INSTRUCTION,
+    TIMELINE_INDEX,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// Number of recent entries used as the IDF reference corpus
+const CORPUS_SIZE: i32 = 200;
+const MAX_SUGGESTIONS: usize = 5;
+
+// Relative trust in each kind of candidate
+const KEYWORD_WEIGHT: f64 = 0.8;
+const RELATED_WEIGHT: f64 = 0.7;
+const USAGE_PRIOR_WEIGHT: f64 = 0.15;
+
+#[derive(Debug, Deserialize)]
+struct SuggestTagsRequest {
+    content: String,
+    title: Option<String>,
+    existing_tags: Option<Vec<String>>,
+    mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SuggestionSource {
+    Vocabulary,
+    Keyword,
+    Related,
+    Ai,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TagSuggestion {
+    tag: String,
+    confidence: f64,
+    source: SuggestionSource,
+}
+
+// POST /entries/suggest-tags
+pub(crate) async fn suggest_tags(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Parse request body
+    let body = match &event.body {
+        Some(b) => b,
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let request: SuggestTagsRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(e) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+    };
+
+    let existing_tags = normalize_tags(request.existing_tags.unwrap_or_default());
+    let text = match &request.title {
+        Some(title) => format!("{}\n{}", title, request.content),
+        None => request.content.clone(),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let stats = match get_tag_stats(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            tracing::warn!("Failed to load tag stats: {}", e);
+            TagStats::default()
+        }
+    };
+
+    if request.mode.as_deref() == Some("ai") {
+        match ai_suggestions(&dynamo_client, &claims.tenant_id, &claims.sub, &text, &stats, &existing_tags).await {
+            Ok(suggestions) => return Ok(json_response(200, &suggestions)),
+            Err(e) => tracing::warn!("AI tag suggestions failed, using local ranking: {}", e),
+        }
+    }
+
+    let corpus = match load_corpus(&dynamo_client, &claims.tenant_id, &claims.sub).await {
+        Ok(corpus) => corpus,
+        Err(e) => {
+            tracing::warn!("Failed to load suggestion corpus: {}", e);
+            DocumentFrequencies::default()
+        }
+    };
+
+    Ok(json_response(200, &rank_suggestions(&text, &corpus, &stats, &existing_tags)))
+}
+
+// Document frequencies over the user's most recent entries
+async fn load_corpus(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+) -> Result<DocumentFrequencies, JournalError> {
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+
+    let response = client
+        .query()
+        .table_name(table_name)
+        .index_name(TIMELINE_INDEX)
+        .key_condition_expression("timeline_pk = :pk")
+        .expression_attribute_values(":pk", AttributeValue::S(timeline_pk(tenant_id, user_id)))
+        .projection_expression("#title, #content, unlock_at")
+        .expression_attribute_names("#title", "title")
+        .expression_attribute_names("#content", "content")
+        .scan_index_forward(false)
+        .limit(CORPUS_SIZE)
+        .send()
+        .await
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to load entries: {}", e)))?;
+
+    // Sealed time capsules stay out of the corpus
+    let now = chrono::Utc::now();
+    let documents = response.items().iter().filter(|item| !is_sealed(item, now)).map(|item| {
+        let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).map(String::as_str).unwrap_or("");
+        format!("{}\n{}", get_s("title"), get_s("content"))
+    });
+    Ok(DocumentFrequencies::from_documents(documents))
+}
+
+fn rank_suggestions(
+    text: &str,
+    corpus: &DocumentFrequencies,
+    stats: &TagStats,
+    existing_tags: &[String],
+) -> Vec<TagSuggestion> {
+    let terms = score_terms(text, Some(corpus));
+    let Some(top_score) = terms.first().map(|t| t.score).filter(|s| *s > 0.0) else {
+        return Vec::new();
+    };
+    let term_scores: HashMap<&str, f64> = terms.iter().map(|t| (t.term.as_str(), t.score / top_score)).collect();
+
+    let max_count = stats.tags.iter().map(|t| t.count).max().unwrap_or(0).max(1) as f64;
+    let mut candidates: HashMap<String, (f64, SuggestionSource)> = HashMap::new();
+    let mut offer = |tag: String, score: f64, source: SuggestionSource| {
+        if existing_tags.contains(&tag) {
+            return;
+        }
+        let current = candidates.entry(tag).or_insert((0.0, source));
+        if score > current.0 {
+            *current = (score, source);
+        }
+    };
+
+    // Existing tags whose words all appear in the text
+    let mut folded: HashSet<String> = HashSet::new();
+    let mut anchors: Vec<(String, f64)> = existing_tags.iter().map(|t| (t.clone(), 1.0)).collect();
+
+    for stat in stats.tags.iter().filter(|t| t.count > 0) {
+        let leaf = stat.tag.rsplit('/').next().unwrap_or(&stat.tag);
+        let leaf_terms: Vec<String> = tokenize(leaf).iter().map(|w| stem(w)).collect();
+        if leaf_terms.is_empty() || !leaf_terms.iter().all(|t| term_scores.contains_key(t.as_str())) {
+            continue;
+        }
+
+        let relevance = leaf_terms.iter().map(|t| term_scores[t.as_str()]).sum::<f64>() / leaf_terms.len() as f64;
+        let usage = (1.0 + stat.count as f64).ln() / (1.0 + max_count).ln();
+        let score = relevance * (1.0 - USAGE_PRIOR_WEIGHT) + usage * USAGE_PRIOR_WEIGHT;
+
+        folded.extend(leaf_terms);
+        anchors.push((stat.tag.clone(), score));
+        offer(stat.tag.clone(), score, SuggestionSource::Vocabulary);
+    }
+
+    // Distinctive words that are not already covered by a known tag
+    for term in terms.iter().filter(|t| !folded.contains(&t.term)).take(MAX_SUGGESTIONS * 2) {
+        if let Some(tag) = normalize_tag(&term.display) {
+            offer(tag, term.score / top_score * KEYWORD_WEIGHT, SuggestionSource::Keyword);
+        }
+    }
+
+    // Tags usually used alongside the chosen or matched ones
+    let counts: HashMap<&str, i64> = stats.tags.iter().map(|t| (t.tag.as_str(), t.count)).collect();
+    for (anchor, anchor_score) in &anchors {
+        let anchor_count = counts.get(anchor.as_str()).copied().unwrap_or(0).max(1) as f64;
+        for (related, pair_count) in stats.related(anchor).into_iter().take(3) {
+            let likelihood = (pair_count as f64 / anchor_count).min(1.0);
+            offer(related, anchor_score * likelihood * RELATED_WEIGHT, SuggestionSource::Related);
+        }
+    }
+
+    let mut suggestions: Vec<TagSuggestion> = candidates
+        .into_iter()
+        .map(|(tag, (score, source))| TagSuggestion {
+            tag,
+            confidence: (score.clamp(0.0, 1.0) * 100.0).round() / 100.0,
+            source,
+        })
+        .filter(|s| s.confidence > 0.0)
+        .collect();
+
+    suggestions.sort_by(|a, b| {
+        b.confidence
+            .partial_cmp(&a.confidence)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.tag.cmp(&b.tag))
+    });
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+// Ask the configured AI provider for tags, preferring the user's vocabulary.
+// Users who turned off AI insights, and tenants over their monthly AI quota,
+// get the local ranking instead.
+async fn ai_suggestions(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    text: &str,
+    stats: &TagStats,
+    existing_tags: &[String],
+) -> Result<Vec<TagSuggestion>, JournalError> {
+    // Without the user's settings their text isn't sent anywhere
+    let settings = get_privacy_settings(client, tenant_id, user_id).await?;
+    if !settings.ai_insights_enabled {
+        return Err(JournalError::ValidationError("AI insights are turned off in the user's settings".into()));
+    }
+
+    let mut vocabulary: Vec<&journal_common::TagStat> = stats.tags.iter().filter(|t| t.count > 0).collect();
+    vocabulary.sort_by_key(|stat| std::cmp::Reverse(stat.count));
+    let vocabulary: Vec<&str> = vocabulary.iter().take(50).map(|t| t.tag.as_str()).collect();
+
+    let mut system_prompt = format!(
+        "You suggest tags for journal entries. Suggest up to {} short lowercase tags for the entry. \
+         Prefer tags from the writer's existing tags when they fit: [{}]. \
+         Do not suggest any of these, which are already applied: [{}]. \
+         Respond only with JSON: {{\"tags\": [{{\"tag\": string, \"confidence\": number between 0 and 1}}]}}",
+        MAX_SUGGESTIONS,
+        vocabulary.join(", "),
+        existing_tags.join(", "),
+    );
+
+    if ai_quota_exceeded(client, tenant_id).await.is_some() {
+        return Err(JournalError::ExternalApiError("Monthly AI quota exceeded".into()));
+    }
+
+    // Same provider as the analyses when AI_PROVIDER is unset
+    let provider = llm_provider_from_env(LlmProviderKind::OpenAi)?;
+    let mut redactor = RedactionMode::from_env()?.redactor(&settings);
+    let prompt = match redactor.as_mut() {
+        Some(redactor) => {
+            system_prompt.push(' ');
+            system_prompt.push_str(REDACTION_INSTRUCTION);
+            redactor.redact(text)
+        }
+        None => text.to_string(),
+    };
+
+    let request = LlmRequest {
+        system: system_prompt,
+        prompt,
+        temperature: 0.2,
+        max_tokens: 300,
+        json: true,
+    };
+    let response = provider.complete(&request).await?;
+    if let Err(e) = record_ai_usage(client, tenant_id, user_id, "tag_suggestions", &response).await {
+        tracing::warn!("Failed to record AI usage: {}", e);
+    }
+    let response_text = response.text;
+
+    let parsed: serde_json::Value = parse_json_reply(&response_text)
+        .map_err(|e| JournalError::ExternalApiError(format!("Failed to parse tag suggestions JSON: {}", e)))?;
+
+    let mut seen = HashSet::new();
+    let suggestions: Vec<TagSuggestion> = parsed["tags"]
+        .as_array()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|t| {
+                    let tag = t["tag"].as_str()?;
+                    let tag = match &redactor {
+                        Some(redactor) => normalize_tag(&redactor.restore(tag))?,
+                        None => normalize_tag(tag)?,
+                    };
+                    let confidence = t["confidence"].as_f64().unwrap_or(0.5).clamp(0.0, 1.0);
+                    Some(TagSuggestion { tag, confidence, source: SuggestionSource::Ai })
+                })
+                .filter(|s| !existing_tags.contains(&s.tag) && seen.insert(s.tag.clone()))
+                .take(MAX_SUGGESTIONS)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if suggestions.is_empty() {
+        return Err(JournalError::ExternalApiError("AI provider returned no tags".into()));
+    }
+
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use journal_common::test_util::{lock_env, set_mock_ai, EmptyJsonServer};
+    use journal_common::TagStat;
+
+    const TEXT: &str = "Long walk by the river with my sister, then dinner at home.";
+
+    fn stats() -> TagStats {
+        let tag = |tag: &str, count| TagStat {
+            tag: tag.to_string(),
+            count,
+            total_count: count,
+            first_used: None,
+            last_used: None,
+        };
+        TagStats { tags: vec![tag("family", 12), tag("outdoors", 4)], pairs: HashMap::new() }
+    }
+
+    fn ranked(text: &str, existing_tags: &[&str]) -> Vec<(String, SuggestionSource)> {
+        let mut stats = stats();
+        stats.tags.push(TagStat {
+            tag: "hobbies/walking".to_string(),
+            count: 3,
+            total_count: 3,
+            first_used: None,
+            last_used: None,
+        });
+        stats.pairs.insert(("hobbies/walking".to_string(), "outdoors".to_string()), 3);
+        let corpus = DocumentFrequencies::from_documents(["Dinner at home", "Dinner with friends", "Home early"]);
+        let existing_tags: Vec<String> = existing_tags.iter().map(|t| t.to_string()).collect();
+        rank_suggestions(text, &corpus, &stats, &existing_tags)
+            .into_iter()
+            .map(|s| (s.tag, s.source))
+            .collect()
+    }
+
+    #[test]
+    fn rank_folds_words_into_known_tags() {
+        let suggestions = ranked("Walking along the river, then more walking.", &[]);
+        assert!(suggestions.contains(&("hobbies/walking".to_string(), SuggestionSource::Vocabulary)), "{:?}", suggestions);
+        assert!(!suggestions.iter().any(|(tag, _)| tag == "walking" || tag == "walk"), "{:?}", suggestions);
+        assert!(suggestions.contains(&("river".to_string(), SuggestionSource::Keyword)), "{:?}", suggestions);
+    }
+
+    #[test]
+    fn rank_suggests_tags_used_with_matched_ones() {
+        let suggestions = ranked("Walking along the river.", &[]);
+        assert!(suggestions.contains(&("outdoors".to_string(), SuggestionSource::Related)), "{:?}", suggestions);
+    }
+
+    #[test]
+    fn rank_prefers_words_rare_in_the_users_entries() {
+        let suggestions = ranked("Dinner at home, then pottery.", &[]);
+        let position = |tag: &str| suggestions.iter().position(|(t, _)| t == tag);
+        let pottery = position("pottery").expect("pottery is suggested");
+        assert!(position("dinner").is_none_or(|dinner| pottery < dinner), "{:?}", suggestions);
+    }
+
+    #[test]
+    fn rank_leaves_out_chosen_tags() {
+        let suggestions = ranked("Walking along the river.", &["river", "hobbies/walking"]);
+        assert!(!suggestions.iter().any(|(tag, _)| tag == "river" || tag == "hobbies/walking"), "{:?}", suggestions);
+        assert!(suggestions.contains(&("outdoors".to_string(), SuggestionSource::Related)), "{:?}", suggestions);
+        assert!(ranked("the and of", &[]).is_empty());
+    }
+
+    async fn suggest(server: &EmptyJsonServer, existing_tags: &[String]) -> Result<Vec<TagSuggestion>, JournalError> {
+        ai_suggestions(&server.dynamo_client(), "tenant-1", "user-1", TEXT, &stats(), existing_tags).await
+    }
+
+    #[tokio::test]
+    async fn suggests_normalized_tags_from_a_clean_reply() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai(
+            "mock",
+            &[r#"{"tags": [{"tag": "Family", "confidence": 1.4}, {"tag": "walk"}, {"tag": "River ", "confidence": 0.6},
+                 {"tag": "family", "confidence": 0.3}]}"#],
+        );
+
+        let suggestions = suggest(&server, &["walk".to_string()]).await.unwrap();
+        let tags: Vec<(&str, f64)> = suggestions.iter().map(|s| (s.tag.as_str(), s.confidence)).collect();
+        assert_eq!(tags, vec![("family", 1.0), ("river", 0.6)]);
+        assert!(suggestions.iter().all(|s| s.source == SuggestionSource::Ai));
+    }
+
+    #[tokio::test]
+    async fn reads_tags_wrapped_in_prose_and_fences() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("mock", &["Here are some tags:\n```json\n{\"tags\": [{\"tag\": \"outdoors\", \"confidence\": 0.8}]}\n```"]);
+
+        let suggestions = suggest(&server, &[]).await.unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].tag, "outdoors");
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_provider() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("anthropic,mock", &[r#"{"tags": [{"tag": "family", "confidence": 0.9}]}"#]);
+        // Anthropic gets `{}` back, which has no reply in it
+        std::env::set_var("ANTHROPIC_API_KEY", "test");
+        std::env::set_var("ANTHROPIC_BASE_URL", &server.url);
+
+        let suggestions = suggest(&server, &[]).await.unwrap();
+        assert_eq!(suggestions[0].tag, "family");
+    }
+
+    #[tokio::test]
+    async fn fails_without_usable_tags() {
+        let _env = lock_env().await;
+        let server = EmptyJsonServer::start().await;
+        set_mock_ai("mock", &[r#"{"tags": [{"tag": "family"}]}"#]);
+        assert!(suggest(&server, &["family".to_string()]).await.is_err());
+
+        set_mock_ai("mock", &["no json at all"]);
+        assert!(suggest(&server, &[]).await.is_err());
+    }
+}
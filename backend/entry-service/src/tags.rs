@@ -0,0 +1,403 @@
+// Tag management: rename, merge and delete across all of a user's entries
+//
+// Each operation reads the user's timeline, computes the new tag set for every
+// affected entry and writes the changes back in transactions of up to
+// TRANSACTION_BATCH_SIZE entries. Every write is conditional on the entry's tags
+// being unchanged since they were read, so a concurrent edit is never
+// overwritten; such entries are reported as conflicts instead. Any other write
+// failure stops the rewrite with an error. Tag statistics are adjusted once for
+// all entries that were rewritten, and a tag that takes over older tags keeps
+// the earliest first use among them. Tag goals are renamed along with the
+// entries and their progress recounted.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::{
+    apply_tag_stats_delta, awaiting_unlock, backdate_first_used, chrono, error_response, extract_tenant_context,
+    get_dynamo_client, get_tag_stats, json_response, lambda_runtime::Error, normalize_tag, normalize_tags,
+    query_timeline, reparent_tag, serde_json, tag_is_within, JournalError, TagStat, TagStatsDelta,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::goals;
+
+// DynamoDB allows up to 100 items per transaction; smaller batches keep conflicts cheap
+const TRANSACTION_BATCH_SIZE: usize = 25;
+
+#[derive(Debug, Deserialize)]
+struct RenameTagRequest {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeTagsRequest {
+    sources: Vec<String>,
+    target: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TagRewriteSummary {
+    matched: usize,
+    updated: usize,
+    conflicts: usize,
+}
+
+// A pending change to one entry's tags
+struct TagRewrite {
+    entry_id: String,
+    created_at: String,
+    old_tags: Vec<String>,
+    new_tags: Vec<String>,
+    // Capsules awaiting unlock aren't in the tag statistics yet
+    counted: bool,
+    // (old tag, the tag it became) for tags the mapping changed
+    moves: Vec<(String, String)>,
+}
+
+impl TagRewrite {
+    // Add a written rewrite to the stats delta and the tags moved so far
+    fn record(&self, stats_delta: &mut TagStatsDelta, moved: &mut HashMap<String, BTreeSet<String>>) {
+        if !self.counted {
+            return;
+        }
+        stats_delta.merge(TagStatsDelta::between(&self.old_tags, &self.new_tags));
+        for (from, to) in &self.moves {
+            moved.entry(to.clone()).or_default().insert(from.clone());
+        }
+    }
+}
+
+// POST /entries/tags/rename - rename a tag and move its children with it
+pub(crate) async fn rename_tag(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let request: RenameTagRequest = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(req)) => req,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let (Some(from), Some(to)) = (normalize_tag(&request.from), normalize_tag(&request.to)) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Tags must not be empty".into())));
+    };
+
+    if from != to && tag_is_within(&to, &from) {
+        return Ok(error_response(400, &JournalError::ValidationError("Cannot move a tag underneath itself".into())));
+    }
+
+    let dynamo_client = get_dynamo_client().await;
+    let result = rewrite_tags(&dynamo_client, &claims.tenant_id, &claims.sub, |tag| {
+        reparent_tag(tag, &from, &to).or_else(|| Some(tag.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(summary) => Ok(json_response(200, &summary)),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// POST /entries/tags/merge - fold several tags (and their children) into one
+pub(crate) async fn merge_tags(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let request: MergeTagsRequest = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(req)) => req,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let Some(target) = normalize_tag(&request.target) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Target tag must not be empty".into())));
+    };
+
+    let sources: Vec<String> = normalize_tags(&request.sources)
+        .into_iter()
+        .filter(|source| *source != target)
+        .collect();
+
+    if sources.is_empty() {
+        return Ok(error_response(400, &JournalError::ValidationError("At least one source tag is required".into())));
+    }
+
+    if sources.iter().any(|source| tag_is_within(&target, source)) {
+        return Ok(error_response(400, &JournalError::ValidationError("Cannot merge a tag into its own child".into())));
+    }
+
+    let dynamo_client = get_dynamo_client().await;
+    let result = rewrite_tags(&dynamo_client, &claims.tenant_id, &claims.sub, |tag| {
+        sources
+            .iter()
+            .find_map(|source| reparent_tag(tag, source, &target))
+            .or_else(|| Some(tag.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(summary) => Ok(json_response(200, &summary)),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// DELETE /entries/tags/{tag} - remove a tag from every entry.
+// Child tags are kept unless `include_children=true` is passed.
+pub(crate) async fn delete_tag(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    // Hierarchical tags contain '/', so the route uses a greedy {tag+} parameter
+    let raw_tag = event
+        .path_parameters
+        .get("tag")
+        .cloned()
+        .or_else(|| event.path.as_deref().and_then(|p| p.strip_prefix("/entries/tags/")).map(str::to_string));
+
+    let Some(tag) = raw_tag.as_deref().and_then(normalize_tag) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing tag".into())));
+    };
+
+    let include_children = event
+        .query_string_parameters
+        .first("include_children")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    let dynamo_client = get_dynamo_client().await;
+    let result = rewrite_tags(&dynamo_client, &claims.tenant_id, &claims.sub, |t| {
+        let removed = if include_children { tag_is_within(t, &tag) } else { t == tag };
+        (!removed).then(|| t.to_string())
+    })
+    .await;
+
+    match result {
+        Ok(summary) => Ok(json_response(200, &summary)),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// Apply a per-tag mapping to every entry of the user. The mapping returns the
+// replacement tag, or None to drop it. Tags are normalized on the way through,
+// which also folds legacy variants like "Work " into "work".
+async fn rewrite_tags<F>(
+    client: &DynamoDbClient,
+    tenant_id: &str,
+    user_id: &str,
+    map_tag: F,
+) -> Result<TagRewriteSummary, JournalError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let items = query_timeline(client, &table_name, tenant_id, user_id, None, None).await?;
+
+    let rewrites: Vec<TagRewrite> = items
+        .iter()
+        .filter_map(|item| {
+            let old_tags = item.get("tags").and_then(|v| v.as_ss().ok())?.clone();
+            let mapped: Vec<(String, Option<String>)> = old_tags
+                .iter()
+                .filter_map(|tag| normalize_tag(tag))
+                .map(|tag| {
+                    let new_tag = map_tag(&tag);
+                    (tag, new_tag)
+                })
+                .collect();
+            let new_tags = normalize_tags(mapped.iter().filter_map(|(_, new_tag)| new_tag.as_deref()));
+            let moves = mapped
+                .into_iter()
+                .filter_map(|(tag, new_tag)| new_tag.filter(|new_tag| *new_tag != tag).map(|new_tag| (tag, new_tag)))
+                .collect();
+
+            let mut sorted_old = old_tags.clone();
+            sorted_old.sort();
+            if sorted_old == new_tags {
+                return None;
+            }
+
+            Some(TagRewrite {
+                entry_id: item.get("id").and_then(|v| v.as_s().ok())?.clone(),
+                created_at: item.get("created_at").and_then(|v| v.as_s().ok())?.clone(),
+                old_tags,
+                new_tags,
+                counted: !awaiting_unlock(item),
+                moves,
+            })
+        })
+        .collect();
+
+    let mut summary = TagRewriteSummary {
+        matched: rewrites.len(),
+        updated: 0,
+        conflicts: 0,
+    };
+    let mut stats_delta = TagStatsDelta::default();
+    let mut moved: HashMap<String, BTreeSet<String>> = HashMap::new();
+    let mut rewritten: Vec<&str> = Vec::new();
+    let mut failure = None;
+
+    'batches: for batch in rewrites.chunks(TRANSACTION_BATCH_SIZE) {
+        let updates = batch
+            .iter()
+            .map(|rewrite| tag_update(&table_name, tenant_id, user_id, rewrite))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let result = client
+            .transact_write_items()
+            .set_transact_items(Some(
+                updates.into_iter().map(|u| TransactWriteItem::builder().update(u).build()).collect(),
+            ))
+            .send()
+            .await;
+
+        if result.is_ok() {
+            summary.updated += batch.len();
+            for rewrite in batch {
+                rewrite.record(&mut stats_delta, &mut moved);
+                rewritten.push(&rewrite.created_at);
+            }
+            continue;
+        }
+
+        // A single changed entry cancels the whole transaction; retry one by one
+        // so only the entries that were actually edited concurrently are skipped
+        for rewrite in batch {
+            let update = tag_update(&table_name, tenant_id, user_id, rewrite)?;
+            let result = client
+                .update_item()
+                .table_name(update.table_name())
+                .set_key(Some(update.key().clone()))
+                .update_expression(update.update_expression())
+                .set_condition_expression(update.condition_expression().map(str::to_string))
+                .set_expression_attribute_values(update.expression_attribute_values().cloned())
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => {
+                    summary.updated += 1;
+                    rewrite.record(&mut stats_delta, &mut moved);
+                    rewritten.push(&rewrite.created_at);
+                }
+                Err(e) => {
+                    let e = e.into_service_error();
+                    if !e.is_conditional_check_failed_exception() {
+                        failure = Some(JournalError::DatabaseError(format!(
+                            "Failed to rewrite tags of entry {}: {}",
+                            rewrite.entry_id, e
+                        )));
+                        break 'batches;
+                    }
+                    tracing::warn!("Skipping tag rewrite for entry {}: it changed since it was read", rewrite.entry_id);
+                    summary.conflicts += 1;
+                }
+            }
+        }
+    }
+
+    // Earliest first use among the tags each new tag took over, read before
+    // the delta drops stats for tags no entry carries any more
+    let first_used = if moved.is_empty() {
+        HashMap::new()
+    } else {
+        match get_tag_stats(client, tenant_id, user_id).await {
+            Ok(stats) => earliest_first_used(&stats.tags, &moved),
+            Err(e) => {
+                tracing::warn!("Failed to read tag stats: {}", e);
+                HashMap::new()
+            }
+        }
+    };
+
+    // Keep tag statistics in step with the rewritten entries
+    let now = chrono::Utc::now().to_rfc3339();
+    if let Err(e) = apply_tag_stats_delta(client, tenant_id, user_id, &stats_delta, &now).await {
+        tracing::warn!("Failed to update tag stats: {}", e);
+    }
+    for (tag, first) in &first_used {
+        if let Err(e) = backdate_first_used(client, tenant_id, user_id, tag, first).await {
+            tracing::warn!("Failed to keep the first use of tag {}: {}", tag, e);
+        }
+    }
+
+    // Tag goals follow the new tags before the rewritten periods are recounted
+    if let Err(e) = goals::rename_goal_tags(client, tenant_id, user_id, &map_tag).await {
+        tracing::warn!("Failed to update goal tags: {}", e);
+    }
+    if let Err(e) = goals::evaluate_goals_for_entries(client, tenant_id, user_id, &rewritten).await {
+        tracing::warn!("Failed to update goal progress: {}", e);
+    }
+
+    // Reported once the entries already rewritten are accounted for
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    Ok(summary)
+}
+
+// For each tag that took over others, the earliest first_used among them
+fn earliest_first_used(
+    stats: &[TagStat],
+    moved: &HashMap<String, BTreeSet<String>>,
+) -> HashMap<String, String> {
+    let first_used: HashMap<&str, &str> = stats
+        .iter()
+        .filter_map(|stat| Some((stat.tag.as_str(), stat.first_used.as_deref()?)))
+        .collect();
+    moved
+        .iter()
+        .filter_map(|(tag, sources)| {
+            let earliest = sources.iter().filter_map(|source| first_used.get(source.as_str())).min()?;
+            Some((tag.clone(), earliest.to_string()))
+        })
+        .collect()
+}
+
+fn tag_update(
+    table_name: &str,
+    tenant_id: &str,
+    user_id: &str,
+    rewrite: &TagRewrite,
+) -> Result<Update, JournalError> {
+    let mut values = HashMap::new();
+    values.insert(":user_id".to_string(), AttributeValue::S(user_id.to_string()));
+    values.insert(":old_tags".to_string(), AttributeValue::Ss(rewrite.old_tags.clone()));
+    values.insert(":updated_at".to_string(), AttributeValue::S(chrono::Utc::now().to_rfc3339()));
+
+    // Empty sets are not allowed in DynamoDB, so removing the last tag drops the attribute
+    let update_expression = if rewrite.new_tags.is_empty() {
+        "SET updated_at = :updated_at REMOVE tags"
+    } else {
+        values.insert(":tags".to_string(), AttributeValue::Ss(rewrite.new_tags.clone()));
+        "SET updated_at = :updated_at, tags = :tags"
+    };
+
+    Update::builder()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(rewrite.entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .update_expression(update_expression)
+        .condition_expression("user_id = :user_id AND tags = :old_tags")
+        .set_expression_attribute_values(Some(values))
+        .build()
+        .map_err(|e| JournalError::DatabaseError(format!("Failed to build tag update: {}", e)))
+}
@@ -0,0 +1,247 @@
+// Entry template CRUD endpoints
+//
+// Deleting a template leaves entries written from it untouched: they keep their
+// template_id and field values, which are still returned with the entry.
+
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use journal_common::{
+    chrono, error_response, extract_tenant_context, get_dynamo_client, get_template,
+    item_to_template, json_response, lambda_runtime::Error, serde_json, template_to_item,
+    templates_table, uuid::Uuid, validate_template_fields, EntryTemplate, JournalError,
+    TemplateField,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct CreateTemplateInput {
+    name: String,
+    description: Option<String>,
+    body: Option<String>,
+    fields: Option<Vec<TemplateField>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateTemplateInput {
+    name: Option<String>,
+    description: Option<String>,
+    body: Option<String>,
+    fields: Option<Vec<TemplateField>>,
+}
+
+// Template id from a /templates/{id} path
+fn path_template_id(event: &ApiGatewayProxyRequest) -> Option<String> {
+    event
+        .path_parameters
+        .get("id")
+        .cloned()
+        .or_else(|| event.path.as_deref().and_then(|p| p.strip_prefix("/templates/")).map(str::to_string))
+        .filter(|id| !id.is_empty())
+}
+
+// GET /templates
+pub(crate) async fn list_templates(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let result = dynamo_client
+        .query()
+        .table_name(templates_table())
+        .index_name("UserIndex")
+        .key_condition_expression("tenant_id = :tenant_id AND user_id = :user_id")
+        .expression_attribute_values(":tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .expression_attribute_values(":user_id", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => {
+            let mut templates: Vec<EntryTemplate> = response.items().iter().map(item_to_template).collect();
+            templates.sort_by_key(|t| t.name.to_lowercase());
+            Ok(json_response(200, &templates))
+        }
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to list templates: {}", e)),
+        )),
+    }
+}
+
+// POST /templates
+pub(crate) async fn create_template(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let input: CreateTemplateInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    if input.name.trim().is_empty() {
+        return Ok(error_response(400, &JournalError::ValidationError("Name is required".into())));
+    }
+
+    let fields = input.fields.unwrap_or_default();
+    if let Err(e) = validate_template_fields(&fields) {
+        return Ok(error_response(400, &e));
+    }
+
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let template = EntryTemplate {
+        id: Uuid::new_v4().to_string(),
+        name: input.name.trim().to_string(),
+        description: input.description,
+        body: input.body.unwrap_or_default(),
+        fields,
+        created_at: Some(timestamp.clone()),
+        updated_at: Some(timestamp),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match dynamo_client
+        .put_item()
+        .table_name(templates_table())
+        .set_item(Some(template_to_item(&claims.tenant_id, &claims.sub, &template)))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(201, &template)),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to save template: {}", e)),
+        )),
+    }
+}
+
+// GET /templates/{id}
+pub(crate) async fn get_template_by_id(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(template_id) = path_template_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing template ID".into())));
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, &template_id).await {
+        Ok(Some(template)) => Ok(json_response(200, &template)),
+        Ok(None) => Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+        Err(e) => Ok(error_response(500, &e)),
+    }
+}
+
+// PUT /templates/{id}
+pub(crate) async fn update_template(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(template_id) = path_template_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing template ID".into())));
+    };
+
+    let input: UpdateTemplateInput = match event.body.as_deref().map(serde_json::from_str) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => return Ok(error_response(400, &JournalError::ValidationError(e.to_string()))),
+        None => return Ok(error_response(400, &JournalError::ValidationError("Missing request body".into()))),
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    let mut template = match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, &template_id).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    };
+
+    if let Some(name) = input.name {
+        if name.trim().is_empty() {
+            return Ok(error_response(400, &JournalError::ValidationError("Name must not be empty".into())));
+        }
+        template.name = name.trim().to_string();
+    }
+    if let Some(description) = input.description {
+        template.description = Some(description).filter(|d| !d.is_empty());
+    }
+    if let Some(body) = input.body {
+        template.body = body;
+    }
+    if let Some(fields) = input.fields {
+        if let Err(e) = validate_template_fields(&fields) {
+            return Ok(error_response(400, &e));
+        }
+        template.fields = fields;
+    }
+    template.updated_at = Some(chrono::Utc::now().to_rfc3339());
+
+    match dynamo_client
+        .put_item()
+        .table_name(templates_table())
+        .set_item(Some(template_to_item(&claims.tenant_id, &claims.sub, &template)))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(200, &template)),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to update template: {}", e)),
+        )),
+    }
+}
+
+// DELETE /templates/{id}
+pub(crate) async fn delete_template(
+    event: ApiGatewayProxyRequest,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    // Extract tenant context
+    let claims = match extract_tenant_context(&event.headers).await {
+        Ok(claims) => claims,
+        Err(e) => return Ok(error_response(401, &e)),
+    };
+
+    let Some(template_id) = path_template_id(&event) else {
+        return Ok(error_response(400, &JournalError::ValidationError("Missing template ID".into())));
+    };
+
+    let dynamo_client = get_dynamo_client().await;
+    match get_template(&dynamo_client, &claims.tenant_id, &claims.sub, &template_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(error_response(404, &JournalError::NotFoundError("Template not found".into()))),
+        Err(e) => return Ok(error_response(500, &e)),
+    }
+
+    match dynamo_client
+        .delete_item()
+        .table_name(templates_table())
+        .key("id", AttributeValue::S(template_id))
+        .key("tenant_id", AttributeValue::S(claims.tenant_id.clone()))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(json_response(200, &serde_json::json!({ "success": true }))),
+        Err(e) => Ok(error_response(
+            500,
+            &JournalError::DatabaseError(format!("Failed to delete template: {}", e)),
+        )),
+    }
+}
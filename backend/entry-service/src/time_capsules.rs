@@ -0,0 +1,137 @@
+// Scheduled unlocking of time capsules
+//
+// Capsules unlock by themselves once `unlock_at` passes; reads only compare it
+// with the clock. This job finds capsules that are due through the sparse
+// CapsuleIndex, publishes a TimeCapsuleUnlocked event for each one for the
+// notification path, links the entry into the wiki-link graph and counts it in
+// the rollups, tag statistics and goals it was kept out of while sealed,
+// requests its AI analysis, and removes capsule_pk so it isn't announced again.
+// The entry also gets title_pk, which makes it linkable by title.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::{
+    apply_entry_rollup, apply_tag_stats_delta, chrono, entry_notebook_id, format_unlock_at, get_dynamo_client,
+    get_notebook, link_title_pk, publish_event, request_analysis, serde_json, JournalError, RollupDelta,
+    TagStatsDelta, CAPSULE_INDEX, CAPSULE_SEALED,
+};
+use std::collections::HashMap;
+
+use crate::{goals, item_tags, links};
+
+pub(crate) async fn publish_unlocked_capsules() -> Result<usize, JournalError> {
+    let dynamo_client = get_dynamo_client().await;
+    let table_name = std::env::var("ENTRIES_TABLE").unwrap_or_else(|_| "reflekt-entries".to_string());
+    let now = format_unlock_at(chrono::Utc::now());
+    let mut published = 0;
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+
+    loop {
+        let response = dynamo_client
+            .query()
+            .table_name(&table_name)
+            .index_name(CAPSULE_INDEX)
+            .key_condition_expression("capsule_pk = :sealed AND unlock_at <= :now")
+            .expression_attribute_values(":sealed", AttributeValue::S(CAPSULE_SEALED.to_string()))
+            .expression_attribute_values(":now", AttributeValue::S(now.clone()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| JournalError::DatabaseError(format!("Failed to query time capsules: {}", e)))?;
+
+        for item in response.items() {
+            match unlock_capsule(&dynamo_client, &table_name, item, &now).await {
+                Ok(true) => published += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to unlock time capsule: {}", e),
+            }
+        }
+
+        match response.last_evaluated_key() {
+            Some(key) if !key.is_empty() => start_key = Some(key.clone()),
+            _ => break,
+        }
+    }
+
+    Ok(published)
+}
+
+// Mark one capsule as unlocked and announce it; false if another run got there first
+async fn unlock_capsule(
+    client: &DynamoDbClient,
+    table_name: &str,
+    item: &HashMap<String, AttributeValue>,
+    now: &str,
+) -> Result<bool, JournalError> {
+    let get_s = |key: &str| item.get(key).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+    let (entry_id, tenant_id, user_id) = (get_s("id"), get_s("tenant_id"), get_s("user_id"));
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(entry_id.clone()))
+        .key("tenant_id", AttributeValue::S(tenant_id.clone()))
+        // Unlocked capsules can be linked to by title; an edit since the unlock
+        // time has already set title_pk
+        .update_expression("REMOVE capsule_pk SET unlocked_at = :now, title_pk = if_not_exists(title_pk, :title_pk)")
+        .condition_expression("attribute_exists(capsule_pk)")
+        .expression_attribute_values(":now", AttributeValue::S(now.to_string()))
+        .expression_attribute_values(
+            ":title_pk",
+            AttributeValue::S(link_title_pk(&tenant_id, &user_id, &get_s("title"))),
+        )
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew)
+        .send()
+        .await;
+
+    // The entry as it is now, in case it changed since the index was read
+    let unlocked = match result {
+        Ok(response) => response.attributes.unwrap_or_else(|| item.clone()),
+        Err(e) => {
+            let e = e.into_service_error();
+            if e.is_conditional_check_failed_exception() {
+                return Ok(false);
+            }
+            return Err(JournalError::DatabaseError(format!("Failed to unlock entry {}: {}", entry_id, e)));
+        }
+    };
+
+    if let Err(e) = apply_entry_rollup(client, &unlocked, &RollupDelta::for_entry(&unlocked, 1)).await {
+        tracing::warn!("Failed to update daily rollup: {}", e);
+    }
+    let tag_delta = TagStatsDelta::between(&[] as &[String], &item_tags(&unlocked));
+    if let Err(e) = apply_tag_stats_delta(client, &tenant_id, &user_id, &tag_delta, &get_s("created_at")).await {
+        tracing::warn!("Failed to update tag stats: {}", e);
+    }
+    if let Err(e) = goals::evaluate_goals_after_write(client, &unlocked).await {
+        tracing::warn!("Failed to update goal progress: {}", e);
+    }
+
+    if let Err(e) = links::sync_links_after_write(client, &tenant_id, &user_id, None, &unlocked).await {
+        tracing::warn!("Failed to update entry links: {}", e);
+    }
+
+    // The AI service records a skip if the notebook has AI analysis turned off
+    let ai_enabled = match get_notebook(client, &tenant_id, &user_id, entry_notebook_id(&unlocked)).await {
+        Ok(notebook) => notebook.is_none_or(|n| n.ai_enabled),
+        Err(e) => {
+            tracing::warn!("Failed to load notebook: {}", e);
+            true
+        }
+    };
+    if let Err(e) = request_analysis(&unlocked, ai_enabled, false).await {
+        tracing::warn!("Failed to request analysis of entry {}: {}", entry_id, e);
+    }
+
+    let event_detail = serde_json::json!({
+        "entry_id": entry_id,
+        "tenant_id": tenant_id,
+        "user_id": user_id,
+        "title": get_s("title"),
+        "created_at": get_s("created_at"),
+        "unlock_at": get_s("unlock_at"),
+    });
+
+    publish_event("TimeCapsuleUnlocked", event_detail).await?;
+    Ok(true)
+}
@@ -1,12 +1,15 @@
 use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
 use aws_lambda_events::eventbridge::EventBridgeEvent;
-use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::error::BuildError;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem, Update};
 use journal_common::{
     chrono, error_response, extract_tenant_context, gamification::*, get_dynamo_client,
     json_response, lambda_runtime::{self, service_fn, Error, LambdaEvent}, serde_json, tracing,
-    tracing_subscriber, uuid, JournalError, JwtClaims,
+    tracing_subscriber, uuid, JournalError, JwtClaims, MAX_ACTIVE_GOALS,
 };
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 
 #[tokio::main]
@@ -79,6 +82,7 @@ async fn handle_eventbridge_event(
         "EntryDeleted" => process_entry_deleted(Some(event.detail)).await,
         "AIInsightRequested" => process_ai_insight(Some(event.detail)).await,
         "PromptUsed" => process_prompt_used(Some(event.detail)).await,
+        "GoalCompleted" => process_goal_completed(Some(event.detail)).await,
         _ => {
             tracing::warn!("Unknown event type: {}", detail_type);
             Ok(json_response(200, &serde_json::json!({"status": "ignored"})))
@@ -323,25 +327,64 @@ async fn process_entry_created(
 
 /// Process EntryUpdated event
 async fn process_entry_updated(
-    _detail: Option<serde_json::Value>,
+    detail: Option<serde_json::Value>,
 ) -> Result<ApiGatewayProxyResponse, Error> {
-    // For updates, we don't award additional points - just acknowledge
+    // For updates, we don't award additional points - only keep word totals right
     tracing::info!("Entry updated event received");
-    Ok(json_response(
-        200,
-        &serde_json::json!({"status": "acknowledged"}),
-    ))
+    apply_word_count_delta(detail).await
 }
 
 /// Process EntryDeleted event
 async fn process_entry_deleted(
-    _detail: Option<serde_json::Value>,
+    detail: Option<serde_json::Value>,
 ) -> Result<ApiGatewayProxyResponse, Error> {
-    // For deletes, we might want to adjust stats but not deduct points
+    // For deletes, we adjust word totals but don't deduct points
     tracing::info!("Entry deleted event received");
+    apply_word_count_delta(detail).await
+}
+
+/// Apply the word count change of an edited or deleted entry to total_words
+async fn apply_word_count_delta(
+    detail: Option<serde_json::Value>,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    let entry: EntryEvent = match detail.map(serde_json::from_value) {
+        Some(Ok(e)) => e,
+        Some(Err(e)) => {
+            tracing::error!("Failed to parse entry event: {:?}", e);
+            return Ok(json_response(
+                200,
+                &serde_json::json!({"status": "parse_error"}),
+            ));
+        }
+        None => {
+            return Ok(json_response(
+                200,
+                &serde_json::json!({"status": "no_detail"}),
+            ))
+        }
+    };
+
+    if entry.word_count_delta == 0 {
+        return Ok(json_response(
+            200,
+            &serde_json::json!({"status": "acknowledged"}),
+        ));
+    }
+
+    let client = get_dynamo_client().await;
+    let table = env::var("GAMIFICATION_TABLE").unwrap_or_else(|_| "GamificationTable".to_string());
+
+    let mut stats = get_or_create_stats(&client, &table, &entry.user_id, &entry.tenant_id).await?;
+    stats.total_words = (stats.total_words + entry.word_count_delta).max(0);
+    stats.updated_at = chrono::Utc::now().to_rfc3339();
+
+    // Word achievements unlock on growth; unlocked ones are never taken back
+    update_achievements(&mut stats);
+    save_stats(&client, &table, &stats).await?;
+
     Ok(json_response(
         200,
-        &serde_json::json!({"status": "acknowledged"}),
+        &serde_json::json!({"status": "success", "total_words": stats.total_words}),
     ))
 }
 
@@ -465,8 +508,141 @@ async fn process_prompt_used(
     ))
 }
 
+/// Process GoalCompleted event - award points for meeting a goal in one period.
+/// Each goal is rewarded once per period, and at most MAX_ACTIVE_GOALS goals
+/// per period, so redelivered events and deleted and recreated goals don't
+/// pay twice.
+async fn process_goal_completed(
+    detail: Option<serde_json::Value>,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    let detail = match detail {
+        Some(d) => d,
+        None => {
+            return Ok(json_response(
+                200,
+                &serde_json::json!({"status": "no_detail"}),
+            ))
+        }
+    };
+
+    let event: GoalCompletedEvent = match serde_json::from_value(detail) {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::error!("Failed to parse goal event: {:?}", e);
+            return Ok(json_response(
+                200,
+                &serde_json::json!({"status": "parse_error"}),
+            ));
+        }
+    };
+
+    let client = get_dynamo_client().await;
+    let table = env::var("GAMIFICATION_TABLE").unwrap_or_else(|_| "GamificationTable".to_string());
+
+    let mut stats = get_or_create_stats(&client, &table, &event.user_id, &event.tenant_id).await?;
+
+    let points_earned = goal_points(&event.period);
+    stats.points_balance += points_earned;
+    stats.lifetime_points += points_earned;
+    stats.goals_completed += 1;
+
+    let (level, title) = get_level_from_points(stats.lifetime_points);
+    stats.level = level;
+    stats.level_title = title.to_string();
+    stats.updated_at = chrono::Utc::now().to_rfc3339();
+
+    update_achievements(&mut stats);
+
+    let description = format!("Completed {} goal \"{}\" ({})", event.period, event.name, event.period_start);
+    if let Some(status) = award_goal(&client, &table, &event, &stats, points_earned, description).await? {
+        tracing::info!("Not rewarding goal {} for {}: {}", event.goal_id, event.period_start, status);
+        return Ok(json_response(200, &serde_json::json!({"status": status})));
+    }
+
+    Ok(json_response(
+        200,
+        &serde_json::json!({"status": "success", "points_awarded": points_earned}),
+    ))
+}
+
 // Helper functions
 
+// Award a goal's points for its period. The claim on the goal and period, the
+// period's award count, the updated stats and the points transaction are
+// written in one transaction, so a failed award leaves no claim behind and a
+// redelivered event can still pay out. Returns why it can't be rewarded:
+// "duplicate" if the goal was already rewarded for the period, or
+// "limit_reached" if the user has had the most goal awards for the period.
+async fn award_goal(
+    client: &aws_sdk_dynamodb::Client,
+    table: &str,
+    event: &GoalCompletedEvent,
+    stats: &GamificationStats,
+    points: i64,
+    description: String,
+) -> Result<Option<&'static str>, Error> {
+    let pk = format!("USER#{}", event.user_id);
+    let build_error = |e: BuildError| Error::from(format!("Failed to build goal award: {}", e));
+
+    let claim = Put::builder()
+        .table_name(table)
+        .item("pk", AttributeValue::S(pk.clone()))
+        .item("sk", AttributeValue::S(format!("GOAL#{}#{}", event.goal_id, event.period_start)))
+        .item("tenant_id", AttributeValue::S(event.tenant_id.clone()))
+        .item("created_at", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .condition_expression("attribute_not_exists(sk)")
+        .build()
+        .map_err(build_error)?;
+    let award_count = Update::builder()
+        .table_name(table)
+        .key("pk", AttributeValue::S(pk))
+        .key("sk", AttributeValue::S(format!("GOALAWARDS#{}#{}", event.period, event.period_start)))
+        .update_expression("ADD award_count :one")
+        .condition_expression("attribute_not_exists(award_count) OR award_count < :max")
+        .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        .expression_attribute_values(":max", AttributeValue::N(MAX_ACTIVE_GOALS.to_string()))
+        .build()
+        .map_err(build_error)?;
+    let stats_put = Put::builder()
+        .table_name(table)
+        .set_item(Some(stats_item(stats)))
+        .build()
+        .map_err(build_error)?;
+    let transaction_put = Put::builder()
+        .table_name(table)
+        .set_item(Some(transaction_item(&event.user_id, &event.tenant_id, "goal_completed", points, description)))
+        .build()
+        .map_err(build_error)?;
+
+    let result = client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(claim).build())
+        .transact_items(TransactWriteItem::builder().update(award_count).build())
+        .transact_items(TransactWriteItem::builder().put(stats_put).build())
+        .transact_items(TransactWriteItem::builder().put(transaction_put).build())
+        .send()
+        .await;
+    let Err(e) = result else {
+        return Ok(None);
+    };
+
+    // Cancellation reasons are listed in the order of the transaction's items
+    let e = e.into_service_error();
+    if let TransactWriteItemsError::TransactionCanceledException(cancelled) = &e {
+        let failed = |i: usize| {
+            cancelled.cancellation_reasons().get(i).and_then(|r| r.code()) == Some("ConditionalCheckFailed")
+        };
+        if failed(0) {
+            return Ok(Some("duplicate"));
+        }
+        if failed(1) {
+            return Ok(Some("limit_reached"));
+        }
+    }
+    tracing::error!("Failed to award goal: {:?}", e);
+    Err(Error::from(e.to_string()))
+}
+
 async fn get_or_create_stats(
     client: &aws_sdk_dynamodb::Client,
     table: &str,
@@ -507,59 +683,10 @@ async fn save_stats(
     table: &str,
     stats: &GamificationStats,
 ) -> Result<(), Error> {
-    let achievements_json = serde_json::to_string(&stats.achievements).unwrap_or_default();
-
     client
         .put_item()
         .table_name(table)
-        .item("pk", AttributeValue::S(format!("USER#{}", stats.user_id)))
-        .item("sk", AttributeValue::S("STATS".to_string()))
-        .item("tenant_id", AttributeValue::S(stats.tenant_id.clone()))
-        .item(
-            "points_balance",
-            AttributeValue::N(stats.points_balance.to_string()),
-        )
-        .item(
-            "lifetime_points",
-            AttributeValue::N(stats.lifetime_points.to_string()),
-        )
-        .item("level", AttributeValue::N(stats.level.to_string()))
-        .item("level_title", AttributeValue::S(stats.level_title.clone()))
-        .item(
-            "current_streak",
-            AttributeValue::N(stats.current_streak.to_string()),
-        )
-        .item(
-            "longest_streak",
-            AttributeValue::N(stats.longest_streak.to_string()),
-        )
-        .item(
-            "last_entry_date",
-            stats
-                .last_entry_date
-                .as_ref()
-                .map(|d| AttributeValue::S(d.clone()))
-                .unwrap_or(AttributeValue::Null(true)),
-        )
-        .item("achievements", AttributeValue::S(achievements_json))
-        .item(
-            "total_entries",
-            AttributeValue::N(stats.total_entries.to_string()),
-        )
-        .item(
-            "total_words",
-            AttributeValue::N(stats.total_words.to_string()),
-        )
-        .item(
-            "insights_requested",
-            AttributeValue::N(stats.insights_requested.to_string()),
-        )
-        .item(
-            "prompts_used",
-            AttributeValue::N(stats.prompts_used.to_string()),
-        )
-        .item("created_at", AttributeValue::S(stats.created_at.clone()))
-        .item("updated_at", AttributeValue::S(stats.updated_at.clone()))
+        .set_item(Some(stats_item(stats)))
         .send()
         .await
         .map_err(|e| {
@@ -570,6 +697,39 @@ async fn save_stats(
     Ok(())
 }
 
+// DynamoDB item holding a user's stats
+fn stats_item(stats: &GamificationStats) -> HashMap<String, AttributeValue> {
+    let achievements_json = serde_json::to_string(&stats.achievements).unwrap_or_default();
+
+    let mut item = HashMap::new();
+    item.insert("pk".to_string(), AttributeValue::S(format!("USER#{}", stats.user_id)));
+    item.insert("sk".to_string(), AttributeValue::S("STATS".to_string()));
+    item.insert("tenant_id".to_string(), AttributeValue::S(stats.tenant_id.clone()));
+    item.insert("points_balance".to_string(), AttributeValue::N(stats.points_balance.to_string()));
+    item.insert("lifetime_points".to_string(), AttributeValue::N(stats.lifetime_points.to_string()));
+    item.insert("level".to_string(), AttributeValue::N(stats.level.to_string()));
+    item.insert("level_title".to_string(), AttributeValue::S(stats.level_title.clone()));
+    item.insert("current_streak".to_string(), AttributeValue::N(stats.current_streak.to_string()));
+    item.insert("longest_streak".to_string(), AttributeValue::N(stats.longest_streak.to_string()));
+    item.insert(
+        "last_entry_date".to_string(),
+        stats
+            .last_entry_date
+            .as_ref()
+            .map(|d| AttributeValue::S(d.clone()))
+            .unwrap_or(AttributeValue::Null(true)),
+    );
+    item.insert("achievements".to_string(), AttributeValue::S(achievements_json));
+    item.insert("total_entries".to_string(), AttributeValue::N(stats.total_entries.to_string()));
+    item.insert("total_words".to_string(), AttributeValue::N(stats.total_words.to_string()));
+    item.insert("insights_requested".to_string(), AttributeValue::N(stats.insights_requested.to_string()));
+    item.insert("prompts_used".to_string(), AttributeValue::N(stats.prompts_used.to_string()));
+    item.insert("goals_completed".to_string(), AttributeValue::N(stats.goals_completed.to_string()));
+    item.insert("created_at".to_string(), AttributeValue::S(stats.created_at.clone()));
+    item.insert("updated_at".to_string(), AttributeValue::S(stats.updated_at.clone()));
+    item
+}
+
 async fn record_transaction(
     client: &aws_sdk_dynamodb::Client,
     table: &str,
@@ -579,23 +739,10 @@ async fn record_transaction(
     points: i64,
     description: String,
 ) -> Result<(), Error> {
-    let now = chrono::Utc::now();
-    let txn_id = uuid::Uuid::new_v4().to_string();
-
     client
         .put_item()
         .table_name(table)
-        .item("pk", AttributeValue::S(format!("USER#{}", user_id)))
-        .item(
-            "sk",
-            AttributeValue::S(format!("TXN#{}", now.to_rfc3339())),
-        )
-        .item("txn_id", AttributeValue::S(txn_id))
-        .item("tenant_id", AttributeValue::S(tenant_id.to_string()))
-        .item("action", AttributeValue::S(action.to_string()))
-        .item("points", AttributeValue::N(points.to_string()))
-        .item("description", AttributeValue::S(description))
-        .item("created_at", AttributeValue::S(now.to_rfc3339()))
+        .set_item(Some(transaction_item(user_id, tenant_id, action, points, description)))
         .send()
         .await
         .map_err(|e| {
@@ -606,6 +753,29 @@ async fn record_transaction(
     Ok(())
 }
 
+// DynamoDB item recording one points transaction
+fn transaction_item(
+    user_id: &str,
+    tenant_id: &str,
+    action: &str,
+    points: i64,
+    description: String,
+) -> HashMap<String, AttributeValue> {
+    let now = chrono::Utc::now();
+    let txn_id = uuid::Uuid::new_v4().to_string();
+
+    HashMap::from([
+        ("pk".to_string(), AttributeValue::S(format!("USER#{}", user_id))),
+        ("sk".to_string(), AttributeValue::S(format!("TXN#{}", now.to_rfc3339()))),
+        ("txn_id".to_string(), AttributeValue::S(txn_id)),
+        ("tenant_id".to_string(), AttributeValue::S(tenant_id.to_string())),
+        ("action".to_string(), AttributeValue::S(action.to_string())),
+        ("points".to_string(), AttributeValue::N(points.to_string())),
+        ("description".to_string(), AttributeValue::S(description)),
+        ("created_at".to_string(), AttributeValue::S(now.to_rfc3339())),
+    ])
+}
+
 fn item_to_stats(
     item: std::collections::HashMap<String, AttributeValue>,
     user_id: &str,
@@ -656,6 +826,7 @@ fn item_to_stats(
         total_words: get_n("total_words"),
         insights_requested: get_i32("insights_requested"),
         prompts_used: get_i32("prompts_used"),
+        goals_completed: get_i32("goals_completed"),
         created_at: get_s("created_at"),
         updated_at: get_s("updated_at"),
     }
@@ -2,14 +2,18 @@ use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use journal_common::lambda_http::{run, service_fn, Body, Error, Request, Response, IntoResponse};
 use journal_common::lambda_http::http::{Method, StatusCode};
-use journal_common::{get_dynamo_client, serde_json};
+use journal_common::{
+    ai_quota_exceeded, complete_structured, extract_tenant_context, get_dynamo_client, llm_provider_from_env,
+    record_ai_usage, serde_json, JwtClaims, LlmProviderKind, LlmRequest, QuotaAction,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
 use std::fmt;
 use tracing::{error, info};
 use uuid::Uuid;
 
+mod templates;
+
 const PROMPTS_TABLE: &str = "PROMPTS_TABLE";
 
 // Error types
@@ -139,94 +143,10 @@ struct GeneratePromptRequest {
     count: Option<i32>,
 }
 
-// OpenAI API structures
-#[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    temperature: f32,
-    max_tokens: u32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
-}
-
-#[derive(Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
-}
-
-// Anthropic API structures
-#[derive(Serialize)]
-struct AnthropicRequest {
-    model: String,
-    max_tokens: u32,
-    temperature: f32,
-    system: String,
-    messages: Vec<AnthropicMessage>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct AnthropicMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Deserialize)]
-struct AnthropicResponse {
-    content: Vec<AnthropicContent>,
-}
-
-#[derive(Deserialize)]
-struct AnthropicContent {
-    text: String,
-}
-
-// AI provider enum
-enum AiProvider {
-    OpenAI,
-    Anthropic,
-    None,
-}
-
-// Get AI provider based on configuration
-fn get_ai_provider() -> AiProvider {
-    let provider = env::var("AI_PROVIDER").unwrap_or_else(|_| "none".to_string());
-    
-    match provider.to_lowercase().as_str() {
-        "openai" => AiProvider::OpenAI,
-        "anthropic" => AiProvider::Anthropic,
-        _ => AiProvider::None, // Default to no AI provider
-    }
-}
-
-// API key getters
-fn get_openai_api_key() -> Result<String, Error> {
-    env::var("OPENAI_API_KEY").map_err(|_| {
-        error!("OPENAI_API_KEY environment variable is not set");
-        Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "OPENAI_API_KEY environment variable is not set",
-        )) as Error
-    })
-}
-
-fn get_anthropic_api_key() -> Result<String, Error> {
-    env::var("ANTHROPIC_API_KEY").map_err(|_| {
-        error!("ANTHROPIC_API_KEY environment variable is not set");
-        Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "ANTHROPIC_API_KEY environment variable is not set",
-        )) as Error
-    })
+// Prompts as returned by the model
+#[derive(Debug, Deserialize)]
+struct GeneratedPrompts {
+    prompts: Vec<String>,
 }
 
 async fn handle_request(event: Request) -> Result<impl IntoResponse, Error> {
@@ -328,7 +248,19 @@ async fn handle_request(event: Request) -> Result<impl IntoResponse, Error> {
                 }
             };
 
-            generate_prompts(client, generate_request).await
+            // Usage is metered per tenant and user, so generating needs the claims
+            let claims = match extract_tenant_context(event.headers()).await {
+                Ok(claims) => claims,
+                Err(e) => {
+                    error!("Error extracting tenant context: {}", e);
+                    return Ok(create_error_response(
+                        StatusCode::UNAUTHORIZED,
+                        "Invalid or missing token".to_string(),
+                    ));
+                }
+            };
+
+            generate_prompts(client, &claims, generate_request).await
         }
 
         // GET /prompts/{id} - Get prompt by ID
@@ -419,6 +351,11 @@ async fn handle_request(event: Request) -> Result<impl IntoResponse, Error> {
             delete_prompt(client, id).await
         }
 
+        // /prompt-templates/{name}[/active] - Tenant prompt templates (tenant admins only)
+        (_, Some(&"prompt-templates"), Some(name)) if path_parts.len() <= 4 => {
+            templates::handle_template_request(client, &event, name, path_parts.get(3).copied()).await
+        }
+
         // Not found
         _ => Ok(create_error_response(
             StatusCode::NOT_FOUND,
@@ -430,30 +367,36 @@ async fn handle_request(event: Request) -> Result<impl IntoResponse, Error> {
 // Generate prompts using AI service
 async fn generate_prompts(
     client: aws_sdk_dynamodb::Client,
+    claims: &JwtClaims,
     request: GeneratePromptRequest,
 ) -> Result<Response<Body>, Error> {
-    // Check what AI provider to use
-    let provider = get_ai_provider();
+    // Once the tenant's monthly AI quota is used up, serve the stored prompts
+    // for the category or refuse, depending on AI_QUOTA_ACTION
+    match ai_quota_exceeded(&client, &claims.tenant_id).await {
+        Some(QuotaAction::Block) => {
+            return Ok(create_error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Monthly AI quota exceeded".to_string(),
+            ));
+        }
+        Some(QuotaAction::Downgrade) => {
+            info!("AI quota exceeded for tenant {}, serving stored prompts", claims.tenant_id);
+            return get_prompts_by_category(client, &request.category).await;
+        }
+        None => {}
+    }
     
-    match provider {
-        AiProvider::None => {
+    // Check what AI provider to use; prompt generation stays off until AI_PROVIDER is set
+    let provider = match llm_provider_from_env(LlmProviderKind::Disabled) {
+        Ok(provider) => provider,
+        Err(e) => {
+            error!("AI provider unavailable: {}", e);
             return Ok(create_error_response(
                 StatusCode::SERVICE_UNAVAILABLE,
                 "AI service is not configured. Set AI_PROVIDER environment variable.".to_string(),
             ));
-        },
-        AiProvider::OpenAI => generate_with_openai(client, request).await,
-        AiProvider::Anthropic => generate_with_anthropic(client, request).await,
-    }
-}
-
-// Generate prompts with OpenAI
-async fn generate_with_openai(
-    client: aws_sdk_dynamodb::Client, 
-    request: GeneratePromptRequest
-) -> Result<Response<Body>, Error> {
-    let api_key = get_openai_api_key()?;
-    let http_client = reqwest::Client::new();
+        }
+    };
     
     // Determine how many prompts to generate
     let count = request.count.unwrap_or(1).min(5); // Limit to 5 max
@@ -477,156 +420,56 @@ async fn generate_with_openai(
         prompt.push_str(&format!(" with a {} tone", mood));
     }
     
-    prompt.push_str(". Format the response as a JSON array of strings, with each string being a prompt.");
+    prompt.push_str(". Format the response as a JSON object with a \"prompts\" field holding an array of strings, with each string being a prompt.");
     
-    // Create OpenAI request
-    let openai_request = OpenAIRequest {
-        model: env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo".to_string()),
-        messages: vec![
-            OpenAIMessage {
-                role: "system".to_string(),
-                content: "You are a thoughtful journaling assistant that creates meaningful prompts for self-reflection.".to_string(),
-            },
-            OpenAIMessage {
-                role: "user".to_string(),
-                content: prompt,
-            },
-        ],
+    let llm_request = LlmRequest {
+        system: "You are a thoughtful journaling assistant that creates meaningful prompts for self-reflection.".to_string(),
+        prompt,
         temperature: 0.7,
         max_tokens: 400,
+        json: true,
     };
     
-    // Call OpenAI API
-    let response = http_client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&openai_request)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Error calling OpenAI API: {:?}", e);
-            Box::new(e)
-        })?;
-        
-    let response_body: OpenAIResponse = response
-        .json()
-        .await
-        .map_err(|e| {
-            error!("Error parsing OpenAI response: {:?}", e);
-            Box::new(e)
-        })?;
-        
-    if response_body.choices.is_empty() {
-        return Ok(create_error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Empty response from OpenAI".to_string(),
-        ));
-    }
-    
-    // Parse the response
-    let content = &response_body.choices[0].message.content;
-    let prompt_texts: Vec<String> = serde_json::from_str(content)
-        .map_err(|e| {
-            error!("Error parsing prompts from OpenAI response: {:?}", e);
-            Box::new(e)
-        })?;
-    
-    // Save the prompts to DynamoDB
-    let prompts = save_generated_prompts(client, prompt_texts, &request.category).await?;
-    
-    // Return response
-    Ok(create_json_response(
-        StatusCode::OK,
-        serde_json::to_string(&PromptsResponse { prompts })?,
-    ))
-}
-
-// Generate prompts with Anthropic
-async fn generate_with_anthropic(
-    client: aws_sdk_dynamodb::Client, 
-    request: GeneratePromptRequest
-) -> Result<Response<Body>, Error> {
-    let api_key = get_anthropic_api_key()?;
-    let http_client = reqwest::Client::new();
-    
-    // Determine how many prompts to generate
-    let count = request.count.unwrap_or(1).min(5); // Limit to 5 max
-    
-    // Prepare the prompt
-    let mut prompt = format!("Generate {} thoughtful and deep journaling prompts", count);
-    
-    // Add category if provided
-    prompt.push_str(&format!(" in the category of '{}'", request.category));
-    
-    // Add themes if provided
-    if let Some(themes) = &request.themes {
-        if !themes.is_empty() {
-            prompt.push_str(" that relate to the following themes: ");
-            prompt.push_str(&themes.join(", "));
+    // Keep the non-empty prompts, no more than were asked for
+    let validate = |generated: GeneratedPrompts| {
+        let prompts: Vec<String> = generated
+            .prompts
+            .into_iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .take(count.max(1) as usize)
+            .collect();
+        if prompts.is_empty() {
+            return Err("prompts must hold at least one prompt".to_string());
         }
-    }
-    
-    // Add mood if provided
-    if let Some(mood) = &request.mood {
-        prompt.push_str(&format!(" with a {} tone", mood));
-    }
-    
-    prompt.push_str(". Format the response as a JSON array of strings, with each string being a prompt.");
-    
-    // Create Anthropic request
-    let anthropic_request = AnthropicRequest {
-        model: env::var("ANTHROPIC_MODEL").unwrap_or_else(|_| "claude-3-haiku-20240307".to_string()),
-        max_tokens: 400,
-        temperature: 0.7,
-        system: "You are a thoughtful journaling assistant that creates meaningful prompts for self-reflection.".to_string(),
-        messages: vec![
-            AnthropicMessage {
-                role: "user".to_string(),
-                content: prompt,
-            },
-        ],
+        Ok(GeneratedPrompts { prompts })
     };
     
-    // Call Anthropic API
-    let response = http_client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("Content-Type", "application/json")
-        .json(&anthropic_request)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Error calling Anthropic API: {:?}", e);
-            Box::new(e)
-        })?;
-        
-    let response_body: AnthropicResponse = response
-        .json()
-        .await
-        .map_err(|e| {
-            error!("Error parsing Anthropic response: {:?}", e);
-            Box::new(e)
-        })?;
-        
-    if response_body.content.is_empty() {
-        return Ok(create_error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Empty response from Anthropic".to_string(),
-        ));
-    }
-    
-    // Parse the response
-    let content = &response_body.content[0].text;
-    let prompt_texts: Vec<String> = serde_json::from_str(content)
-        .map_err(|e| {
-            error!("Error parsing prompts from Anthropic response: {:?}", e);
-            Box::new(e)
-        })?;
+    // Call the provider
+    let generated = match complete_structured(provider.as_ref(), &llm_request, validate).await {
+        Ok((generated, response)) => {
+            if let Err(e) = record_ai_usage(&client, &claims.tenant_id, &claims.sub, "prompts", &response).await {
+                error!("Failed to record AI usage: {}", e);
+            }
+            generated
+        }
+        Err(e) => {
+            error!("Error calling {} API: {}", provider.name(), e);
+            // Replies that couldn't be used were still paid for
+            if let Some(spent) = &e.spent {
+                if let Err(e) = record_ai_usage(&client, &claims.tenant_id, &claims.sub, "prompts", spent).await {
+                    error!("Failed to record AI usage: {}", e);
+                }
+            }
+            return Ok(create_error_response(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to generate prompts: {}", e),
+            ));
+        }
+    };
     
     // Save the prompts to DynamoDB
-    let prompts = save_generated_prompts(client, prompt_texts, &request.category).await?;
+    let prompts = save_generated_prompts(client, generated.prompts, &request.category).await?;
     
     // Return response
     Ok(create_json_response(
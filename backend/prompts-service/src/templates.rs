@@ -0,0 +1,187 @@
+// Management of the tenant's analysis prompt templates
+//
+// GET    /prompt-templates/{name}         versions and rollouts in use for the tenant
+// POST   /prompt-templates/{name}         add a tenant version {system, prompt, description}
+// PUT    /prompt-templates/{name}/active  choose {version, candidate_version, candidate_percent}
+// DELETE /prompt-templates/{name}/active  go back to the global templates
+//
+// Only tenant admins can use these. Global templates are written to the
+// prompt templates table by operators.
+
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use journal_common::lambda_http::http::{Method, StatusCode};
+use journal_common::lambda_http::{Body, Error, Request, Response};
+use journal_common::{
+    builtin_template, create_template_version, delete_template_rollout, extract_tenant_context,
+    get_template_rollout, is_known_template, list_template_versions, serde_json, set_template_rollout,
+    tenant_template_scope, JournalError, PromptTemplate, TemplateRollout, GLOBAL_TEMPLATE_SCOPE,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{create_error_response, create_json_response};
+
+#[derive(Debug, Deserialize)]
+struct CreateTemplateRequest {
+    system: String,
+    prompt: String,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRolloutRequest {
+    version: u32,
+    candidate_version: Option<u32>,
+    candidate_percent: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScopeTemplates {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollout: Option<TemplateRollout>,
+    versions: Vec<PromptTemplate>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplatesResponse {
+    name: String,
+    // Which templates the tenant's analyses use: "tenant", "global" or "builtin"
+    in_use: &'static str,
+    builtin: Option<PromptTemplate>,
+    global: ScopeTemplates,
+    tenant: ScopeTemplates,
+}
+
+fn journal_error_response(e: &JournalError) -> Response<Body> {
+    let status = match e {
+        JournalError::ValidationError(_) => StatusCode::BAD_REQUEST,
+        JournalError::NotFoundError(_) => StatusCode::NOT_FOUND,
+        _ => {
+            error!("Prompt template error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    create_error_response(status, e.to_string())
+}
+
+async fn scope_templates(client: &DynamoDbClient, scope: &str, name: &str) -> Result<ScopeTemplates, JournalError> {
+    Ok(ScopeTemplates {
+        rollout: get_template_rollout(client, scope, name).await?,
+        versions: list_template_versions(client, scope, name).await?,
+    })
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(event: &Request) -> Result<T, JournalError> {
+    let body = match event.body() {
+        Body::Text(text) => text.clone(),
+        Body::Binary(bin) => String::from_utf8_lossy(bin).to_string(),
+        _ => return Err(JournalError::ValidationError("Invalid request body".to_string())),
+    };
+    serde_json::from_str(&body).map_err(|e| JournalError::ValidationError(format!("Invalid request format: {}", e)))
+}
+
+pub(crate) async fn handle_template_request(
+    client: DynamoDbClient,
+    event: &Request,
+    name: &str,
+    action: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let claims = match extract_tenant_context(event.headers()).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            error!("Error extracting tenant context: {}", e);
+            return Ok(create_error_response(StatusCode::UNAUTHORIZED, "Invalid or missing token".to_string()));
+        }
+    };
+    if claims.role.as_deref() != Some("admin") {
+        return Ok(create_error_response(
+            StatusCode::FORBIDDEN,
+            "Only tenant admins can manage prompt templates".to_string(),
+        ));
+    }
+    if !is_known_template(name) {
+        return Ok(create_error_response(StatusCode::NOT_FOUND, format!("Unknown template: {}", name)));
+    }
+
+    let scope = tenant_template_scope(&claims.tenant_id);
+    let method = event.method();
+
+    match action {
+        None if method == Method::GET => {
+            let (global, tenant) = match tokio::try_join!(
+                scope_templates(&client, GLOBAL_TEMPLATE_SCOPE, name),
+                scope_templates(&client, &scope, name)
+            ) {
+                Ok(templates) => templates,
+                Err(e) => return Ok(journal_error_response(&e)),
+            };
+
+            let in_use = if tenant.rollout.is_some() {
+                "tenant"
+            } else if global.rollout.is_some() {
+                "global"
+            } else {
+                "builtin"
+            };
+            let response = TemplatesResponse {
+                name: name.to_string(),
+                in_use,
+                builtin: builtin_template(name),
+                global,
+                tenant,
+            };
+            Ok(create_json_response(StatusCode::OK, serde_json::to_string(&response)?))
+        }
+
+        None if method == Method::POST => {
+            let request: CreateTemplateRequest = match parse_body(event) {
+                Ok(request) => request,
+                Err(e) => return Ok(journal_error_response(&e)),
+            };
+            match create_template_version(
+                &client,
+                &scope,
+                name,
+                &request.system,
+                &request.prompt,
+                request.description.as_deref(),
+            )
+            .await
+            {
+                Ok(template) => Ok(create_json_response(StatusCode::CREATED, serde_json::to_string(&template)?)),
+                Err(e) => Ok(journal_error_response(&e)),
+            }
+        }
+
+        Some("active") if method == Method::PUT => {
+            let request: SetRolloutRequest = match parse_body(event) {
+                Ok(request) => request,
+                Err(e) => return Ok(journal_error_response(&e)),
+            };
+            let candidate = match (request.candidate_version, request.candidate_percent) {
+                (Some(version), percent) => Some((version, percent.unwrap_or(0))),
+                (None, Some(_)) => {
+                    return Ok(create_error_response(
+                        StatusCode::BAD_REQUEST,
+                        "candidate_percent needs a candidate_version".to_string(),
+                    ))
+                }
+                (None, None) => None,
+            };
+            match set_template_rollout(&client, &scope, name, request.version, candidate).await {
+                Ok(rollout) => Ok(create_json_response(StatusCode::OK, serde_json::to_string(&rollout)?)),
+                Err(e) => Ok(journal_error_response(&e)),
+            }
+        }
+
+        Some("active") if method == Method::DELETE => match delete_template_rollout(&client, &scope, name).await {
+            Ok(()) => Ok(create_json_response(
+                StatusCode::OK,
+                serde_json::json!({ "success": true }).to_string(),
+            )),
+            Err(e) => Ok(journal_error_response(&e)),
+        },
+
+        _ => Ok(create_error_response(StatusCode::NOT_FOUND, "Endpoint not found".to_string())),
+    }
+}
@@ -2,10 +2,11 @@ use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
 use journal_common::lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use aws_sdk_dynamodb::types::AttributeValue;
 use journal_common::{
-    error_response, extract_tenant_context, get_dynamo_client, json_response, serde_json, JournalError,
+    attribute_strings, chrono_tz, error_response, extract_tenant_context, get_dynamo_client, json_response,
+    serde_json, JournalError, DEFAULT_INSIGHT_TONE, INSIGHT_TONES, MAX_REDACTION_NAMES, MAX_REDACTION_NAME_LENGTH,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 // Category model
@@ -31,7 +32,14 @@ struct UserSettings {
     date_format: Option<String>,
     time_format: Option<String>,
     language: Option<String>,
+    timezone: Option<String>,
+    // Whether the user's entries are analyzed by the AI service
+    ai_insights_enabled: Option<bool>,
     privacy_level: Option<String>,
+    // Names replaced with placeholders in text sent to AI providers
+    redaction_names: Option<Vec<String>>,
+    // Tone AI insights and reflections are written in
+    insight_tone: Option<String>,
     notification_preferences: Option<NotificationPreferences>,
     display_preferences: Option<DisplayPreferences>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -80,7 +88,11 @@ struct UpdateSettingsInput {
     date_format: Option<String>,
     time_format: Option<String>,
     language: Option<String>,
+    timezone: Option<String>,
+    ai_insights_enabled: Option<bool>,
     privacy_level: Option<String>,
+    redaction_names: Option<Vec<String>>,
+    insight_tone: Option<String>,
     notification_preferences: Option<NotificationPreferences>,
     display_preferences: Option<DisplayPreferences>,
 }
@@ -432,7 +444,11 @@ async fn get_settings(
                     date_format: item.get("date_format").and_then(|v| v.as_s().ok().map(|s| s.clone())),
                     time_format: item.get("time_format").and_then(|v| v.as_s().ok().map(|s| s.clone())),
                     language: item.get("language").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                    timezone: item.get("timezone").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                    ai_insights_enabled: Some(item.get("ai_insights_enabled").and_then(|v| v.as_bool().ok().copied()).unwrap_or(true)),
                     privacy_level: item.get("privacy_level").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                    redaction_names: Some(attribute_strings(item.get("redaction_names"))),
+                    insight_tone: Some(item.get("insight_tone").and_then(|v| v.as_s().ok().cloned()).unwrap_or_else(|| DEFAULT_INSIGHT_TONE.to_string())),
                     notification_preferences: if let Some(AttributeValue::M(prefs)) = item.get("notification_preferences") {
                         Some(NotificationPreferences {
                             email_notifications: prefs.get("email_notifications")
@@ -480,7 +496,11 @@ async fn get_settings(
                     date_format: Some("MM/DD/YYYY".to_string()),
                     time_format: Some("12h".to_string()),
                     language: Some("en".to_string()),
+                    timezone: Some("UTC".to_string()),
+                    ai_insights_enabled: Some(true),
                     privacy_level: Some("private".to_string()),
+                    redaction_names: Some(Vec::new()),
+                    insight_tone: Some(DEFAULT_INSIGHT_TONE.to_string()),
                     notification_preferences: Some(NotificationPreferences {
                         email_notifications: false,
                         journal_reminders: false,
@@ -565,6 +585,29 @@ async fn update_settings(
         is_first = false;
     }
 
+    // Add timezone if present; must be an IANA name such as "Europe/Berlin"
+    if let Some(timezone) = &input.timezone {
+        if timezone.parse::<chrono_tz::Tz>().is_err() {
+            return Ok(error_response(400, &JournalError::ValidationError(format!("Unknown timezone: {}", timezone))));
+        }
+        if !is_first {
+            update_expression.push_str(", ");
+        }
+        update_expression.push_str("timezone = :timezone");
+        expression_values.insert(":timezone".to_string(), AttributeValue::S(timezone.clone()));
+        is_first = false;
+    }
+
+    // Add ai_insights_enabled if present
+    if let Some(ai_insights_enabled) = input.ai_insights_enabled {
+        if !is_first {
+            update_expression.push_str(", ");
+        }
+        update_expression.push_str("ai_insights_enabled = :ai_insights_enabled");
+        expression_values.insert(":ai_insights_enabled".to_string(), AttributeValue::Bool(ai_insights_enabled));
+        is_first = false;
+    }
+
     // Add privacy_level if present
     if let Some(privacy_level) = &input.privacy_level {
         if !is_first {
@@ -575,6 +618,53 @@ async fn update_settings(
         is_first = false;
     }
 
+    // Add insight_tone if present; one of INSIGHT_TONES
+    if let Some(insight_tone) = &input.insight_tone {
+        if !INSIGHT_TONES.contains(&insight_tone.as_str()) {
+            return Ok(error_response(400, &JournalError::ValidationError(format!(
+                "insightTone must be one of: {}",
+                INSIGHT_TONES.join(", ")
+            ))));
+        }
+        if !is_first {
+            update_expression.push_str(", ");
+        }
+        update_expression.push_str("insight_tone = :insight_tone");
+        expression_values.insert(":insight_tone".to_string(), AttributeValue::S(insight_tone.clone()));
+        is_first = false;
+    }
+
+    // Add redaction_names if present; trimmed, without blanks or duplicates
+    if let Some(names) = &input.redaction_names {
+        let mut seen = HashSet::new();
+        let names: Vec<String> = names
+            .iter()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty() && seen.insert(name.to_lowercase()))
+            .collect();
+        if names.len() > MAX_REDACTION_NAMES {
+            return Ok(error_response(400, &JournalError::ValidationError(format!(
+                "At most {} redaction names are allowed",
+                MAX_REDACTION_NAMES
+            ))));
+        }
+        if let Some(name) = names.iter().find(|name| name.chars().count() > MAX_REDACTION_NAME_LENGTH) {
+            return Ok(error_response(400, &JournalError::ValidationError(format!(
+                "Redaction name is longer than {} characters: {}",
+                MAX_REDACTION_NAME_LENGTH, name
+            ))));
+        }
+        if !is_first {
+            update_expression.push_str(", ");
+        }
+        update_expression.push_str("redaction_names = :redaction_names");
+        expression_values.insert(
+            ":redaction_names".to_string(),
+            AttributeValue::L(names.into_iter().map(AttributeValue::S).collect()),
+        );
+        is_first = false;
+    }
+
     // Add notification_preferences if present
     if let Some(notification_prefs) = &input.notification_preferences {
         if !is_first {
@@ -677,7 +767,11 @@ async fn update_settings(
                 date_format: updated_item.get("date_format").and_then(|v| v.as_s().ok().map(|s| s.clone())),
                 time_format: updated_item.get("time_format").and_then(|v| v.as_s().ok().map(|s| s.clone())),
                 language: updated_item.get("language").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                timezone: updated_item.get("timezone").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                ai_insights_enabled: Some(updated_item.get("ai_insights_enabled").and_then(|v| v.as_bool().ok().copied()).unwrap_or(true)),
                 privacy_level: updated_item.get("privacy_level").and_then(|v| v.as_s().ok().map(|s| s.clone())),
+                redaction_names: Some(attribute_strings(updated_item.get("redaction_names"))),
+                insight_tone: Some(updated_item.get("insight_tone").and_then(|v| v.as_s().ok().cloned()).unwrap_or_else(|| DEFAULT_INSIGHT_TONE.to_string())),
                 notification_preferences: if let Some(AttributeValue::M(prefs)) = updated_item.get("notification_preferences") {
                     Some(NotificationPreferences {
                         email_notifications: prefs.get("email_notifications")